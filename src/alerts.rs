@@ -0,0 +1,287 @@
+//! Down/recovery and threshold detection for alerting sinks.
+//!
+//! A target is down after `down_after` consecutive lost probes and back up
+//! with its next reply. RTT and loss thresholds are judged over the last
+//! `window` probes; a breach is reported once and cleared when the value
+//! drops back to the threshold or below.
+
+use chrono::Local;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::time::Instant;
+
+use crate::sinks::Target;
+use crate::{PingResult, TIMESTAMP_FORMAT};
+
+/// Placeholders understood by `render`
+const PLACEHOLDERS: &[&str] = &[
+    "host", "ip", "run_id", "event", "timestamp", "loss", "avg_rtt", "last_rtt", "threshold",
+    "duration",
+];
+
+/// Conditions that raise alerts
+#[derive(Clone, Debug)]
+pub struct AlertRules {
+    pub down_after: u32,
+    pub rtt_ms: Option<f64>,
+    pub loss_percent: Option<f64>,
+    pub window: usize,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AlertKind {
+    Down,
+    Up,
+    RttHigh,
+    RttNormal,
+    LossHigh,
+    LossNormal,
+}
+
+impl AlertKind {
+    /// Short human-readable label
+    pub fn title(self) -> &'static str {
+        match self {
+            AlertKind::Down => "DOWN",
+            AlertKind::Up => "RECOVERED",
+            AlertKind::RttHigh => "HIGH LATENCY",
+            AlertKind::RttNormal => "LATENCY NORMAL",
+            AlertKind::LossHigh => "HIGH PACKET LOSS",
+            AlertKind::LossNormal => "PACKET LOSS NORMAL",
+        }
+    }
+
+    /// True for the event that opens a problem, false for its recovery
+    pub fn is_problem(self) -> bool {
+        matches!(self, AlertKind::Down | AlertKind::RttHigh | AlertKind::LossHigh)
+    }
+
+    /// The problem this event opens or closes, e.g. "rtt" for both RTT events
+    pub fn problem(self) -> &'static str {
+        match self {
+            AlertKind::Down | AlertKind::Up => "down",
+            AlertKind::RttHigh | AlertKind::RttNormal => "rtt",
+            AlertKind::LossHigh | AlertKind::LossNormal => "loss",
+        }
+    }
+
+    /// Name as in the config file's `events`, e.g. "rtt_high"
+    pub fn name(self) -> &'static str {
+        match self {
+            AlertKind::Down => "down",
+            AlertKind::Up => "up",
+            AlertKind::RttHigh => "rtt_high",
+            AlertKind::RttNormal => "rtt_normal",
+            AlertKind::LossHigh => "loss_high",
+            AlertKind::LossNormal => "loss_normal",
+        }
+    }
+}
+
+/// One state change of a target
+#[derive(Clone, Debug, Serialize)]
+pub struct Alert {
+    pub kind: AlertKind,
+    pub timestamp: String,
+    /// Loss over the alert window
+    pub loss_percent: f64,
+    /// Mean RTT of the replies in the alert window
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub avg_rtt_ms: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_rtt_ms: Option<f64>,
+    /// Threshold that was crossed, for RTT and loss alerts
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub threshold: Option<f64>,
+    /// How long the problem lasted, for recoveries
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub duration_seconds: Option<f64>,
+}
+
+#[derive(Default)]
+struct TargetState {
+    consecutive_lost: u32,
+    // Time of the first probe in the current run of losses
+    lost_since: Option<Instant>,
+    down_since: Option<Instant>,
+    rtt_since: Option<Instant>,
+    loss_since: Option<Instant>,
+    window: VecDeque<Option<f64>>,
+}
+
+pub struct AlertDetector {
+    rules: AlertRules,
+    states: HashMap<String, TargetState>,
+}
+
+impl AlertDetector {
+    pub fn new(rules: AlertRules) -> Self {
+        AlertDetector { rules, states: HashMap::new() }
+    }
+
+    /// Feed one probe of `host` and return the alerts it triggers
+    pub fn observe(&mut self, host: &str, result: &PingResult) -> Vec<Alert> {
+        let rules = &self.rules;
+        let state = self.states.entry(host.to_string()).or_default();
+        let now = Instant::now();
+        let rtt = if result.success() { result.rtt_ms } else { None };
+
+        state.window.push_back(rtt);
+        while state.window.len() > rules.window.max(1) {
+            state.window.pop_front();
+        }
+        let lost = state.window.iter().filter(|r| r.is_none()).count();
+        let loss_percent = lost as f64 / state.window.len() as f64 * 100.0;
+        let replies: Vec<f64> = state.window.iter().flatten().copied().collect();
+        let avg_rtt_ms = if replies.is_empty() {
+            None
+        } else {
+            Some(replies.iter().sum::<f64>() / replies.len() as f64)
+        };
+
+        let round = |v: f64| (v * 100.0).round() / 100.0;
+        let alert = |kind, threshold, since: Option<Instant>| Alert {
+            kind,
+            timestamp: result
+                .timestamp
+                .unwrap_or_else(Local::now)
+                .format(TIMESTAMP_FORMAT)
+                .to_string(),
+            loss_percent: round(loss_percent),
+            avg_rtt_ms: avg_rtt_ms.map(round),
+            last_rtt_ms: rtt,
+            threshold,
+            duration_seconds: since.map(|s| round(now.duration_since(s).as_secs_f64())),
+        };
+        let mut alerts = Vec::new();
+
+        if result.success() {
+            state.consecutive_lost = 0;
+            state.lost_since = None;
+            if let Some(since) = state.down_since.take() {
+                alerts.push(alert(AlertKind::Up, None, Some(since)));
+            }
+        } else {
+            if state.consecutive_lost == 0 {
+                state.lost_since = Some(now);
+            }
+            state.consecutive_lost += 1;
+            if state.consecutive_lost >= rules.down_after.max(1) && state.down_since.is_none() {
+                state.down_since = state.lost_since;
+                alerts.push(alert(AlertKind::Down, None, None));
+            }
+        }
+
+        // RTT is only judged on replies; a down target is reported as such
+        if let (Some(limit), Some(avg)) = (rules.rtt_ms, avg_rtt_ms) {
+            if result.success() {
+                if avg > limit && state.rtt_since.is_none() {
+                    state.rtt_since = Some(now);
+                    alerts.push(alert(AlertKind::RttHigh, Some(limit), None));
+                } else if avg <= limit {
+                    if let Some(since) = state.rtt_since.take() {
+                        alerts.push(alert(AlertKind::RttNormal, Some(limit), Some(since)));
+                    }
+                }
+            }
+        }
+
+        // Loss needs a full window so the first lost probe isn't 100%
+        if let Some(limit) = rules.loss_percent {
+            if state.window.len() >= rules.window.max(1) {
+                if loss_percent > limit && state.loss_since.is_none() {
+                    state.loss_since = Some(now);
+                    alerts.push(alert(AlertKind::LossHigh, Some(limit), None));
+                } else if loss_percent <= limit {
+                    if let Some(since) = state.loss_since.take() {
+                        alerts.push(alert(AlertKind::LossNormal, Some(limit), Some(since)));
+                    }
+                }
+            }
+        }
+
+        alerts
+    }
+}
+
+/// "1h 02m 03s", "2m 05s" or "12s"
+pub fn human_duration(seconds: f64) -> String {
+    let total = seconds.round() as u64;
+    let (h, m, s) = (total / 3600, total / 60 % 60, total % 60);
+    if h > 0 {
+        format!("{}h {:02}m {:02}s", h, m, s)
+    } else if m > 0 {
+        format!("{}m {:02}s", m, s)
+    } else {
+        format!("{}s", s)
+    }
+}
+
+/// What an alert is about in one line, e.g. "HIGH LATENCY: avg 120.50 ms over the alert window, above 100 ms"
+pub fn describe(alert: &Alert) -> String {
+    let ms = |v: Option<f64>| v.map_or_else(|| "-".to_string(), |v| format!("{:.2} ms", v));
+    let threshold = alert.threshold.map_or_else(String::new, |limit| limit.to_string());
+    let mut text = match alert.kind {
+        AlertKind::Down => format!("{:.1}% of the recent probes lost", alert.loss_percent),
+        AlertKind::Up => format!("replying again in {}", ms(alert.last_rtt_ms)),
+        AlertKind::RttHigh => format!("avg {} over the alert window, above {} ms", ms(alert.avg_rtt_ms), threshold),
+        AlertKind::RttNormal => format!("avg {} over the alert window, {} ms or below", ms(alert.avg_rtt_ms), threshold),
+        AlertKind::LossHigh => format!("{:.1}% loss over the alert window, above {}%", alert.loss_percent, threshold),
+        AlertKind::LossNormal => format!("{:.1}% loss over the alert window, {}% or below", alert.loss_percent, threshold),
+    };
+    if let Some(duration) = alert.duration_seconds {
+        text.push_str(&format!(", after {}", human_duration(duration)));
+    }
+    format!("{}: {}", alert.kind.title(), text)
+}
+
+fn expand(template: &str, mut value: impl FnMut(&str) -> Option<String>) -> Result<String, String> {
+    let mut out = String::new();
+    let mut rest = template;
+
+    while let Some(open) = rest.find('{') {
+        out.push_str(&rest[..open]);
+        let close = rest[open..]
+            .find('}')
+            .ok_or_else(|| format!("Unclosed '{{' in template '{}'", template))?;
+        let name = &rest[open + 1..open + close];
+        let expanded = value(name).ok_or_else(|| {
+            format!(
+                "Unknown placeholder '{{{}}}' in template '{}' (use {})",
+                name,
+                template,
+                PLACEHOLDERS.join(", ")
+            )
+        })?;
+        out.push_str(&expanded);
+        rest = &rest[open + close + 1..];
+    }
+    out.push_str(rest);
+    Ok(out)
+}
+
+/// Reject templates with unknown placeholders before they are needed
+pub fn check_template(template: &str) -> Result<(), String> {
+    expand(template, |name| PLACEHOLDERS.contains(&name).then(String::new)).map(|_| ())
+}
+
+/// Fill `{host}`, `{event}`, `{loss}`... in a notification template; missing values become "-"
+pub fn render(template: &str, target: &Target, alert: &Alert) -> Result<String, String> {
+    let num = |v: Option<f64>| v.map_or_else(|| "-".to_string(), |v| v.to_string());
+    expand(template, |name| {
+        Some(match name {
+            "host" => target.host.to_string(),
+            "ip" => target.addr.to_string(),
+            "run_id" => target.run_id.to_string(),
+            "event" => alert.kind.title().to_string(),
+            "timestamp" => alert.timestamp.clone(),
+            "loss" => alert.loss_percent.to_string(),
+            "avg_rtt" => num(alert.avg_rtt_ms),
+            "last_rtt" => num(alert.last_rtt_ms),
+            "threshold" => num(alert.threshold),
+            "duration" => num(alert.duration_seconds),
+            _ => return None,
+        })
+    })
+}
@@ -0,0 +1,235 @@
+//! Anycast site switches.
+//!
+//! Behind an anycast address several sites answer, and which one a probe
+//! reaches can change in the middle of a run. In the RTT that is only a step
+//! to a new level. With `--anycast` three things that move with the site are
+//! tracked: the TTL the replies arrive with (another site is usually a
+//! different number of hops away), the level the RTT settles at, and the
+//! router in front of the target, found every little while by a probe whose
+//! TTL runs out one hop short. A change of any of them is a possible site
+//! switch; two of them changing together make it a likely one.
+
+use chrono::{DateTime, Local};
+use colored::*;
+use std::collections::VecDeque;
+use std::net::IpAddr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+use rust_ping::SiteSwitch;
+
+use crate::identifier::Identifier;
+use crate::trace::wait_answer;
+use crate::transport::{self, IcmpReceiver, IcmpSender, ECHO_REPLY, TIME_EXCEEDED};
+use crate::{write_icmp_packet, PACKET_LEN, TIMESTAMP_FORMAT};
+
+// Replies in a row with a new TTL before it counts, so a stray one isn't taken for a switch
+const TTL_CONFIRM: u32 = 3;
+
+// Replies whose median RTT is compared with that of the ones before them
+const RTT_WINDOW: usize = 10;
+
+// An RTT level moves by at least this much, and this share of the old level, to be a step
+const RTT_STEP_MS: f64 = 2.0;
+const RTT_STEP_RATIO: f64 = 0.25;
+
+// Changes this many probes apart are taken as the same switch
+const MERGE_PROBES: u32 = 2 * RTT_WINDOW as u32;
+
+// How often the hop in front of the target is looked up, and how far to look for the target
+const HOP_INTERVAL: Duration = Duration::from_secs(30);
+const HOP_TIMEOUT: Duration = Duration::from_secs(1);
+const MAX_HOPS: u8 = 30;
+
+/// One line on a site switch, with its evidence
+pub fn print_switch(switch: &SiteSwitch) {
+    let kind = if switch.likely { "Likely anycast site switch" } else { "Possible anycast site switch" };
+    println!(
+        "  {} {} {}",
+        "⇄".yellow(),
+        format!("{} at seq={}:", kind, switch.seq).yellow().bold(),
+        switch.evidence.join(", ")
+    );
+}
+
+/// Follows the signs of one target's site over a run
+#[derive(Default)]
+pub struct Tracker {
+    ttl: Option<u8>,
+    // A TTL seen in a row since this probe, not confirmed yet
+    new_ttl: Option<(u8, u32, u32)>,
+    // Recent RTTs with their probes, two windows at most
+    rtts: VecDeque<(u32, f64)>,
+    hop: Option<IpAddr>,
+    // Changes that can still be joined by another one
+    pending: Option<SiteSwitch>,
+}
+
+impl Tracker {
+    /// Take in the reply to probe `seq`; a switch comes out once no other change can join it
+    pub fn reply(&mut self, seq: u32, ttl: Option<u8>, rtt: f64, at: DateTime<Local>) -> Option<SiteSwitch> {
+        let mut finished = self.expire(seq);
+
+        if let Some(ttl) = ttl {
+            match (self.ttl, self.new_ttl) {
+                (None, _) => self.ttl = Some(ttl),
+                (Some(old), _) if old == ttl => self.new_ttl = None,
+                (Some(old), Some((new, since, count))) if new == ttl => {
+                    if count + 1 >= TTL_CONFIRM {
+                        self.ttl = Some(ttl);
+                        self.new_ttl = None;
+                        finished = finished.or(self.change(since, at, format!("TTL {} → {}", old, ttl)));
+                    } else {
+                        self.new_ttl = Some((new, since, count + 1));
+                    }
+                }
+                _ => self.new_ttl = Some((ttl, seq, 1)),
+            }
+        }
+
+        self.rtts.push_back((seq, rtt));
+        if self.rtts.len() == 2 * RTT_WINDOW {
+            let before = median(self.rtts.iter().take(RTT_WINDOW).map(|(_, rtt)| *rtt));
+            let after = median(self.rtts.iter().skip(RTT_WINDOW).map(|(_, rtt)| *rtt));
+            if (after - before).abs() >= RTT_STEP_MS.max(before * RTT_STEP_RATIO) {
+                // The windows move one reply at a time, so the step is usually a few replies back
+                let step = (RTT_WINDOW / 2..2 * RTT_WINDOW)
+                    .find(|&i| (self.rtts[i].1 - after).abs() < (self.rtts[i].1 - before).abs())
+                    .unwrap_or(RTT_WINDOW);
+                let since = self.rtts[step].0;
+                // The new level is what the next step is measured from
+                self.rtts.drain(..step);
+                finished = finished.or(self.change(since, at, format!("RTT {:.2} → {:.2} ms", before, after)));
+            } else {
+                self.rtts.pop_front();
+            }
+        }
+        finished
+    }
+
+    /// Take in the hop in front of the target, as found before probe `seq`
+    pub fn hop(&mut self, seq: u32, hop: IpAddr, at: DateTime<Local>) -> Option<SiteSwitch> {
+        let finished = self.expire(seq);
+        match self.hop.replace(hop) {
+            Some(old) if old != hop => finished.or(self.change(seq, at, format!("last hop {} → {}", old, hop))),
+            _ => finished,
+        }
+    }
+
+    /// The switch still open at the end of the run
+    pub fn finish(&mut self) -> Option<SiteSwitch> {
+        self.pending.take()
+    }
+
+    // Close the open switch once probe `seq` is too far from it to join
+    fn expire(&mut self, seq: u32) -> Option<SiteSwitch> {
+        if self.pending.as_ref().is_some_and(|pending| seq > pending.seq + MERGE_PROBES) {
+            return self.pending.take();
+        }
+        None
+    }
+
+    // Join a change at probe `seq` to the open switch, or start another and close the open one
+    fn change(&mut self, seq: u32, at: DateTime<Local>, evidence: String) -> Option<SiteSwitch> {
+        if let Some(pending) = self.pending.as_mut() {
+            if seq.abs_diff(pending.seq) <= MERGE_PROBES {
+                pending.seq = pending.seq.min(seq);
+                pending.evidence.push(evidence);
+                pending.likely = true;
+                return None;
+            }
+        }
+        let timestamp = at.format(TIMESTAMP_FORMAT).to_string();
+        self.pending.replace(SiteSwitch { seq, timestamp, likely: false, evidence: vec![evidence] })
+    }
+}
+
+fn median(values: impl Iterator<Item = f64>) -> f64 {
+    let mut values: Vec<f64> = values.collect();
+    values.sort_by(f64::total_cmp);
+    values[values.len() / 2]
+}
+
+/// Looks up the hop in front of the target in the background, on a socket of its own
+pub struct HopWatch {
+    pub hops: Receiver<IpAddr>,
+    stop: Arc<AtomicBool>,
+}
+
+impl HopWatch {
+    pub fn start(addr: IpAddr) -> Result<Self, String> {
+        let (mut sender, mut receiver) = transport::open(addr.is_ipv6())?;
+        let identifier = Identifier::claim()?;
+        let (tx, hops) = mpsc::channel();
+        let stop = Arc::new(AtomicBool::new(false));
+        let stopped = stop.clone();
+
+        thread::spawn(move || {
+            let mut seq = 0;
+            let mut hop_count = 1;
+            while !stopped.load(Ordering::Relaxed) {
+                let round = Instant::now();
+                match last_hop(sender.as_mut(), receiver.as_mut(), identifier.value(), addr, hop_count, &mut seq) {
+                    Ok(Some((count, hop))) => {
+                        hop_count = count;
+                        if let Some(hop) = hop {
+                            if tx.send(hop).is_err() {
+                                break;
+                            }
+                        }
+                    }
+                    Ok(None) => hop_count = 1,
+                    // The run's own probes report what is wrong with the network
+                    Err(_) => break,
+                }
+                while round.elapsed() < HOP_INTERVAL && !stopped.load(Ordering::Relaxed) {
+                    thread::sleep(Duration::from_millis(100));
+                }
+            }
+        });
+        Ok(HopWatch { hops, stop })
+    }
+}
+
+impl Drop for HopWatch {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+}
+
+/// Hops to `addr` and the one before it, starting one hop short of `hop_count`
+///
+/// Where the target answers the first probe the path got shorter and it is
+/// walked again from the first hop. `None` when the target wasn't reached.
+fn last_hop(
+    sender: &mut dyn IcmpSender,
+    receiver: &mut dyn IcmpReceiver,
+    identifier: u16,
+    addr: IpAddr,
+    hop_count: u8,
+    seq: &mut u16,
+) -> Result<Option<(u8, Option<IpAddr>)>, String> {
+    let first = hop_count.saturating_sub(1).max(1);
+    let mut packet = vec![vec![0u8; PACKET_LEN]];
+    let mut previous = None;
+    for ttl in first..=MAX_HOPS {
+        sender.set_ttl(ttl).map_err(|e| format!("Failed to set TTL: {}", e))?;
+        write_icmp_packet(&mut packet[0], *seq, identifier, addr.is_ipv6());
+        *seq = seq.wrapping_add(1);
+        if let (_, Some(e)) = sender.send_batch(&packet, addr) {
+            return Err(format!("Send error: {}", e));
+        }
+        match wait_answer(receiver, identifier, HOP_TIMEOUT)? {
+            Some(reply) if reply.icmp_type == ECHO_REPLY && ttl == first && first > 1 => {
+                return last_hop(sender, receiver, identifier, addr, 1, seq);
+            }
+            Some(reply) if reply.icmp_type == ECHO_REPLY => return Ok(Some((ttl, previous))),
+            Some(reply) if reply.icmp_type == TIME_EXCEEDED => previous = Some(reply.source),
+            Some(_) => return Ok(None),
+            None => previous = None,
+        }
+    }
+    Ok(None)
+}
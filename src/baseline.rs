@@ -0,0 +1,115 @@
+//! Comparison of a run with a saved baseline report.
+//!
+//! The loss and the RTT percentiles of each target are checked against the
+//! statistics of an earlier report. A metric has regressed when it is worse
+//! by more than the tolerance; latency must be worse both relatively and by
+//! a minimum number of milliseconds, so sub-millisecond jitter on fast paths
+//! doesn't fail the check. Any regression makes the process exit with 1.
+
+use colored::*;
+use comfy_table::modifiers::UTF8_ROUND_CORNERS;
+use comfy_table::presets::UTF8_FULL_CONDENSED;
+use comfy_table::{Cell, CellAlignment, Color, ContentArrangement, Table};
+use std::net::IpAddr;
+use rust_ping::PingReport;
+
+use crate::export::{load_report, sanitize};
+use crate::PingStatistics;
+
+/// How much worse than the baseline a run may be
+#[derive(Clone, Copy, Debug)]
+pub struct Tolerance {
+    /// Relative RTT increase, in percent
+    pub percent: f64,
+    /// RTT increases below this many milliseconds are ignored
+    pub ms: f64,
+    /// Loss increase, in percentage points
+    pub loss: f64,
+}
+
+/// One metric compared with the baseline
+pub struct Check {
+    pub metric: &'static str,
+    pub baseline: Option<f64>,
+    pub current: Option<f64>,
+    pub regressed: bool,
+}
+
+/// Baseline of a target: `{host}` and `{ip}` in the path are replaced like in export paths
+pub fn load(template: &str, host: &str, addr: IpAddr) -> Result<PingReport, String> {
+    let path = template.replace("{host}", &sanitize(host)).replace("{ip}", &sanitize(&addr.to_string()));
+    load_report(&path).map_err(|e| format!("Baseline: {}", e))
+}
+
+pub fn compare(baseline: &PingStatistics, current: &PingStatistics, tolerance: Tolerance) -> Vec<Check> {
+    let mut checks = vec![Check {
+        metric: "Loss %",
+        baseline: Some(baseline.packet_loss_percent),
+        current: Some(current.packet_loss_percent),
+        regressed: current.packet_loss_percent > baseline.packet_loss_percent + tolerance.loss,
+    }];
+
+    let latency = [
+        ("p50 ms", baseline.p50_ms, current.p50_ms),
+        ("p95 ms", baseline.p95_ms, current.p95_ms),
+        ("p99 ms", baseline.p99_ms, current.p99_ms),
+    ];
+    for (metric, base, now) in latency {
+        let regressed = match (base, now) {
+            (Some(base), Some(now)) => now > base * (1.0 + tolerance.percent / 100.0) && now - base > tolerance.ms,
+            // Replies in the baseline but none now
+            (Some(_), None) => true,
+            (None, _) => false,
+        };
+        checks.push(Check { metric, baseline: base, current: now, regressed });
+    }
+    checks
+}
+
+fn value_cell(value: Option<f64>) -> Cell {
+    Cell::new(value.map_or("-".to_string(), |v| format!("{:.2}", v))).set_alignment(CellAlignment::Right)
+}
+
+pub fn regressed(checks: &[Check]) -> bool {
+    checks.iter().any(|check| check.regressed)
+}
+
+/// Print the comparison of one target
+pub fn print(host: &str, checks: &[Check]) {
+    println!("\n{}", "╔════════════════════════════════════════════════════════════╗".blue());
+    println!("{}", "║                   📐 BASELINE COMPARISON                    ║".blue());
+    println!("{}", "╚════════════════════════════════════════════════════════════╝".blue());
+    println!("  Host: {}", host.yellow());
+
+    let mut table = Table::new();
+    table
+        .load_preset(UTF8_FULL_CONDENSED)
+        .apply_modifier(UTF8_ROUND_CORNERS)
+        .set_content_arrangement(ContentArrangement::Dynamic)
+        .set_header(vec!["Metric", "Baseline", "Now", "Change", ""]);
+    for check in checks {
+        let change = match (check.baseline, check.current) {
+            (Some(base), Some(now)) => format!("{:+.2}", now - base),
+            _ => "-".to_string(),
+        };
+        let verdict = if check.regressed {
+            Cell::new("regressed").fg(Color::Red)
+        } else {
+            Cell::new("ok").fg(Color::Green)
+        };
+        table.add_row(vec![
+            Cell::new(check.metric),
+            value_cell(check.baseline),
+            value_cell(check.current),
+            Cell::new(change).set_alignment(CellAlignment::Right),
+            verdict,
+        ]);
+    }
+    println!("{}", table);
+
+    if regressed(checks) {
+        println!("  {} {}", "✗".red(), "Regressed against the baseline".red().bold());
+    } else {
+        println!("  {} {}", "✓".green(), "Within the tolerance of the baseline".green());
+    }
+}
@@ -0,0 +1,434 @@
+//! Where the RTT is spent: in this host or out on the network.
+//!
+//! With `--latency-breakdown` a small XDP program is attached to the
+//! interfaces the targets are reached through. It stamps every echo reply as
+//! the driver hands it to the kernel, before the IP stack sees it. Together
+//! with the send call and the socket's arrival stamp each RTT splits into the
+//! send call (this host), the time until the reply is back at the driver
+//! (wire, routers and the remote host) and the receive path up to the socket
+//! (this host). Loading eBPF needs a kernel with XDP links (5.9 or later) and
+//! a build with the `ebpf` feature; IPv4 only.
+
+use colored::*;
+use comfy_table::modifiers::UTF8_ROUND_CORNERS;
+use comfy_table::presets::UTF8_FULL_CONDENSED;
+use comfy_table::{Cell, CellAlignment, ContentArrangement, Table};
+use std::net::IpAddr;
+use std::time::{Duration, SystemTime};
+
+use crate::selftest::spread;
+
+// Driver stamps this far outside their probe's send and arrival times belong to another probe
+const SLACK: Duration = Duration::from_millis(1);
+
+/// Attach the reply stamping to the interfaces that reach `addrs`, once per run
+#[cfg(all(feature = "ebpf", target_os = "linux"))]
+pub fn attach(addrs: &[IpAddr]) -> Result<Vec<String>, String> {
+    xdp::attach(addrs)
+}
+
+#[cfg(not(all(feature = "ebpf", target_os = "linux")))]
+pub fn attach(_addrs: &[IpAddr]) -> Result<Vec<String>, String> {
+    Err("--latency-breakdown needs Linux and a build with eBPF support (cargo build --features ebpf)".to_string())
+}
+
+/// Driver arrival time of the reply to probe `sequence` of `identifier`, taken out of the stamps
+#[cfg(all(feature = "ebpf", target_os = "linux"))]
+fn driver_stamp(identifier: u16, sequence: u16) -> Option<SystemTime> {
+    xdp::take(identifier, sequence)
+}
+
+#[cfg(not(all(feature = "ebpf", target_os = "linux")))]
+fn driver_stamp(_identifier: u16, _sequence: u16) -> Option<SystemTime> {
+    None
+}
+
+#[cfg(all(feature = "ebpf", target_os = "linux"))]
+fn attached() -> bool {
+    xdp::attached()
+}
+
+#[cfg(not(all(feature = "ebpf", target_os = "linux")))]
+fn attached() -> bool {
+    false
+}
+
+/// One reply split up, in microseconds
+struct Split {
+    send: f64,
+    network: f64,
+    receive: f64,
+}
+
+fn micros(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1e6
+}
+
+/// The splits of one target's replies
+pub struct Breakdown {
+    splits: Vec<Split>,
+    // Replies without a usable driver stamp, e.g. arriving on another interface
+    unstamped: u32,
+}
+
+impl Breakdown {
+    /// `None` unless the stamping was attached for this run
+    pub fn start() -> Option<Self> {
+        attached().then(|| Breakdown { splits: Vec::new(), unstamped: 0 })
+    }
+
+    /// Split the `rtt` of a reply to a probe sent at `sent_at` by a send call that took `send_call`
+    pub fn record(&mut self, identifier: u16, seq: u32, sent_at: SystemTime, send_call: Duration, rtt: Duration) {
+        let received = sent_at + rtt;
+        let stamp = driver_stamp(identifier, seq as u16)
+            .filter(|stamp| *stamp + SLACK >= sent_at && *stamp <= received + SLACK);
+        let Some(stamp) = stamp else {
+            self.unstamped += 1;
+            return;
+        };
+        // On loopback the reply can be back before the send call returns
+        let to_driver = stamp.duration_since(sent_at).unwrap_or_default();
+        let send = send_call.min(to_driver);
+        self.splits.push(Split {
+            send: micros(send),
+            network: micros(to_driver - send),
+            receive: micros(received.duration_since(stamp).unwrap_or_default()),
+        });
+    }
+
+    pub fn print(&self, host: &str) {
+        println!("\n{}", "╔════════════════════════════════════════════════════════════╗".magenta());
+        println!("{}", "║                   🔬 LATENCY BREAKDOWN                      ║".magenta());
+        println!("{}", "╚════════════════════════════════════════════════════════════╝".magenta());
+        println!("  Host: {}", host.yellow());
+        if self.splits.is_empty() {
+            println!("  {}", "No reply was stamped at the driver".yellow());
+            return;
+        }
+
+        let send = spread(self.splits.iter().map(|s| s.send).collect());
+        let network = spread(self.splits.iter().map(|s| s.network).collect());
+        let receive = spread(self.splits.iter().map(|s| s.receive).collect());
+
+        let mut table = Table::new();
+        table
+            .load_preset(UTF8_FULL_CONDENSED)
+            .apply_modifier(UTF8_ROUND_CORNERS)
+            .set_content_arrangement(ContentArrangement::Dynamic)
+            .set_header(vec!["µs", "Min", "Median", "p99", "Max"]);
+        let rows = [
+            ("Send call (this host)", send),
+            ("Network (wire and remote host)", network),
+            ("Receive path (this host)", receive),
+        ];
+        for (name, [min, median, p99, max]) in rows {
+            table.add_row(vec![Cell::new(name), us_cell(min), us_cell(median), us_cell(p99), us_cell(max)]);
+        }
+        println!("{}", table);
+
+        let local = send[1] + receive[1];
+        let total = local + network[1];
+        println!(
+            "  Inside this host: {:.1} µs of a median {:.1} µs ({:.1}%)",
+            local,
+            total,
+            if total > 0.0 { local / total * 100.0 } else { 0.0 }
+        );
+        if self.unstamped > 0 {
+            println!("  {}", format!("{} replies had no driver stamp and are left out", self.unstamped).dimmed());
+        }
+    }
+}
+
+fn us_cell(value: f64) -> Cell {
+    Cell::new(format!("{:.1}", value)).set_alignment(CellAlignment::Right)
+}
+
+/// The XDP program and its map, loaded with the `bpf` syscall
+#[cfg(all(feature = "ebpf", target_os = "linux"))]
+mod xdp {
+    use std::ffi::CStr;
+    use std::io;
+    use std::mem;
+    use std::net::{IpAddr, Ipv4Addr, UdpSocket};
+    use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+    use std::sync::OnceLock;
+    use std::time::{Duration, SystemTime};
+
+    const BPF_MAP_CREATE: libc::c_long = 0;
+    const BPF_MAP_LOOKUP_ELEM: libc::c_long = 1;
+    const BPF_MAP_DELETE_ELEM: libc::c_long = 3;
+    const BPF_PROG_LOAD: libc::c_long = 5;
+    const BPF_LINK_CREATE: libc::c_long = 28;
+    const BPF_MAP_TYPE_LRU_HASH: u32 = 9;
+    const BPF_PROG_TYPE_XDP: u32 = 6;
+    const BPF_XDP: u32 = 37;
+    const XDP_FLAGS_SKB_MODE: u32 = 1 << 1;
+    // Replies stamped and not looked up yet; the oldest are evicted
+    const MAX_STAMPS: u32 = 65536;
+    const LOG_SIZE: usize = 64 * 1024;
+
+    /// Attached for the rest of the process; the kernel detaches when the links are closed on exit
+    struct Stamps {
+        map: OwnedFd,
+        _program: OwnedFd,
+        _links: Vec<OwnedFd>,
+    }
+
+    static STAMPS: OnceLock<Stamps> = OnceLock::new();
+
+    #[repr(C)]
+    struct MapCreate {
+        map_type: u32,
+        key_size: u32,
+        value_size: u32,
+        max_entries: u32,
+    }
+
+    #[repr(C)]
+    struct ProgLoad {
+        prog_type: u32,
+        insn_cnt: u32,
+        insns: u64,
+        license: u64,
+        log_level: u32,
+        log_size: u32,
+        log_buf: u64,
+    }
+
+    #[repr(C)]
+    struct MapElem {
+        map_fd: u32,
+        _pad: u32,
+        key: u64,
+        value: u64,
+        flags: u64,
+    }
+
+    #[repr(C)]
+    struct LinkCreate {
+        prog_fd: u32,
+        target_ifindex: u32,
+        attach_type: u32,
+        flags: u32,
+    }
+
+    fn bpf<T>(command: libc::c_long, attr: &mut T) -> io::Result<libc::c_int> {
+        let result = unsafe { libc::syscall(libc::SYS_bpf, command, attr as *mut T, mem::size_of::<T>()) };
+        if result < 0 {
+            Err(io::Error::last_os_error())
+        } else {
+            Ok(result as libc::c_int)
+        }
+    }
+
+    fn bpf_fd<T>(command: libc::c_long, attr: &mut T) -> io::Result<OwnedFd> {
+        bpf(command, attr).map(|fd| unsafe { OwnedFd::from_raw_fd(fd) })
+    }
+
+    #[repr(C)]
+    #[derive(Clone, Copy)]
+    struct Insn {
+        code: u8,
+        regs: u8,
+        off: i16,
+        imm: i32,
+    }
+
+    const fn insn(code: u8, dst: u8, src: u8, off: i16, imm: i32) -> Insn {
+        Insn { code, regs: dst | src << 4, off, imm }
+    }
+
+    /// Stamp echo replies with `bpf_ktime_get_ns()` in `map`, keyed on their identifier and
+    /// sequence as they are on the wire, and let every packet pass
+    fn program(map: &OwnedFd) -> Vec<Insn> {
+        const MOV: u8 = 0xbf;
+        const MOV_IMM: u8 = 0xb7;
+        const ADD_IMM: u8 = 0x07;
+        const LOAD_W: u8 = 0x61;
+        const LOAD_H: u8 = 0x69;
+        const LOAD_B: u8 = 0x71;
+        const STORE_W: u8 = 0x63;
+        const STORE_DW: u8 = 0x7b;
+        const JGT: u8 = 0x2d;
+        const JNE_IMM: u8 = 0x55;
+        const CALL: u8 = 0x85;
+        const EXIT: u8 = 0x95;
+        const LOAD_IMM64: u8 = 0x18;
+        const PSEUDO_MAP_FD: u8 = 1;
+        const KTIME_GET_NS: i32 = 5;
+        const MAP_UPDATE_ELEM: i32 = 2;
+        const XDP_PASS: i32 = 2;
+        // Ethernet, an IPv4 header without options and the ICMP echo header
+        const HEADERS: i32 = 14 + 20 + 8;
+        let ipv4 = u16::from_ne_bytes([0x08, 0x00]) as i32;
+
+        // Jumps go to the last two instructions, counted from the one after the jump
+        let pass = |at: i16| 26 - (at + 1);
+        vec![
+            insn(MOV, 6, 1, 0, 0),
+            insn(LOAD_W, 2, 6, 0, 0),
+            insn(LOAD_W, 3, 6, 4, 0),
+            insn(MOV, 4, 2, 0, 0),
+            insn(ADD_IMM, 4, 0, 0, HEADERS),
+            insn(JGT, 4, 3, pass(5), 0),
+            // EtherType IPv4, version 4 with a 20-byte header, protocol ICMP, type echo reply
+            insn(LOAD_H, 5, 2, 12, 0),
+            insn(JNE_IMM, 5, 0, pass(7), ipv4),
+            insn(LOAD_B, 5, 2, 14, 0),
+            insn(JNE_IMM, 5, 0, pass(9), 0x45),
+            insn(LOAD_B, 5, 2, 23, 0),
+            insn(JNE_IMM, 5, 0, pass(11), 1),
+            insn(LOAD_B, 5, 2, 34, 0),
+            insn(JNE_IMM, 5, 0, pass(13), 0),
+            // Identifier and sequence as the key
+            insn(LOAD_W, 5, 2, 38, 0),
+            insn(STORE_W, 10, 5, -4, 0),
+            insn(CALL, 0, 0, 0, KTIME_GET_NS),
+            insn(STORE_DW, 10, 0, -16, 0),
+            insn(LOAD_IMM64, 1, PSEUDO_MAP_FD, 0, map.as_raw_fd()),
+            insn(0, 0, 0, 0, 0),
+            insn(MOV, 2, 10, 0, 0),
+            insn(ADD_IMM, 2, 0, 0, -4),
+            insn(MOV, 3, 10, 0, 0),
+            insn(ADD_IMM, 3, 0, 0, -16),
+            insn(MOV_IMM, 4, 0, 0, 0),
+            insn(CALL, 0, 0, 0, MAP_UPDATE_ELEM),
+            insn(MOV_IMM, 0, 0, 0, XDP_PASS),
+            insn(EXIT, 0, 0, 0, 0),
+        ]
+    }
+
+    fn load(map: &OwnedFd) -> Result<OwnedFd, String> {
+        let program = program(map);
+        let license = c"GPL";
+        let mut log = vec![0u8; LOG_SIZE];
+        let mut attr = ProgLoad {
+            prog_type: BPF_PROG_TYPE_XDP,
+            insn_cnt: program.len() as u32,
+            insns: program.as_ptr() as u64,
+            license: license.as_ptr() as u64,
+            log_level: 1,
+            log_size: LOG_SIZE as u32,
+            log_buf: log.as_mut_ptr() as u64,
+        };
+        bpf_fd(BPF_PROG_LOAD, &mut attr).map_err(|e| {
+            // The verifier's last words say why
+            let log = CStr::from_bytes_until_nul(&log).map(|log| log.to_string_lossy().into_owned()).unwrap_or_default();
+            let reason = log.lines().rev().find(|line| !line.trim().is_empty()).unwrap_or_default().to_string();
+            format!("Failed to load the XDP program: {} {}", e, reason)
+        })
+    }
+
+    /// Interface index and name the kernel would send to `addr` through
+    fn interface(addr: Ipv4Addr) -> Result<(u32, String), String> {
+        let socket = UdpSocket::bind("0.0.0.0:0").map_err(|e| format!("Route lookup for {}: {}", addr, e))?;
+        socket.connect((addr, 9)).map_err(|e| format!("No route to {}: {}", addr, e))?;
+        let local = match socket.local_addr() {
+            Ok(local) if local.ip() != IpAddr::V4(addr) => local.ip(),
+            // Our own addresses are answered through loopback
+            _ => IpAddr::from([127, 0, 0, 1]),
+        };
+
+        let mut found = None;
+        unsafe {
+            let mut list: *mut libc::ifaddrs = std::ptr::null_mut();
+            if libc::getifaddrs(&mut list) != 0 {
+                return Err(format!("Failed to list interfaces: {}", io::Error::last_os_error()));
+            }
+            let mut entry = list;
+            while !entry.is_null() {
+                let ifa = &*entry;
+                if !ifa.ifa_addr.is_null() && (*ifa.ifa_addr).sa_family as i32 == libc::AF_INET {
+                    let sin = &*(ifa.ifa_addr as *const libc::sockaddr_in);
+                    if IpAddr::from(u32::from_be(sin.sin_addr.s_addr).to_be_bytes()) == local {
+                        let index = libc::if_nametoindex(ifa.ifa_name);
+                        found = Some((index, CStr::from_ptr(ifa.ifa_name).to_string_lossy().into_owned()));
+                        break;
+                    }
+                }
+                entry = ifa.ifa_next;
+            }
+            libc::freeifaddrs(list);
+        }
+        found
+            .filter(|(index, _)| *index != 0)
+            .ok_or_else(|| format!("No interface has the address {} used to reach {}", local, addr))
+    }
+
+    pub fn attach(addrs: &[IpAddr]) -> Result<Vec<String>, String> {
+        let mut interfaces: Vec<(u32, String)> = Vec::new();
+        for addr in addrs {
+            let IpAddr::V4(v4) = addr else {
+                return Err(format!("--latency-breakdown supports IPv4 only, not {}", addr));
+            };
+            let interface = interface(*v4)?;
+            if !interfaces.contains(&interface) {
+                interfaces.push(interface);
+            }
+        }
+
+        let mut attr = MapCreate {
+            map_type: BPF_MAP_TYPE_LRU_HASH,
+            key_size: mem::size_of::<u32>() as u32,
+            value_size: mem::size_of::<u64>() as u32,
+            max_entries: MAX_STAMPS,
+        };
+        let map = bpf_fd(BPF_MAP_CREATE, &mut attr).map_err(|e| format!("Failed to create the eBPF map (root and kernel 5.9+?): {}", e))?;
+        let program = load(&map)?;
+
+        let mut links = Vec::new();
+        for (index, name) in &interfaces {
+            let mut attr = LinkCreate {
+                prog_fd: program.as_raw_fd() as u32,
+                target_ifindex: *index,
+                attach_type: BPF_XDP,
+                flags: 0,
+            };
+            // In the driver where it supports XDP, else in the generic hook just after it
+            let link = bpf_fd(BPF_LINK_CREATE, &mut attr).or_else(|_| {
+                attr.flags = XDP_FLAGS_SKB_MODE;
+                bpf_fd(BPF_LINK_CREATE, &mut attr)
+            });
+            // EBUSY: another XDP program is attached already
+            links.push(link.map_err(|e| format!("Failed to attach XDP to {}: {}", name, e))?);
+        }
+
+        let _ = STAMPS.set(Stamps { map, _program: program, _links: links });
+        Ok(interfaces.into_iter().map(|(_, name)| name).collect())
+    }
+
+    pub fn attached() -> bool {
+        STAMPS.get().is_some()
+    }
+
+    fn clock(id: libc::clockid_t) -> Duration {
+        let mut now: libc::timespec = unsafe { mem::zeroed() };
+        unsafe {
+            libc::clock_gettime(id, &mut now);
+        }
+        Duration::new(now.tv_sec as u64, now.tv_nsec as u32)
+    }
+
+    pub fn take(identifier: u16, sequence: u16) -> Option<SystemTime> {
+        let stamps = STAMPS.get()?;
+        let [a, b] = identifier.to_be_bytes();
+        let [c, d] = sequence.to_be_bytes();
+        let key = u32::from_ne_bytes([a, b, c, d]);
+        let mut nanos = 0u64;
+        let mut attr = MapElem {
+            map_fd: stamps.map.as_raw_fd() as u32,
+            _pad: 0,
+            key: &key as *const u32 as u64,
+            value: &mut nanos as *mut u64 as u64,
+            flags: 0,
+        };
+        bpf(BPF_MAP_LOOKUP_ELEM, &mut attr).ok()?;
+        // A later probe with the same identifier and sequence must not find this stamp
+        let _ = bpf(BPF_MAP_DELETE_ELEM, &mut attr);
+
+        // The stamp is on the monotonic clock; how long ago it was is the same on both
+        let ago = clock(libc::CLOCK_MONOTONIC).checked_sub(Duration::from_nanos(nanos))?;
+        SystemTime::now().checked_sub(ago)
+    }
+}
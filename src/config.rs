@@ -0,0 +1,226 @@
+//! TOML configuration file.
+//!
+//! Settings that don't fit on a command line, such as notification
+//! credentials, message templates and host aliases, are read from the file
+//! given with `--config`, or from `rust_ping/config.toml` in the user's
+//! configuration directory when it exists. It can also hold defaults for
+//! the options of everyday runs and the RTTs the output is colored by.
+
+use serde::de::Error as _;
+use serde::{Deserialize, Deserializer};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use crate::alerts::AlertKind;
+use crate::parse_duration;
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    #[serde(default)]
+    pub alerting: AlertingConfig,
+    /// `@name` on the command line stands for these hosts
+    #[serde(default)]
+    pub aliases: BTreeMap<String, Alias>,
+    #[serde(default)]
+    pub defaults: Defaults,
+    #[serde(default)]
+    pub colors: Colors,
+    /// `--profile NAME` stands for the hosts and options of `[profile.NAME]`
+    #[serde(default)]
+    pub profile: BTreeMap<String, Profile>,
+}
+
+/// `[profile.NAME]`: a saved set of hosts and options
+#[derive(Debug, Deserialize)]
+pub struct Profile {
+    #[serde(default)]
+    pub hosts: Vec<String>,
+    /// Shown by `profiles list`
+    pub description: Option<String>,
+    /// Options of `ping` by their long name, e.g. `interval = "500ms"` or `graph = true`
+    #[serde(flatten)]
+    pub options: BTreeMap<String, toml::Value>,
+}
+
+/// `[defaults]`: options of `ping`, `monitor` and `serve` used when the command line doesn't give them
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Defaults {
+    pub count: Option<u32>,
+    #[serde(default, deserialize_with = "duration")]
+    pub interval: Option<Duration>,
+    #[serde(default, deserialize_with = "duration")]
+    pub timeout: Option<Duration>,
+    /// Reverse DNS of reply addresses, unless -n asks for numbers
+    pub resolve: Option<bool>,
+    /// Export paths, templates included
+    pub json: Option<String>,
+    pub csv: Option<String>,
+    pub xlsx: Option<String>,
+    pub yaml: Option<String>,
+    pub cbor: Option<String>,
+    pub msgpack: Option<String>,
+    pub html: Option<String>,
+}
+
+/// `[colors]`: RTTs in ms up to which a reply is green, yellow and orange; red beyond
+#[derive(Clone, Copy, Debug, Deserialize)]
+#[serde(deny_unknown_fields, default)]
+pub struct Colors {
+    pub good: f64,
+    pub fair: f64,
+    pub poor: f64,
+}
+
+impl Default for Colors {
+    fn default() -> Self {
+        Colors { good: 20.0, fair: 50.0, poor: 100.0 }
+    }
+}
+
+/// A duration given in seconds (`interval = 0.5`) or with a unit (`interval = "500ms"`)
+fn duration<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<Duration>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Value {
+        Seconds(f64),
+        Text(String),
+    }
+    match Value::deserialize(deserializer)? {
+        Value::Seconds(seconds) => Duration::try_from_secs_f64(seconds).map(Some).map_err(D::Error::custom),
+        Value::Text(text) => parse_duration(&text).map(Some).map_err(D::Error::custom),
+    }
+}
+
+/// `[aliases]` entry: one host or a group of them
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+pub enum Alias {
+    Host(String),
+    Group(Vec<String>),
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AlertingConfig {
+    pub email: Option<EmailConfig>,
+    pub slack: Option<WebhookConfig>,
+    pub discord: Option<WebhookConfig>,
+    pub telegram: Option<TelegramConfig>,
+    pub pagerduty: Option<PagerDutyConfig>,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SmtpTls {
+    /// Plain connection upgraded with STARTTLS (port 587)
+    #[default]
+    Starttls,
+    /// TLS from the first byte (port 465)
+    Tls,
+    /// Unencrypted, for a local relay (port 25)
+    None,
+}
+
+/// `[alerting.email]`
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct EmailConfig {
+    pub server: String,
+    pub port: Option<u16>,
+    #[serde(default)]
+    pub tls: SmtpTls,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    pub from: String,
+    pub to: Vec<String>,
+    /// Alert kinds to send; all of them when absent
+    pub events: Option<Vec<AlertKind>>,
+    pub subject: Option<String>,
+    pub body: Option<String>,
+}
+
+/// `[alerting.slack]` and `[alerting.discord]`
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct WebhookConfig {
+    pub webhook: String,
+    pub events: Option<Vec<AlertKind>>,
+}
+
+/// Numeric id of a Telegram chat, or `@name` of a public channel
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+pub enum ChatId {
+    Id(i64),
+    Name(String),
+}
+
+impl fmt::Display for ChatId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ChatId::Id(id) => write!(f, "{}", id),
+            ChatId::Name(name) => write!(f, "{}", name),
+        }
+    }
+}
+
+/// `[alerting.telegram]`
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TelegramConfig {
+    pub bot_token: String,
+    pub chat_id: ChatId,
+    pub events: Option<Vec<AlertKind>>,
+}
+
+/// `[alerting.pagerduty]`
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PagerDutyConfig {
+    /// Integration key of an Events API v2 service
+    pub routing_key: String,
+    /// Events API endpoint, e.g. the EU one
+    pub url: Option<String>,
+    pub events: Option<Vec<AlertKind>>,
+}
+
+impl Config {
+    pub fn load(path: &str) -> Result<Self, String> {
+        let text = fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read config file '{}': {}", path, e))?;
+        let config: Config = toml::from_str(&text).map_err(|e| format!("Invalid config file '{}': {}", path, e))?;
+        let Colors { good, fair, poor } = config.colors;
+        if !(0.0 < good && good <= fair && fair <= poor) {
+            return Err(format!("Invalid config file '{}': [colors] needs 0 < good <= fair <= poor", path));
+        }
+        Ok(config)
+    }
+
+    /// Config file read when `--config` isn't given, if it exists
+    pub fn default_path() -> Option<PathBuf> {
+        let path = dirs::config_dir()?.join("rust_ping").join("config.toml");
+        path.is_file().then_some(path)
+    }
+
+    /// Replace `@name` arguments with the hosts of that alias
+    pub fn expand_hosts(&self, hosts: &[String]) -> Result<Vec<String>, String> {
+        let mut expanded = Vec::new();
+        for host in hosts {
+            let Some(name) = host.strip_prefix('@') else {
+                expanded.push(host.clone());
+                continue;
+            };
+            match self.aliases.get(name) {
+                Some(Alias::Host(host)) => expanded.push(host.clone()),
+                Some(Alias::Group(group)) => expanded.extend(group.iter().cloned()),
+                None => return Err(format!("Unknown alias '@{}' (define it under [aliases] in the config file)", name)),
+            }
+        }
+        Ok(expanded)
+    }
+}
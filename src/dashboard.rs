@@ -0,0 +1,849 @@
+//! Full-screen dashboard for a handful of targets (`--output dashboard`).
+//!
+//! Every target gets a pane with a sparkline of its recent RTTs and its
+//! current statistics, and an overview lists all of them in one table that
+//! can be sorted by any summary column. Results reach the board like any
+//! other sink; the screen is redrawn a few times a second on the terminal's
+//! alternate screen, so the usual summary is printed below the shell prompt
+//! once the run ends.
+//!
+//! The sparklines can be scrolled back through the last hour of probes, and
+//! the screen as it looks at any moment can be saved as an HTML page with its
+//! colors, to share what was seen.
+//!
+//! The mouse works too: clicking a point of a sparkline shows that probe,
+//! dragging across one zooms all of them into that range of probes, and
+//! clicking a column header of the overview sorts by it.
+//!
+//! The board is drawn with ratatui on crossterm, which also reads the keys
+//! and the mouse while it is shown; the ping threads only see the quit and
+//! pause flags of `Keys`.
+
+use clap::ValueEnum;
+use crossterm::event::{
+    self, DisableMouseCapture, EnableMouseCapture, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers, MouseButton,
+    MouseEvent, MouseEventKind,
+};
+use crossterm::execute;
+use crossterm::terminal::{self, EnterAlternateScreen, LeaveAlternateScreen};
+use ratatui::backend::CrosstermBackend;
+use ratatui::buffer::{self, Buffer};
+use ratatui::layout::{self, Constraint, Flex, Rect};
+use ratatui::style::{Color, Modifier, Style, Stylize};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, BorderType, Cell, Padding, Paragraph, Row, Table};
+use ratatui::{Frame, Terminal};
+use std::fmt::Write as _;
+use std::fs;
+use std::io::{self, Stdout};
+use std::net::IpAddr;
+use std::ops::Range;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::history::History;
+use crate::interrupt;
+use crate::keys::Keys;
+use crate::report::escape;
+use crate::sinks::{Sink, Target};
+use crate::stats::RttStats;
+use crate::summary::{compare, SortKey};
+use crate::{PingResult, PingStatistics, ProbeStatus};
+
+// How often the screen is redrawn when no key is pressed
+const REDRAW: Duration = Duration::from_millis(250);
+// RTTs kept per target for scrolling back its sparkline
+const RECENT: usize = 3600;
+// Probes scrolled per key press
+const SCROLL_STEP: usize = 30;
+// How long a note replaces the key help
+const NOTE_TIME: Duration = Duration::from_secs(5);
+// Lines taken by one pane
+const PANE_HEIGHT: usize = 4;
+// Screen column where the sparkline of a pane starts
+const CHART_LEFT: usize = 2;
+// Width of the sparklines in the overview
+const OVERVIEW_CHART: usize = 16;
+const SPARKS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+
+/// What a key or the wheel asks of the board
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Command {
+    /// Select the previous target (k or up arrow)
+    Up,
+    /// Select the next target (j or down arrow)
+    Down,
+    /// Switch between the panes and the overview (Enter, Tab or o)
+    Select,
+    /// Sort by the next column
+    Sort,
+    /// Scroll the sparklines back to earlier probes ([, Page Up or the wheel)
+    Older,
+    /// Scroll the sparklines forward again (], Page Down or the wheel)
+    Newer,
+}
+
+impl Command {
+    fn from_key(key: KeyCode) -> Option<Self> {
+        match key {
+            KeyCode::Char('k') | KeyCode::Up => Some(Command::Up),
+            KeyCode::Char('j') | KeyCode::Down => Some(Command::Down),
+            KeyCode::Enter | KeyCode::Tab | KeyCode::Char('o') => Some(Command::Select),
+            KeyCode::Char('s') => Some(Command::Sort),
+            KeyCode::Char('[') | KeyCode::PageUp => Some(Command::Older),
+            KeyCode::Char(']') | KeyCode::PageDown => Some(Command::Newer),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum MouseAction {
+    Press,
+    /// Moved with the left button held
+    Drag,
+    Release,
+    RightClick,
+}
+
+/// Mouse event at a 0-based screen cell
+#[derive(Clone, Copy)]
+struct Mouse {
+    action: MouseAction,
+    column: usize,
+    row: usize,
+}
+
+/// One probe as the board keeps it
+struct Probe {
+    seq: u32,
+    rtt: Option<f64>,
+    status: ProbeStatus,
+    at: chrono::DateTime<chrono::Local>,
+}
+
+/// What one target has done so far
+struct Pane {
+    host: String,
+    addr: IpAddr,
+    sent: u32,
+    rtt: RttStats,
+    recent: History<Probe>,
+    last: Option<f64>,
+    // Consecutive lost probes
+    lost_streak: u32,
+    finished: bool,
+}
+
+impl Pane {
+    fn statistics(&self) -> PingStatistics {
+        self.rtt.statistics(self.sent)
+    }
+
+    fn state(&self, down_after: u32) -> (&'static str, Color) {
+        if self.sent == 0 {
+            ("waiting", Color::DarkGray)
+        } else if self.lost_streak >= down_after {
+            ("DOWN", Color::Red)
+        } else if self.lost_streak > 0 {
+            ("lossy", Color::Yellow)
+        } else if self.finished {
+            ("done", Color::DarkGray)
+        } else {
+            ("up", Color::Green)
+        }
+    }
+
+    /// Recent probes with their number in the run, counted from 0
+    fn numbered(&self) -> impl Iterator<Item = (usize, &Probe)> + '_ {
+        let first = self.sent as usize - self.recent.len();
+        self.recent.iter().enumerate().map(move |(i, probe)| (first + i, probe))
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum View {
+    Panes,
+    Overview,
+}
+
+struct Board {
+    panes: Vec<Pane>,
+    down_after: u32,
+    // Index into `panes`, so the selection follows its target when the order changes
+    selected: usize,
+    view: View,
+    sort: Option<SortKey>,
+    // Probes the sparklines are scrolled back by
+    offset: usize,
+    // Range of probe numbers the sparklines are zoomed into
+    zoom: Option<(usize, usize)>,
+    // Sparkline columns being dragged across
+    drag: Option<Drag>,
+    note: Option<(String, Instant)>,
+    paused: bool,
+}
+
+#[derive(Clone, Copy)]
+struct Drag {
+    pane: usize,
+    from: usize,
+    to: usize,
+}
+
+/// Where the last frame put what can be clicked, in screen rows and columns
+#[derive(Default)]
+struct Regions {
+    // First row of each pane on screen
+    panes: Vec<(usize, usize)>,
+    chart_width: usize,
+    // Row of each target in the overview
+    rows: Vec<(usize, usize)>,
+    // Row and columns of the overview headers that sort
+    headers: Vec<(usize, Range<usize>, SortKey)>,
+}
+
+impl Board {
+    /// Pane indexes in display order
+    fn order(&self) -> Vec<usize> {
+        let mut order: Vec<usize> = (0..self.panes.len()).collect();
+        if let Some(key) = self.sort {
+            // Same order as the summary table
+            let stats: Vec<PingStatistics> = self.panes.iter().map(Pane::statistics).collect();
+            order.sort_by(|&a, &b| compare(key, (&self.panes[a].host, &stats[a]), (&self.panes[b].host, &stats[b])));
+        }
+        order
+    }
+
+    fn handle(&mut self, command: Command) {
+        let order = self.order();
+        let position = order.iter().position(|&i| i == self.selected).unwrap_or(0);
+        match command {
+            Command::Up => self.selected = order[position.saturating_sub(1)],
+            Command::Down => self.selected = order[(position + 1).min(order.len() - 1)],
+            Command::Select => {
+                self.view = match self.view {
+                    View::Panes => View::Overview,
+                    View::Overview => View::Panes,
+                }
+            }
+            Command::Older => {
+                self.zoom = None;
+                let longest = self.panes.iter().map(|p| p.recent.len()).max().unwrap_or(0);
+                self.offset = (self.offset + SCROLL_STEP).min(longest.saturating_sub(1));
+            }
+            Command::Newer => {
+                self.zoom = None;
+                self.offset = self.offset.saturating_sub(SCROLL_STEP);
+            }
+            Command::Sort => {
+                // None, then every column in turn
+                let keys = SortKey::value_variants();
+                self.sort = match self.sort {
+                    None => keys.first().copied(),
+                    Some(key) => keys.iter().skip_while(|&&k| k != key).nth(1).copied(),
+                };
+            }
+        }
+    }
+
+    /// Probes of `pane` in a sparkline `width` columns wide, with the columns each one takes
+    fn shown<'a>(&self, pane: &'a Pane, width: usize) -> (Vec<(usize, &'a Probe)>, usize) {
+        match self.zoom {
+            Some((from, to)) => {
+                let probes: Vec<_> = pane.numbered().filter(|(n, _)| (from..=to).contains(n)).collect();
+                let step = (width / probes.len().max(1)).max(1);
+                (probes.into_iter().take(width / step).collect(), step)
+            }
+            None => {
+                let end = pane.recent.len().saturating_sub(self.offset);
+                (pane.numbered().take(end).skip(end.saturating_sub(width)).collect(), 1)
+            }
+        }
+    }
+
+    fn click(&mut self, regions: &Regions, mouse: Mouse) {
+        let pane = regions.panes.iter().find(|(top, _)| (*top..top + PANE_HEIGHT).contains(&mouse.row)).copied();
+        let column = mouse.column.saturating_sub(CHART_LEFT).min(regions.chart_width.saturating_sub(1));
+        match mouse.action {
+            MouseAction::RightClick => self.zoom_out(),
+            MouseAction::Press => {
+                if let Some((top, i)) = pane {
+                    self.selected = i;
+                    if mouse.row == top + 1 && mouse.column >= CHART_LEFT {
+                        self.drag = Some(Drag { pane: i, from: column, to: column });
+                    }
+                } else if let Some(&(_, i)) = regions.rows.iter().find(|(row, _)| *row == mouse.row) {
+                    self.selected = i;
+                } else if let Some(&(_, _, key)) =
+                    regions.headers.iter().find(|(row, columns, _)| *row == mouse.row && columns.contains(&mouse.column))
+                {
+                    // The sorted column again goes back to target order
+                    self.sort = if self.sort == Some(key) { None } else { Some(key) };
+                } else if mouse.row == 1 {
+                    self.zoom_out();
+                }
+            }
+            MouseAction::Drag => {
+                if let Some(drag) = &mut self.drag {
+                    drag.to = column;
+                }
+            }
+            MouseAction::Release => {
+                if let Some(drag) = self.drag.take() {
+                    self.release(drag, regions.chart_width);
+                }
+            }
+        }
+    }
+
+    /// A drag across one probe shows it, across several zooms into them
+    fn release(&mut self, drag: Drag, width: usize) {
+        let pane = &self.panes[drag.pane];
+        let (probes, step) = self.shown(pane, width);
+        let Some(last) = probes.len().checked_sub(1) else {
+            return;
+        };
+        let first = (drag.from.min(drag.to) / step).min(last);
+        let last = (drag.from.max(drag.to) / step).min(last);
+        let note = if first == last {
+            let (n, probe) = probes[first];
+            let outcome = match probe.rtt {
+                Some(rtt) => format!("{:.3} ms", rtt),
+                None => probe.status.name().to_string(),
+            };
+            format!("{} · probe {} (seq {}) at {} · {}", pane.host, n + 1, probe.seq, probe.at.format("%H:%M:%S%.3f"), outcome)
+        } else {
+            let (from, to) = (probes[first].0, probes[last].0);
+            self.zoom = Some((from, to));
+            self.offset = 0;
+            format!("Zoomed into probes {}-{}; right-click to zoom out", from + 1, to + 1)
+        };
+        self.note = Some((note, Instant::now()));
+    }
+
+    fn zoom_out(&mut self) {
+        self.zoom = None;
+        self.drag = None;
+        self.offset = 0;
+    }
+}
+
+/// RTTs as block characters `step` columns wide, scaled between their min and max, lost probes as ✗
+fn sparkline(probes: &[(usize, &Probe)], step: usize) -> Vec<char> {
+    let min = probes.iter().filter_map(|(_, p)| p.rtt).fold(f64::INFINITY, f64::min);
+    let max = probes.iter().filter_map(|(_, p)| p.rtt).fold(f64::NEG_INFINITY, f64::max);
+    probes
+        .iter()
+        .flat_map(|(_, probe)| {
+            let spark = match probe.rtt {
+                Some(rtt) if max > min => SPARKS[((rtt - min) / (max - min) * (SPARKS.len() - 1) as f64).round() as usize],
+                Some(_) => SPARKS[SPARKS.len() / 2],
+                None => '✗',
+            };
+            std::iter::repeat_n(spark, step)
+        })
+        .collect()
+}
+
+fn ms(value: Option<f64>) -> String {
+    value.map_or("-".to_string(), |v| format!("{:.2}ms", v))
+}
+
+/// Same colors as the loss column of the summary table
+fn loss_color(loss: f64) -> Color {
+    if loss == 0.0 {
+        Color::Green
+    } else if loss < 100.0 {
+        Color::Yellow
+    } else {
+        Color::Red
+    }
+}
+
+fn draw_pane(frame: &mut Frame, area: Rect, board: &Board, i: usize) {
+    let pane = &board.panes[i];
+    let (state, color) = pane.state(board.down_after);
+    let state = Span::styled(format!(" {} ", state), Style::new().fg(color));
+    let state = if color == Color::Red { state.bold() } else { state };
+    let border = if i == board.selected { Style::new().cyan().bold() } else { Style::new().dim() };
+    let block = Block::bordered()
+        .border_type(BorderType::Rounded)
+        .border_style(border)
+        .title_top(Line::from(format!(" {} ({}) ", pane.host, pane.addr)).bold())
+        .title_top(Line::from(state).right_aligned())
+        .padding(Padding::horizontal(1));
+
+    let (probes, step) = board.shown(pane, block.inner(area).width as usize);
+    let spark = sparkline(&probes, step);
+    // Columns being dragged across are highlighted
+    let marked = match board.drag {
+        Some(drag) if drag.pane == i => drag.from.min(drag.to)..drag.from.max(drag.to) + 1,
+        _ => 0..0,
+    };
+    let part = |columns: Range<usize>| -> String { spark[columns.start.min(spark.len())..columns.end.min(spark.len())].iter().collect() };
+    let chart = Line::from(vec![
+        part(0..marked.start).cyan(),
+        part(marked.clone()).cyan().reversed(),
+        part(marked.end..spark.len()).cyan(),
+    ]);
+
+    let stats = pane.statistics();
+    let line = format!(
+        "last {}  avg {}  p95 {}  max {}  loss {:.1}%  {}/{} recv",
+        ms(pane.last),
+        ms(stats.avg_ms),
+        ms(stats.p95_ms),
+        ms(stats.max_ms),
+        stats.packet_loss_percent,
+        stats.packets_received,
+        stats.packets_sent
+    );
+    frame.render_widget(Paragraph::new(vec![chart, Line::raw(line)]).block(block), area);
+}
+
+/// Columns of the overview, with the sort key of those that sort by a click on their header
+const OVERVIEW_COLUMNS: [(&str, Option<SortKey>); 10] = [
+    ("", None),
+    ("Host", Some(SortKey::Host)),
+    ("IP", None),
+    ("State", None),
+    ("Last ms", None),
+    ("Avg ms", Some(SortKey::Avg)),
+    ("p95 ms", None),
+    ("Loss", Some(SortKey::Loss)),
+    ("Sent", Some(SortKey::Sent)),
+    ("Recent", None),
+];
+
+fn draw_overview(frame: &mut Frame, area: Rect, board: &Board, order: &[usize], regions: &mut Regions) {
+    let header: Vec<String> = OVERVIEW_COLUMNS
+        .iter()
+        .map(|&(title, key)| match key {
+            Some(key) if board.sort == Some(key) => format!("{} ▼", title),
+            _ => title.to_string(),
+        })
+        .collect();
+    let cell = |value: Option<f64>| value.map_or("-".to_string(), |v| format!("{:.2}", v));
+    let lines: Vec<(usize, [String; 10], Color, Color)> = order
+        .iter()
+        .map(|&i| {
+            let pane = &board.panes[i];
+            let stats = pane.statistics();
+            let (state, color) = pane.state(board.down_after);
+            let (probes, step) = board.shown(pane, OVERVIEW_CHART);
+            let texts = [
+                if i == board.selected { "▶" } else { "" }.to_string(),
+                pane.host.clone(),
+                pane.addr.to_string(),
+                state.to_string(),
+                cell(pane.last),
+                cell(stats.avg_ms),
+                cell(stats.p95_ms),
+                format!("{:.1}%", stats.packet_loss_percent),
+                stats.packets_sent.to_string(),
+                String::from_iter(sparkline(&probes, step)),
+            ];
+            (i, texts, color, loss_color(stats.packet_loss_percent))
+        })
+        .collect();
+
+    // Every column as wide as its widest cell, so the headers can be found for clicks
+    let widths: Vec<Constraint> = (0..OVERVIEW_COLUMNS.len())
+        .map(|c| {
+            let cells = lines.iter().map(|(_, texts, _, _)| &texts[c]).chain([&header[c]]);
+            Constraint::Length(cells.map(|text| Span::raw(text.as_str()).width()).max().unwrap_or(0) as u16)
+        })
+        .collect();
+    let rows = lines.iter().map(|(i, texts, state, loss)| {
+        Row::new(texts.iter().enumerate().map(|(c, text)| {
+            let line = Line::from(text.as_str());
+            // The numbers
+            let line = if (4..=8).contains(&c) { line.right_aligned() } else { line };
+            let style = match c {
+                0 => Style::new().cyan(),
+                1 if *i == board.selected => Style::new().cyan(),
+                3 => Style::new().fg(*state),
+                7 => Style::new().fg(*loss),
+                _ => Style::new(),
+            };
+            Cell::from(line).style(style)
+        }))
+    });
+    let block = Block::bordered().border_type(BorderType::Rounded).border_style(Style::new().dim());
+    let area = Rect { height: area.height.min(lines.len() as u16 + 3), ..area };
+    let inner = block.inner(area);
+    // Laid out as the table lays out its columns
+    let columns = layout::Layout::horizontal(widths.clone()).flex(Flex::Start).spacing(1).split(inner);
+    regions.headers = columns
+        .iter()
+        .zip(OVERVIEW_COLUMNS)
+        .filter_map(|(column, (_, key))| Some((inner.y as usize, column.x as usize..column.right() as usize, key?)))
+        .collect();
+    regions.rows = order.iter().take(inner.height.saturating_sub(1) as usize).enumerate().map(|(n, &i)| (inner.y as usize + 1 + n, i)).collect();
+
+    let table = Table::new(rows, widths).header(Row::new(header).bold()).column_spacing(1).flex(Flex::Start).block(block);
+    frame.render_widget(table, area);
+}
+
+/// The terminal in raw mode on its alternate screen, with the mouse reported, for as long as this lives
+struct Screen {
+    terminal: Terminal<CrosstermBackend<Stdout>>,
+}
+
+impl Screen {
+    fn enter() -> io::Result<Self> {
+        terminal::enable_raw_mode()?;
+        let terminal = Terminal::new(CrosstermBackend::new(io::stdout())).inspect_err(|_| {
+            let _ = terminal::disable_raw_mode();
+        })?;
+        // From here on dropping the screen puts the terminal back, also when entering fails half way
+        let mut screen = Screen { terminal };
+        execute!(io::stdout(), EnterAlternateScreen, EnableMouseCapture)?;
+        screen.terminal.hide_cursor()?;
+        Ok(screen)
+    }
+}
+
+impl Drop for Screen {
+    fn drop(&mut self) {
+        let _ = execute!(io::stdout(), DisableMouseCapture, LeaveAlternateScreen);
+        let _ = self.terminal.show_cursor();
+        let _ = terminal::disable_raw_mode();
+    }
+}
+
+/// Shared between the sink fed by the targets and the drawing thread
+pub struct Dashboard {
+    board: Arc<Mutex<Board>>,
+}
+
+impl Dashboard {
+    pub fn new(targets: &[(&String, IpAddr)], down_after: u32) -> Self {
+        let panes = targets
+            .iter()
+            .map(|(host, addr)| Pane {
+                host: host.to_string(),
+                addr: *addr,
+                sent: 0,
+                rtt: RttStats::new(),
+                recent: History::new(RECENT),
+                last: None,
+                lost_streak: 0,
+                finished: false,
+            })
+            .collect();
+        let board = Board {
+            panes,
+            down_after,
+            selected: 0,
+            view: View::Panes,
+            sort: None,
+            offset: 0,
+            zoom: None,
+            drag: None,
+            note: None,
+            paused: false,
+        };
+        Dashboard { board: Arc::new(Mutex::new(board)) }
+    }
+
+    /// Sink that updates the panes
+    pub fn sink(&self) -> Box<dyn Sink> {
+        Box::new(BoardSink { board: Arc::clone(&self.board) })
+    }
+
+    /// Draw and handle keys until the run is `done` or `q` is pressed
+    pub fn run(&self, keys: &Keys, done: &AtomicBool) {
+        let mut screen = match Screen::enter() {
+            Ok(screen) => screen,
+            Err(e) => {
+                eprintln!("Failed to open the dashboard: {}", e);
+                keys.request_quit();
+                return;
+            }
+        };
+        while !done.load(Ordering::Relaxed) && !keys.quit_requested() {
+            let mut regions = Regions::default();
+            let drawn = screen.terminal.draw(|frame| regions = draw(frame, &self.board.lock().unwrap()));
+            let shown = match drawn {
+                Ok(drawn) => drawn.buffer.clone(),
+                Err(_) => Buffer::empty(Rect::default()),
+            };
+            if !event::poll(REDRAW).unwrap_or(false) {
+                continue;
+            }
+            match event::read() {
+                Ok(Event::Key(key)) if key.kind == KeyEventKind::Press => self.key(key, keys, &shown),
+                Ok(Event::Mouse(mouse)) => self.mouse(mouse, &regions),
+                _ => {}
+            }
+        }
+    }
+
+    fn key(&self, key: KeyEvent, keys: &Keys, shown: &Buffer) {
+        let mut board = self.board.lock().unwrap();
+        match key.code {
+            // Raw mode turns Ctrl+C into a key; it stops the run as the signal would
+            KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                if !interrupt::record() {
+                    keys.request_quit();
+                }
+            }
+            KeyCode::Char('q') => keys.request_quit(),
+            KeyCode::Char('p' | ' ') => board.paused = keys.toggle_pause(),
+            KeyCode::Char('e') => board.note = Some((export(shown), Instant::now())),
+            code => {
+                if let Some(command) = Command::from_key(code) {
+                    board.handle(command);
+                }
+            }
+        }
+    }
+
+    fn mouse(&self, event: MouseEvent, regions: &Regions) {
+        let mut board = self.board.lock().unwrap();
+        let action = match event.kind {
+            MouseEventKind::ScrollUp => return board.handle(Command::Older),
+            MouseEventKind::ScrollDown => return board.handle(Command::Newer),
+            MouseEventKind::Down(MouseButton::Left) => MouseAction::Press,
+            MouseEventKind::Drag(MouseButton::Left) => MouseAction::Drag,
+            MouseEventKind::Up(MouseButton::Left) => MouseAction::Release,
+            MouseEventKind::Down(MouseButton::Right) => MouseAction::RightClick,
+            _ => return,
+        };
+        board.click(regions, Mouse { action, column: event.column as usize, row: event.row as usize });
+    }
+}
+
+/// Save the screen as it is shown; what happened, for the footer
+fn export(shown: &Buffer) -> String {
+    let path = format!("rust_ping-{}.html", chrono::Local::now().format("%Y%m%d-%H%M%S"));
+    match fs::write(&path, to_html(shown, "rust_ping dashboard")) {
+        Ok(()) => format!("Saved the screen to {}", path),
+        Err(e) => format!("Failed to write {}: {}", path, e),
+    }
+}
+
+/// The whole screen, and where its parts are
+fn draw(frame: &mut Frame, board: &Board) -> Regions {
+    let area = frame.area();
+    let width = area.width.min(120);
+    let row = |y: u16, height: u16| Rect::new(area.x, area.y + y, width, height.min(area.height.saturating_sub(y)));
+    let order = board.order();
+    let sort = board
+        .sort
+        .and_then(|key| key.to_possible_value())
+        .map_or("in target order".to_string(), |v| format!("sorted by {}", v.get_name()));
+
+    let header = Line::from(vec![
+        "📡 rust_ping dashboard".cyan().bold(),
+        format!(" · {} targets · {} · {}", board.panes.len(), sort, chrono::Local::now().format("%H:%M:%S")).dim(),
+    ]);
+    frame.render_widget(header, row(0, 1));
+    let status = if board.paused {
+        "⏸ Paused, p or space to resume".yellow().bold()
+    } else if let Some((from, to)) = board.zoom {
+        format!("🔍 probes {}-{}, click here or right-click to zoom out", from + 1, to + 1).yellow()
+    } else if board.offset > 0 {
+        format!("◀ {} probes back, ] to scroll forward", board.offset).yellow()
+    } else {
+        Span::raw("")
+    };
+    frame.render_widget(status, row(1, 1));
+
+    // Between the two lines at the top and the footer
+    let body = row(2, area.height.saturating_sub(3));
+    let mut regions = Regions { chart_width: width.saturating_sub(4) as usize, ..Regions::default() };
+    match board.view {
+        View::Panes => {
+            // Scrolled so the selected pane is on screen
+            let fits = (body.height as usize / PANE_HEIGHT).max(1);
+            let position = order.iter().position(|&i| i == board.selected).unwrap_or(0);
+            let first = position.saturating_sub(fits - 1);
+            for (n, &i) in order.iter().skip(first).take(fits).enumerate() {
+                let top = body.y + (n * PANE_HEIGHT) as u16;
+                let pane = Rect { y: top, height: (PANE_HEIGHT as u16).min(area.bottom().saturating_sub(top)), ..body };
+                regions.panes.push((top as usize, i));
+                draw_pane(frame, pane, board, i);
+            }
+        }
+        View::Overview => draw_overview(frame, body, board, &order, &mut regions),
+    }
+
+    let footer = match &board.note {
+        Some((note, at)) if at.elapsed() < NOTE_TIME => note.clone().green(),
+        _ => "j/k or ↑/↓ select · Enter overview/panes · s sort · [/] scroll · click/drag a chart · p pause · e save as HTML · q quit".dim(),
+    };
+    frame.render_widget(footer, row(area.height.saturating_sub(1), 1));
+    regions
+}
+
+struct BoardSink {
+    board: Arc<Mutex<Board>>,
+}
+
+impl Sink for BoardSink {
+    fn name(&self) -> &'static str {
+        "Dashboard"
+    }
+
+    fn send_result(&mut self, target: &Target, result: &PingResult) -> Result<(), String> {
+        let mut board = self.board.lock().unwrap();
+        if let Some(pane) = board.panes.iter_mut().find(|p| p.host == target.host && p.addr == target.addr) {
+            pane.sent += 1;
+            pane.recent.push(Probe {
+                seq: result.seq,
+                rtt: result.rtt_ms,
+                status: result.status,
+                at: result.timestamp.unwrap_or_else(chrono::Local::now),
+            });
+            match result.rtt_ms {
+                Some(rtt) => {
+                    pane.rtt.add(rtt);
+                    pane.last = Some(rtt);
+                    pane.lost_streak = 0;
+                }
+                None => {
+                    pane.rtt.add_failure(result.status);
+                    pane.lost_streak += 1;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn send_summary(&mut self, target: &Target, _stats: &PingStatistics) -> Result<(), String> {
+        let mut board = self.board.lock().unwrap();
+        if let Some(pane) = board.panes.iter_mut().find(|p| p.host == target.host && p.addr == target.addr) {
+            pane.finished = true;
+        }
+        Ok(())
+    }
+}
+
+/// The 16 basic terminal colors, as xterm shows them
+const BASIC_COLORS: [(u8, u8, u8); 16] = [
+    (0, 0, 0),
+    (205, 0, 0),
+    (0, 205, 0),
+    (205, 205, 0),
+    (0, 0, 238),
+    (205, 0, 205),
+    (0, 205, 205),
+    (229, 229, 229),
+    (127, 127, 127),
+    (255, 0, 0),
+    (0, 255, 0),
+    (255, 255, 0),
+    (92, 92, 255),
+    (255, 0, 255),
+    (0, 255, 255),
+    (255, 255, 255),
+];
+
+/// Color `n` of the 256-color palette
+fn palette(n: u8) -> (u8, u8, u8) {
+    match n {
+        0..=15 => BASIC_COLORS[n as usize],
+        16..=231 => {
+            let level = |v: u8| if v == 0 { 0 } else { 55 + v * 40 };
+            let n = n - 16;
+            (level(n / 36), level(n / 6 % 6), level(n % 6))
+        }
+        _ => {
+            let gray = 8 + (n - 232) * 10;
+            (gray, gray, gray)
+        }
+    }
+}
+
+/// RGB of a cell color; none for the terminal's default
+fn rgb(color: Color) -> Option<(u8, u8, u8)> {
+    let basic = match color {
+        Color::Reset => return None,
+        Color::Rgb(r, g, b) => return Some((r, g, b)),
+        Color::Indexed(n) => return Some(palette(n)),
+        Color::Black => 0,
+        Color::Red => 1,
+        Color::Green => 2,
+        Color::Yellow => 3,
+        Color::Blue => 4,
+        Color::Magenta => 5,
+        Color::Cyan => 6,
+        Color::Gray => 7,
+        Color::DarkGray => 8,
+        Color::LightRed => 9,
+        Color::LightGreen => 10,
+        Color::LightYellow => 11,
+        Color::LightBlue => 12,
+        Color::LightMagenta => 13,
+        Color::LightCyan => 14,
+        Color::White => 15,
+    };
+    Some(BASIC_COLORS[basic])
+}
+
+/// CSS for how a cell looks, empty for plain text
+fn css(cell: &buffer::Cell) -> String {
+    let mut css = String::new();
+    let (mut fg, mut bg) = (rgb(cell.fg), rgb(cell.bg));
+    if cell.modifier.contains(Modifier::REVERSED) {
+        // The page's own colors stand in for the defaults
+        (fg, bg) = (bg.or(Some((30, 30, 30))), fg.or(Some((212, 212, 212))));
+    }
+    if let Some((r, g, b)) = fg {
+        let _ = write!(css, "color:rgb({},{},{});", r, g, b);
+    }
+    if let Some((r, g, b)) = bg {
+        let _ = write!(css, "background:rgb({},{},{});", r, g, b);
+    }
+    if cell.modifier.contains(Modifier::BOLD) {
+        css.push_str("font-weight:bold;");
+    }
+    if cell.modifier.contains(Modifier::DIM) {
+        css.push_str("opacity:0.6;");
+    }
+    css
+}
+
+/// A page showing `screen` as the terminal did, colors kept
+fn to_html(screen: &Buffer, title: &str) -> String {
+    let mut body = String::new();
+    let area = screen.area;
+    for y in area.top()..area.bottom() {
+        let mut style = String::new();
+        // Cells covered by a wide character before them
+        let mut covered = 0;
+        for x in area.left()..area.right() {
+            if covered > 0 {
+                covered -= 1;
+                continue;
+            }
+            let cell = &screen[(x, y)];
+            let next = css(cell);
+            if next != style {
+                if !style.is_empty() {
+                    body.push_str("</span>");
+                }
+                if !next.is_empty() {
+                    let _ = write!(body, r#"<span style="{}">"#, next);
+                }
+                style = next;
+            }
+            body.push_str(&escape(cell.symbol()));
+            covered = Span::raw(cell.symbol()).width().saturating_sub(1);
+        }
+        if !style.is_empty() {
+            body.push_str("</span>");
+        }
+        body.push('\n');
+    }
+
+    format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{}</title>\n</head>\n<body style=\"background:#1e1e1e;margin:0\">\n<pre style=\"color:#d4d4d4;font-family:monospace;padding:1em;margin:0\">{}</pre>\n</body>\n</html>\n",
+        escape(title),
+        body
+    )
+}
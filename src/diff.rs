@@ -0,0 +1,247 @@
+//! `diff` subcommand: comparison of two saved reports.
+//!
+//! Lines up the statistics of a run before and after a change to the same
+//! target (loss, average, percentiles, jitter) with the difference of each,
+//! and tells whether the differences are more than chance: loss with a
+//! two-proportion z-test, latency with a Mann-Whitney U test on the RTTs of
+//! every reply, which doesn't assume RTTs to be normally distributed. The
+//! latency test needs the per-probe results, so reports that only kept
+//! per-second aggregates are compared on loss alone.
+
+use colored::*;
+use comfy_table::modifiers::UTF8_ROUND_CORNERS;
+use comfy_table::presets::UTF8_FULL_CONDENSED;
+use comfy_table::{Cell, CellAlignment, Color, ContentArrangement, Table};
+use rust_ping::PingReport;
+
+use crate::export::load_report;
+
+/// Options of the `diff` subcommand
+#[derive(clap::Args, Debug)]
+pub struct DiffArgs {
+    /// Report of the earlier run (JSON, YAML, CBOR or MessagePack)
+    #[arg(value_name = "BEFORE")]
+    before: String,
+
+    /// Report of the later run
+    #[arg(value_name = "AFTER")]
+    after: String,
+}
+
+// p-value below which a difference is taken for more than chance
+const SIGNIFICANCE: f64 = 0.05;
+
+/// Outcome of a test of a difference
+struct Test {
+    p: f64,
+    // The later run has the larger value
+    higher: bool,
+}
+
+impl Test {
+    fn significant(&self) -> bool {
+        self.p < SIGNIFICANCE
+    }
+}
+
+/// Complementary error function, to within 1.2e-7 (Numerical Recipes' erfcc)
+fn erfc(x: f64) -> f64 {
+    let z = x.abs();
+    let t = 1.0 / (1.0 + 0.5 * z);
+    let poly = -z * z - 1.26551223
+        + t * (1.00002368
+            + t * (0.37409196
+                + t * (0.09678418
+                    + t * (-0.18628806
+                        + t * (0.27886807 + t * (-1.13520398 + t * (1.48851587 + t * (-0.82215223 + t * 0.17087277))))))));
+    let result = t * poly.exp();
+    if x >= 0.0 {
+        result
+    } else {
+        2.0 - result
+    }
+}
+
+/// Two-sided p-value of a standard normal score
+fn p_value(z: f64) -> f64 {
+    erfc(z.abs() / std::f64::consts::SQRT_2).min(1.0)
+}
+
+/// Whether the share of probes lost differs between the runs
+fn loss_test(before_lost: u32, before_sent: u32, after_lost: u32, after_sent: u32) -> Option<Test> {
+    if before_sent == 0 || after_sent == 0 {
+        return None;
+    }
+    let (n1, n2) = (before_sent as f64, after_sent as f64);
+    let (p1, p2) = (before_lost as f64 / n1, after_lost as f64 / n2);
+    let pooled = (before_lost + after_lost) as f64 / (n1 + n2);
+    let se = (pooled * (1.0 - pooled) * (1.0 / n1 + 1.0 / n2)).sqrt();
+    if se == 0.0 {
+        // None lost in either run, or every probe in both
+        return Some(Test { p: 1.0, higher: false });
+    }
+    Some(Test { p: p_value((p2 - p1) / se), higher: p2 > p1 })
+}
+
+/// Whether the RTTs of one run tend to be larger than those of the other,
+/// with the normal approximation and ties corrected for
+fn latency_test(before: &[f64], after: &[f64]) -> Option<Test> {
+    let (n1, n2) = (after.len() as f64, before.len() as f64);
+    if after.is_empty() || before.is_empty() {
+        return None;
+    }
+    let mut all: Vec<(f64, bool)> = after.iter().map(|&rtt| (rtt, true)).chain(before.iter().map(|&rtt| (rtt, false))).collect();
+    all.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+    // Tied RTTs share the average of their ranks
+    let mut rank_sum = 0.0;
+    let mut ties = 0.0;
+    let mut start = 0;
+    while start < all.len() {
+        let end = start + all[start..].iter().take_while(|(rtt, _)| *rtt == all[start].0).count();
+        let rank = (start + end + 1) as f64 / 2.0;
+        rank_sum += rank * all[start..end].iter().filter(|(_, later)| *later).count() as f64;
+        let t = (end - start) as f64;
+        ties += t * t * t - t;
+        start = end;
+    }
+
+    let n = n1 + n2;
+    let u = rank_sum - n1 * (n1 + 1.0) / 2.0;
+    let mean = n1 * n2 / 2.0;
+    let variance = n1 * n2 / 12.0 * ((n + 1.0) - ties / (n * (n - 1.0)));
+    if variance <= 0.0 {
+        return Some(Test { p: 1.0, higher: false });
+    }
+    Some(Test { p: p_value((u - mean) / variance.sqrt()), higher: u > mean })
+}
+
+/// RTTs of the replies of a report, None when only aggregates were kept
+fn rtts(report: &PingReport) -> Option<Vec<f64>> {
+    if report.results.is_empty() {
+        return None;
+    }
+    Some(report.results.iter().filter(|result| !result.duplicate).filter_map(|result| result.rtt_ms).collect())
+}
+
+fn value_cell(value: Option<f64>) -> Cell {
+    Cell::new(value.map_or("-".to_string(), |v| format!("{:.2}", v))).set_alignment(CellAlignment::Right)
+}
+
+/// Change of a metric, colored red when it is larger (worse) after
+fn change_cells(before: Option<f64>, after: Option<f64>) -> [Cell; 2] {
+    let (Some(before), Some(after)) = (before, after) else {
+        return [value_cell(None), value_cell(None)];
+    };
+    let delta = after - before;
+    let color = if delta > 0.0 {
+        Color::Red
+    } else if delta < 0.0 {
+        Color::Green
+    } else {
+        Color::Reset
+    };
+    let percent = if before != 0.0 { format!("{:+.1}%", delta / before * 100.0) } else { "-".to_string() };
+    [
+        Cell::new(format!("{:+.2}", delta)).fg(color).set_alignment(CellAlignment::Right),
+        Cell::new(percent).fg(color).set_alignment(CellAlignment::Right),
+    ]
+}
+
+fn describe_run(label: &str, report: &PingReport) {
+    println!(
+        "  {} {} ({}), run {}: {} -> {}{}",
+        label,
+        report.host.yellow(),
+        report.ip_address,
+        report.run_id,
+        report.timestamp_start,
+        report.timestamp_end,
+        if report.complete { "" } else { " (incomplete)" }
+    );
+}
+
+/// One line on whether a metric changed by more than chance
+fn print_test(metric: &str, test: Option<&Test>, worse: &str, better: &str, missing: &str) {
+    let Some(test) = test else {
+        println!("  {}: {}", metric, missing.dimmed());
+        return;
+    };
+    let p = if test.p < 0.001 { "p < 0.001".to_string() } else { format!("p = {:.3}", test.p) };
+    if !test.significant() {
+        println!("  {}: {} ({})", metric, "no significant change".green(), p);
+    } else if test.higher {
+        println!("  {}: {} ({})", metric, worse.red().bold(), p);
+    } else {
+        println!("  {}: {} ({})", metric, better.green().bold(), p);
+    }
+}
+
+pub fn run(args: &DiffArgs) -> Result<(), String> {
+    let before = load_report(&args.before)?;
+    let after = load_report(&args.after)?;
+    let (old, new) = (&before.statistics, &after.statistics);
+
+    println!("\n{}", "╔════════════════════════════════════════════════════════════╗".blue());
+    println!("{}", "║                     🔀 RUN COMPARISON                       ║".blue());
+    println!("{}", "╚════════════════════════════════════════════════════════════╝".blue());
+    describe_run("Before:", &before);
+    describe_run("After: ", &after);
+    if before.host != after.host && before.ip_address != after.ip_address {
+        println!(
+            "  {} The reports are of different targets, so differences may be those of the paths",
+            "⚠".yellow()
+        );
+    }
+
+    let mut table = Table::new();
+    table
+        .load_preset(UTF8_FULL_CONDENSED)
+        .apply_modifier(UTF8_ROUND_CORNERS)
+        .set_content_arrangement(ContentArrangement::Dynamic)
+        .set_header(vec!["Metric", "Before", "After", "Change", "Change %"]);
+    // More probes is neither better nor worse
+    table.add_row(vec![
+        Cell::new("Sent"),
+        Cell::new(old.packets_sent).set_alignment(CellAlignment::Right),
+        Cell::new(new.packets_sent).set_alignment(CellAlignment::Right),
+        value_cell(None),
+        value_cell(None),
+    ]);
+    let rows = [
+        ("Loss %", Some(old.packet_loss_percent), Some(new.packet_loss_percent)),
+        ("Avg ms", old.avg_ms, new.avg_ms),
+        ("p50 ms", old.p50_ms, new.p50_ms),
+        ("p95 ms", old.p95_ms, new.p95_ms),
+        ("p99 ms", old.p99_ms, new.p99_ms),
+        ("Max ms", old.max_ms, new.max_ms),
+        ("Jitter ms", old.jitter_ms, new.jitter_ms),
+    ];
+    for (metric, old, new) in rows {
+        let [change, percent] = change_cells(old, new);
+        table.add_row(vec![Cell::new(metric), value_cell(old), value_cell(new), change, percent]);
+    }
+    println!("{}", table);
+
+    let loss = loss_test(old.packets_lost, old.packets_sent, new.packets_lost, new.packets_sent);
+    let latency = match (rtts(&before), rtts(&after)) {
+        (Some(before), Some(after)) => latency_test(&before, &after),
+        _ => None,
+    };
+    let latency_missing = if before.results.is_empty() || after.results.is_empty() {
+        "not tested, a report kept only per-second aggregates"
+    } else {
+        "not tested, a run had no replies"
+    };
+    print_test("Loss", loss.as_ref(), "worse after", "better after", "not tested, a run sent no probes");
+    print_test("Latency", latency.as_ref(), "higher after", "lower after", latency_missing);
+
+    let worse = [&loss, &latency].into_iter().flatten().any(|test| test.significant() && test.higher);
+    let better = [&loss, &latency].into_iter().flatten().any(|test| test.significant() && !test.higher);
+    match (worse, better) {
+        (true, _) => println!("  {} {}", "✗".red(), "Worse after".red().bold()),
+        (false, true) => println!("  {} {}", "✓".green(), "Better after".green().bold()),
+        (false, false) => println!("  {} {}", "✓".green(), "No change beyond chance".green()),
+    }
+    Ok(())
+}
@@ -0,0 +1,59 @@
+//! Largest-Triangle-Three-Buckets downsampling for the line graph.
+//!
+//! Probes are split into one bucket per column. Each column shows the reply
+//! that forms the largest triangle with the point chosen for the previous
+//! column and the average of the next one, so outliers survive however many
+//! probes end up in a column, where plain averaging would flatten a spike.
+//! The first and last columns have no neighbour on one side and show their
+//! largest RTT, so a spike at either end of the run is kept as well.
+
+/// What one graph column shows
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Column {
+    /// Chosen RTT, `None` when every probe in the column was lost
+    pub rtt: Option<f64>,
+    /// Index of the first lost probe in the column
+    pub lost: Option<usize>,
+}
+
+/// Reduce per-probe RTTs (`None` for a loss) to at most `width` columns
+pub fn lttb(rtts: &[Option<f64>], width: usize) -> Vec<Column> {
+    let width = width.min(rtts.len());
+    if width == 0 {
+        return Vec::new();
+    }
+    let bucket = |c: usize| &rtts[c * rtts.len() / width..(c + 1) * rtts.len() / width];
+    let replies = |c: usize| {
+        let start = c * rtts.len() / width;
+        bucket(c)
+            .iter()
+            .enumerate()
+            .filter_map(move |(i, rtt)| rtt.map(|rtt| ((start + i) as f64, rtt)))
+    };
+
+    let mut columns = Vec::with_capacity(width);
+    let mut previous: Option<(f64, f64)> = None;
+    for c in 0..width {
+        let lost = bucket(c).iter().position(|rtt| rtt.is_none()).map(|i| c * rtts.len() / width + i);
+
+        // Average of the next column with replies
+        let next = (c + 1..width).find_map(|n| {
+            let (count, x, y) = replies(n).fold((0, 0.0, 0.0), |(k, x, y), (px, py)| (k + 1, x + px, y + py));
+            (count > 0).then(|| (x / count as f64, y / count as f64))
+        });
+
+        let chosen = match (previous, next) {
+            (Some((ax, ay)), Some((cx, cy))) => replies(c).max_by(|(bx, by), (dx, dy)| {
+                let area = |x: f64, y: f64| ((ax - cx) * (y - ay) - (ax - x) * (cy - ay)).abs();
+                area(*bx, *by).total_cmp(&area(*dx, *dy))
+            }),
+            _ => replies(c).max_by(|(_, a), (_, b)| a.total_cmp(b)),
+        };
+
+        if chosen.is_some() {
+            previous = chosen;
+        }
+        columns.push(Column { rtt: chosen.map(|(_, rtt)| rtt), lost });
+    }
+    columns
+}
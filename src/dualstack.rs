@@ -0,0 +1,123 @@
+//! Picking the address family of a dual-stack host.
+//!
+//! A hostname with both A and AAAA records is pinged on its IPv4 address
+//! unless `--prefer-ipv6` says otherwise. `--happy-eyeballs` decides the way
+//! browsers do (RFC 8305) instead: one echo request goes to the first
+//! address of each family at once, and the family whose reply comes back
+//! first is pinged, IPv6 when neither answers within the timeout. Either way
+//! the choice is told before the host is pinged, so it is never a surprise
+//! which of the two the statistics are about.
+
+use clap::ValueEnum;
+use std::fmt;
+use std::net::{IpAddr, ToSocketAddrs};
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+
+use crate::identifier::Identifier;
+use crate::trace::wait_answer;
+use crate::transport::{self, ECHO_REPLY};
+use crate::{write_icmp_packet, PACKET_LEN};
+
+/// Which family of a dual-stack host is pinged
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum Preference {
+    #[default]
+    Ipv4,
+    Ipv6,
+    /// Race both and ping the one that answers first
+    Race,
+}
+
+/// The address a host was resolved to, and why when it had one of each family
+pub struct Choice {
+    pub addr: IpAddr,
+    /// The first address of the other family, when there is one
+    pub other: Option<IpAddr>,
+    pub reason: Reason,
+}
+
+pub enum Reason {
+    Preferred,
+    /// Replied first, with its RTT in ms
+    Won(f64),
+    /// Neither family answered within the timeout
+    NoAnswer,
+}
+
+impl fmt::Display for Choice {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let family = if self.addr.is_ipv6() { "IPv6" } else { "IPv4" };
+        match (&self.reason, self.other) {
+            (_, None) => write!(f, "{}", family),
+            (Reason::Preferred, Some(other)) => write!(f, "{}, preferred over {}", family, other),
+            (Reason::Won(rtt), Some(other)) => write!(f, "{}, answered first in {:.2}ms, before {}", family, rtt, other),
+            (Reason::NoAnswer, Some(_)) => write!(f, "{}, as neither family answered the race", family),
+        }
+    }
+}
+
+/// Resolve `host` to an address of the family `preference` picks, racing them if asked
+pub fn resolve(host: &str, preference: Preference, timeout: Duration) -> Result<Choice, String> {
+    if let Ok(addr) = host.parse::<IpAddr>() {
+        return Ok(Choice { addr, other: None, reason: Reason::Preferred });
+    }
+    let addrs: Vec<IpAddr> = match (host, 0).to_socket_addrs() {
+        Ok(addrs) => addrs.map(|socket_addr| socket_addr.ip()).collect(),
+        Err(e) => return Err(format!("DNS error: {}", e)),
+    };
+    let v4 = addrs.iter().copied().find(IpAddr::is_ipv4);
+    let v6 = addrs.iter().copied().find(IpAddr::is_ipv6);
+    let (v4, v6) = match (v4, v6) {
+        (Some(v4), Some(v6)) => (v4, v6),
+        (Some(addr), None) | (None, Some(addr)) => return Ok(Choice { addr, other: None, reason: Reason::Preferred }),
+        (None, None) => return Err(format!("Could not resolve: {}", host)),
+    };
+    Ok(match preference {
+        Preference::Ipv4 => Choice { addr: v4, other: Some(v6), reason: Reason::Preferred },
+        Preference::Ipv6 => Choice { addr: v6, other: Some(v4), reason: Reason::Preferred },
+        Preference::Race => match race(v4, v6, timeout) {
+            Some((addr, rtt)) => {
+                let other = if addr == v4 { v6 } else { v4 };
+                Choice { addr, other: Some(other), reason: Reason::Won(rtt) }
+            }
+            None => Choice { addr: v6, other: Some(v4), reason: Reason::NoAnswer },
+        },
+    })
+}
+
+/// Send an echo request to both addresses at once and return the one replying first
+fn race(v4: IpAddr, v6: IpAddr, timeout: Duration) -> Option<(IpAddr, f64)> {
+    let (tx, replies) = mpsc::channel();
+    for addr in [v6, v4] {
+        let tx = tx.clone();
+        // A family that can't be pinged from here just never answers
+        thread::spawn(move || {
+            if let Some(rtt) = echo(addr, timeout) {
+                let _ = tx.send((addr, rtt));
+            }
+        });
+    }
+    drop(tx);
+    replies.recv_timeout(timeout).ok()
+}
+
+/// RTT in ms of one echo request to `addr`, if it's answered within `timeout`
+fn echo(addr: IpAddr, timeout: Duration) -> Option<f64> {
+    let (mut sender, mut receiver) = transport::open(addr.is_ipv6()).ok()?;
+    let identifier = Identifier::claim().ok()?;
+    let mut packet = vec![vec![0u8; PACKET_LEN]];
+    write_icmp_packet(&mut packet[0], 0, identifier.value(), addr.is_ipv6());
+    let sent_at = SystemTime::now();
+    if sender.send_batch(&packet, addr).1.is_some() {
+        return None;
+    }
+    let deadline = Instant::now() + timeout;
+    while let Some(reply) = wait_answer(receiver.as_mut(), identifier.value(), deadline.saturating_duration_since(Instant::now())).ok()? {
+        if reply.icmp_type == ECHO_REPLY && reply.source == addr && reply.sequence == Some(0) {
+            return Some(reply.received.duration_since(sent_at).unwrap_or_default().as_secs_f64() * 1000.0);
+        }
+    }
+    None
+}
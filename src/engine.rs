@@ -0,0 +1,291 @@
+//! How concurrent targets share raw sockets.
+//!
+//! The kernel hands a copy of every incoming ICMP message to every raw
+//! socket, so with a socket per target each reply is copied to and parsed by
+//! every worker, which only keeps the ones carrying its own echo identifier.
+//! A shared socket is read by a single router thread instead, which passes
+//! each reply to its target through a table keyed on that identifier.
+//! IPv4 and IPv6 targets can't share a socket, so each family in use gets one.
+
+use clap::ValueEnum;
+use colored::*;
+use std::collections::HashMap;
+use std::fmt;
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, UdpSocket};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+use rust_ping::PhaseStats;
+
+use crate::http::Prober;
+use crate::identifier::Identifier;
+use crate::replay::Player;
+use crate::transport::{self, IcmpReceiver, IcmpSender, Reply};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum SocketMode {
+    /// A raw socket for every target being pinged
+    PerTarget,
+    /// One raw socket for all targets, replies routed by identifier
+    Shared,
+}
+
+// How often the router checks whether the run is over
+const ROUTER_POLL: Duration = Duration::from_millis(100);
+
+/// Where the probes leave from, on hosts with several interfaces or VRFs
+#[derive(Clone, Debug, Default)]
+pub struct Binding {
+    /// Interface (or VRF device) the probes are sent and answered on, from -I
+    pub interface: Option<String>,
+    /// Local address the probes are sent from, from --source
+    pub address: Option<IpAddr>,
+}
+
+impl Binding {
+    pub fn is_empty(&self) -> bool {
+        self.interface.is_none() && self.address.is_none()
+    }
+
+    /// A source address of the other family than `addr` can't send to it
+    fn check(&self, addr: IpAddr) -> Result<(), String> {
+        match self.address {
+            Some(source) if source.is_ipv6() != addr.is_ipv6() => {
+                Err(format!("Source address {} can't send to {}, which is of the other address family", source, addr))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Bind a raw socket's sender; the address only if it is of the socket's family
+    fn apply(&self, sender: &mut dyn IcmpSender, ipv6: bool) -> Result<(), String> {
+        if let Some(name) = &self.interface {
+            sender.bind_interface(name).map_err(|e| format!("Failed to bind to interface {}: {}", name, e))?;
+        }
+        if let Some(source) = self.address.filter(|source| source.is_ipv6() == ipv6) {
+            sender.bind_source(source).map_err(|e| format!("Failed to send from {}: {}", source, e))?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Binding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match (&self.address, &self.interface) {
+            (Some(address), Some(interface)) => write!(f, "{} on {}", address, interface),
+            (Some(address), None) => write!(f, "{}", address),
+            (None, Some(interface)) => write!(f, "{}", interface),
+            (None, None) => Ok(()),
+        }
+    }
+}
+
+/// One socket for all targets of an address family
+pub struct SharedSocket {
+    ipv6: bool,
+    sender: Mutex<Box<dyn IcmpSender>>,
+    routes: Mutex<HashMap<u16, Sender<Reply>>>,
+    stop: AtomicBool,
+}
+
+impl SharedSocket {
+    /// Open the socket, bound as asked; the receiving half is handed to [`SharedSocket::route`]
+    pub fn open(ipv6: bool, binding: &Binding) -> Result<(Self, Box<dyn IcmpReceiver>), String> {
+        let (mut sender, receiver) = transport::open(ipv6)?;
+        binding.apply(sender.as_mut(), ipv6)?;
+        let socket = SharedSocket {
+            ipv6,
+            sender: Mutex::new(sender),
+            routes: Mutex::new(HashMap::new()),
+            stop: AtomicBool::new(false),
+        };
+        Ok((socket, receiver))
+    }
+
+    /// Pass replies on to their targets until [`SharedSocket::stop`]; runs on its own thread
+    pub fn route(&self, mut receiver: Box<dyn IcmpReceiver>) {
+        while !self.stop.load(Ordering::Relaxed) {
+            match receiver.next_with_timeout(ROUTER_POLL) {
+                Ok(Some(reply)) => {
+                    // Replies nobody is waiting for (stray traffic, late arrivals) are dropped
+                    if let Some(target) = self.routes.lock().unwrap().get(&reply.identifier) {
+                        let _ = target.send(reply);
+                    }
+                }
+                Ok(None) => {}
+                Err(e) => {
+                    eprintln!("  {} Receive error: {}", "⚠".yellow(), e);
+                    thread::sleep(ROUTER_POLL);
+                }
+            }
+        }
+    }
+
+    /// Whether probes to `addr` go out on this socket
+    pub fn serves(&self, addr: IpAddr) -> bool {
+        self.ipv6 == addr.is_ipv6()
+    }
+
+    pub fn stop(&self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+}
+
+enum Route<'a> {
+    Own { sender: Box<dyn IcmpSender>, receiver: Box<dyn IcmpReceiver> },
+    Shared { socket: &'a SharedSocket, replies: Receiver<Reply> },
+    Replay(Box<Player>),
+    Http(Box<Prober>),
+}
+
+/// Where one target sends its probes and gets its replies
+pub struct Channel<'a> {
+    route: Route<'a>,
+    // With --udp: probes are datagrams to this port, sent from the identifier's port
+    udp: Option<(UdpSocket, u16)>,
+    // Declared last so the route is removed before the identifier is released
+    identifier: Identifier,
+}
+
+impl<'a> Channel<'a> {
+    /// Register `identifier` on the shared socket, or open a socket of its own for `addr`
+    pub fn open(shared: Option<&'a SharedSocket>, identifier: Identifier, addr: IpAddr) -> Result<Self, String> {
+        let route = match shared {
+            Some(socket) => {
+                let (tx, replies) = mpsc::channel();
+                socket.routes.lock().unwrap().insert(identifier.value(), tx);
+                Route::Shared { socket, replies }
+            }
+            None => {
+                let (sender, receiver) = transport::open(addr.is_ipv6())?;
+                Route::Own { sender, receiver }
+            }
+        };
+        Ok(Channel { route, udp: None, identifier })
+    }
+
+    /// Answer probes from a saved report instead of the network
+    pub fn replay(player: Player, identifier: Identifier) -> Self {
+        Channel { route: Route::Replay(Box::new(player)), udp: None, identifier }
+    }
+
+    /// Send the probes from the interface and address of `binding`
+    pub fn bind(mut self, addr: IpAddr, binding: &Binding) -> Result<Self, String> {
+        binding.check(addr)?;
+        match &mut self.route {
+            Route::Own { sender, .. } => binding.apply(sender.as_mut(), addr.is_ipv6())?,
+            // Shared sockets are bound when opened, and replays and HTTP requests don't send probes
+            Route::Shared { .. } | Route::Replay(_) | Route::Http(_) => {}
+        }
+        Ok(self)
+    }
+
+    /// Send UDP datagrams to `port` instead of echo requests, from the
+    /// interface and address of `binding`; the target's ICMP port
+    /// unreachable answers them
+    pub fn udp(mut self, addr: IpAddr, port: u16, binding: &Binding) -> Result<Self, String> {
+        if cfg!(windows) {
+            return Err("--udp needs the port unreachable answers of a raw ICMP socket, which Windows doesn't offer".to_string());
+        }
+        let local = match (binding.address, addr) {
+            (Some(source), _) => source,
+            (None, IpAddr::V4(_)) => IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+            (None, IpAddr::V6(_)) => IpAddr::V6(Ipv6Addr::UNSPECIFIED),
+        };
+        let source = self.identifier.value();
+        let socket = UdpSocket::bind((local, source))
+            .map_err(|e| format!("Failed to bind UDP source port {}: {}", source, e))?;
+        if let Some(name) = &binding.interface {
+            transport::bind_udp_interface(&socket, name).map_err(|e| format!("Failed to bind to interface {}: {}", name, e))?;
+        }
+        self.udp = Some((socket, port));
+        Ok(self)
+    }
+
+    /// Send the probes with this TTL (hop limit for IPv6) instead of the system's default
+    pub fn ttl(mut self, addr: IpAddr, ttl: u8) -> Result<Self, String> {
+        let set = match (&self.udp, &mut self.route) {
+            (Some((socket, _)), _) if addr.is_ipv6() => transport::set_udp_hop_limit(socket, ttl),
+            (Some((socket, _)), _) => socket.set_ttl(ttl as u32),
+            (None, Route::Own { sender, .. }) => sender.set_ttl(ttl),
+            // All targets of the run ask for the same TTL
+            (None, Route::Shared { socket, .. }) => socket.sender.lock().unwrap().set_ttl(ttl),
+            (None, Route::Replay(_) | Route::Http(_)) => Ok(()),
+        };
+        set.map_err(|e| format!("Failed to set TTL: {}", e))?;
+        Ok(self)
+    }
+
+    /// Make HTTP requests instead of sending echo requests
+    pub fn http(prober: Prober, identifier: Identifier) -> Self {
+        Channel { route: Route::Http(Box::new(prober)), udp: None, identifier }
+    }
+
+    /// Time spent in each phase of the HTTP requests
+    pub fn http_phases(&self) -> Option<Vec<PhaseStats>> {
+        match &self.route {
+            Route::Http(prober) => Some(prober.phases()),
+            _ => None,
+        }
+    }
+
+    /// Echo identifier of this target's probes
+    pub fn identifier(&self) -> u16 {
+        self.identifier.value()
+    }
+
+    pub fn send_batch(&mut self, packets: &[Vec<u8>], addr: IpAddr) -> (usize, Option<io::Error>) {
+        if let Some((socket, port)) = &self.udp {
+            // The echo request's payload, so the datagram is as long as an echo request's data
+            for (i, packet) in packets.iter().enumerate() {
+                if let Err(e) = socket.send_to(&packet[8..], (addr, *port)) {
+                    return (i, Some(e));
+                }
+            }
+            return (packets.len(), None);
+        }
+        match &mut self.route {
+            Route::Own { sender, .. } => sender.send_batch(packets, addr),
+            Route::Shared { socket, .. } => socket.sender.lock().unwrap().send_batch(packets, addr),
+            Route::Replay(player) => player.send_batch(packets),
+            Route::Http(prober) => prober.send_batch(packets),
+        }
+    }
+
+    /// Next message for this target, or `None` if none arrived within `timeout`
+    pub fn next_with_timeout(&mut self, timeout: Duration) -> io::Result<Option<Reply>> {
+        match &mut self.route {
+            Route::Own { receiver, .. } => {
+                // Replies to other targets and other sessions arrive on this socket too
+                let identifier = self.identifier.value();
+                let deadline = Instant::now() + timeout;
+                loop {
+                    match receiver.next_with_timeout(deadline.saturating_duration_since(Instant::now()))? {
+                        Some(reply) if reply.identifier != identifier => continue,
+                        other => return Ok(other),
+                    }
+                }
+            }
+            Route::Shared { replies, .. } => match replies.recv_timeout(timeout) {
+                Ok(reply) => Ok(Some(reply)),
+                Err(RecvTimeoutError::Timeout) => Ok(None),
+                Err(RecvTimeoutError::Disconnected) => {
+                    Err(io::Error::new(io::ErrorKind::BrokenPipe, "reply router stopped"))
+                }
+            },
+            Route::Replay(player) => player.next_with_timeout(self.identifier.value(), timeout),
+            Route::Http(prober) => prober.next_with_timeout(),
+        }
+    }
+}
+
+impl Drop for Channel<'_> {
+    fn drop(&mut self) {
+        if let Route::Shared { socket, .. } = &self.route {
+            socket.routes.lock().unwrap().remove(&self.identifier.value());
+        }
+    }
+}
@@ -0,0 +1,964 @@
+//! Incremental JSON/CSV report writers.
+//!
+//! Records are appended while the run is in progress and flushed/synced
+//! periodically, so an interrupted run still leaves its data on disk. The
+//! statistics are written when the writer is finished (or dropped during a
+//! panic), in which case the report is marked as incomplete.
+//!
+//! Reports are written to a hidden temporary file next to the target and
+//! renamed into place once closed, so readers never see a half-written
+//! report under the final name. The temporary JSON of a run that was killed
+//! outright still loads, as an incomplete report of the records written.
+//!
+//! The CSV export is annotated by default: `#` comment lines describe the
+//! run and further sections follow the results. With `--csv-strict` it is a
+//! single RFC 4180 table, a header row and a row per record, which pandas and
+//! spreadsheets import as it is. Either way the delimiter and the decimal
+//! separator can be changed for locales that use a decimal comma.
+
+use chrono::{DateTime, Local};
+use serde::Serialize;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use rust_ping::{AddressStats, Marker, Outage, PhaseStats, PingReport, SiteSwitch, SizeStats, SCHEMA_VERSION};
+
+use crate::report;
+use crate::xlsx;
+use crate::stats::RttStats;
+use crate::{PingResult, PingStatistics, SecondAggregate, TIMESTAMP_FORMAT};
+
+/// Export file name that stands for stdout
+pub const STDOUT: &str = "-";
+
+/// How often buffered records are pushed to the OS
+const FLUSH_INTERVAL: Duration = Duration::from_secs(1);
+/// How often written data is forced to disk
+const SYNC_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Fields known before the first probe is sent
+#[derive(Clone)]
+pub struct ReportHeader {
+    pub run_id: String,
+    pub host: String,
+    pub ip_address: String,
+    pub timestamp_start: String,
+    pub timeout_ms: f64,
+    pub interval_seconds: f64,
+    /// Local overhead measured with --calibrate
+    pub overhead_ms: Option<f64>,
+    /// Whether it was subtracted from the RTTs; results then also carry `raw_rtt_ms`
+    pub overhead_subtracted: bool,
+    /// TTL the probes were sent with, when --ttl set one
+    pub ttl: Option<u8>,
+    /// Interface and local address the probes were sent from, when -I and --source chose them
+    pub interface: Option<String>,
+    pub source_address: Option<String>,
+    /// Command line the run was started with
+    pub command_line: Vec<String>,
+}
+
+/// Requested export destinations, after placeholder expansion
+#[derive(Default)]
+pub struct ExportFiles {
+    pub json: Option<String>,
+    pub csv: Option<String>,
+    pub xlsx: Option<String>,
+    pub yaml: Option<String>,
+    pub cbor: Option<String>,
+    pub msgpack: Option<String>,
+    pub html: Option<String>,
+    pub csv_format: CsvFormat,
+}
+
+/// How the CSV export is laid out
+#[derive(Clone, Copy, Debug)]
+pub struct CsvFormat {
+    /// Only the header row and the records: no comments, statistics or other sections
+    pub strict: bool,
+    pub delimiter: char,
+    pub decimal: char,
+}
+
+impl Default for CsvFormat {
+    fn default() -> Self {
+        CsvFormat { strict: false, delimiter: ',', decimal: '.' }
+    }
+}
+
+impl CsvFormat {
+    /// Column names separated by commas (and ending in a line feed), in this format
+    fn columns(&self, names: &str) -> String {
+        let mut out = String::with_capacity(names.len());
+        for c in names.chars() {
+            match c {
+                ',' => out.push(self.delimiter),
+                '\n' => out.push_str(self.line_end()),
+                c => out.push(c),
+            }
+        }
+        out
+    }
+
+    /// One record; strict rows end in CRLF as RFC 4180 has it
+    fn row(&self, fields: &[String]) -> String {
+        let mut row = self.join(fields);
+        row.push_str(self.line_end());
+        row
+    }
+
+    fn join(&self, fields: &[String]) -> String {
+        fields.join(self.delimiter.encode_utf8(&mut [0; 4]))
+    }
+
+    fn line_end(&self) -> &'static str {
+        if self.strict { "\r\n" } else { "\n" }
+    }
+
+    /// `value` with `decimals` digits after the decimal separator; the only
+    /// place the separator is changed, so timestamps and text keep their dots
+    fn number(&self, value: f64, decimals: usize) -> String {
+        let text = format!("{:.*}", decimals, value);
+        if self.decimal == '.' { text } else { text.replace('.', self.decimal.encode_utf8(&mut [0; 4])) }
+    }
+
+    /// Milliseconds with two decimals, empty when there are none
+    fn ms(&self, value: Option<f64>) -> String {
+        value.map_or(String::new(), |v| self.number(v, 2))
+    }
+}
+
+impl ExportFiles {
+    pub fn is_empty(&self) -> bool {
+        self.json.is_none() && self.csv.is_none() && !self.needs_full_report()
+    }
+
+    // Formats serialized in one piece from the complete report
+    fn needs_full_report(&self) -> bool {
+        self.xlsx.is_some() || self.yaml.is_some() || self.cbor.is_some() || self.msgpack.is_some() || self.html.is_some()
+    }
+}
+
+/// Read a report exported as JSON, YAML, CBOR or MessagePack, by file extension
+pub fn load_report(path: &str) -> Result<PingReport, String> {
+    let data = fs::read(path).map_err(|e| format!("Failed to read {}: {}", path, e))?;
+    let extension = Path::new(path)
+        .extension()
+        .and_then(|e| e.to_str())
+        .unwrap_or("")
+        .to_ascii_lowercase();
+    let report = match extension.as_str() {
+        "yaml" | "yml" => serde_yaml_ng::from_slice(&data).map_err(|e| e.to_string()),
+        "cbor" => ciborium::from_reader(data.as_slice()).map_err(|e| e.to_string()),
+        "msgpack" | "mp" => rmp_serde::from_slice(&data).map_err(|e| e.to_string()),
+        _ => serde_json::from_slice(&data).map_err(|e| e.to_string()),
+    };
+    report
+        .or_else(|e| recover_json(&data).ok_or(e))
+        .map_err(|e| format!("{} is not a readable report: {}", path, e))
+}
+
+/// Write a report as JSON, YAML, CBOR or MessagePack, by file extension
+pub fn save_report(report: &PingReport, path: &str) -> Result<(), String> {
+    let extension = Path::new(path)
+        .extension()
+        .and_then(|e| e.to_str())
+        .unwrap_or("")
+        .to_ascii_lowercase();
+    let data = match extension.as_str() {
+        "yaml" | "yml" => serde_yaml_ng::to_string(report).map(String::into_bytes).map_err(|e| e.to_string()),
+        "cbor" => {
+            let mut data = Vec::new();
+            ciborium::into_writer(report, &mut data).map(|()| data).map_err(|e| e.to_string())
+        }
+        "msgpack" | "mp" => rmp_serde::to_vec_named(report).map_err(|e| e.to_string()),
+        _ => serde_json::to_vec_pretty(report).map_err(|e| e.to_string()),
+    };
+    let data = data.map_err(|e| format!("Failed to serialize {}: {}", path, e))?;
+    write_atomic(path, &data)
+}
+
+/// The report of a run killed while its JSON export was being written, its
+/// temporary file cut off somewhere in the results: the records that made it
+/// to disk, with statistics counted from them and marked incomplete
+fn recover_json(data: &[u8]) -> Option<PingReport> {
+    let text = String::from_utf8_lossy(data);
+    let (head, rows) = text.split_once("\n  \"results\": [")?;
+    let mut report: serde_json::Value = serde_json::from_str(&format!("{}\n  \"results\": []\n}}", head)).ok()?;
+
+    // Records are one per line; the last one may be cut short
+    let rows: Vec<serde_json::Value> = rows
+        .trim_start()
+        .lines()
+        .map_while(|line| serde_json::from_str(line.trim().trim_end_matches(',')).ok())
+        .collect();
+    let results: Vec<PingResult> = serde_json::from_value(rows.clone().into()).ok()?;
+
+    let end = rows.last().map_or(report["timestamp_start"].clone(), |row| row["timestamp"].clone());
+    report["results"] = rows.into();
+    report["timestamp_end"] = end;
+    report["complete"] = false.into();
+    report["statistics"] = serde_json::to_value(statistics_of(&results)).ok()?;
+    serde_json::from_value(report).ok()
+}
+
+/// Statistics of saved results, counted as the run counted them
+pub fn statistics_of(results: &[PingResult]) -> PingStatistics {
+    let mut rtt = RttStats::new();
+    let mut sent = 0;
+    for result in results {
+        if result.duplicate {
+            rtt.add_duplicate();
+            continue;
+        }
+        sent += 1;
+        match result.rtt_ms {
+            Some(ms) => rtt.add(ms),
+            None => rtt.add_failure(result.status),
+        }
+        if result.reordered {
+            rtt.add_reordered();
+        }
+        if result.corrupted {
+            rtt.add_payload_corrupted();
+        }
+    }
+    rtt.statistics(sent)
+}
+
+/// Short random identifier distinguishing runs started in the same second
+pub fn new_run_id() -> String {
+    use std::collections::hash_map::RandomState;
+    use std::hash::{BuildHasher, Hasher};
+
+    let mut hasher = RandomState::new().build_hasher();
+    hasher.write_u32(std::process::id());
+    format!("{:08x}", hasher.finish() as u32)
+}
+
+/// Make a value safe to embed in a file name
+pub fn sanitize(value: &str) -> String {
+    value
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '.' || c == '-' { c } else { '_' })
+        .collect()
+}
+
+/// Expand `{host}`, `{ip}`, `{date}`, `{time}` and `{run_id}` in an export path
+pub fn expand_path(
+    template: &str,
+    header: &ReportHeader,
+    start: &DateTime<Local>,
+) -> Result<String, String> {
+    let mut out = String::new();
+    let mut rest = template;
+
+    while let Some(open) = rest.find('{') {
+        out.push_str(&rest[..open]);
+        let close = rest[open..]
+            .find('}')
+            .ok_or_else(|| format!("Unclosed '{{' in export path '{}'", template))?;
+        let value = match &rest[open + 1..open + close] {
+            "host" => sanitize(&header.host),
+            "ip" => sanitize(&header.ip_address),
+            "date" => start.format("%Y-%m-%d").to_string(),
+            "time" => start.format("%H%M%S").to_string(),
+            "run_id" => header.run_id.clone(),
+            other => {
+                return Err(format!(
+                    "Unknown placeholder '{{{}}}' in export path '{}' (use host, ip, date, time, run_id)",
+                    other, template
+                ))
+            }
+        };
+        out.push_str(&value);
+        rest = &rest[open + close + 1..];
+    }
+    out.push_str(rest);
+    Ok(out)
+}
+
+/// Insert `-{host}` before the extension unless the path already names the target
+pub fn with_host_placeholder(template: &str) -> String {
+    if template.contains("{host}") || template.contains("{ip}") {
+        return template.to_string();
+    }
+    let path = Path::new(template);
+    match (path.file_stem(), path.extension()) {
+        (Some(stem), Some(ext)) => path
+            .with_file_name(format!("{}-{{host}}.{}", stem.to_string_lossy(), ext.to_string_lossy()))
+            .to_string_lossy()
+            .into_owned(),
+        _ => format!("{}-{{host}}", template),
+    }
+}
+
+/// Operating system and architecture the reports record, e.g. "linux x86_64"
+pub fn platform() -> String {
+    format!("{} {}", std::env::consts::OS, std::env::consts::ARCH)
+}
+
+/// A command line as it would be typed, arguments with spaces quoted
+fn shell_line(args: &[String]) -> String {
+    args.iter()
+        .map(|arg| if arg.is_empty() || arg.contains(char::is_whitespace) { format!("'{}'", arg) } else { arg.clone() })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Hidden per-process temporary path in the same directory as `filename`
+fn temp_path(filename: &str) -> PathBuf {
+    let path = Path::new(filename);
+    let name = path
+        .file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    path.with_file_name(format!(".{}.{}.tmp", name, std::process::id()))
+}
+
+/// Where a streamed export goes
+enum Destination {
+    // Hidden temporary file, moved over the target when closed
+    File(PathBuf, BufWriter<File>),
+    Stdout(io::Stdout),
+}
+
+struct Output {
+    filename: String,
+    destination: Destination,
+    records: usize,
+}
+
+impl Output {
+    fn create(filename: &str) -> Result<Self, String> {
+        let temp = temp_path(filename);
+        let file = File::create(&temp)
+            .map_err(|e| format!("Failed to create file '{}': {}", filename, e))?;
+        Ok(Output {
+            filename: filename.to_string(),
+            destination: Destination::File(temp, BufWriter::new(file)),
+            records: 0,
+        })
+    }
+
+    /// The file of a streamed export, or stdout for `-`
+    fn open(filename: &str) -> Result<Self, String> {
+        if filename != STDOUT {
+            return Output::create(filename);
+        }
+        Ok(Output { filename: "stdout".to_string(), destination: Destination::Stdout(io::stdout()), records: 0 })
+    }
+
+    fn writer(&mut self) -> &mut dyn Write {
+        match &mut self.destination {
+            Destination::File(_, file) => file,
+            Destination::Stdout(stdout) => stdout,
+        }
+    }
+
+    fn write_bytes(&mut self, data: &[u8]) -> Result<(), String> {
+        let filename = self.filename.clone();
+        self.writer()
+            .write_all(data)
+            .map_err(|e| format!("Failed to write to file '{}': {}", filename, e))
+    }
+
+    /// Close the temporary file and move it over the target; stdout is only flushed
+    fn commit(mut self) -> Result<String, String> {
+        self.flush(true)?;
+        let Output { filename, destination, .. } = self;
+        if let Destination::File(temp, file) = destination {
+            drop(file);
+            fs::rename(&temp, &filename)
+                .map_err(|e| format!("Failed to move '{}' to '{}': {}", temp.display(), filename, e))?;
+        }
+        Ok(filename)
+    }
+
+    fn write(&mut self, text: &str) -> Result<(), String> {
+        self.write_bytes(text.as_bytes())
+    }
+
+    fn flush(&mut self, sync: bool) -> Result<(), String> {
+        self.writer()
+            .flush()
+            .map_err(|e| format!("Failed to write to file '{}': {}", self.filename, e))?;
+        if let (true, Destination::File(_, file)) = (sync, &self.destination) {
+            file.get_ref()
+                .sync_data()
+                .map_err(|e| format!("Failed to sync file '{}': {}", self.filename, e))?;
+        }
+        Ok(())
+    }
+}
+
+/// Streams results to the requested export files as they arrive
+pub struct ExportWriter {
+    header: ReportHeader,
+    json: Option<Output>,
+    csv: Option<Output>,
+    csv_format: CsvFormat,
+    // Whole-file formats are built in memory and written when the run ends
+    xlsx: Option<String>,
+    yaml: Option<String>,
+    cbor: Option<String>,
+    msgpack: Option<String>,
+    html: Option<String>,
+    keep_results: bool,
+    results: Vec<PingResult>,
+    // Raw results are the streamed records unless only aggregates are kept
+    raw: bool,
+    aggregate: bool,
+    sent: u32,
+    rtt: RttStats,
+    aggregates: Vec<SecondAggregate>,
+    markers: Vec<Marker>,
+    site_switches: Vec<SiteSwitch>,
+    http_phases: Vec<PhaseStats>,
+    size_sweep: Vec<SizeStats>,
+    addresses: Vec<AddressStats>,
+    outages: Vec<Outage>,
+    last_flush: Instant,
+    last_sync: Instant,
+    finished: bool,
+}
+
+fn json_value<T: Serialize + ?Sized>(value: &T) -> Result<String, String> {
+    serde_json::to_string(value).map_err(|e| format!("Failed to serialize JSON: {}", e))
+}
+
+fn result_row(result: &PingResult, raw_rtt: bool, format: &CsvFormat) -> String {
+    let mut fields = vec![
+        result.seq.to_string(),
+        format.ms(result.rtt_ms),
+        result.success().to_string(),
+        result
+            .timestamp
+            .map(|ts| ts.format(TIMESTAMP_FORMAT).to_string())
+            .unwrap_or_default(),
+        result.status.name().to_string(),
+        result.status.code().map_or(String::new(), |code| code.to_string()),
+        result.ttl.map_or(String::new(), |ttl| ttl.to_string()),
+    ];
+    if raw_rtt {
+        fields.push(format.ms(result.raw_rtt_ms));
+    }
+    fields.extend([result.duplicate, result.reordered, result.corrupted].map(|flag| flag.to_string()));
+    format.row(&fields)
+}
+
+fn aggregate_row(agg: &SecondAggregate, format: &CsvFormat) -> String {
+    format.row(&[
+        agg.second.to_string(),
+        agg.timestamp.to_string(),
+        agg.sent.to_string(),
+        agg.received.to_string(),
+        agg.lost.to_string(),
+        format.number(agg.loss_percent, 2),
+        format.ms(agg.min_ms),
+        format.ms(agg.avg_ms),
+        format.ms(agg.max_ms),
+    ])
+}
+
+const RESULT_COLUMNS: &str = "seq,rtt_ms,success,timestamp,status,icmp_code,ttl";
+const AGGREGATE_COLUMNS: &str =
+    "second,timestamp,sent,received,lost,loss_percent,min_ms,avg_ms,max_ms\n";
+
+impl ExportWriter {
+    /// Create the export files and write everything known up front
+    pub fn create(
+        header: &ReportHeader,
+        files: &ExportFiles,
+        aggregate: bool,
+        raw_samples: bool,
+    ) -> Result<Self, String> {
+        let raw = !aggregate || raw_samples;
+
+        let json = match files.json.as_deref() {
+            Some(filename) => {
+                let mut out = Output::open(filename)?;
+                let overhead = match header.overhead_ms {
+                    Some(ms) => format!(
+                        "\n  \"overhead_ms\": {},{}",
+                        json_value(&ms)?,
+                        if header.overhead_subtracted { "\n  \"overhead_subtracted\": true," } else { "" }
+                    ),
+                    None => String::new(),
+                };
+                // How the probes were sent, when chosen
+                let mut sending = match header.ttl {
+                    Some(ttl) => format!("\n  \"ttl\": {},", ttl),
+                    None => String::new(),
+                };
+                for (name, value) in [("interface", &header.interface), ("source_address", &header.source_address)] {
+                    if let Some(value) = value {
+                        sending.push_str(&format!("\n  \"{}\": {},", name, json_value(value)?));
+                    }
+                }
+                out.write(&format!(
+                    "{{\n  \"schema_version\": {},\n  \"tool_version\": {},\n  \"os\": {},\n  \"command_line\": {},\n  \"run_id\": {},\n  \"host\": {},\n  \"ip_address\": {},\n  \"timestamp_start\": {},\n  \"timeout_ms\": {},\n  \"interval_seconds\": {},{}{}\n  \"{}\": [",
+                    SCHEMA_VERSION,
+                    json_value(env!("CARGO_PKG_VERSION"))?,
+                    json_value(&platform())?,
+                    json_value(&header.command_line)?,
+                    json_value(&header.run_id)?,
+                    json_value(&header.host)?,
+                    json_value(&header.ip_address)?,
+                    json_value(&header.timestamp_start)?,
+                    json_value(&header.timeout_ms)?,
+                    json_value(&header.interval_seconds)?,
+                    overhead,
+                    sending,
+                    if raw { "results" } else { "aggregates" },
+                ))?;
+                Some(out)
+            }
+            None => None,
+        };
+
+        let csv = match files.csv.as_deref() {
+            Some(filename) => {
+                let mut out = Output::open(filename)?;
+                let overhead = match header.overhead_ms {
+                    Some(ms) if header.overhead_subtracted => format!("# Local overhead: {:.3} ms, subtracted from rtt_ms\n", ms),
+                    Some(ms) => format!("# Local overhead: {:.3} ms\n", ms),
+                    None => String::new(),
+                };
+                let mut sending = header.ttl.map_or(String::new(), |ttl| format!("# TTL: {}\n", ttl));
+                if let Some(interface) = &header.interface {
+                    sending.push_str(&format!("# Interface: {}\n", interface));
+                }
+                if let Some(source) = &header.source_address {
+                    sending.push_str(&format!("# Source: {}\n", source));
+                }
+                let columns = match (raw, header.overhead_subtracted) {
+                    (true, true) => format!("{},raw_rtt_ms,duplicate,reordered,corrupted\n", RESULT_COLUMNS),
+                    (true, false) => format!("{},duplicate,reordered,corrupted\n", RESULT_COLUMNS),
+                    (false, _) => AGGREGATE_COLUMNS.to_string(),
+                };
+                let columns = files.csv_format.columns(&columns);
+                if files.csv_format.strict {
+                    out.write(&columns)?;
+                } else {
+                    out.write(&format!(
+                        "# Ping Report\n# Tool: rust_ping {} on {}, schema {}\n# Command: {}\n# Run: {}\n# Host: {}\n# IP: {}\n# Generated: {}\n# Timeout: {} ms\n{}{}#\n{}",
+                        env!("CARGO_PKG_VERSION"),
+                        platform(),
+                        SCHEMA_VERSION,
+                        shell_line(&header.command_line),
+                        header.run_id,
+                        header.host,
+                        header.ip_address,
+                        Local::now().format("%Y-%m-%d %H:%M:%S"),
+                        header.timeout_ms,
+                        overhead,
+                        sending,
+                        columns,
+                    ))?;
+                }
+                Some(out)
+            }
+            None => None,
+        };
+
+        let mut writer = ExportWriter {
+            header: header.clone(),
+            json,
+            csv,
+            csv_format: files.csv_format,
+            xlsx: files.xlsx.clone(),
+            yaml: files.yaml.clone(),
+            cbor: files.cbor.clone(),
+            msgpack: files.msgpack.clone(),
+            html: files.html.clone(),
+            keep_results: files.needs_full_report(),
+            results: Vec::new(),
+            raw,
+            aggregate,
+            sent: 0,
+            rtt: RttStats::new(),
+            aggregates: Vec::new(),
+            markers: Vec::new(),
+            site_switches: Vec::new(),
+            http_phases: Vec::new(),
+            size_sweep: Vec::new(),
+            addresses: Vec::new(),
+            outages: Vec::new(),
+            last_flush: Instant::now(),
+            last_sync: Instant::now(),
+            finished: false,
+        };
+        writer.flush(true)?;
+        Ok(writer)
+    }
+
+    fn flush(&mut self, sync: bool) -> Result<(), String> {
+        for out in [self.json.as_mut(), self.csv.as_mut()].into_iter().flatten() {
+            out.flush(sync)?;
+        }
+        Ok(())
+    }
+
+    fn maybe_flush(&mut self) -> Result<(), String> {
+        if self.last_sync.elapsed() >= SYNC_INTERVAL {
+            self.flush(true)?;
+            self.last_sync = Instant::now();
+            self.last_flush = self.last_sync;
+        } else if self.last_flush.elapsed() >= FLUSH_INTERVAL {
+            self.flush(false)?;
+            self.last_flush = Instant::now();
+        }
+        Ok(())
+    }
+
+    fn append_json(&mut self, record: String) -> Result<(), String> {
+        if let Some(out) = self.json.as_mut() {
+            let sep = if out.records == 0 { "" } else { "," };
+            out.write(&format!("{}\n    {}", sep, record))?;
+            out.records += 1;
+        }
+        Ok(())
+    }
+
+    fn append_csv(&mut self, row: String) -> Result<(), String> {
+        if let Some(out) = self.csv.as_mut() {
+            out.write(&row)?;
+            out.records += 1;
+        }
+        Ok(())
+    }
+
+    /// Record the outcome of one probe
+    pub fn record_result(&mut self, result: &PingResult) -> Result<(), String> {
+        self.sent += 1;
+        match result.rtt_ms {
+            Some(rtt) => self.rtt.add(rtt),
+            None => self.rtt.add_failure(result.status),
+        }
+        if result.reordered {
+            self.rtt.add_reordered();
+        }
+        if result.corrupted {
+            self.rtt.add_payload_corrupted();
+        }
+        if self.raw {
+            self.append_json(json_value(result)?)?;
+            self.append_csv(result_row(result, self.header.overhead_subtracted, &self.csv_format))?;
+            if self.keep_results {
+                self.results.push(result.clone());
+            }
+        }
+        self.maybe_flush()
+    }
+
+    /// Record a further reply to a probe recorded already, which isn't counted as sent
+    pub fn record_duplicate(&mut self, result: &PingResult) -> Result<(), String> {
+        self.rtt.add_duplicate();
+        if self.raw {
+            self.append_json(json_value(result)?)?;
+            self.append_csv(result_row(result, self.header.overhead_subtracted, &self.csv_format))?;
+            if self.keep_results {
+                self.results.push(result.clone());
+            }
+        }
+        self.maybe_flush()
+    }
+
+    /// Count a reply discarded for a bad checksum
+    pub fn record_corrupted(&mut self) {
+        self.rtt.add_corrupted();
+    }
+
+    /// Keep an event marker for the end of the report
+    pub fn record_marker(&mut self, marker: &Marker) {
+        self.markers.push(marker.clone());
+    }
+
+    /// Keep a likely anycast site switch for the end of the report
+    pub fn record_site_switch(&mut self, switch: &SiteSwitch) {
+        self.site_switches.push(switch.clone());
+    }
+
+    /// Time spent in each phase of the HTTP requests, once the run is over
+    pub fn record_http_phases(&mut self, phases: Vec<PhaseStats>) {
+        self.http_phases = phases;
+    }
+
+    /// RTT and loss of each payload size of a sweep, once the run is over
+    pub fn record_size_sweep(&mut self, sizes: Vec<SizeStats>) {
+        self.size_sweep = sizes;
+    }
+
+    /// Statistics of each address of a re-resolved host, once the run is over
+    pub fn record_addresses(&mut self, addresses: Vec<AddressStats>) {
+        self.addresses = addresses;
+    }
+
+    /// Every time the target was down, once the run is over
+    pub fn record_outages(&mut self, outages: Vec<Outage>) {
+        self.outages = outages;
+    }
+
+    /// Record a completed per-second summary
+    pub fn record_aggregate(&mut self, agg: &SecondAggregate) -> Result<(), String> {
+        // Kept for the trailing section / workbook sheet
+        self.aggregates.push(agg.clone());
+        if !self.raw {
+            self.append_json(json_value(agg)?)?;
+            self.append_csv(aggregate_row(agg, &self.csv_format))?;
+        }
+        self.maybe_flush()
+    }
+
+    fn full_report(&self, timestamp_end: &str, complete: bool, stats: &PingStatistics) -> PingReport {
+        PingReport {
+            schema_version: SCHEMA_VERSION,
+            tool_version: env!("CARGO_PKG_VERSION").to_string(),
+            os: platform(),
+            command_line: self.header.command_line.clone(),
+            run_id: self.header.run_id.clone(),
+            host: self.header.host.clone(),
+            ip_address: self.header.ip_address.clone(),
+            timestamp_start: self.header.timestamp_start.clone(),
+            timeout_ms: self.header.timeout_ms,
+            interval_seconds: self.header.interval_seconds,
+            overhead_ms: self.header.overhead_ms,
+            overhead_subtracted: self.header.overhead_subtracted,
+            ttl: self.header.ttl,
+            interface: self.header.interface.clone(),
+            source_address: self.header.source_address.clone(),
+            results: self.results.clone(),
+            aggregates: if self.aggregate { Some(self.aggregates.clone()) } else { None },
+            markers: self.markers.clone(),
+            site_switches: self.site_switches.clone(),
+            http_phases: self.http_phases.clone(),
+            size_sweep: self.size_sweep.clone(),
+            addresses: self.addresses.clone(),
+            outages: self.outages.clone(),
+            timestamp_end: timestamp_end.to_string(),
+            complete,
+            statistics: stats.clone(),
+        }
+    }
+
+    fn write_trailer(&mut self, timestamp_end: &str, complete: bool) -> Result<(), String> {
+        let stats = self.rtt.statistics(self.sent);
+
+        if let Some(filename) = &self.xlsx {
+            let aggregates = if self.aggregate { Some(self.aggregates.as_slice()) } else { None };
+            let data = xlsx::build_workbook(
+                &self.header,
+                timestamp_end,
+                complete,
+                &self.results,
+                aggregates,
+                &stats,
+            )?;
+            write_atomic(filename, &data)?;
+        }
+
+        if let Some(filename) = &self.yaml {
+            let report = self.full_report(timestamp_end, complete, &stats);
+            let yaml = serde_yaml_ng::to_string(&report)
+                .map_err(|e| format!("Failed to serialize YAML: {}", e))?;
+            write_atomic(filename, yaml.as_bytes())?;
+        }
+
+        if let Some(filename) = &self.cbor {
+            let report = self.full_report(timestamp_end, complete, &stats);
+            let mut data = Vec::new();
+            ciborium::into_writer(&report, &mut data)
+                .map_err(|e| format!("Failed to serialize CBOR: {}", e))?;
+            write_atomic(filename, &data)?;
+        }
+
+        if let Some(filename) = &self.msgpack {
+            let report = self.full_report(timestamp_end, complete, &stats);
+            let data = rmp_serde::to_vec_named(&report)
+                .map_err(|e| format!("Failed to serialize MessagePack: {}", e))?;
+            write_atomic(filename, &data)?;
+        }
+
+        if let Some(filename) = &self.html {
+            let report = self.full_report(timestamp_end, complete, &stats);
+            write_atomic(filename, report::html_page(&report, &report::probes(&report)).as_bytes())?;
+        }
+
+        if self.json.is_some() {
+            let mut trailer = String::from("\n  ],\n");
+            if self.raw && self.aggregate {
+                let rows: Vec<String> = self
+                    .aggregates
+                    .iter()
+                    .map(json_value)
+                    .collect::<Result<_, _>>()?;
+                trailer.push_str(&format!(
+                    "  \"aggregates\": [\n    {}\n  ],\n",
+                    rows.join(",\n    ")
+                ));
+            }
+            if !self.markers.is_empty() {
+                let rows: Vec<String> = self.markers.iter().map(json_value).collect::<Result<_, _>>()?;
+                trailer.push_str(&format!("  \"markers\": [\n    {}\n  ],\n", rows.join(",\n    ")));
+            }
+            if !self.site_switches.is_empty() {
+                let rows: Vec<String> = self.site_switches.iter().map(json_value).collect::<Result<_, _>>()?;
+                trailer.push_str(&format!("  \"site_switches\": [\n    {}\n  ],\n", rows.join(",\n    ")));
+            }
+            if !self.http_phases.is_empty() {
+                let rows: Vec<String> = self.http_phases.iter().map(json_value).collect::<Result<_, _>>()?;
+                trailer.push_str(&format!("  \"http_phases\": [\n    {}\n  ],\n", rows.join(",\n    ")));
+            }
+            if !self.size_sweep.is_empty() {
+                let rows: Vec<String> = self.size_sweep.iter().map(json_value).collect::<Result<_, _>>()?;
+                trailer.push_str(&format!("  \"size_sweep\": [\n    {}\n  ],\n", rows.join(",\n    ")));
+            }
+            if !self.addresses.is_empty() {
+                let rows: Vec<String> = self.addresses.iter().map(json_value).collect::<Result<_, _>>()?;
+                trailer.push_str(&format!("  \"addresses\": [\n    {}\n  ],\n", rows.join(",\n    ")));
+            }
+            if !self.outages.is_empty() {
+                let rows: Vec<String> = self.outages.iter().map(json_value).collect::<Result<_, _>>()?;
+                trailer.push_str(&format!("  \"outages\": [\n    {}\n  ],\n", rows.join(",\n    ")));
+            }
+            let stats_json = serde_json::to_string_pretty(&stats)
+                .map_err(|e| format!("Failed to serialize JSON: {}", e))?
+                .replace('\n', "\n  ");
+            trailer.push_str(&format!(
+                "  \"timestamp_end\": {},\n  \"complete\": {},\n  \"statistics\": {}\n}}\n",
+                json_value(timestamp_end)?,
+                complete,
+                stats_json
+            ));
+            if let Some(out) = self.json.as_mut() {
+                out.write(&trailer)?;
+            }
+        }
+
+        // A strict CSV is the records alone
+        if self.csv.is_some() && !self.csv_format.strict {
+            let mut trailer = String::new();
+            if self.raw && self.aggregate && !self.aggregates.is_empty() {
+                trailer.push_str("\n# Per-second aggregates\n");
+                trailer.push_str(&self.csv_format.columns(AGGREGATE_COLUMNS));
+                for agg in &self.aggregates {
+                    trailer.push_str(&aggregate_row(agg, &self.csv_format));
+                }
+            }
+            if !self.outages.is_empty() {
+                trailer.push_str("\n# Outages\n");
+                trailer.push_str(&self.csv_format.columns("start,end,duration_seconds,first_seq,lost\n"));
+                for outage in &self.outages {
+                    trailer.push_str(&self.csv_format.row(&[
+                        outage.start.clone(),
+                        outage.end.clone().unwrap_or_default(),
+                        self.csv_format.number(outage.duration_seconds, 3),
+                        outage.first_seq.to_string(),
+                        outage.lost.to_string(),
+                    ]));
+                }
+            }
+            trailer.push_str(&stats_section(&stats, complete, &self.csv_format));
+            if let Some(out) = self.csv.as_mut() {
+                out.write(&trailer)?;
+            }
+        }
+
+        self.flush(true)
+    }
+
+    /// Write the statistics and move the report files into place.
+    /// Returns the format and name of every file written.
+    pub fn finish(mut self, timestamp_end: &str) -> Result<Vec<(&'static str, String)>, String> {
+        self.finished = true;
+        self.write_trailer(timestamp_end, true)?;
+
+        let mut written = Vec::new();
+        if let Some(out) = self.json.take() {
+            written.push(("JSON", out.commit()?));
+        }
+        if let Some(out) = self.csv.take() {
+            written.push(("CSV", out.commit()?));
+        }
+        let whole_files = [
+            ("XLSX", &self.xlsx),
+            ("YAML", &self.yaml),
+            ("CBOR", &self.cbor),
+            ("MessagePack", &self.msgpack),
+            ("HTML", &self.html),
+        ];
+        for (format, filename) in whole_files {
+            if let Some(filename) = filename {
+                written.push((format, filename.clone()));
+            }
+        }
+        Ok(written)
+    }
+}
+
+impl Drop for ExportWriter {
+    // Reached without finish() when the run is aborted by a panic or error
+    fn drop(&mut self) {
+        if !self.finished {
+            let timestamp_end = Local::now().format("%Y-%m-%d %H:%M:%S").to_string();
+            if self.write_trailer(&timestamp_end, false).is_ok() {
+                for out in [self.json.take(), self.csv.take()].into_iter().flatten() {
+                    let _ = out.commit();
+                }
+            }
+        }
+    }
+}
+
+/// Write a complete file in one go through a temporary file
+pub fn write_atomic(filename: &str, data: &[u8]) -> Result<(), String> {
+    let mut out = Output::create(filename)?;
+    out.write_bytes(data)?;
+    out.commit().map(|_| ())
+}
+
+/// Columns of the statistics of a target in CSV
+pub const STATS_COLUMNS: &str = "packets_sent,packets_received,packets_lost,loss_percent,min_ms,avg_ms,max_ms,std_dev_ms,p50_ms,p95_ms,p99_ms,packets_corrupted,packets_timeout,packets_unreachable,packets_ttl_exceeded,packets_send_error,packets_parameter_problem,jitter_ms,delta_avg_ms,delta_max_ms,packets_duplicate,packets_reordered,packets_payload_corrupted,longest_loss_streak,loss_bursts";
+
+/// The statistics of a target as a CSV row of [`STATS_COLUMNS`], loss bursts
+/// as "length:count" pairs separated by spaces
+pub fn stats_values(stats: &PingStatistics, format: &CsvFormat) -> String {
+    format.join(&[
+        stats.packets_sent.to_string(),
+        stats.packets_received.to_string(),
+        stats.packets_lost.to_string(),
+        format.number(stats.packet_loss_percent, 2),
+        format.ms(stats.min_ms),
+        format.ms(stats.avg_ms),
+        format.ms(stats.max_ms),
+        format.ms(stats.std_dev_ms),
+        format.ms(stats.p50_ms),
+        format.ms(stats.p95_ms),
+        format.ms(stats.p99_ms),
+        stats.packets_corrupted.to_string(),
+        stats.packets_timeout.to_string(),
+        stats.packets_unreachable.to_string(),
+        stats.packets_ttl_exceeded.to_string(),
+        stats.packets_send_error.to_string(),
+        stats.packets_parameter_problem.to_string(),
+        format.ms(stats.jitter_ms),
+        format.ms(stats.delta_avg_ms),
+        format.ms(stats.delta_max_ms),
+        stats.packets_duplicate.to_string(),
+        stats.packets_reordered.to_string(),
+        stats.packets_payload_corrupted.to_string(),
+        stats.longest_loss_streak.to_string(),
+        stats.loss_bursts.iter().map(|(length, n)| format!("{}:{}", length, n)).collect::<Vec<_>>().join(" "),
+    ])
+}
+
+fn stats_section(stats: &PingStatistics, complete: bool, format: &CsvFormat) -> String {
+    format!(
+        "\n# Statistics{}\n# {}\n{}\n",
+        if complete { "" } else { " (incomplete run)" },
+        format.columns(STATS_COLUMNS),
+        stats_values(stats, format)
+    )
+}
@@ -0,0 +1,42 @@
+//! Fixed-size history of recent probes for on-screen graphs.
+//!
+//! Exports and live sinks receive every result as it happens; what is drawn
+//! only needs the most recent ones, so the oldest entry is dropped once the
+//! history is full and memory stays flat however long a run lasts.
+
+use std::collections::VecDeque;
+
+#[derive(Clone, Debug)]
+pub struct History<T> {
+    entries: VecDeque<T>,
+    capacity: usize,
+}
+
+impl<T> History<T> {
+    /// Keep at most `capacity` entries (at least one)
+    pub fn new(capacity: usize) -> Self {
+        // Grown on demand, a short run never pays for the full capacity
+        History { entries: VecDeque::new(), capacity: capacity.max(1) }
+    }
+
+    /// Append, dropping the oldest entry when full
+    pub fn push(&mut self, entry: T) {
+        if self.entries.len() == self.capacity {
+            self.entries.pop_front();
+        }
+        self.entries.push_back(entry);
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Oldest first
+    pub fn iter(&self) -> impl Iterator<Item = &T> + '_ {
+        self.entries.iter()
+    }
+}
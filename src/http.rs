@@ -0,0 +1,300 @@
+//! HTTP(S) probes.
+//!
+//! With `--http` every HOST is a URL, and each probe is a GET (HEAD with
+//! `--head`) on a new connection, timed the way curl does: the name lookup,
+//! the TCP connect, the TLS handshake and the time from sending the request
+//! to the first byte of the response. Together they make the probe's RTT,
+//! so statistics, graphs, exports and sinks treat a web endpoint as they do
+//! a host. Any response counts as a reply, whatever its status. A probe whose
+//! name doesn't resolve or whose connection or handshake fails is a send
+//! error; one without a byte of response within the timeout has timed out.
+
+use colored::*;
+use comfy_table::modifiers::UTF8_ROUND_CORNERS;
+use comfy_table::presets::UTF8_FULL_CONDENSED;
+use comfy_table::{Cell, ContentArrangement, Table};
+use rustls::pki_types::ServerName;
+use std::collections::VecDeque;
+use std::io::{self, Read, Write};
+use std::net::{IpAddr, SocketAddr, TcpStream, ToSocketAddrs};
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime};
+use ureq::http::Uri;
+use rust_ping::PhaseStats;
+
+use crate::stats::RttStats;
+use crate::summary::ms_cell;
+use crate::transport::{Reply, ECHO_REPLY};
+
+/// Where a URL's requests go
+pub struct Endpoint {
+    tls: bool,
+    /// Host name or address, without the brackets of an IPv6 literal
+    pub host: String,
+    port: u16,
+    /// Value of the Host header
+    authority: String,
+    path: String,
+}
+
+impl Endpoint {
+    pub fn parse(url: &str) -> Result<Self, String> {
+        let invalid = |reason: &str| format!("Invalid URL '{}': {}", url, reason);
+        let uri: Uri = url.parse().map_err(|e| invalid(&format!("{}", e)))?;
+        let tls = match uri.scheme_str() {
+            Some("https") => true,
+            Some("http") => false,
+            _ => return Err(invalid("use http:// or https://")),
+        };
+        let authority = uri.authority().ok_or_else(|| invalid("no host"))?;
+        Ok(Endpoint {
+            tls,
+            host: authority.host().trim_start_matches('[').trim_end_matches(']').to_string(),
+            port: authority.port_u16().unwrap_or(if tls { 443 } else { 80 }),
+            authority: authority.as_str().rsplit('@').next().unwrap_or_default().to_string(),
+            path: uri.path_and_query().map_or("/".to_string(), |p| p.as_str().to_string()),
+        })
+    }
+}
+
+/// Time spent in each phase of a probe
+struct Phases {
+    dns: Duration,
+    connect: Duration,
+    tls: Option<Duration>,
+    first_byte: Duration,
+}
+
+/// Sends the requests of one target and answers its probes with their outcome
+pub struct Prober {
+    endpoint: Endpoint,
+    ipv6: bool,
+    head: bool,
+    timeout: Duration,
+    identifier: u16,
+    tls: Arc<rustls::ClientConfig>,
+    replies: VecDeque<Reply>,
+    // DNS, connect, TLS and time to first byte of the probes that got a response
+    phases: [RttStats; 4],
+}
+
+impl Prober {
+    /// Requests to `url`, connecting to addresses of the same family as `addr`
+    pub fn new(url: &str, addr: IpAddr, head: bool, timeout: Duration, identifier: u16) -> Result<Self, String> {
+        let roots = rustls::RootCertStore { roots: webpki_roots::TLS_SERVER_ROOTS.to_vec() };
+        // Spelled out, as more than one crypto provider is linked in
+        let tls = rustls::ClientConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
+            .with_safe_default_protocol_versions()
+            .map_err(|e| format!("TLS setup failed: {}", e))?
+            .with_root_certificates(roots)
+            .with_no_client_auth();
+        Ok(Prober {
+            endpoint: Endpoint::parse(url)?,
+            ipv6: addr.is_ipv6(),
+            head,
+            timeout,
+            identifier,
+            tls: Arc::new(tls),
+            replies: VecDeque::new(),
+            phases: std::array::from_fn(|_| RttStats::new()),
+        })
+    }
+
+    /// Make one request per probe; errors before the request was sent fail the probe
+    pub fn send_batch(&mut self, packets: &[Vec<u8>]) -> (usize, Option<io::Error>) {
+        for (i, packet) in packets.iter().enumerate() {
+            match self.request() {
+                Ok(Some((source, phases))) => {
+                    self.replies.push_back(Reply {
+                        source,
+                        icmp_type: ECHO_REPLY,
+                        code: 0,
+                        identifier: self.identifier,
+                        sequence: packet.get(6..8).map(|b| u16::from_be_bytes([b[0], b[1]])),
+                        checksum_ok: true,
+                        received: SystemTime::now(),
+                        ttl: None,
+                        mtu: None,
+                        payload: None,
+                    });
+                    let times = [Some(phases.dns), Some(phases.connect), phases.tls, Some(phases.first_byte)];
+                    for (stats, time) in self.phases.iter_mut().zip(times) {
+                        if let Some(time) = time {
+                            stats.add(time.as_secs_f64() * 1000.0);
+                        }
+                    }
+                }
+                Ok(None) => {}
+                Err(e) => return (i, Some(e)),
+            }
+        }
+        (packets.len(), None)
+    }
+
+    /// The outcome of the request made last, which is already known
+    pub fn next_with_timeout(&mut self) -> io::Result<Option<Reply>> {
+        Ok(self.replies.pop_front())
+    }
+
+    /// Address that answered and the time of each phase; `None` when no response came in time
+    fn request(&self) -> io::Result<Option<(IpAddr, Phases)>> {
+        let started = Instant::now();
+        let endpoint = &self.endpoint;
+        let addr = (endpoint.host.as_str(), endpoint.port)
+            .to_socket_addrs()
+            .map_err(|e| phase_error("DNS", e))?
+            .find(|addr| addr.is_ipv6() == self.ipv6)
+            .ok_or_else(|| phase_error("DNS", io::Error::new(io::ErrorKind::NotFound, "no address of this family")))?;
+        let dns = started.elapsed();
+
+        let remaining = || self.timeout.checked_sub(started.elapsed()).filter(|d| !d.is_zero());
+        let Some(wait) = remaining() else {
+            return Ok(None);
+        };
+        let mut tcp = match TcpStream::connect_timeout(&addr, wait) {
+            Ok(tcp) => tcp,
+            Err(e) if e.kind() == io::ErrorKind::TimedOut => return Ok(None),
+            Err(e) => return Err(phase_error("connect", e)),
+        };
+        let connect = started.elapsed() - dns;
+
+        let request = format!(
+            "{} {} HTTP/1.1\r\nHost: {}\r\nUser-Agent: rust_ping/{}\r\nAccept: */*\r\nConnection: close\r\n\r\n",
+            if self.head { "HEAD" } else { "GET" },
+            endpoint.path,
+            endpoint.authority,
+            env!("CARGO_PKG_VERSION")
+        );
+        let mut byte = [0u8; 1];
+        let (tls, first_byte) = if endpoint.tls {
+            let name = ServerName::try_from(endpoint.host.clone()).map_err(|e| phase_error("TLS", e))?;
+            let mut conn = rustls::ClientConnection::new(Arc::clone(&self.tls), name).map_err(|e| phase_error("TLS", e))?;
+            while conn.is_handshaking() {
+                match set_timeouts(&tcp, remaining()).and_then(|_| conn.complete_io(&mut tcp)) {
+                    Ok(_) => {}
+                    Err(e) if timed_out(&e) => return Ok(None),
+                    Err(e) => return Err(phase_error("TLS", e)),
+                }
+            }
+            let tls = started.elapsed() - dns - connect;
+            let timeouts = tcp.try_clone()?;
+            let mut stream = rustls::StreamOwned::new(conn, tcp);
+            match exchange(&mut stream, &request, &mut byte, || set_timeouts(&timeouts, remaining())) {
+                Ok(time) => (Some(tls), time),
+                Err(e) if timed_out(&e) => return Ok(None),
+                Err(e) => return Err(e),
+            }
+        } else {
+            let timeouts = tcp.try_clone()?;
+            match exchange(&mut tcp, &request, &mut byte, || set_timeouts(&timeouts, remaining())) {
+                Ok(time) => (None, time),
+                Err(e) if timed_out(&e) => return Ok(None),
+                Err(e) => return Err(e),
+            }
+        };
+        Ok(Some((addr.ip(), Phases { dns, connect, tls, first_byte })))
+    }
+
+    /// How long each phase took over the run
+    pub fn phases(&self) -> Vec<PhaseStats> {
+        let names = ["DNS", "Connect", "TLS", "First byte"];
+        names
+            .iter()
+            .zip(&self.phases)
+            .filter(|(&name, _)| name != "TLS" || self.endpoint.tls)
+            .map(|(name, stats)| {
+                let summary = stats.statistics(stats.count() as u32);
+                PhaseStats {
+                    phase: name.to_string(),
+                    avg_ms: summary.avg_ms,
+                    p50_ms: summary.p50_ms,
+                    p95_ms: summary.p95_ms,
+                    max_ms: summary.max_ms,
+                }
+            })
+            .collect()
+    }
+}
+
+/// Send the request and wait for the first byte of the response; the time both took
+fn exchange(
+    stream: &mut impl ReadWrite,
+    request: &str,
+    byte: &mut [u8],
+    set_timeouts: impl Fn() -> io::Result<()>,
+) -> io::Result<Duration> {
+    let started = Instant::now();
+    set_timeouts()?;
+    stream
+        .write_all(request.as_bytes())
+        .map_err(|e| if timed_out(&e) { e } else { phase_error("request", e) })?;
+    set_timeouts()?;
+    match stream.read(byte) {
+        Ok(0) => Err(phase_error("response", io::Error::new(io::ErrorKind::UnexpectedEof, "connection closed"))),
+        Ok(_) => Ok(started.elapsed()),
+        Err(e) if timed_out(&e) => Err(e),
+        Err(e) => Err(phase_error("response", e)),
+    }
+}
+
+trait ReadWrite: Read + Write {}
+impl<T: Read + Write> ReadWrite for T {}
+
+/// Limit reads and writes to what is left of the timeout; a timeout error once nothing is left
+fn set_timeouts(tcp: &TcpStream, remaining: Option<Duration>) -> io::Result<()> {
+    let remaining = remaining.ok_or_else(|| io::Error::from(io::ErrorKind::TimedOut))?;
+    tcp.set_read_timeout(Some(remaining))?;
+    tcp.set_write_timeout(Some(remaining))
+}
+
+fn timed_out(e: &io::Error) -> bool {
+    matches!(e.kind(), io::ErrorKind::TimedOut | io::ErrorKind::WouldBlock)
+}
+
+fn phase_error(phase: &str, e: impl std::fmt::Display) -> io::Error {
+    io::Error::other(format!("{}: {}", phase, e))
+}
+
+pub fn print_phases(host: &str, phases: &[PhaseStats]) {
+    println!("\n{}", "╔════════════════════════════════════════════════════════════╗".magenta());
+    println!("{}", "║                     🌐 HTTP PHASES                          ║".magenta());
+    println!("{}", "╚════════════════════════════════════════════════════════════╝".magenta());
+    println!("  URL: {}", host.yellow());
+    if phases.iter().all(|phase| phase.avg_ms.is_none()) {
+        println!("  {}", "No request got a response".yellow());
+        return;
+    }
+
+    let mut table = Table::new();
+    table
+        .load_preset(UTF8_FULL_CONDENSED)
+        .apply_modifier(UTF8_ROUND_CORNERS)
+        .set_content_arrangement(ContentArrangement::Dynamic)
+        .set_header(vec!["Phase", "Avg ms", "p50 ms", "p95 ms", "Max ms"]);
+    for phase in phases {
+        table.add_row(vec![
+            Cell::new(&phase.phase),
+            ms_cell(phase.avg_ms),
+            ms_cell(phase.p50_ms),
+            ms_cell(phase.p95_ms),
+            ms_cell(phase.max_ms),
+        ]);
+    }
+    println!("{}", table);
+}
+
+/// The target's address, for the header and the exports
+pub fn resolve(url: &str, ipv6: bool) -> Result<IpAddr, String> {
+    let endpoint = Endpoint::parse(url)?;
+    let addrs: Vec<SocketAddr> = (endpoint.host.as_str(), endpoint.port)
+        .to_socket_addrs()
+        .map_err(|e| format!("Failed to resolve {}: {}", endpoint.host, e))?
+        .collect();
+    // IPv4 is preferred, as for hosts
+    addrs
+        .iter()
+        .find(|addr| addr.is_ipv6() == ipv6)
+        .or(if ipv6 { None } else { addrs.first() })
+        .map(SocketAddr::ip)
+        .ok_or_else(|| format!("No {} address found for {}", if ipv6 { "IPv6" } else { "IPv4" }, endpoint.host))
+}
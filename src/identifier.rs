@@ -0,0 +1,91 @@
+//! Echo identifiers.
+//!
+//! Raw sockets see the replies to every ping running on the machine, which
+//! are told apart only by the identifier of the echo request. Each target
+//! gets a random identifier instead of the (truncated, reused) PID, and
+//! claims it by creating a file named after it in a directory shared by all
+//! sessions, so two concurrent runs never use the same one. Files left behind
+//! by sessions that died are reclaimed.
+
+use std::collections::hash_map::RandomState;
+use std::fs::{self, OpenOptions};
+use std::hash::{BuildHasher, Hasher};
+use std::io::{ErrorKind, Write};
+use std::path::PathBuf;
+use std::time::SystemTime;
+
+// Random picks before giving up on finding a free identifier
+const ATTEMPTS: usize = 64;
+
+fn registry() -> PathBuf {
+    std::env::temp_dir().join("rust_ping-identifiers")
+}
+
+fn random_identifier() -> u16 {
+    let mut hasher = RandomState::new().build_hasher();
+    hasher.write_u32(std::process::id());
+    if let Ok(now) = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH) {
+        hasher.write_u128(now.as_nanos());
+    }
+    // 0 is what a truncated packet parses as
+    (hasher.finish() as u16).max(1)
+}
+
+/// Whether the session that claimed an identifier is still running
+#[cfg(target_os = "linux")]
+fn alive(pid: u32) -> bool {
+    std::path::Path::new("/proc").join(pid.to_string()).exists()
+}
+
+#[cfg(not(target_os = "linux"))]
+fn alive(_pid: u32) -> bool {
+    true
+}
+
+/// An identifier held for the lifetime of a target's run
+pub struct Identifier {
+    value: u16,
+    // None when the registry isn't writable; the identifier is then only random
+    claim: Option<PathBuf>,
+}
+
+impl Identifier {
+    pub fn claim() -> Result<Self, String> {
+        let dir = registry();
+        if fs::create_dir_all(&dir).is_err() {
+            return Ok(Identifier { value: random_identifier(), claim: None });
+        }
+
+        for _ in 0..ATTEMPTS {
+            let value = random_identifier();
+            let path = dir.join(value.to_string());
+            match OpenOptions::new().write(true).create_new(true).open(&path) {
+                Ok(mut file) => {
+                    let _ = write!(file, "{}", std::process::id());
+                    return Ok(Identifier { value, claim: Some(path) });
+                }
+                Err(e) if e.kind() == ErrorKind::AlreadyExists => {
+                    let owner = fs::read_to_string(&path).ok().and_then(|pid| pid.trim().parse().ok());
+                    if owner.is_some_and(|pid| !alive(pid)) {
+                        // Left behind by a session that died; free for the next attempt
+                        let _ = fs::remove_file(&path);
+                    }
+                }
+                Err(_) => return Ok(Identifier { value, claim: None }),
+            }
+        }
+        Err("No free ICMP identifier (too many concurrent ping sessions?)".to_string())
+    }
+
+    pub fn value(&self) -> u16 {
+        self.value
+    }
+}
+
+impl Drop for Identifier {
+    fn drop(&mut self) {
+        if let Some(path) = &self.claim {
+            let _ = fs::remove_file(path);
+        }
+    }
+}
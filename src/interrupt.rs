@@ -0,0 +1,100 @@
+//! Ending a run with Ctrl+C.
+//!
+//! The first SIGINT or SIGTERM asks the run to stop instead of killing it:
+//! the probes in flight are waited for, later targets are skipped, and the
+//! statistics, graphs and exports cover what was sent so far, as when the
+//! count was reached. A second signal ends the process at once. SIGQUIT
+//! (Ctrl+\) asks for the statistics so far without stopping, as with
+//! iputils ping.
+
+#[cfg(unix)]
+mod signal {
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+    static WATCHING: AtomicBool = AtomicBool::new(false);
+    static REQUESTED: AtomicBool = AtomicBool::new(false);
+    static INTERIM: AtomicUsize = AtomicUsize::new(0);
+
+    /// Take a signal as a request to stop; false when it should end the process instead
+    pub fn record() -> bool {
+        // Only atomics, which are safe in a signal handler
+        WATCHING.load(Ordering::Relaxed) && !REQUESTED.swap(true, Ordering::Relaxed)
+    }
+
+    extern "C" fn handle(signal: libc::c_int) {
+        if !record() {
+            unsafe {
+                libc::signal(signal, libc::SIG_DFL);
+                libc::raise(signal);
+            }
+        }
+    }
+
+    extern "C" fn count_interim(_signal: libc::c_int) {
+        INTERIM.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn watch() -> Result<(), String> {
+        WATCHING.store(true, Ordering::Relaxed);
+        let handler = handle as extern "C" fn(libc::c_int);
+        for signal in [libc::SIGINT, libc::SIGTERM] {
+            if unsafe { libc::signal(signal, handler as libc::sighandler_t) } == libc::SIG_ERR {
+                return Err("Failed to install the SIGINT handler".to_string());
+            }
+        }
+        let handler = count_interim as extern "C" fn(libc::c_int);
+        if unsafe { libc::signal(libc::SIGQUIT, handler as libc::sighandler_t) } == libc::SIG_ERR {
+            return Err("Failed to install the SIGQUIT handler".to_string());
+        }
+        Ok(())
+    }
+
+    pub fn requested() -> bool {
+        REQUESTED.load(Ordering::Relaxed)
+    }
+
+    pub fn interim_requests() -> usize {
+        INTERIM.load(Ordering::Relaxed)
+    }
+}
+
+// Ctrl+C still ends the process right away on Windows
+#[cfg(not(unix))]
+mod signal {
+    pub fn record() -> bool {
+        false
+    }
+
+    pub fn watch() -> Result<(), String> {
+        Ok(())
+    }
+
+    pub fn requested() -> bool {
+        false
+    }
+
+    pub fn interim_requests() -> usize {
+        0
+    }
+}
+
+/// Stop the run on the first SIGINT or SIGTERM from now on
+pub fn watch() -> Result<(), String> {
+    signal::watch()
+}
+
+/// Whether the run was asked to stop
+pub fn requested() -> bool {
+    signal::requested()
+}
+
+/// SIGQUITs received so far; each asks for the statistics of the run so far
+pub fn interim_requests() -> usize {
+    signal::interim_requests()
+}
+
+/// For other signal handlers: take SIGINT or SIGTERM as a request to stop
+/// if that is being watched for and wasn't asked already
+pub fn record() -> bool {
+    signal::record()
+}
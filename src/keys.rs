@@ -0,0 +1,255 @@
+//! Keyboard controls for interactive runs.
+//!
+//! A thread reads single key presses from the terminal while probes keep
+//! going. On Linux the terminal is switched to non-canonical mode without
+//! echo, so keys act immediately; output processing is left alone so the
+//! regular output still renders. Elsewhere a key is followed by Enter.
+//! The dashboard reads the terminal itself and only shares the quit and
+//! pause flags with the ping threads.
+
+use colored::*;
+use std::io::{self, Read, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver};
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Key {
+    /// Stop sending until pressed again
+    Pause,
+    /// Halve the interval
+    Faster,
+    /// Double the interval
+    Slower,
+    /// Start the statistics over
+    Reset,
+    /// Show or hide the bar graph
+    ToggleGraph,
+    /// Show or hide the line graph at the end
+    ToggleLineGraph,
+    /// Record an event marker with the name typed after `m`
+    Mark(String),
+    /// End the run, producing statistics and exports as usual
+    Quit,
+    Help,
+}
+
+impl Key {
+    fn from_byte(byte: u8) -> Option<Self> {
+        match byte {
+            b'p' | b' ' => Some(Key::Pause),
+            b'+' | b'=' => Some(Key::Faster),
+            b'-' | b'_' => Some(Key::Slower),
+            b'r' => Some(Key::Reset),
+            b'g' => Some(Key::ToggleGraph),
+            b'l' => Some(Key::ToggleLineGraph),
+            b'q' => Some(Key::Quit),
+            b'?' | b'h' => Some(Key::Help),
+            _ => None,
+        }
+    }
+}
+
+pub fn print_help() {
+    println!(
+        "  {}",
+        "Keys: p/space pause · +/- faster/slower · r reset stats · g bar graph · l line graph · m marker · q quit".dimmed()
+    );
+}
+
+/// Read a marker name up to Enter, echoing it since the terminal doesn't
+fn read_name(bytes: &mut impl Iterator<Item = u8>) -> String {
+    let mut stdout = io::stdout();
+    let _ = write!(stdout, "  {} ", "Marker name:".cyan());
+    let _ = stdout.flush();
+    let mut name = Vec::new();
+    for byte in bytes.by_ref() {
+        match byte {
+            b'\n' | b'\r' => break,
+            // Backspace and delete
+            0x08 | 0x7f => {
+                if name.pop().is_some() {
+                    let _ = write!(stdout, "\x08 \x08");
+                }
+            }
+            byte => {
+                name.push(byte);
+                let _ = stdout.write_all(&[byte]);
+            }
+        }
+        let _ = stdout.flush();
+    }
+    let _ = writeln!(stdout);
+    let name = String::from_utf8_lossy(&name).trim().to_string();
+    if name.is_empty() { "marker".to_string() } else { name }
+}
+
+pub struct Keys {
+    presses: Mutex<Receiver<Key>>,
+    quit: AtomicBool,
+    paused: AtomicBool,
+    // None when the dashboard has the terminal
+    _terminal: Option<terminal::Mode>,
+}
+
+impl Keys {
+    /// Start reading keys from stdin
+    pub fn start() -> Result<Self, String> {
+        let terminal = terminal::Mode::enable();
+        let (tx, presses) = mpsc::channel();
+        thread::Builder::new()
+            .name("keys".to_string())
+            .spawn(move || {
+                let mut bytes = io::stdin().lock().bytes().map_while(Result::ok);
+                while let Some(byte) = bytes.next() {
+                    let key = match byte.to_ascii_lowercase() {
+                        b'm' => Key::Mark(read_name(&mut bytes)),
+                        byte => match Key::from_byte(byte) {
+                            Some(key) => key,
+                            None => continue,
+                        },
+                    };
+                    if tx.send(key).is_err() {
+                        break;
+                    }
+                }
+            })
+            .map_err(|e| format!("Failed to start key reader: {}", e))?;
+
+        Ok(Keys { presses: Mutex::new(presses), quit: AtomicBool::new(false), paused: AtomicBool::new(false), _terminal: Some(terminal) })
+    }
+
+    /// Only the quit and pause flags, for the dashboard, which reads the keys itself
+    pub fn shared() -> Self {
+        let (_, presses) = mpsc::channel();
+        Keys { presses: Mutex::new(presses), quit: AtomicBool::new(false), paused: AtomicBool::new(false), _terminal: None }
+    }
+
+    /// Next key pressed within `timeout`
+    pub fn wait(&self, timeout: Duration) -> Option<Key> {
+        let key = self.presses.lock().unwrap().recv_timeout(timeout).ok();
+        if key == Some(Key::Quit) {
+            self.quit.store(true, Ordering::Relaxed);
+        }
+        key
+    }
+
+    /// Whether `q` was pressed; later targets are skipped
+    pub fn quit_requested(&self) -> bool {
+        self.quit.load(Ordering::Relaxed)
+    }
+
+    /// Dashboard: end the run as `q` does
+    pub fn request_quit(&self) {
+        self.quit.store(true, Ordering::Relaxed);
+    }
+
+    /// Dashboard: stop or resume sending to every target; whether it is paused now
+    pub fn toggle_pause(&self) -> bool {
+        !self.paused.fetch_xor(true, Ordering::Relaxed)
+    }
+
+    /// Whether the dashboard paused the run
+    pub fn paused(&self) -> bool {
+        self.paused.load(Ordering::Relaxed)
+    }
+}
+
+const ENTER_ALTERNATE_SCREEN: &str = "\x1b[?1049h\x1b[?25l";
+const LEAVE_ALTERNATE_SCREEN: &str = "\x1b[?25h\x1b[?1049l";
+
+/// Draw on the terminal's alternate screen, leaving the scrollback untouched, until switched off
+pub fn alternate_screen(on: bool) {
+    terminal::set_alternate(on);
+    let mut stdout = io::stdout();
+    let _ = stdout.write_all(if on { ENTER_ALTERNATE_SCREEN } else { LEAVE_ALTERNATE_SCREEN }.as_bytes());
+    let _ = stdout.flush();
+}
+
+#[cfg(target_os = "linux")]
+mod terminal {
+    use std::mem;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::OnceLock;
+
+    // Settings to put back on exit, also from the SIGINT handler
+    static ORIGINAL: OnceLock<libc::termios> = OnceLock::new();
+    // Whether the handler must also leave the alternate screen
+    static ALTERNATE: AtomicBool = AtomicBool::new(false);
+
+    pub fn set_alternate(on: bool) {
+        ALTERNATE.store(on, Ordering::Relaxed);
+    }
+
+    extern "C" fn restore_and_exit(signal: libc::c_int) {
+        // A run that ends normally puts the terminal back itself
+        if crate::interrupt::record() {
+            return;
+        }
+        if ALTERNATE.load(Ordering::Relaxed) {
+            let leave = super::LEAVE_ALTERNATE_SCREEN;
+            unsafe {
+                libc::write(libc::STDOUT_FILENO, leave.as_ptr().cast(), leave.len());
+            }
+        }
+        if let Some(original) = ORIGINAL.get() {
+            unsafe {
+                libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, original);
+                libc::signal(signal, libc::SIG_DFL);
+                libc::raise(signal);
+            }
+        }
+    }
+
+    /// Non-canonical, no-echo terminal for as long as this lives
+    pub struct Mode {
+        enabled: bool,
+    }
+
+    impl Mode {
+        pub fn enable() -> Self {
+            unsafe {
+                let mut settings: libc::termios = mem::zeroed();
+                if libc::isatty(libc::STDIN_FILENO) == 0 || libc::tcgetattr(libc::STDIN_FILENO, &mut settings) != 0 {
+                    return Mode { enabled: false };
+                }
+                let _ = ORIGINAL.set(settings);
+                settings.c_lflag &= !(libc::ICANON | libc::ECHO);
+                settings.c_cc[libc::VMIN] = 1;
+                settings.c_cc[libc::VTIME] = 0;
+                libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &settings);
+                // Ctrl+C must not leave the shell without echo
+                let handler = restore_and_exit as extern "C" fn(libc::c_int);
+                libc::signal(libc::SIGINT, handler as libc::sighandler_t);
+                libc::signal(libc::SIGTERM, handler as libc::sighandler_t);
+            }
+            Mode { enabled: true }
+        }
+    }
+
+    impl Drop for Mode {
+        fn drop(&mut self) {
+            if let (true, Some(original)) = (self.enabled, ORIGINAL.get()) {
+                unsafe {
+                    libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, original);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+mod terminal {
+    /// Line-buffered terminal, keys need Enter
+    pub struct Mode;
+
+    pub fn set_alternate(_on: bool) {}
+
+    impl Mode {
+        pub fn enable() -> Self {
+            Mode
+        }
+    }
+}
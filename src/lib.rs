@@ -0,0 +1,407 @@
+//! Data structures of the reports rust_ping exports.
+//!
+//! Reports written with `--json`, `--yaml`, `--cbor` or `--msgpack` are a
+//! [`PingReport`], and rust_ping reads them back with these same types, so a
+//! program depending on this crate deserializes them with serde in any of
+//! those formats and gets what the tool wrote. Fields added later have
+//! defaults, so older reports keep reading; a change older readers would get
+//! wrong raises [`SCHEMA_VERSION`], which every report carries.
+
+use chrono::{DateTime, Local};
+use serde::ser::SerializeStruct;
+use serde::{Deserialize, Serialize, Serializer};
+use std::collections::BTreeMap;
+use std::net::IpAddr;
+
+/// Version of the report layout
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// How probe timestamps are shown and exported
+pub const TIMESTAMP_FORMAT: &str = "%Y-%m-%d %H:%M:%S%.3f";
+
+/// Outcome of one probe
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ProbeStatus {
+    Success,
+    Timeout,
+    /// ICMP destination unreachable, with its code (e.g. 1 = host unreachable)
+    Unreachable { code: u8 },
+    /// The echo request could not be sent
+    SendError,
+    /// ICMP time exceeded: the TTL ran out on the way
+    TtlExceeded,
+    /// ICMP parameter problem, with its code: the probe's header was refused
+    ParameterProblem { code: u8 },
+}
+
+impl ProbeStatus {
+    /// Name used in exports
+    pub fn name(&self) -> &'static str {
+        match self {
+            ProbeStatus::Success => "success",
+            ProbeStatus::Timeout => "timeout",
+            ProbeStatus::Unreachable { .. } => "unreachable",
+            ProbeStatus::SendError => "send_error",
+            ProbeStatus::TtlExceeded => "ttl_exceeded",
+            ProbeStatus::ParameterProblem { .. } => "parameter_problem",
+        }
+    }
+
+    /// ICMP code of an unreachable or parameter problem reply
+    pub fn code(&self) -> Option<u8> {
+        match self {
+            ProbeStatus::Unreachable { code } | ProbeStatus::ParameterProblem { code } => Some(*code),
+            _ => None,
+        }
+    }
+
+    /// Inverse of [`ProbeStatus::name`], for reading reports back
+    pub fn from_name(name: &str, code: Option<u8>) -> Option<Self> {
+        match name {
+            "success" => Some(ProbeStatus::Success),
+            "timeout" => Some(ProbeStatus::Timeout),
+            "unreachable" => Some(ProbeStatus::Unreachable { code: code.unwrap_or(0) }),
+            "send_error" => Some(ProbeStatus::SendError),
+            "ttl_exceeded" => Some(ProbeStatus::TtlExceeded),
+            "parameter_problem" => Some(ProbeStatus::ParameterProblem { code: code.unwrap_or(0) }),
+            _ => None,
+        }
+    }
+
+    /// Shown on the line graph for a column with this failure
+    pub fn marker(&self) -> char {
+        match self {
+            ProbeStatus::Success => '●',
+            ProbeStatus::Timeout => '✗',
+            ProbeStatus::Unreachable { .. } => 'U',
+            ProbeStatus::SendError => '!',
+            ProbeStatus::TtlExceeded => 'T',
+            ProbeStatus::ParameterProblem { .. } => 'P',
+        }
+    }
+}
+
+/// Result of each probe, or of a further reply to one
+#[derive(Clone, Debug, Deserialize)]
+#[serde(try_from = "StoredResult")]
+pub struct PingResult {
+    pub seq: u32,
+    pub rtt_ms: Option<f64>,
+    /// The measured RTT when the calibrated local overhead was subtracted from `rtt_ms`
+    pub raw_rtt_ms: Option<f64>,
+    pub status: ProbeStatus,
+    /// TTL the reply arrived with
+    pub ttl: Option<u8>,
+    // Formatted only when written somewhere
+    pub timestamp: Option<DateTime<Local>>,
+    /// Another reply to probe `seq`, which was answered already; not a probe of its own
+    pub duplicate: bool,
+    /// The reply came after that of a later probe
+    pub reordered: bool,
+    /// The reply didn't carry the payload that was sent
+    pub corrupted: bool,
+}
+
+impl PingResult {
+    pub fn success(&self) -> bool {
+        self.status == ProbeStatus::Success
+    }
+}
+
+// `success` is kept next to `status` for consumers of the older format
+impl Serialize for PingResult {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        // CBOR and MessagePack write the field count up front, so skipped fields can't be counted
+        let fields = 3
+            + self.rtt_ms.is_some() as usize
+            + self.raw_rtt_ms.is_some() as usize
+            + self.status.code().is_some() as usize
+            + self.ttl.is_some() as usize
+            + self.timestamp.is_some() as usize
+            + self.duplicate as usize
+            + self.reordered as usize
+            + self.corrupted as usize;
+        let mut out = serializer.serialize_struct("PingResult", fields)?;
+        out.serialize_field("seq", &self.seq)?;
+        match self.rtt_ms {
+            Some(rtt) => out.serialize_field("rtt_ms", &rtt)?,
+            None => out.skip_field("rtt_ms")?,
+        }
+        match self.raw_rtt_ms {
+            Some(rtt) => out.serialize_field("raw_rtt_ms", &rtt)?,
+            None => out.skip_field("raw_rtt_ms")?,
+        }
+        out.serialize_field("success", &self.success())?;
+        out.serialize_field("status", self.status.name())?;
+        match self.status.code() {
+            Some(code) => out.serialize_field("icmp_code", &code)?,
+            None => out.skip_field("icmp_code")?,
+        }
+        match self.ttl {
+            Some(ttl) => out.serialize_field("ttl", &ttl)?,
+            None => out.skip_field("ttl")?,
+        }
+        match &self.timestamp {
+            Some(ts) => out.serialize_field("timestamp", &format_args!("{}", ts.format(TIMESTAMP_FORMAT)))?,
+            None => out.skip_field("timestamp")?,
+        }
+        for (name, set) in [("duplicate", self.duplicate), ("reordered", self.reordered), ("corrupted", self.corrupted)] {
+            if set {
+                out.serialize_field(name, &true)?;
+            } else {
+                out.skip_field(name)?;
+            }
+        }
+        out.end()
+    }
+}
+
+/// A result as written to a report; those from before `status` only have `success`
+#[derive(Deserialize)]
+struct StoredResult {
+    seq: u32,
+    rtt_ms: Option<f64>,
+    raw_rtt_ms: Option<f64>,
+    success: bool,
+    status: Option<String>,
+    icmp_code: Option<u8>,
+    ttl: Option<u8>,
+    timestamp: Option<String>,
+    #[serde(default)]
+    duplicate: bool,
+    #[serde(default)]
+    reordered: bool,
+    #[serde(default)]
+    corrupted: bool,
+}
+
+impl TryFrom<StoredResult> for PingResult {
+    type Error = String;
+
+    fn try_from(stored: StoredResult) -> Result<Self, String> {
+        let status = match stored.status.as_deref() {
+            Some(name) => ProbeStatus::from_name(name, stored.icmp_code)
+                .ok_or_else(|| format!("unknown status '{}'", name))?,
+            None if stored.success => ProbeStatus::Success,
+            None => ProbeStatus::Timeout,
+        };
+        let timestamp = stored
+            .timestamp
+            .and_then(|ts| chrono::NaiveDateTime::parse_from_str(&ts, TIMESTAMP_FORMAT).ok())
+            .and_then(|ts| ts.and_local_timezone(Local).single());
+        Ok(PingResult {
+            seq: stored.seq,
+            rtt_ms: stored.rtt_ms,
+            raw_rtt_ms: stored.raw_rtt_ms,
+            status,
+            ttl: stored.ttl,
+            timestamp,
+            duplicate: stored.duplicate,
+            reordered: stored.reordered,
+            corrupted: stored.corrupted,
+        })
+    }
+}
+
+/// Per-second summary used instead of per-probe lines at high rates
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SecondAggregate {
+    pub second: u64,
+    pub timestamp: String,
+    pub sent: u32,
+    pub received: u32,
+    pub lost: u32,
+    pub loss_percent: f64,
+    pub min_ms: Option<f64>,
+    pub avg_ms: Option<f64>,
+    pub max_ms: Option<f64>,
+}
+
+/// Statistics of a target; counters missing from older reports read as 0
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PingStatistics {
+    pub min_ms: Option<f64>,
+    pub max_ms: Option<f64>,
+    pub avg_ms: Option<f64>,
+    pub std_dev_ms: Option<f64>,
+    pub p50_ms: Option<f64>,
+    pub p95_ms: Option<f64>,
+    pub p99_ms: Option<f64>,
+    /// RFC 3550 interarrival jitter over consecutive replies
+    pub jitter_ms: Option<f64>,
+    /// Mean and largest RTT difference between consecutive replies
+    pub delta_avg_ms: Option<f64>,
+    pub delta_max_ms: Option<f64>,
+    pub packets_sent: u32,
+    pub packets_received: u32,
+    pub packets_lost: u32,
+    /// Lost probes broken down by cause
+    pub packets_timeout: u32,
+    pub packets_unreachable: u32,
+    pub packets_ttl_exceeded: u32,
+    pub packets_parameter_problem: u32,
+    pub packets_send_error: u32,
+    pub packet_loss_percent: f64,
+    /// Replies discarded because a checksum didn't add up
+    pub packets_corrupted: u32,
+    /// Replies counted as received whose payload wasn't the one sent
+    pub packets_payload_corrupted: u32,
+    /// Further replies to probes that were answered already (DUP!)
+    pub packets_duplicate: u32,
+    /// Replies that came after those of later probes
+    pub packets_reordered: u32,
+    /// Most probes lost in a row
+    pub longest_loss_streak: u32,
+    /// Runs of probes lost in a row, by how many: 1 for a loss on its own
+    pub loss_bursts: BTreeMap<u32, u32>,
+}
+
+/// Complete report, mirroring the layout of the streamed JSON export
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PingReport {
+    /// [`SCHEMA_VERSION`] of the rust_ping that wrote the report; 0 from before it was recorded
+    #[serde(default)]
+    pub schema_version: u32,
+    /// Version of rust_ping that wrote the report
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub tool_version: String,
+    /// Operating system and architecture of the run, e.g. "linux x86_64"
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub os: String,
+    /// Command line the run was started with
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub command_line: Vec<String>,
+    pub run_id: String,
+    pub host: String,
+    pub ip_address: String,
+    pub timestamp_start: String,
+    // Absent from reports that still had `timeout_seconds`
+    #[serde(default)]
+    pub timeout_ms: f64,
+    pub interval_seconds: f64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub overhead_ms: Option<f64>,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub overhead_subtracted: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ttl: Option<u8>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub interface: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source_address: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub results: Vec<PingResult>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub aggregates: Option<Vec<SecondAggregate>>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub markers: Vec<Marker>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub site_switches: Vec<SiteSwitch>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub http_phases: Vec<PhaseStats>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub size_sweep: Vec<SizeStats>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub addresses: Vec<AddressStats>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub outages: Vec<Outage>,
+    pub timestamp_end: String,
+    pub complete: bool,
+    pub statistics: PingStatistics,
+}
+
+impl PingReport {
+    /// Where the probes were sent from, when -I or --source chose it
+    pub fn source(&self) -> Option<String> {
+        match (&self.source_address, &self.interface) {
+            (Some(address), Some(interface)) => Some(format!("{} on {}", address, interface)),
+            (address, interface) => address.clone().or_else(|| interface.clone()),
+        }
+    }
+}
+
+/// A named point in time recorded during a run
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Marker {
+    pub name: String,
+    /// First probe sent after the marker
+    pub seq: u32,
+    pub timestamp: String,
+}
+
+impl Marker {
+    pub fn new(name: &str, seq: u32, at: DateTime<Local>) -> Self {
+        Marker { name: name.to_string(), seq, timestamp: at.format(TIMESTAMP_FORMAT).to_string() }
+    }
+}
+
+/// A probable change of the site answering, with what changed
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SiteSwitch {
+    /// First probe answered by the new site, as far as can be told
+    pub seq: u32,
+    pub timestamp: String,
+    /// More than one sign changed together
+    pub likely: bool,
+    /// What changed, e.g. "TTL 57 → 55"
+    pub evidence: Vec<String>,
+}
+
+impl SiteSwitch {
+    /// As a marker, to be drawn on the line graphs
+    pub fn marker(&self) -> Marker {
+        let name = if self.likely { "site switch" } else { "site switch?" };
+        Marker { name: name.to_string(), seq: self.seq, timestamp: self.timestamp.clone() }
+    }
+}
+
+/// One time a target was down
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Outage {
+    /// Send time of the first probe lost
+    pub start: String,
+    /// Send time of the probe whose reply ended it; absent when the run ended first
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub end: Option<String>,
+    pub duration_seconds: f64,
+    pub first_seq: u32,
+    /// Probes lost in a row
+    pub lost: u32,
+}
+
+/// One phase over a run, in the reports
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct PhaseStats {
+    pub phase: String,
+    pub avg_ms: Option<f64>,
+    pub p50_ms: Option<f64>,
+    pub p95_ms: Option<f64>,
+    pub max_ms: Option<f64>,
+}
+
+/// One payload size over a run, in the reports
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SizeStats {
+    pub size: usize,
+    pub sent: u32,
+    pub received: u32,
+    pub loss_percent: f64,
+    pub min_ms: Option<f64>,
+    pub avg_ms: Option<f64>,
+    pub max_ms: Option<f64>,
+}
+
+/// One address of a re-resolved host over a run, in the reports
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct AddressStats {
+    pub ip: IpAddr,
+    /// First probe sent to the address
+    pub first_seq: u32,
+    pub sent: u32,
+    pub received: u32,
+    pub loss_percent: f64,
+    pub min_ms: Option<f64>,
+    pub avg_ms: Option<f64>,
+    pub max_ms: Option<f64>,
+}
@@ -1,34 +1,350 @@
-use clap::Parser;
+use clap::parser::ValueSource;
+use clap::{ArgMatches, CommandFactory, FromArgMatches, Parser, Subcommand, ValueEnum};
 use colored::*;
 use chrono::{DateTime, Local};
 use pnet::packet::icmp::echo_request::MutableEchoRequestPacket;
-use pnet::packet::icmp::{IcmpCode, IcmpTypes};
-use pnet::packet::ip::IpNextHeaderProtocols;
+use pnet::packet::icmp::{IcmpCode, IcmpType, IcmpTypes};
 use pnet::packet::Packet;
-use pnet::transport::{
-    icmp_packet_iter, transport_channel, TransportChannelType::Layer4,
-    TransportProtocol::Ipv4,
-};
-use serde::Serialize;
-use std::fs::File;
-use std::io::Write;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::io::{self, Write};
 use std::net::IpAddr;
-use std::time::{Duration, Instant};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Mutex, OnceLock};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+
+mod alerts;
+mod anycast;
+mod baseline;
+mod breakdown;
+mod config;
+mod dashboard;
+mod diff;
+mod downsample;
+mod dualstack;
+mod engine;
+mod export;
+mod history;
+mod http;
+mod identifier;
+mod interrupt;
+mod keys;
+mod markers;
+mod merge;
+mod monitor;
+mod outages;
+mod pmtu;
+mod profiles;
+mod rdns;
+mod recent;
+mod replay;
+mod reresolve;
+mod report;
+mod selftest;
+mod serve;
+mod stamp;
+mod scan;
+mod sinks;
+mod stats;
+mod summary;
+mod sweep;
+mod trace;
+mod transport;
+mod xlsx;
+
+use anycast::{HopWatch, Tracker};
+use breakdown::Breakdown;
+use config::{Colors, Config, Defaults};
+use dashboard::Dashboard;
+use dualstack::Preference;
+use engine::{Binding, Channel, SharedSocket, SocketMode};
+use export::{expand_path, new_run_id, with_host_placeholder, CsvFormat, ExportFiles, ExportWriter, ReportHeader, STDOUT};
+use history::History;
+use identifier::Identifier;
+use keys::{Key, Keys};
+use outages::OutageLog;
+use reresolve::{DnsWatch, PerAddress};
+use sinks::{Sinks, Target};
+use stats::RttStats;
+use summary::{print_summary_table, SortKey, TargetSummary};
+use sweep::{Sweep, SweepRange};
+use transport::Reply;
+use rust_ping::{Marker, PingResult, PingStatistics, ProbeStatus, SecondAggregate, SiteSwitch, TIMESTAMP_FORMAT};
 
 /// Rust Ping Tool with CLI graphs and export options
 #[derive(Parser, Debug)]
 #[command(author, version, about)]
+struct Cli {
+    /// Read settings such as notification channels, aliases and option defaults from this TOML file
+    #[arg(long, value_name = "FILE", global = true)]
+    config: Option<String>,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Ping one or more hosts (the default: `rust_ping HOST` is `rust_ping ping HOST`)
+    Ping(Box<Args>),
+    /// Show the route to a host, hop by hop
+    Trace(trace::TraceArgs),
+    /// Ping hosts until interrupted, storing every result in a SQLite database
+    Monitor(Box<monitor::MonitorArgs>),
+    /// Ping hosts until interrupted, serving their statistics and results as JSON over HTTP
+    Serve(Box<serve::ServeArgs>),
+    /// Find the largest packet that reaches a host without being fragmented
+    Pmtu(pmtu::PmtuArgs),
+    /// Sweep a network for the addresses that answer
+    Scan(scan::ScanArgs),
+    /// Work with the profiles of the config file
+    Profiles(profiles::ProfilesArgs),
+    /// List recently pinged targets with their last statistics
+    History(recent::HistoryArgs),
+    /// Work with saved reports
+    Report(report::ReportArgs),
+    /// Draw a saved report again, as `report render` does
+    Replay(report::RenderArgs),
+    /// Compare two saved reports of a target, with whether the differences are more than chance
+    Diff(diff::DiffArgs),
+    /// Join saved reports of a target, such as hourly runs, into one
+    Merge(merge::MergeArgs),
+    /// Check packet handling and measure the tool's own overhead on loopback
+    Selftest(selftest::SelftestArgs),
+}
+
+/// Options of the `ping` subcommand
+#[derive(clap::Args, Clone, Debug)]
 struct Args {
-    /// IP address or hostname to ping
-    host: String,
+    /// IP addresses or hostnames to ping
+    #[arg(required_unless_present_any = ["replay", "profile", "targets_file"], num_args = 1.., value_name = "HOST")]
+    hosts: Vec<String>,
+
+    /// Also ping the hosts listed in FILE, one per line (- reads them from stdin; # starts a comment)
+    #[arg(long, value_name = "FILE", conflicts_with = "replay")]
+    targets_file: Option<String>,
 
-    /// Number of pings to send
+    /// Add the hosts and options of [profile.NAME] in the config file (options given here win)
+    #[arg(long, value_name = "NAME")]
+    profile: Option<String>,
+
+    /// Resolve hostnames to IPv6 addresses only (IPv4 is preferred otherwise)
+    #[arg(short = '6', long)]
+    ipv6: bool,
+
+    /// Ping the IPv4 address of hosts that have both (the default)
+    #[arg(long, overrides_with_all = ["prefer_ipv6", "happy_eyeballs"], conflicts_with_all = ["ipv6", "http"])]
+    prefer_ipv4: bool,
+
+    /// Ping the IPv6 address of hosts that have both
+    #[arg(long, overrides_with_all = ["prefer_ipv4", "happy_eyeballs"], conflicts_with = "http")]
+    prefer_ipv6: bool,
+
+    /// Race an echo request to the IPv4 and IPv6 address of hosts that have both, and ping the first to answer
+    #[arg(long, overrides_with_all = ["prefer_ipv4", "prefer_ipv6"], conflicts_with_all = ["ipv6", "http", "replay"])]
+    happy_eyeballs: bool,
+
+    /// Number of pings to send (0 pings until interrupted)
     #[arg(short, long, default_value_t = 10)]
     count: u32,
 
-    /// Timeout in seconds
-    #[arg(short, long, default_value_t = 2)]
-    timeout: u64,
+    /// Send N probes first to prime ARP/ND and route caches; they're shown but not counted or exported
+    #[arg(long, value_name = "N", default_value_t = 0, conflicts_with = "replay")]
+    warmup: u32,
+
+    /// Ping until interrupted with Ctrl+C, like system ping
+    #[arg(long, conflicts_with_all = ["count", "duration"])]
+    forever: bool,
+
+    /// Keep pinging for this long instead of a number of pings (e.g. 30s, 5m, 2h)
+    #[arg(long, value_name = "DURATION", value_parser = parse_duration, conflicts_with = "count")]
+    duration: Option<Duration>,
+
+    /// Stop after this long whatever the count, without waiting for the probes still out (e.g. 10, 30s)
+    #[arg(short = 'w', long, value_name = "DURATION", value_parser = parse_duration)]
+    deadline: Option<Duration>,
+
+    /// Packet loss a target may have and still exit with status 0; above it 1, and 2 when nothing was answered
+    #[arg(long, value_name = "PERCENT", default_value_t = 0.0, value_parser = parse_percent)]
+    max_loss: f64,
+
+    /// Timeout for each request, in seconds or with a unit (e.g. 2, 500ms)
+    #[arg(short, long, default_value = "2", value_parser = parse_duration)]
+    timeout: Duration,
+
+    /// Interval between pings, in seconds or with a unit (e.g. 0.01, 10ms, 1.5s)
+    #[arg(short, long, default_value = "1", value_parser = parse_duration)]
+    interval: Duration,
+
+    /// Bytes of payload in each echo request (UDP datagram with --udp)
+    #[arg(short, long, value_name = "BYTES", default_value_t = (PACKET_LEN - ICMP_HEADER_LEN) as u16,
+        value_parser = clap::value_parser!(u16).range(..=sweep::MAX_PAYLOAD as i64))]
+    size: u16,
+
+    /// Fill the payload with this pattern of up to 16 bytes, repeated (hex, e.g. ff00 or deadbeef)
+    #[arg(short, long, value_name = "HEX", value_parser = parse_pattern, conflicts_with = "http")]
+    pattern: Option<Pattern>,
+
+    /// Send the content of FILE as the payload of every echo request, which sets its size
+    #[arg(long, value_name = "FILE", conflicts_with_all = ["size", "sweep", "pattern", "http"])]
+    payload_file: Option<String>,
+
+    /// Grow the payload from MIN to MAX bytes by STEP each probe, then start over, and report RTT and loss per size
+    #[arg(long, value_name = "MIN:MAX:STEP", value_parser = sweep::parse_sweep, conflicts_with = "size")]
+    sweep: Option<SweepRange>,
+
+    /// Number of targets pinged at the same time
+    #[arg(long, value_name = "N", default_value_t = 1, value_parser = clap::value_parser!(u32).range(1..))]
+    parallel: u32,
+
+    /// Give every target its own raw socket, or share one between all of them
+    #[arg(long, value_enum, value_name = "MODE", default_value_t = SocketMode::PerTarget)]
+    socket: SocketMode,
+
+    /// Send this many probes back-to-back at each interval (count is the total)
+    #[arg(long, value_name = "N", default_value_t = 1, value_parser = clap::value_parser!(u32).range(1..))]
+    burst: u32,
+
+    /// Flood ping (root only): send as fast as replies come back, at least 100 a second, or at RATE packets a second with --flood=RATE
+    #[arg(short, long, value_name = "RATE", num_args = 0..=1, require_equals = true,
+        conflicts_with_all = ["interval", "burst", "aggregate", "interactive", "graph", "replay", "http"])]
+    flood: Option<Option<f64>>,
+
+    /// Per-second aggregation of results (auto enables it above 5 packets/s)
+    #[arg(long, value_enum, default_value_t = AggregateMode::Auto)]
+    aggregate: AggregateMode,
+
+    /// Control the run from the keyboard: pause, change the interval, reset statistics, toggle graphs
+    #[arg(long, conflicts_with = "parallel")]
+    interactive: bool,
+
+    /// Record a marker with this name whenever the process receives SIGUSR1
+    #[arg(long, value_name = "NAME")]
+    mark_on_signal: Option<String>,
+
+    /// Compare with the statistics of a saved report ({host} and {ip} allowed) and exit with 1 if worse
+    #[arg(long, value_name = "FILE")]
+    baseline: Option<String>,
+
+    /// RTT percentiles may exceed the baseline by this many percent
+    #[arg(long, value_name = "PERCENT", default_value_t = 20.0, requires = "baseline")]
+    tolerance: f64,
+
+    /// RTT increases below this many milliseconds never count as a regression
+    #[arg(long, value_name = "MS", default_value_t = 1.0, requires = "baseline")]
+    tolerance_ms: f64,
+
+    /// Packet loss may exceed the baseline by this many percentage points
+    #[arg(long, value_name = "POINTS", default_value_t = 1.0, requires = "baseline")]
+    loss_tolerance: f64,
+
+    /// Measure the local stack's overhead on loopback before the run and report it
+    #[arg(long)]
+    calibrate: bool,
+
+    /// Subtract the calibrated overhead from every RTT (implies --calibrate; exports keep the raw RTT too)
+    #[arg(long)]
+    subtract_overhead: bool,
+
+    /// Split each RTT into time spent in this host and on the network, with eBPF stamps at the driver (root, `ebpf` builds)
+    #[arg(long, conflicts_with = "replay")]
+    latency_breakdown: bool,
+
+    /// Watch reply TTLs, RTT levels and the last hop for signs of an anycast address switching sites
+    #[arg(long)]
+    anycast: bool,
+
+    /// Send UDP datagrams to this port and time the ICMP port unreachable answering them, for hosts that rate-limit echo replies
+    #[arg(long, value_name = "PORT", conflicts_with_all = ["replay", "http", "latency_breakdown"])]
+    udp: Option<u16>,
+
+    /// TTL (hop limit for IPv6) of the probes; they come back as TTL exceeded from the router where it runs out
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u8).range(1..), conflicts_with_all = ["replay", "http"])]
+    ttl: Option<u8>,
+
+    /// Show the names of the addresses replies come from (reverse DNS, looked up in the background)
+    #[arg(long, overrides_with = "numeric")]
+    resolve: bool,
+
+    /// Show addresses as numbers only, even with `resolve = true` in the config
+    #[arg(short = 'n', long, overrides_with = "resolve")]
+    numeric: bool,
+
+    /// Send and receive the probes on this interface or VRF device only, e.g. eth1 (Linux)
+    #[arg(short = 'I', long, value_name = "NAME", conflicts_with_all = ["replay", "http"])]
+    interface: Option<String>,
+
+    /// Send the probes from this local address; hostnames resolve to its address family
+    #[arg(long, value_name = "ADDR", conflicts_with_all = ["replay", "http"])]
+    source: Option<IpAddr>,
+
+    /// Resolve the hostname again this often (e.g. 5m) and follow it to a new address, with statistics per address
+    #[arg(long, value_name = "INTERVAL", value_parser = parse_duration, conflicts_with_all = ["replay", "http"])]
+    re_resolve: Option<Duration>,
+
+    /// Probe web endpoints: every HOST is an http:// or https:// URL, timed up to the first byte of the response
+    #[arg(long, conflicts_with_all = ["replay", "burst", "anycast", "latency_breakdown", "calibrate", "subtract_overhead", "size", "sweep"])]
+    http: bool,
+
+    /// With --http: send HEAD requests instead of GET
+    #[arg(long, requires = "http")]
+    head: bool,
+
+    /// Play back the results of a saved report instead of pinging (no root or network needed)
+    #[arg(long, value_name = "FILE", conflicts_with_all = ["hosts", "count", "duration", "burst", "parallel", "size", "sweep"])]
+    replay: Option<String>,
+
+    /// Play the report back this many times faster than it was recorded
+    #[arg(long, value_name = "FACTOR", default_value_t = 1.0, requires = "replay")]
+    speed: f64,
+
+    /// Don't add this run to the recent targets listed by `history`
+    #[arg(long)]
+    forget: bool,
+
+    /// Keep raw per-probe samples in exports when aggregating
+    #[arg(long)]
+    raw_samples: bool,
+
+    /// Guarantee constant memory for long runs by refusing options that keep every probe
+    #[arg(long, conflicts_with_all = ["xlsx", "yaml", "cbor", "msgpack", "html"])]
+    stream_only: bool,
+
+    /// Number of most recent probes kept for graphs (all of them are still exported)
+    #[arg(long, value_name = "N", default_value_t = 86400, value_parser = clap::value_parser!(u32).range(1..))]
+    history: u32,
+
+    /// Format of the output written to stdout
+    #[arg(long, value_enum, default_value_t = OutputFormat::Text)]
+    output: OutputFormat,
+
+    /// Full-screen live view, the same as --output dashboard
+    #[arg(long, conflicts_with = "output")]
+    tui: bool,
+
+    /// Stream one JSON object per probe to stdout, the same as --output ndjson
+    #[arg(long, conflicts_with_all = ["output", "tui"])]
+    ndjson: bool,
+
+    /// Print only the final statistics, one plain line per target (no banners, per-packet lines or graphs)
+    #[arg(short, long, conflicts_with_all = ["tui", "interactive", "graph", "line_graph"])]
+    quiet: bool,
+
+    /// Print a line with the loss and average RTT of the probes since the last one this often (e.g. 30s, 5m)
+    #[arg(long, value_name = "DURATION", value_parser = parse_duration)]
+    stats_interval: Option<Duration>,
+
+    /// Sort the multi-host summary table by this column
+    #[arg(long, value_enum, value_name = "COLUMN")]
+    sort_by: Option<SortKey>,
+
+    /// Write the final statistics of every target to one JSON file
+    #[arg(long, value_name = "FILE")]
+    summary_json: Option<String>,
+
+    /// Write the final statistics of every target to one CSV file, a row per target
+    #[arg(long, value_name = "FILE")]
+    summary_csv: Option<String>,
 
     /// Show bar graph
     #[arg(short, long)]
@@ -38,49 +354,418 @@ struct Args {
     #[arg(short, long)]
     line_graph: bool,
 
-    /// Export results to JSON file
+    /// Export results to JSON file ({host}, {ip}, {date}, {time}, {run_id} are expanded), or - for stdout
     #[arg(long, value_name = "FILE")]
     json: Option<String>,
 
-    /// Export results to CSV file
+    /// Export results to CSV file ({host}, {ip}, {date}, {time}, {run_id} are expanded), or - for stdout
     #[arg(long, value_name = "FILE")]
     csv: Option<String>,
+
+    /// Write the CSV as a single table (header row and records) without comments or statistics
+    #[arg(long, requires = "csv")]
+    csv_strict: bool,
+
+    /// CSV field delimiter: , ; | or tab
+    #[arg(long, value_name = "CHAR", default_value = ",", value_parser = parse_csv_delimiter)]
+    csv_delimiter: char,
+
+    /// CSV decimal separator: . or , (with another delimiter)
+    #[arg(long, value_name = "CHAR", default_value = ".", value_parser = parse_csv_decimal)]
+    csv_decimal: char,
+
+    /// Export results and a statistics sheet to an Excel workbook
+    #[arg(long, value_name = "FILE")]
+    xlsx: Option<String>,
+
+    /// Export the report as YAML (same structure as the JSON export)
+    #[arg(long, value_name = "FILE")]
+    yaml: Option<String>,
+
+    /// Export the report as CBOR (binary, same structure as the JSON export)
+    #[arg(long, value_name = "FILE")]
+    cbor: Option<String>,
+
+    /// Export the report as MessagePack (binary, same structure as the JSON export)
+    #[arg(long, value_name = "FILE")]
+    msgpack: Option<String>,
+
+    /// Export a standalone HTML report with an interactive latency chart, a loss timeline and the statistics
+    #[arg(long, value_name = "FILE")]
+    html: Option<String>,
+
+    /// Publish each result as JSON to a Redis server (redis://host:port/db)
+    #[arg(long, value_name = "URL", help_heading = "Live sinks")]
+    redis: Option<String>,
+
+    /// Redis pub/sub channel for results
+    #[arg(long, value_name = "NAME", default_value = "rust_ping", help_heading = "Live sinks")]
+    redis_channel: String,
+
+    /// Also write RedisTimeSeries samples to <PREFIX>:<host>:rtt and :up
+    #[arg(long, value_name = "PREFIX", help_heading = "Live sinks")]
+    redis_timeseries: Option<String>,
+
+    /// Store results in PostgreSQL/TimescaleDB (e.g. "host=db user=ping dbname=net")
+    #[arg(long, value_name = "DSN", help_heading = "Live sinks")]
+    postgres: Option<String>,
+
+    /// Store every result in a SQLite database, for `report query` (created when missing)
+    #[arg(long, value_name = "FILE", help_heading = "Live sinks")]
+    sqlite: Option<String>,
+
+    /// Push samples to a Prometheus remote_write endpoint
+    #[arg(long, value_name = "URL", help_heading = "Live sinks")]
+    remote_write: Option<String>,
+
+    /// Extra HTTP header for remote_write, e.g. "Authorization: Bearer TOKEN" (repeatable)
+    #[arg(long, value_name = "HEADER", help_heading = "Live sinks")]
+    remote_write_header: Vec<String>,
+
+    /// Seconds between remote_write pushes
+    #[arg(long, value_name = "SECONDS", default_value_t = 10, help_heading = "Live sinks")]
+    remote_write_interval: u64,
+
+    /// Push metrics to an OpenTelemetry collector's OTLP/HTTP endpoint (e.g. http://localhost:4318)
+    #[arg(long, value_name = "URL", help_heading = "Live sinks")]
+    otlp: Option<String>,
+
+    /// Extra HTTP header for OTLP, e.g. "Authorization: Bearer TOKEN" (repeatable)
+    #[arg(long, value_name = "HEADER", requires = "otlp", help_heading = "Live sinks")]
+    otlp_header: Vec<String>,
+
+    /// Seconds between OTLP pushes
+    #[arg(long, value_name = "SECONDS", default_value_t = 10, help_heading = "Live sinks")]
+    otlp_interval: u64,
+
+    /// Also send a span per probe to the OTLP endpoint
+    #[arg(long, requires = "otlp", help_heading = "Live sinks")]
+    otlp_spans: bool,
+
+    /// Write every result as InfluxDB line protocol to stdout (-), a file, or an http(s) write endpoint
+    #[arg(long, value_name = "DEST", help_heading = "Live sinks")]
+    influx: Option<String>,
+
+    /// Token for the InfluxDB write endpoint (default: $INFLUX_TOKEN)
+    #[arg(long, value_name = "TOKEN", requires = "influx", help_heading = "Live sinks")]
+    influx_token: Option<String>,
+
+    /// Serve Prometheus metrics on http://ADDR/metrics while pinging (e.g. 0.0.0.0:9898)
+    #[arg(long, value_name = "ADDR", help_heading = "Live sinks")]
+    prometheus: Option<String>,
+
+    /// Serve statistics, recent results and a live stream as JSON on http://ADDR while pinging, also for Grafana's JSON datasource (e.g. 127.0.0.1:9899)
+    #[arg(long, value_name = "ADDR", help_heading = "Live sinks")]
+    serve: Option<String>,
+
+    /// Stream results to Grafana Live at this Grafana URL (e.g. http://localhost:3000)
+    #[arg(long, value_name = "URL", help_heading = "Live sinks")]
+    grafana: Option<String>,
+
+    /// Grafana service account token allowed to publish (default: $GRAFANA_TOKEN)
+    #[arg(long, value_name = "TOKEN", requires = "grafana", help_heading = "Live sinks")]
+    grafana_token: Option<String>,
+
+    /// Grafana Live stream; panels subscribe to stream/<NAME>/rust_ping
+    #[arg(long, value_name = "NAME", default_value = "rust_ping", help_heading = "Live sinks")]
+    grafana_stream: String,
+
+    /// Publish results to an MQTT broker (mqtt://[user[:pass]@]host[:port])
+    #[arg(long, value_name = "URL", help_heading = "Live sinks")]
+    mqtt: Option<String>,
+
+    /// MQTT topic prefix; results go to <PREFIX>/<host>/state
+    #[arg(long, value_name = "PREFIX", default_value = "rust_ping", help_heading = "Live sinks")]
+    mqtt_topic: String,
+
+    /// MQTT topic of each result; {prefix}, {host}, {ip} and {run_id} are replaced
+    #[arg(long, value_name = "TEMPLATE", default_value = "{prefix}/{host}/state", help_heading = "Live sinks")]
+    mqtt_state_topic: String,
+
+    /// MQTT topic of the statistics of each target, retained; placeholders as for --mqtt-state-topic
+    #[arg(long, value_name = "TEMPLATE", default_value = "{prefix}/{host}/summary", help_heading = "Live sinks")]
+    mqtt_summary_topic: String,
+
+    /// Also publish the statistics so far every this many seconds (0: only at the end)
+    #[arg(long, value_name = "SECONDS", default_value_t = 0, help_heading = "Live sinks")]
+    mqtt_summary_interval: u64,
+
+    /// Announce each target to Home Assistant via MQTT discovery
+    #[arg(long, requires = "mqtt", help_heading = "Live sinks")]
+    mqtt_ha_discovery: bool,
+
+    /// Home Assistant discovery topic prefix
+    #[arg(long, value_name = "PREFIX", default_value = "homeassistant", help_heading = "Live sinks")]
+    mqtt_discovery_prefix: String,
+
+    /// Send timing and counter metrics per probe to a StatsD/DogStatsD server (host[:port])
+    #[arg(long, value_name = "HOST", help_heading = "Live sinks")]
+    statsd: Option<String>,
+
+    /// Prefix of StatsD metric names
+    #[arg(long, value_name = "PREFIX", default_value = "rust_ping", help_heading = "Live sinks")]
+    statsd_prefix: String,
+
+    /// Extra DogStatsD tag on every metric, e.g. "env:prod" (repeatable)
+    #[arg(long, value_name = "TAG", requires = "statsd", help_heading = "Live sinks")]
+    statsd_tag: Vec<String>,
+
+    /// Plain StatsD: the host goes in metric names instead of tags
+    #[arg(long, requires = "statsd", conflicts_with = "statsd_tag", help_heading = "Live sinks")]
+    statsd_plain: bool,
+
+    /// Push rust_ping.rtt[<target>] and rust_ping.loss[<target>] to a Zabbix server or proxy (host[:port])
+    #[arg(long, value_name = "SERVER", requires = "zabbix_host", help_heading = "Live sinks")]
+    zabbix: Option<String>,
+
+    /// Zabbix host the trapper items belong to
+    #[arg(long, value_name = "NAME", requires = "zabbix", help_heading = "Live sinks")]
+    zabbix_host: Option<String>,
+
+    /// Send an SNMPv2c trap to this receiver (host[:port]) on alerts
+    #[arg(long, value_name = "HOST", help_heading = "Alerting")]
+    snmp_trap: Option<String>,
+
+    /// SNMP community for traps
+    #[arg(long, value_name = "NAME", default_value = "public", help_heading = "Alerting")]
+    snmp_community: String,
+
+    /// POST alerts as JSON to this URL; Slack and Discord webhooks get a chat message (repeatable)
+    #[arg(long, value_name = "URL", help_heading = "Alerting")]
+    webhook: Vec<String>,
+
+    /// Only post a problem to webhooks once it has lasted this long (e.g. 30s, 2m)
+    #[arg(long, value_name = "DURATION", default_value = "0", value_parser = parse_duration, help_heading = "Alerting")]
+    webhook_debounce: Duration,
+
+    /// Consider a target down after this many consecutive lost probes
+    #[arg(long, value_name = "N", default_value_t = 3, help_heading = "Alerting")]
+    down_after: u32,
+
+    /// Alert when the average RTT over the alert window exceeds this (ms)
+    #[arg(long, value_name = "MS", help_heading = "Alerting")]
+    alert_rtt: Option<f64>,
+
+    /// Alert when packet loss over the alert window exceeds this (%)
+    #[arg(long, value_name = "PERCENT", help_heading = "Alerting")]
+    alert_loss: Option<f64>,
+
+    /// Number of recent probes RTT and loss thresholds are judged over
+    #[arg(long, value_name = "N", default_value_t = 20, help_heading = "Alerting")]
+    alert_window: usize,
+
+    /// Ring the terminal bell when an alert is raised
+    #[arg(long, help_heading = "Alerting")]
+    alert_bell: bool,
+
+    /// Run this shell command on every alert, with the event in RUST_PING_* environment variables
+    #[arg(long, value_name = "CMD", help_heading = "Alerting")]
+    on_alert: Option<String>,
+}
+
+impl Args {
+    /// Family pinged on hosts that have both
+    fn preference(&self) -> Preference {
+        if self.happy_eyeballs {
+            Preference::Race
+        } else if self.prefer_ipv6 {
+            Preference::Ipv6
+        } else {
+            Preference::Ipv4
+        }
+    }
+
+    /// Interface and source address the probes are sent from
+    fn binding(&self) -> Binding {
+        Binding { interface: self.interface.clone(), address: self.source }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+enum OutputFormat {
+    /// Decorated human-readable output
+    Text,
+    /// netdata external plugin protocol (CHART/DIMENSION/SET)
+    Netdata,
+    /// InfluxDB line protocol, e.g. for Telegraf's exec input
+    InfluxStdout,
+    /// collectd exec plugin PUTVAL lines
+    Collectd,
+    /// One JSON object per probe and per target summary, line by line
+    Ndjson,
+    /// Full-screen panes with a sparkline and live statistics per target, all pinged at once
+    Dashboard,
+    /// Nothing but the JSON or CSV export written to `-`
+    #[value(skip)]
+    Export,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+enum AggregateMode {
+    Auto,
+    Always,
+    Never,
+}
+
+/// Size of an echo request, unless --size asks for another payload
+const PACKET_LEN: usize = 64;
+
+/// Type, code, checksum, identifier and sequence in front of the payload
+const ICMP_HEADER_LEN: usize = 8;
+
+/// Longest sleep between probes before checking whether the run was quit or interrupted
+const QUIT_POLL: Duration = Duration::from_millis(100);
+
+/// Longest gap between flood probes when replies come back slower, as with the system ping
+const FLOOD_INTERVAL: Duration = Duration::from_millis(10);
+
+/// What an ICMP destination unreachable code means
+fn unreachable_reason(code: u8) -> &'static str {
+    match code {
+        0 => "Network unreachable",
+        1 => "Host unreachable",
+        2 => "Protocol unreachable",
+        3 => "Port unreachable",
+        4 => "Fragmentation needed",
+        5 => "Source route failed",
+        6 | 7 => "Destination unknown",
+        9 | 10 | 13 => "Administratively prohibited",
+        11 | 12 => "Unreachable for type of service",
+        _ => "Unreachable",
+    }
+}
+
+/// What an ICMP or ICMPv6 parameter problem code means; the two number them differently
+fn parameter_problem_reason(code: u8, ipv6: bool) -> &'static str {
+    match (code, ipv6) {
+        (0, _) => "Parameter problem in the header",
+        (1, false) => "Parameter problem: missing option",
+        (2, false) => "Parameter problem: bad length",
+        (1, true) => "Parameter problem: unrecognized next header",
+        (2, true) => "Parameter problem: unrecognized option",
+        _ => "Parameter problem",
+    }
+}
+
+/// What an ICMP redirect code points the way for
+fn redirect_reason(code: u8) -> &'static str {
+    match code {
+        0 | 2 => "network redirect",
+        _ => "host redirect",
+    }
+}
+
+// Probes reported since the last --stats-interval line
+#[derive(Default)]
+struct Interim {
+    sent: u32,
+    received: u32,
+    rtt_sum: f64,
+}
+
+impl Interim {
+    fn add(&mut self, rtt: Option<f64>) {
+        self.sent += 1;
+        if let Some(rtt) = rtt {
+            self.received += 1;
+            self.rtt_sum += rtt;
+        }
+    }
+
+    /// One line on the probes since the last one and on the run so far
+    fn print(&self, host: &str, total: &PingStatistics, decorate: bool) {
+        let loss = if self.sent == 0 { 0.0 } else { 100.0 * (self.sent - self.received) as f64 / self.sent as f64 };
+        let avg = match self.received {
+            0 => "-".to_string(),
+            n => format!("{:.2}ms", self.rtt_sum / n as f64),
+        };
+        let line = format!(
+            "{} {}: {} sent, {} received, {:.1}% loss, avg {} (total {} sent, {:.1}% loss)",
+            Local::now().format("%H:%M:%S"),
+            host,
+            self.sent,
+            self.received,
+            loss,
+            avg,
+            total.packets_sent,
+            total.packet_loss_percent
+        );
+        if decorate {
+            println!("  {} {}", "⏱".cyan(), line.dimmed());
+        } else {
+            println!("{}", line);
+        }
+    }
+}
+
+// Probes collected for the second currently in progress
+struct SecondBucket {
+    second: u64,
+    timestamp: String,
+    sent: u32,
+    times: Vec<f64>,
+}
+
+impl SecondBucket {
+    fn new(second: u64) -> Self {
+        SecondBucket {
+            second,
+            timestamp: Local::now().format("%Y-%m-%d %H:%M:%S").to_string(),
+            sent: 0,
+            times: Vec::new(),
+        }
+    }
+
+    fn finish(&self) -> SecondAggregate {
+        let received = self.times.len() as u32;
+        let lost = self.sent - received;
+        let round = |v: f64| (v * 100.0).round() / 100.0;
+        let (min, avg, max) = if self.times.is_empty() {
+            (None, None, None)
+        } else {
+            let min = self.times.iter().cloned().fold(f64::INFINITY, f64::min);
+            let max = self.times.iter().cloned().fold(f64::NEG_INFINITY, f64::max);
+            let avg = self.times.iter().sum::<f64>() / self.times.len() as f64;
+            (Some(round(min)), Some(round(avg)), Some(round(max)))
+        };
+
+        SecondAggregate {
+            second: self.second,
+            timestamp: self.timestamp.clone(),
+            sent: self.sent,
+            received,
+            lost,
+            loss_percent: round(lost as f64 / self.sent.max(1) as f64 * 100.0),
+            min_ms: min,
+            avg_ms: avg,
+            max_ms: max,
+        }
+    }
 }
 
-// Result of each ping
-#[derive(Clone, Serialize)]
-struct PingResult {
+// A probe sent and not reported yet
+struct Probe {
     seq: u32,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    rtt_ms: Option<f64>,
-    success: bool,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    timestamp: Option<String>,
-}
-
-// Statistics structure for export
-#[derive(Serialize)]
-struct PingStatistics {
-    min_ms: Option<f64>,
-    max_ms: Option<f64>,
-    avg_ms: Option<f64>,
-    std_dev_ms: Option<f64>,
-    packets_sent: u32,
-    packets_received: u32,
-    packets_lost: u32,
-    packet_loss_percent: f64,
-}
-
-// Complete report structure for JSON export
-#[derive(Serialize)]
-struct PingReport {
-    host: String,
-    ip_address: String,
-    timestamp_start: String,
-    timestamp_end: String,
-    timeout_seconds: u64,
-    results: Vec<PingResult>,
-    statistics: PingStatistics,
+    // Where it was sent, which a re-resolved host can change
+    addr: IpAddr,
+    // When its burst went out, which its timeout counts from, and how long sending took
+    start: Instant,
+    sent_at: SystemTime,
+    send_call: Duration,
+    timestamp: DateTime<Local>,
+    // What became of it once known; a probe without an answer when its time is up timed out
+    answer: Option<Answer>,
+    // One of the first `--warmup` probes, shown but left out of the statistics and exports
+    warmup: bool,
+    // Answered after a later probe, and further replies that came before it was reported
+    reordered: bool,
+    duplicates: Vec<Reply>,
+}
+
+enum Answer {
+    Reply(Reply),
+    SendError(Option<String>),
+    ReceiveError(String),
 }
 
 fn checksum(data: &[u8]) -> u16 {
@@ -103,33 +788,92 @@ fn checksum(data: &[u8]) -> u16 {
     !sum as u16
 }
 
-fn create_icmp_packet(sequence: u16, identifier: u16) -> Vec<u8> {
-    let mut buffer = vec![0u8; 64];
-    
-    let mut packet = MutableEchoRequestPacket::new(&mut buffer).unwrap();
-    packet.set_icmp_type(IcmpTypes::EchoRequest);
+/// Write an echo request into a reused buffer, as long as the request
+///
+/// ICMPv6 echo requests have the same layout under another type. Their
+/// checksum covers the source address, which is only known once the kernel
+/// picked a route, so it's left for the kernel to fill in (RFC 3542).
+fn write_icmp_packet(buffer: &mut [u8], sequence: u16, identifier: u16, ipv6: bool) {
+    write_echo_request(buffer, sequence, identifier, ipv6, None, false);
+}
+
+/// Write an echo request whose payload is `pattern` repeated, or the tag followed by zeros without one;
+/// `stamped` puts the send time in front of it where there's room
+fn write_echo_request(buffer: &mut [u8], sequence: u16, identifier: u16, ipv6: bool, pattern: Option<&[u8]>, stamped: bool) {
+    buffer[..ICMP_HEADER_LEN].fill(0);
+    let payload = &mut buffer[ICMP_HEADER_LEN..];
+    let skip = if stamped && stamp::write(payload) { stamp::STAMP_LEN } else { 0 };
+    fill_payload(&mut payload[skip..], pattern);
+
+    let mut packet = MutableEchoRequestPacket::new(buffer).unwrap();
+    if ipv6 {
+        packet.set_icmp_type(IcmpType::new(transport::ICMP6_ECHO_REQUEST));
+    } else {
+        packet.set_icmp_type(IcmpTypes::EchoRequest);
+    }
     packet.set_icmp_code(IcmpCode::new(0));
     packet.set_sequence_number(sequence);
     packet.set_identifier(identifier);
-    packet.set_payload(b"RustPing!");
-    
-    let cs = checksum(packet.packet());
-    packet.set_checksum(cs);
     
-    buffer
+    if !ipv6 {
+        let cs = checksum(packet.packet());
+        packet.set_checksum(cs);
+    }
+}
+
+/// Fill the payload of an echo request, which its reply should carry back unchanged
+fn fill_payload(payload: &mut [u8], pattern: Option<&[u8]>) {
+    payload.fill(0);
+    match pattern.filter(|pattern| !pattern.is_empty()) {
+        Some(pattern) => {
+            for (byte, &value) in payload.iter_mut().zip(pattern.iter().cycle()) {
+                *byte = value;
+            }
+        }
+        None => {
+            let tag = b"RustPing!";
+            let n = tag.len().min(payload.len());
+            payload[..n].copy_from_slice(&tag[..n]);
+        }
+    }
+}
+
+/// Whether an echo reply carried back the payload of a request of `size` bytes, its stamp aside
+fn payload_intact(payload: &[u8], size: usize, pattern: Option<&[u8]>, stamped: bool) -> bool {
+    let skip = if stamped && size >= stamp::STAMP_LEN { stamp::STAMP_LEN } else { 0 };
+    let mut expected = vec![0; size - skip];
+    fill_payload(&mut expected, pattern);
+    payload.len() == size && payload[skip..] == expected
+}
+
+/// "1×5, 3×1" for five probes lost on their own and three lost in a row once
+fn loss_bursts(bursts: &BTreeMap<u32, u32>) -> Option<String> {
+    (!bursts.is_empty()).then(|| bursts.iter().map(|(length, n)| format!("{}×{}", length, n)).collect::<Vec<_>>().join(", "))
 }
 
 /// Get color based on latency
 fn get_latency_color(rtt: f64) -> ColoredString {
-    let rtt_str = format!("{:>7.2}ms", rtt);
-    if rtt < 20.0 {
-        rtt_str.green()
-    } else if rtt < 50.0 {
-        rtt_str.yellow()
-    } else if rtt < 100.0 {
-        rtt_str.truecolor(255, 165, 0) // orange
+    paint(format!("{:>7.2}ms", rtt), rtt)
+}
+
+// RTTs the output is colored by, from the config file
+static COLORS: OnceLock<Colors> = OnceLock::new();
+
+fn colors() -> Colors {
+    COLORS.get().copied().unwrap_or_default()
+}
+
+/// Color text by an RTT: green, yellow, orange or red
+fn paint(text: String, rtt: f64) -> ColoredString {
+    let colors = colors();
+    if rtt < colors.good {
+        text.green()
+    } else if rtt < colors.fair {
+        text.yellow()
+    } else if rtt < colors.poor {
+        text.truecolor(255, 165, 0) // orange
     } else {
-        rtt_str.red()
+        text.red()
     }
 }
 
@@ -145,21 +889,13 @@ fn draw_bar(rtt: f64, max_rtt: f64, width: usize) -> String {
     let empty: String = empty_char.repeat(empty_width);
     
     // Color based on latency
-    let colored_bar = if rtt < 20.0 {
-        bar.green()
-    } else if rtt < 50.0 {
-        bar.yellow()
-    } else if rtt < 100.0 {
-        bar.truecolor(255, 165, 0)
-    } else {
-        bar.red()
-    };
+    let colored_bar = paint(bar, rtt);
     
     format!("│{}{}│", colored_bar, empty.dimmed())
 }
 
 /// Print result with bar graph
-fn print_with_bar(seq: u32, rtt: Option<f64>, max_rtt: f64, addr: IpAddr) {
+fn print_with_bar(seq: u32, rtt: Option<f64>, status: ProbeStatus, max_rtt: f64, addr: IpAddr) {
     const BAR_WIDTH: usize = 40;
     
     match rtt {
@@ -170,7 +906,7 @@ fn print_with_bar(seq: u32, rtt: Option<f64>, max_rtt: f64, addr: IpAddr) {
                 seq,
                 bar,
                 get_latency_color(time),
-                addr.to_string().dimmed()
+                rdns::label(addr).dimmed()
             );
         }
         None => {
@@ -179,14 +915,139 @@ fn print_with_bar(seq: u32, rtt: Option<f64>, max_rtt: f64, addr: IpAddr) {
                 "  seq={:<3} │{}│ {}",
                 seq,
                 timeout_bar.red(),
-                "TIMEOUT".red().bold()
+                status.name().replace('_', " ").to_uppercase().red().bold()
+            );
+        }
+    }
+}
+
+/// Print one per-second summary row
+fn print_aggregate(agg: &SecondAggregate, show_graph: bool, max_rtt: f64) {
+    const BAR_WIDTH: usize = 40;
+
+    let loss = format!("{:>5.1}%", agg.loss_percent);
+    let loss = if agg.lost == 0 { loss.green() } else { loss.red() };
+
+    match (agg.min_ms, agg.avg_ms, agg.max_ms) {
+        (Some(min), Some(avg), Some(max)) => {
+            if show_graph {
+                println!(
+                    "  t={:<4} {} {}  {}/{} recv, loss {}",
+                    format!("{}s", agg.second),
+                    draw_bar(avg, max_rtt.max(1.0), BAR_WIDTH),
+                    get_latency_color(avg),
+                    agg.received,
+                    agg.sent,
+                    loss
+                );
+            } else {
+                println!(
+                    "  {} t={:<4} {}/{} recv, loss {}  min/avg/max = {:.2}/{}/{:.2}ms",
+                    "∑".cyan(),
+                    format!("{}s", agg.second),
+                    agg.received,
+                    agg.sent,
+                    loss,
+                    min,
+                    get_latency_color(avg),
+                    max
+                );
+            }
+        }
+        _ => {
+            println!(
+                "  {} t={:<4} {}/{} recv, loss {}",
+                "✗".red(),
+                format!("{}s", agg.second),
+                agg.received,
+                agg.sent,
+                loss
             );
         }
     }
 }
 
+/// Show a marker in the output and keep it for the line graph and the reports
+fn add_marker(marker: Marker, decorate: bool, marks: &mut Vec<Marker>, exporter: Option<&mut ExportWriter>) {
+    if decorate {
+        println!(
+            "  {} {} {}",
+            "▼".magenta(),
+            marker.name.magenta().bold(),
+            format!("at {} (before seq={})", marker.timestamp, marker.seq).dimmed()
+        );
+    }
+    if let Some(exporter) = exporter {
+        exporter.record_marker(&marker);
+    }
+    marks.push(marker);
+}
+
+/// Show a site switch in the output and keep it for the line graph and the reports
+fn add_site_switch(switch: SiteSwitch, decorate: bool, switches: &mut Vec<SiteSwitch>, exporter: Option<&mut ExportWriter>) {
+    if decorate {
+        anycast::print_switch(&switch);
+    }
+    if let Some(exporter) = exporter {
+        exporter.record_site_switch(&switch);
+    }
+    switches.push(switch);
+}
+
+/// A further reply to probe `seq`, with its own RTT, from the stamp it echoes if `stamped`
+fn duplicate_result(seq: u32, reply: &Reply, sent_at: SystemTime, correction: Option<f64>, stamped: bool) -> PingResult {
+    let elapsed = reply
+        .payload
+        .as_deref()
+        .filter(|_| stamped)
+        .and_then(|payload| stamp::rtt(payload, reply.received))
+        .unwrap_or_else(|| reply.received.duration_since(sent_at).unwrap_or_default());
+    let rtt = elapsed.as_secs_f64() * 1000.0;
+    let rtt = (rtt - correction.unwrap_or(0.0)).max(0.0);
+    PingResult {
+        seq,
+        rtt_ms: Some((rtt * 100.0).round() / 100.0),
+        raw_rtt_ms: None,
+        status: ProbeStatus::Success,
+        ttl: reply.ttl,
+        timestamp: Some(Local::now()),
+        duplicate: true,
+        reordered: false,
+        corrupted: false,
+    }
+}
+
+/// Show a duplicate reply and count it, without taking it for a probe of its
+/// own; `rtt_stats` is None for replies to probes sent before a reset
+fn add_duplicate(
+    result: &PingResult,
+    source: IpAddr,
+    per_probe: bool,
+    rtt_stats: Option<&mut RttStats>,
+    exporter: Option<&mut ExportWriter>,
+) -> Result<(), String> {
+    if per_probe {
+        println!(
+            "  {} Reply from {}: seq={} time={}{} {}",
+            "✓".yellow(),
+            rdns::label(source),
+            result.seq,
+            get_latency_color(result.rtt_ms.unwrap_or_default()),
+            result.ttl.map_or(String::new(), |ttl| format!(" ttl={}", ttl)),
+            "(DUP!)".yellow().bold()
+        );
+    }
+    if let Some(rtt_stats) = rtt_stats {
+        rtt_stats.add_duplicate();
+    }
+    if let Some(exporter) = exporter {
+        exporter.record_duplicate(result)?;
+    }
+    Ok(())
+}
+
 /// Draw ASCII line graph at the end
-fn draw_line_graph(results: &[PingResult]) {
+fn draw_line_graph(results: &History<PingResult>, marks: &[Marker]) {
     let times: Vec<f64> = results.iter()
         .filter_map(|r| r.rtt_ms)
         .collect();
@@ -199,7 +1060,12 @@ fn draw_line_graph(results: &[PingResult]) {
     let max_rtt = times.iter().cloned().fold(0.0_f64, f64::max);
     let min_rtt = times.iter().cloned().fold(f64::MAX, f64::min);
     let height = 10;
-    let width = results.len().min(60);
+
+    // Long runs are downsampled so the whole run fits, spikes included
+    let statuses: Vec<ProbeStatus> = results.iter().map(|r| r.status).collect();
+    let rtts: Vec<Option<f64>> = results.iter().map(|r| r.rtt_ms).collect();
+    let columns = downsample::lttb(&rtts, 60);
+    let width = columns.len();
     
     println!("\n{}", "╔════════════════════════════════════════════════════════════╗".cyan());
     println!("{}", "║              📈 LATENCY GRAPH OVER TIME                     ║".cyan());
@@ -209,8 +1075,8 @@ fn draw_line_graph(results: &[PingResult]) {
     let mut graph: Vec<Vec<char>> = vec![vec![' '; width]; height];
     
     // Fill the graph
-    for (i, result) in results.iter().enumerate().take(width) {
-        if let Some(rtt) = result.rtt_ms {
+    for (i, column) in columns.iter().enumerate() {
+        if let Some(rtt) = column.rtt {
             let normalized = if max_rtt > min_rtt {
                 ((rtt - min_rtt) / (max_rtt - min_rtt) * (height - 1) as f64) as usize
             } else {
@@ -220,14 +1086,15 @@ fn draw_line_graph(results: &[PingResult]) {
             graph[row][i] = '●';
             
             // Fill downward with line
-            for r in (row + 1)..height {
-                if graph[r][i] == ' ' {
-                    graph[r][i] = '│';
-                }
+            for cells in graph.iter_mut().skip(row + 1) {
+                cells[i] = '│';
+            }
+        }
+        if let Some(lost) = column.lost {
+            if graph[height - 1][i] != '●' {
+                // Lost probe - mark the cause at the bottom (✗ timeout, U unreachable, T TTL exceeded, P parameter problem)
+                graph[height - 1][i] = statuses[lost].marker();
             }
-        } else {
-            // Timeout - mark with X at the bottom
-            graph[height - 1][i] = '✗';
         }
     }
     
@@ -245,29 +1112,54 @@ fn draw_line_graph(results: &[PingResult]) {
             line.green()
         };
         
-        if i == 0 {
-            println!("  {} ┤{}", y_label.dimmed(), colored_line);
-        } else if i == height - 1 {
+        if i == 0 || i == height - 1 {
             println!("  {} ┤{}", y_label.dimmed(), colored_line);
         } else {
             println!("  {} │{}", y_label.dimmed(), colored_line);
         }
     }
     
+    // Markers point at the column holding the first probe sent after them
+    let marked: Vec<(usize, &Marker)> = marks
+        .iter()
+        .filter_map(|marker| {
+            let index = results.iter().position(|r| r.seq >= marker.seq)?;
+            let column = (0..width).rev().find(|&c| c * results.len() / width <= index)?;
+            Some((column, marker))
+        })
+        .collect();
+    if !marked.is_empty() {
+        let mut row = vec![' '; width];
+        for (column, _) in &marked {
+            row[*column] = '▼';
+        }
+        println!("            {}", row.iter().collect::<String>().magenta());
+    }
+
     // X axis
     println!("         └{}", "─".repeat(width));
     
-    // X axis labels
-    let x_labels: String = (0..width)
-        .map(|i| if i % 5 == 0 { format!("{}", i % 10) } else { " ".to_string() })
-        .collect();
-    println!("          {}", x_labels.dimmed());
-    println!("          {}", "seq ->".dimmed());
+    if width < results.len() {
+        let first = results.iter().next().map_or(0, |r| r.seq);
+        let last = results.iter().last().map_or(0, |r| r.seq);
+        println!("          {}", format!("seq {} -> {} (~{} probes per column)", first, last, results.len().div_ceil(width)).dimmed());
+    } else {
+        // X axis labels
+        let x_labels: String = (0..width)
+            .map(|i| if i % 5 == 0 { format!("{}", i % 10) } else { " ".to_string() })
+            .collect();
+        println!("          {}", x_labels.dimmed());
+        println!("          {}", "seq ->".dimmed());
+    }
+
+    for (_, marker) in &marked {
+        println!("          {} {} {}", "▼".magenta(), marker.name.magenta(), format!("seq {}", marker.seq).dimmed());
+    }
 }
 
 /// Show latency distribution histogram
-fn draw_histogram(times: &[f64]) {
-    if times.is_empty() {
+fn draw_histogram(rtt: &RttStats) {
+    if rtt.count() == 0 {
         return;
     }
     
@@ -284,17 +1176,17 @@ fn draw_histogram(times: &[f64]) {
         (100.0, f64::MAX, "  >100ms"),
     ];
     
-    let total = times.len();
+    let total = rtt.count();
     
     for (min, max, label) in buckets.iter() {
-        let count = times.iter().filter(|&&t| t >= *min && t < *max).count();
+        let count = rtt.sketch().count_between(*min, *max);
         let percentage = (count as f64 / total as f64) * 100.0;
         let bar_len = (percentage / 2.0) as usize;
         
         let bar = "█".repeat(bar_len);
-        let colored_bar = if *max <= 20.0 {
+        let colored_bar = if *max <= colors().good {
             bar.green()
-        } else if *max <= 50.0 {
+        } else if *max <= colors().fair {
             bar.yellow()
         } else {
             bar.red()
@@ -312,366 +1204,1485 @@ fn draw_histogram(times: &[f64]) {
 
 /// Print color legend
 fn print_legend() {
-    println!("\n  {} {} {} {} {} {} {}",
+    let Colors { good, fair, poor } = colors();
+    println!("\n  {} {} {} {} {} {} {} {} {}",
         "Legend:".dimmed(),
-        "●".green(), "<20ms".green(),
-        "●".yellow(), "20-50ms".yellow(),
-        "●".red(), ">50ms".red()
+        "●".green(), format!("<{}ms", good).green(),
+        "●".yellow(), format!("{}-{}ms", good, fair).yellow(),
+        "●".truecolor(255, 165, 0), format!("{}-{}ms", fair, poor).truecolor(255, 165, 0),
+        "●".red(), format!(">{}ms", poor).red()
     );
 }
 
-fn calculate_statistics(times: &[f64], total: u32) -> PingStatistics {
-    let successful = times.len() as u32;
-    let failed = total - successful;
-    
-    if times.is_empty() {
-        return PingStatistics {
-            min_ms: None,
-            max_ms: None,
-            avg_ms: None,
-            std_dev_ms: None,
-            packets_sent: total,
-            packets_received: successful,
-            packets_lost: failed,
-            packet_loss_percent: 100.0,
-        };
-    }
-    
-    let min = times.iter().cloned().fold(f64::INFINITY, f64::min);
-    let max = times.iter().cloned().fold(f64::NEG_INFINITY, f64::max);
-    let avg: f64 = times.iter().sum::<f64>() / times.len() as f64;
-    
-    let variance: f64 = times.iter()
-        .map(|t| (t - avg).powi(2))
-        .sum::<f64>() / times.len() as f64;
-    let std_dev = variance.sqrt();
-    
-    PingStatistics {
-        min_ms: Some((min * 100.0).round() / 100.0),
-        max_ms: Some((max * 100.0).round() / 100.0),
-        avg_ms: Some((avg * 100.0).round() / 100.0),
-        std_dev_ms: Some((std_dev * 100.0).round() / 100.0),
-        packets_sent: total,
-        packets_received: successful,
-        packets_lost: failed,
-        packet_loss_percent: ((failed as f64 / total as f64) * 100.0 * 100.0).round() / 100.0,
+/// Statistics of a target on one uncolored line, for --quiet
+fn print_quiet_stats(host: &str, addr: IpAddr, stats: &PingStatistics) {
+    let mut line = format!(
+        "{} ({}): {} sent, {} received, {:.1}% loss",
+        host, addr, stats.packets_sent, stats.packets_received, stats.packet_loss_percent
+    );
+    if let (Some(min), Some(avg), Some(max), Some(p95)) = (stats.min_ms, stats.avg_ms, stats.max_ms, stats.p95_ms) {
+        line.push_str(&format!(", rtt min/avg/max/p95 {:.2}/{:.2}/{:.2}/{:.2} ms", min, avg, max, p95));
     }
+    println!("{}", line);
 }
 
-fn print_stats(times: &[f64], total: u32, successful: u32, addr: IpAddr) {
-    let failed = total - successful;
-    
+fn print_stats(stats: &PingStatistics, addr: IpAddr) {
     println!("\n{}", "╔════════════════════════════════════════════════════════════╗".blue());
     println!("{}", "║                      📋 STATISTICS                          ║".blue());
     println!("{}", "╚════════════════════════════════════════════════════════════╝".blue());
     
     println!("  Host: {}", addr.to_string().cyan());
     println!("  Packets: {} sent, {} received, {} lost ({:.1}%)",
-        total.to_string().white(),
-        successful.to_string().green(),
-        failed.to_string().red(),
-        (failed as f64 / total as f64) * 100.0
+        stats.packets_sent.to_string().white(),
+        stats.packets_received.to_string().green(),
+        stats.packets_lost.to_string().red(),
+        stats.packet_loss_percent
     );
+    if stats.packets_lost > 0 {
+        println!("  Lost: {} timeout, {} unreachable, {} TTL exceeded, {} parameter problems, {} send errors",
+            stats.packets_timeout,
+            stats.packets_unreachable,
+            stats.packets_ttl_exceeded,
+            stats.packets_parameter_problem,
+            stats.packets_send_error
+        );
+    }
+    if let Some(bursts) = loss_bursts(&stats.loss_bursts) {
+        println!("  Lost in a row: {} at most; bursts {}", stats.longest_loss_streak.to_string().red(), bursts);
+    }
+    if stats.packets_corrupted > 0 {
+        println!("  Corrupted replies: {} (bad checksum, discarded)", stats.packets_corrupted.to_string().red());
+    }
+    if stats.packets_payload_corrupted > 0 {
+        println!("  Corrupted payloads: {} (received, counted as replies)", stats.packets_payload_corrupted.to_string().red());
+    }
+    if stats.packets_duplicate > 0 || stats.packets_reordered > 0 {
+        println!("  Duplicates: {}, out of order: {}",
+            stats.packets_duplicate.to_string().yellow(),
+            stats.packets_reordered.to_string().yellow()
+        );
+    }
 
-    if !times.is_empty() {
-        let min = times.iter().cloned().fold(f64::INFINITY, f64::min);
-        let max = times.iter().cloned().fold(f64::NEG_INFINITY, f64::max);
-        let avg: f64 = times.iter().sum::<f64>() / times.len() as f64;
-        
-        // Calculate standard deviation
-        let variance: f64 = times.iter()
-            .map(|t| (t - avg).powi(2))
-            .sum::<f64>() / times.len() as f64;
-        let std_dev = variance.sqrt();
-        
+    if let (Some(min), Some(avg), Some(max), Some(std_dev)) =
+        (stats.min_ms, stats.avg_ms, stats.max_ms, stats.std_dev_ms)
+    {
         println!("\n  RTT:");
         println!("    Min: {}", format!("{:.2}ms", min).green());
         println!("    Avg: {}", format!("{:.2}ms", avg).yellow());
         println!("    Max: {}", format!("{:.2}ms", max).red());
         println!("    StdDev: {}", format!("{:.2}ms", std_dev).cyan());
     }
-}
-
-/// Export results to JSON file
-fn export_json(
-    report: &PingReport,
-    filename: &str,
-) -> Result<(), String> {
-    let json = serde_json::to_string_pretty(report)
-        .map_err(|e| format!("Failed to serialize JSON: {}", e))?;
-    
-    let mut file = File::create(filename)
-        .map_err(|e| format!("Failed to create file '{}': {}", filename, e))?;
-    
-    file.write_all(json.as_bytes())
-        .map_err(|e| format!("Failed to write to file '{}': {}", filename, e))?;
-    
-    println!("\n  {} Exported to JSON: {}", "✓".green(), filename.cyan());
-    Ok(())
-}
-
-/// Export results to CSV file
-fn export_csv(
-    results: &[PingResult],
-    stats: &PingStatistics,
-    host: &str,
-    addr: IpAddr,
-    filename: &str,
-) -> Result<(), String> {
-    let mut file = File::create(filename)
-        .map_err(|e| format!("Failed to create file '{}': {}", filename, e))?;
-    
-    // Write header
-    writeln!(file, "# Ping Report")
-        .map_err(|e| format!("Failed to write to file: {}", e))?;
-    writeln!(file, "# Host: {}", host)
-        .map_err(|e| format!("Failed to write to file: {}", e))?;
-    writeln!(file, "# IP: {}", addr)
-        .map_err(|e| format!("Failed to write to file: {}", e))?;
-    writeln!(file, "# Generated: {}", Local::now().format("%Y-%m-%d %H:%M:%S"))
-        .map_err(|e| format!("Failed to write to file: {}", e))?;
-    writeln!(file, "#")
-        .map_err(|e| format!("Failed to write to file: {}", e))?;
-    
-    // Write column headers
-    writeln!(file, "seq,rtt_ms,success,timestamp")
-        .map_err(|e| format!("Failed to write to file: {}", e))?;
-    
-    // Write data rows
-    for result in results {
-        let rtt_str = result.rtt_ms.map_or("".to_string(), |r| format!("{:.2}", r));
-        let timestamp = result.timestamp.clone().unwrap_or_default();
-        writeln!(
-            file,
-            "{},{},{},{}",
-            result.seq,
-            rtt_str,
-            result.success,
-            timestamp
-        ).map_err(|e| format!("Failed to write to file: {}", e))?;
+    if let (Some(jitter), Some(delta_avg), Some(delta_max)) = (stats.jitter_ms, stats.delta_avg_ms, stats.delta_max_ms) {
+        println!("    Jitter: {} (RFC 3550)  delta avg {}  max {}",
+            format!("{:.2}ms", jitter).cyan(),
+            format!("{:.2}ms", delta_avg).cyan(),
+            format!("{:.2}ms", delta_max).cyan()
+        );
+    }
+    if let (Some(p50), Some(p95), Some(p99)) = (stats.p50_ms, stats.p95_ms, stats.p99_ms) {
+        println!("    Percentiles: p50 {}  p95 {}  p99 {}",
+            format!("{:.2}ms", p50).green(),
+            format!("{:.2}ms", p95).yellow(),
+            format!("{:.2}ms", p99).red()
+        );
     }
-    
-    // Write statistics section
-    writeln!(file, "\n# Statistics")
-        .map_err(|e| format!("Failed to write to file: {}", e))?;
-    writeln!(file, "# packets_sent,packets_received,packets_lost,loss_percent,min_ms,avg_ms,max_ms,std_dev_ms")
-        .map_err(|e| format!("Failed to write to file: {}", e))?;
-    writeln!(
-        file,
-        "{},{},{},{:.2},{},{},{},{}",
-        stats.packets_sent,
-        stats.packets_received,
-        stats.packets_lost,
-        stats.packet_loss_percent,
-        stats.min_ms.map_or("".to_string(), |v| format!("{:.2}", v)),
-        stats.avg_ms.map_or("".to_string(), |v| format!("{:.2}", v)),
-        stats.max_ms.map_or("".to_string(), |v| format!("{:.2}", v)),
-        stats.std_dev_ms.map_or("".to_string(), |v| format!("{:.2}", v)),
-    ).map_err(|e| format!("Failed to write to file: {}", e))?;
-    
-    println!("  {} Exported to CSV: {}", "✓".green(), filename.cyan());
-    Ok(())
 }
 
 fn ping(
+    args: &Args,
+    sinks: &Mutex<Sinks>,
+    keys: Option<&Keys>,
+    overhead: Option<f64>,
+    mut channel: Channel,
     host: &str,
-    addr: IpAddr,
-    count: u32,
-    timeout: Duration,
-    show_graph: bool,
-    show_line: bool,
-    json_file: Option<String>,
-    csv_file: Option<String>,
-) -> Result<(), String> {
-    let protocol = Layer4(Ipv4(IpNextHeaderProtocols::Icmp));
-    
-    let (mut tx, mut rx) = transport_channel(1024, protocol)
-        .map_err(|e| format!("Error creating channel (root permissions?): {}", e))?;
+    mut addr: IpAddr,
+) -> Result<PingStatistics, String> {
+    let multi_host = args.hosts.len() > 1;
+    // Machine-readable output modes own stdout, and quiet runs only print their statistics
+    let decorate = args.output == OutputFormat::Text && !args.quiet;
+    // Runs with a duration end on time instead, and endless ones when interrupted
+    let endless = args.forever || args.count == 0;
+    // Warm-up probes go out ahead of those counted
+    let count = if args.duration.is_some() || endless { u32::MAX } else { args.count.saturating_add(args.warmup) };
+    let timeout = args.timeout;
+    // Interval and graphs can be changed from the keyboard in interactive runs
+    let mut interval = args.interval;
+    let mut show_graph = args.graph;
+    let mut show_line = args.line_graph;
+    let rate = args.burst as f64 / args.interval.as_secs_f64();
+    let aggregate = match args.aggregate {
+        AggregateMode::Always => true,
+        AggregateMode::Never => false,
+        AggregateMode::Auto => rate > 5.0 && args.flood.is_none(),
+    };
+    let per_probe = decorate && !aggregate && args.flood.is_none();
+    // Flood pings print a dot for every probe sent and take it back when the reply comes in
+    let dots = decorate && args.flood.is_some();
 
-    let mut rx_iter = icmp_packet_iter(&mut rx);
-    let identifier = std::process::id() as u16;
+    let identifier = channel.identifier();
+    // Keys steer the run in interactive mode; the dashboard reads them itself and only quits here
+    let controls = keys.filter(|_| args.output == OutputFormat::Text);
     
-    let mut results: Vec<PingResult> = Vec::new();
-    let mut times: Vec<f64> = Vec::new();
+    // Recent results for the line graph; sinks and exports see every one as it happens
+    let keep_history = show_line || controls.is_some();
+    let mut results = History::new(if keep_history { args.history as usize } else { 0 });
+    let mut rtt_stats = RttStats::new();
+    let mut bucket = SecondBucket::new(0);
     
     // Initial estimate for bar max
     let mut max_rtt_estimate = 50.0_f64;
     
     let timestamp_start: DateTime<Local> = Local::now();
+    let run_start = Instant::now();
+    // Nothing is sent or waited for past the deadline
+    let cutoff = args.deadline.map(|deadline| run_start + deadline);
+
+    // Exports are written while the run progresses
+    let header = ReportHeader {
+        run_id: new_run_id(),
+        host: host.to_string(),
+        ip_address: addr.to_string(),
+        timestamp_start: timestamp_start.format("%Y-%m-%d %H:%M:%S").to_string(),
+        timeout_ms: timeout.as_secs_f64() * 1000.0,
+        interval_seconds: args.interval.as_secs_f64(),
+        overhead_ms: overhead,
+        overhead_subtracted: overhead.is_some() && args.subtract_overhead,
+        ttl: args.ttl,
+        interface: args.interface.clone(),
+        source_address: args.source.map(|source| source.to_string()),
+        command_line: std::env::args_os().map(|arg| arg.to_string_lossy().into_owned()).collect(),
+    };
+    // Calibrated overhead taken off every RTT
+    let correction = if header.overhead_subtracted { overhead } else { None };
+    // Each target needs its own files when several are pinged
+    let expand = |template: &Option<String>| {
+        template.as_deref()
+            .map(|t| {
+                if multi_host {
+                    expand_path(&with_host_placeholder(t), &header, &timestamp_start)
+                } else {
+                    expand_path(t, &header, &timestamp_start)
+                }
+            })
+            .transpose()
+    };
+    let files = ExportFiles {
+        json: expand(&args.json)?,
+        csv: expand(&args.csv)?,
+        xlsx: expand(&args.xlsx)?,
+        yaml: expand(&args.yaml)?,
+        cbor: expand(&args.cbor)?,
+        msgpack: expand(&args.msgpack)?,
+        html: expand(&args.html)?,
+        csv_format: CsvFormat { strict: args.csv_strict, delimiter: args.csv_delimiter, decimal: args.csv_decimal },
+    };
+    let target = Target {
+        run_id: &header.run_id,
+        host,
+        addr,
+    };
+    let mut exporter = if files.is_empty() {
+        None
+    } else {
+        Some(ExportWriter::create(&header, &files, aggregate, args.raw_samples)?)
+    };
 
     // Header
-    println!("\n{}", "╔════════════════════════════════════════════════════════════╗".cyan());
-    println!("{}       PING {} - {} packets                {}",
-        "║".cyan(),
-        addr.to_string().yellow().bold(),
-        count.to_string().green(),
-        "║".cyan()
-    );
-    println!("{}", "╚════════════════════════════════════════════════════════════╝".cyan());
-    
-    if show_graph {
-        print_legend();
-        println!();
-    }
-
-    for seq in 0..count {
-        let packet = create_icmp_packet(seq as u16, identifier);
-        let start = Instant::now();
-        let ping_timestamp = Local::now().format("%Y-%m-%d %H:%M:%S%.3f").to_string();
-
-        if let Err(e) = tx.send_to(
-            pnet::packet::icmp::IcmpPacket::new(&packet).unwrap(),
-            addr,
-        ) {
-            println!("  {} Send error: {}", "✗".red(), e);
-            results.push(PingResult {
-                seq,
+    if decorate {
+        println!("\n{}", "╔════════════════════════════════════════════════════════════╗".cyan());
+        let length = match (args.duration, args.deadline) {
+            (Some(duration), Some(deadline)) => humantime::format_duration(duration.min(deadline)).to_string(),
+            (Some(duration), None) => humantime::format_duration(duration).to_string(),
+            (None, Some(deadline)) if endless => humantime::format_duration(deadline).to_string(),
+            (None, None) if endless => "until interrupted".to_string(),
+            (None, Some(deadline)) => format!("{} packets within {}", args.count, humantime::format_duration(deadline)),
+            (None, None) => format!("{} packets", args.count),
+        };
+        println!("{}       PING {} - {}                {}",
+            "║".cyan(),
+            if args.http { host.to_string() } else { addr.to_string() }.yellow().bold(),
+            length.green(),
+            "║".cyan()
+        );
+        println!("{}", "╚════════════════════════════════════════════════════════════╝".cyan());
+
+        if let Some(path) = &args.replay {
+            println!("  {}", format!("Replay of {} at {}x speed", path, args.speed).dimmed());
+        }
+        let binding = args.binding();
+        if !binding.is_empty() {
+            println!("  {}", format!("Source: {}", binding).dimmed());
+        }
+        if let Some(port) = args.udp {
+            println!("  {}", format!("UDP probes to port {}, answered by port unreachable", port).dimmed());
+        }
+        match (&args.payload_file, &args.pattern) {
+            (Some(path), _) => println!("  {}", format!("Payload: {} ({} bytes)", path, args.size).dimmed()),
+            (None, Some(pattern)) => {
+                let hex: String = pattern.0.iter().map(|byte| format!("{:02x}", byte)).collect();
+                println!("  {}", format!("Payload pattern: {}", hex).dimmed());
+            }
+            (None, None) => {}
+        }
+        if aggregate {
+            println!("  {}", format!("Aggregating per second ({:.0} packets/s)", rate).dimmed());
+        }
+        match args.flood {
+            Some(Some(rate)) => println!("  {}", format!("Flood ping at {} packets/s", rate).dimmed()),
+            Some(None) => println!("  {}", "Flood ping: as fast as replies come back, at least 100 packets/s".dimmed()),
+            None => {}
+        }
+        if let Some(correction) = correction {
+            println!("  {}", format!("Subtracting {:.3}ms of local overhead from every RTT", correction).dimmed());
+        }
+        if args.warmup > 0 {
+            println!("  {}", format!("Warm-up: the first {} probes aren't counted", args.warmup).dimmed());
+        }
+
+        if show_graph {
+            print_legend();
+            println!();
+        }
+    }
+
+    let burst = args.burst;
+    // Echo requests are written into the same buffers for every burst
+    let mut packets = vec![vec![0u8; ICMP_HEADER_LEN + args.size as usize]; burst as usize];
+    let mut sweep = args.sweep.map(Sweep::new);
+    // Probes go out on schedule while earlier ones are still waited for, and are reported in the order sent
+    let mut in_flight: VecDeque<Probe> = VecDeque::new();
+    // Sequence numbers that got an echo reply, with the probe and when it went out, to tell duplicates by
+    let mut replied: HashMap<u16, (u32, SystemTime)> = HashMap::new();
+    // Latest probe answered, which replies to earlier ones come out of order after
+    let mut latest_reply: Option<u32> = None;
+
+    // Probes counted in the statistics, which can be reset from the keyboard;
+    // those sent before the last reset are still shown but not counted
+    let mut sent = 0;
+    let mut counted_from = 0;
+    // Next probe to send; nothing more is sent once the run is stopping, but the probes in flight are waited for
+    let mut seq = 0;
+    let mut stopping = false;
+    let mut paused = false;
+    let mut next_send = run_start;
+    let mut marks = Vec::new();
+    // Signals that arrived before this target started are not its markers
+    let mut signals_seen = markers::signals_received();
+    // Rolling summaries, and full ones on SIGQUIT; machine output formats have no room for them
+    let interim_shown = decorate || (args.quiet && args.output == OutputFormat::Text);
+    let mut interim = Interim::default();
+    let mut next_interim = args.stats_interval.map(|every| run_start + every);
+    let mut interim_seen = interrupt::interim_requests();
+    let mut breakdown = Breakdown::start();
+    let mut anycast = args.anycast.then(Tracker::default);
+    let mut switches = Vec::new();
+    // Replays have no network to look up hops on
+    let hop_watch = match (args.anycast, &args.replay) {
+        (true, None) => Some(HopWatch::start(addr)?),
+        _ => None,
+    };
+    // Addresses given as such have nothing to look up, and lookups keep to the family pinged
+    let mut dns_watch =
+        args.re_resolve.filter(|_| host.parse::<IpAddr>().is_err()).map(|every| DnsWatch::start(host, addr.is_ipv6(), every));
+    let mut per_address = args.re_resolve.map(|_| PerAddress::default());
+    let mut outages = OutageLog::new(args.down_after);
+    // A payload file is sent as it is, without a send time in front
+    let stamped = args.payload_file.is_none();
+
+    if let (true, Some(_)) = (decorate, controls) {
+        keys::print_help();
+    }
+
+    loop {
+        let past_deadline = cutoff.is_some_and(|cutoff| Instant::now() >= cutoff);
+        if keys.is_some_and(Keys::quit_requested) || interrupt::requested() || past_deadline {
+            stopping = true;
+        }
+
+        // Report the probes that were answered or whose time is up, as long as those before them are done
+        while in_flight
+            .front()
+            .is_some_and(|probe| probe.answer.is_some() || probe.start.elapsed() >= timeout || past_deadline)
+        {
+            let Some(probe) = in_flight.pop_front() else { break };
+            let counted = !probe.warmup && probe.seq >= counted_from;
+
+            // Close the previous second before accounting for this probe
+            let second = run_start.elapsed().as_secs();
+            if aggregate && second != bucket.second {
+                if bucket.sent > 0 {
+                    let agg = bucket.finish();
+                    if decorate {
+                        print_aggregate(&agg, show_graph, max_rtt_estimate);
+                    }
+                    if let Some(exporter) = exporter.as_mut() {
+                        exporter.record_aggregate(&agg)?;
+                    }
+                }
+                bucket = SecondBucket::new(second);
+            }
+            if !probe.warmup {
+                bucket.sent += 1;
+            }
+            if counted {
+                sent += 1;
+            }
+
+            let mut result = PingResult {
+                seq: probe.seq,
                 rtt_ms: None,
-                success: false,
-                timestamp: Some(ping_timestamp),
-            });
-            continue;
+                raw_rtt_ms: None,
+                status: ProbeStatus::Timeout,
+                ttl: None,
+                timestamp: Some(probe.timestamp),
+                duplicate: false,
+                reordered: probe.reordered,
+                corrupted: false,
+            };
+            // Sweeps show the payload of every probe, and warm-up probes are told apart
+            let mut size_note = sweep.as_ref().map_or(String::new(), |sweep| format!(" size={}", sweep.size(probe.seq)));
+            if probe.warmup {
+                size_note.push_str(&" (warm-up)".dimmed().to_string());
+            }
+            // UDP probes are answered by the target's port unreachable
+            let answers_udp = |reply: &Reply| {
+                args.udp.is_some() && reply.icmp_type == transport::DEST_UNREACHABLE && reply.code == 3 && reply.source == probe.addr
+            };
+            match probe.answer {
+                Some(Answer::SendError(e)) => {
+                    result.status = ProbeStatus::SendError;
+                    if let (true, Some(e)) = (per_probe, e) {
+                        println!("  {} Send error: {}", "✗".red(), e);
+                    }
+                }
+                Some(Answer::Reply(reply))
+                    if !answers_udp(&reply)
+                        && matches!(
+                            reply.icmp_type,
+                            transport::DEST_UNREACHABLE | transport::TIME_EXCEEDED | transport::PARAMETER_PROBLEM
+                        ) =>
+                {
+                    // A router or the target itself answered that the probe can't get through
+                    let reason = match reply.icmp_type {
+                        transport::DEST_UNREACHABLE => {
+                            result.status = ProbeStatus::Unreachable { code: reply.code };
+                            unreachable_reason(reply.code)
+                        }
+                        transport::TIME_EXCEEDED => {
+                            result.status = ProbeStatus::TtlExceeded;
+                            "TTL exceeded in transit"
+                        }
+                        _ => {
+                            result.status = ProbeStatus::ParameterProblem { code: reply.code };
+                            parameter_problem_reason(reply.code, probe.addr.is_ipv6())
+                        }
+                    };
+                    if !per_probe {
+                        // Summarized once the second is over, or not shown at all
+                    } else if show_graph {
+                        print_with_bar(probe.seq, None, result.status, max_rtt_estimate, reply.source);
+                    } else {
+                        println!("  {} seq={} {} from {}", "✗".red(), probe.seq, reason, rdns::label(reply.source));
+                    }
+                }
+                Some(Answer::Reply(reply)) => {
+                    let reply_addr = reply.source;
+                    // The payload is compared where the reply was read whole
+                    if let Some(payload) = &reply.payload {
+                        let size = sweep.as_ref().map_or(args.size as usize, |sweep| sweep.size(probe.seq));
+                        result.corrupted = !payload_intact(payload, size, args.pattern.as_ref().map(|pattern| pattern.0.as_slice()), stamped);
+                    }
+                    // Kernel arrival time when available, so queueing in the socket doesn't count
+                    let since_send = reply.received.duration_since(probe.sent_at).unwrap_or_else(|_| probe.start.elapsed());
+                    // Timed from the request the reply echoes, unless its payload came back damaged
+                    let elapsed = reply
+                        .payload
+                        .as_deref()
+                        .filter(|_| stamped && !result.corrupted)
+                        .and_then(|payload| stamp::rtt(payload, reply.received))
+                        .unwrap_or(since_send);
+                    let mut rtt = elapsed.as_secs_f64() * 1000.0;
+                    if let Some(correction) = correction {
+                        result.raw_rtt_ms = Some((rtt * 100.0).round() / 100.0);
+                        rtt = (rtt - correction).max(0.0);
+                    }
+                    if let Some(breakdown) = breakdown.as_mut().filter(|_| !probe.warmup) {
+                        breakdown.record(identifier, probe.seq, probe.sent_at, probe.send_call, since_send);
+                    }
+                    if counted {
+                        rtt_stats.add(rtt);
+                    }
+                    if !probe.warmup {
+                        bucket.times.push(rtt);
+                    }
+                    result.rtt_ms = Some((rtt * 100.0).round() / 100.0);
+                    result.status = ProbeStatus::Success;
+                    result.ttl = reply.ttl;
+                    if result.corrupted && counted {
+                        rtt_stats.add_payload_corrupted();
+                    }
+                    if let Some(tracker) = anycast.as_mut().filter(|_| !probe.warmup) {
+                        if let Some(switch) = tracker.reply(probe.seq, reply.ttl, rtt, probe.timestamp) {
+                            add_site_switch(switch, decorate, &mut switches, exporter.as_mut());
+                        }
+                    }
+
+                    // Update max estimate
+                    max_rtt_estimate = max_rtt_estimate.max(rtt * 1.2);
+
+                    if dots {
+                        print!("\x08");
+                        let _ = io::stdout().flush();
+                    }
+                    if !per_probe {
+                        // Summarized once the second is over, or not shown at all
+                    } else if show_graph {
+                        print_with_bar(probe.seq, Some(rtt), result.status, max_rtt_estimate, reply_addr);
+                    } else {
+                        println!(
+                            "  {} Reply from {}: seq={}{} time={}{}{}{}",
+                            "✓".green(),
+                            rdns::label(reply_addr),
+                            probe.seq,
+                            size_note,
+                            get_latency_color(rtt),
+                            reply.ttl.map_or(String::new(), |ttl| format!(" ttl={}", ttl)),
+                            if probe.reordered { " (out of order)".yellow().to_string() } else { String::new() },
+                            if result.corrupted { " (corrupted payload)".red().to_string() } else { String::new() }
+                        );
+                    }
+                }
+                None => {
+                    if !per_probe {
+                        // Summarized once the second is over, or not shown at all
+                    } else if show_graph {
+                        print_with_bar(probe.seq, None, result.status, max_rtt_estimate, probe.addr);
+                    } else {
+                        println!("  {} Timeout for seq={}{}", "✗".red(), probe.seq, size_note);
+                    }
+                }
+                Some(Answer::ReceiveError(e)) => {
+                    if per_probe {
+                        println!("  {} Error: {}", "✗".red(), e);
+                    }
+                }
+            }
+
+            if probe.warmup {
+                continue;
+            }
+            if counted && !result.success() {
+                rtt_stats.add_failure(result.status);
+            }
+            if counted && result.reordered {
+                rtt_stats.add_reordered();
+            }
+            if let Some(sweep) = sweep.as_mut() {
+                sweep.record(probe.seq, result.rtt_ms);
+            }
+            if let Some(per_address) = per_address.as_mut() {
+                per_address.record(probe.addr, probe.seq, result.rtt_ms);
+            }
+            outages.record(&result);
+            if let Some(exporter) = exporter.as_mut() {
+                exporter.record_result(&result)?;
+            }
+            sinks.lock().unwrap().result(&target, &result);
+            interim.add(result.rtt_ms);
+            if keep_history {
+                results.push(result);
+            }
+            for reply in &probe.duplicates {
+                let result = duplicate_result(probe.seq, reply, probe.sent_at, correction, stamped);
+                add_duplicate(&result, reply.source, per_probe, counted.then_some(&mut rtt_stats), exporter.as_mut())?;
+            }
         }
 
-        match rx_iter.next_with_timeout(timeout) {
-            Ok(Some((_, reply_addr))) => {
-                let rtt = start.elapsed().as_secs_f64() * 1000.0;
-                let rtt_rounded = (rtt * 100.0).round() / 100.0;
-                times.push(rtt);
-                results.push(PingResult {
-                    seq,
-                    rtt_ms: Some(rtt_rounded),
-                    success: true,
-                    timestamp: Some(ping_timestamp),
-                });
-                
-                // Update max estimate
-                max_rtt_estimate = max_rtt_estimate.max(rtt * 1.2);
-                
-                if show_graph {
-                    print_with_bar(seq, Some(rtt), max_rtt_estimate, reply_addr);
-                } else {
-                    println!(
-                        "  {} Reply from {}: seq={} time={}",
-                        "✓".green(),
-                        reply_addr,
-                        seq,
-                        get_latency_color(rtt)
-                    );
+        if let (Some(due), Some(every)) = (next_interim, args.stats_interval) {
+            if Instant::now() >= due {
+                if interim_shown {
+                    interim.print(host, &rtt_stats.statistics(sent), decorate);
                 }
+                interim = Interim::default();
+                next_interim = Some(due + every);
             }
-            Ok(None) => {
-                results.push(PingResult {
-                    seq,
-                    rtt_ms: None,
-                    success: false,
-                    timestamp: Some(ping_timestamp),
-                });
-                if show_graph {
-                    print_with_bar(seq, None, max_rtt_estimate, addr);
-                } else {
-                    println!("  {} Timeout for seq={}", "✗".red(), seq);
+        }
+        let requests = interrupt::interim_requests();
+        if requests != interim_seen {
+            interim_seen = requests;
+            if decorate {
+                print_stats(&rtt_stats.statistics(sent), addr);
+            } else if interim_shown {
+                print_quiet_stats(host, addr, &rtt_stats.statistics(sent));
+            }
+        }
+
+        if stopping && in_flight.is_empty() {
+            break;
+        }
+
+        // Paused from the keyboard or the dashboard: nothing is sent, and sending picks up without a burst
+        let held = paused || keys.is_some_and(Keys::paused);
+        if held {
+            next_send = Instant::now();
+        }
+
+        // Keep a fixed send schedule, however long the replies take; a flood without a rate doesn't wait for it once every reply is in
+        let replies_in = args.flood == Some(None) && in_flight.iter().all(|probe| probe.answer.is_some());
+        if !stopping && !held && (Instant::now() >= next_send || replies_in) {
+            if let Some(name) = &args.mark_on_signal {
+                let received = markers::signals_received();
+                for _ in signals_seen..received {
+                    add_marker(Marker::new(name, seq, markers::last_signal()), decorate, &mut marks, exporter.as_mut());
                 }
+                signals_seen = received;
             }
-            Err(e) => {
-                results.push(PingResult {
-                    seq,
-                    rtt_ms: None,
-                    success: false,
-                    timestamp: Some(ping_timestamp),
+            if let (Some(tracker), Some(watch)) = (anycast.as_mut(), &hop_watch) {
+                while let Ok(hop) = watch.hops.try_recv() {
+                    if let Some(switch) = tracker.hop(seq, hop, Local::now()) {
+                        add_site_switch(switch, decorate, &mut switches, exporter.as_mut());
+                    }
+                }
+            }
+            if let Some(moved) = dns_watch.as_mut().and_then(|watch| watch.moved(addr)) {
+                let marker = Marker::new(&format!("{} moved from {} to {}", host, addr, moved), seq, Local::now());
+                add_marker(marker, decorate, &mut marks, exporter.as_mut());
+                addr = moved;
+            }
+
+            let n = burst.min(count - seq) as usize;
+            for (i, packet) in packets[..n].iter_mut().enumerate() {
+                if let Some(sweep) = &sweep {
+                    packet.resize(ICMP_HEADER_LEN + sweep.size(seq + i as u32), 0);
+                }
+                let pattern = args.pattern.as_ref().map(|pattern| pattern.0.as_slice());
+                write_echo_request(packet, (seq as usize + i) as u16, identifier, addr.is_ipv6(), pattern, stamped);
+                // Replies to the last probe with the same sequence number are no longer told apart
+                replied.remove(&((seq as usize + i) as u16));
+            }
+            let start = Instant::now();
+            let timestamp = Local::now();
+            let sent_at = SystemTime::now();
+            let (burst_sent, error) = channel.send_batch(&packets[..n], addr);
+            let send_call = start.elapsed();
+            let error = error.map(|e| e.to_string());
+            for i in 0..n {
+                // Every probe the send didn't get to failed with it
+                let answer = (i >= burst_sent).then(|| Answer::SendError(error.clone()));
+                in_flight.push_back(Probe {
+                    seq: seq + i as u32,
+                    addr,
+                    start,
+                    sent_at,
+                    send_call,
+                    timestamp,
+                    answer,
+                    warmup: seq + (i as u32) < args.warmup,
+                    reordered: false,
+                    duplicates: Vec::new(),
                 });
-                println!("  {} Error: {}", "✗".red(), e);
+            }
+            if dots {
+                print!("{}", ".".repeat(n));
+                let _ = io::stdout().flush();
+            }
+
+            seq += n as u32;
+            // Such a flood goes as fast as the replies, so its longest wait counts from this probe
+            next_send = if args.flood == Some(None) { start + interval } else { next_send + interval };
+            if seq >= count
+                || args.duration.is_some_and(|duration| next_send >= run_start + duration)
+                || cutoff.is_some_and(|cutoff| next_send >= cutoff)
+            {
+                stopping = true;
+            }
+            continue;
+        }
+
+        // Wait for replies until the next send or the oldest probe's timeout, in slices so keys and quits aren't kept waiting
+        let now = Instant::now();
+        let mut until = now + QUIT_POLL;
+        if !stopping && !held {
+            until = until.min(next_send);
+        }
+        if let Some(probe) = in_flight.front() {
+            until = until.min(probe.start + timeout);
+        }
+        if let Some(cutoff) = cutoff {
+            until = until.min(cutoff);
+        }
+        let wait = until.saturating_duration_since(now);
+        let awaited = in_flight.iter().any(|probe| probe.answer.is_none());
+        if awaited {
+            match channel.next_with_timeout(wait) {
+                Ok(Some(reply)) => {
+                    // The probe it answers; errors quote the probe, and replies without a sequence go to the oldest one
+                    let probe = in_flight
+                        .iter_mut()
+                        .find(|probe| probe.answer.is_none() && reply.sequence.is_none_or(|sequence| sequence == probe.seq as u16));
+                    if !reply.checksum_ok {
+                        // Damaged replies are counted and skipped
+                        rtt_stats.add_corrupted();
+                        if let Some(exporter) = exporter.as_mut() {
+                            exporter.record_corrupted();
+                        }
+                        if per_probe {
+                            let of = probe.map_or(String::new(), |probe| format!(" for seq={}", probe.seq));
+                            println!("  {} Corrupted reply from {}{} (bad checksum), discarded", "⚠".yellow(), rdns::label(reply.source), of);
+                        }
+                    } else if reply.icmp_type == transport::REDIRECT {
+                        // The router still forwards the probe, so its reply is waited for
+                        if per_probe {
+                            println!(
+                                "  {} Redirect from {} ({}), waiting for the reply",
+                                "↪".yellow(),
+                                rdns::label(reply.source),
+                                redirect_reason(reply.code)
+                            );
+                        }
+                    } else if let Some(probe) = probe {
+                        probe.reordered = latest_reply.is_some_and(|latest| latest > probe.seq);
+                        latest_reply = latest_reply.max(Some(probe.seq));
+                        if let (transport::ECHO_REPLY, Some(sequence)) = (reply.icmp_type, reply.sequence) {
+                            replied.insert(sequence, (probe.seq, probe.sent_at));
+                        }
+                        probe.answer = Some(Answer::Reply(reply));
+                    } else if let Some(&(of, sent_at)) =
+                        reply.sequence.filter(|_| reply.icmp_type == transport::ECHO_REPLY).and_then(|sequence| replied.get(&sequence))
+                    {
+                        // Another reply to an answered probe, reported after the probe itself
+                        match in_flight.iter_mut().find(|probe| probe.seq == of) {
+                            Some(probe) => probe.duplicates.push(reply),
+                            None => {
+                                let result = duplicate_result(of, &reply, sent_at, correction, stamped);
+                                let stats = (of >= counted_from).then_some(&mut rtt_stats);
+                                add_duplicate(&result, reply.source, per_probe, stats, exporter.as_mut())?;
+                            }
+                        }
+                    }
+                    // Anything else answers a probe that already timed out
+                }
+                Ok(None) => {}
+                Err(e) => {
+                    if let Some(probe) = in_flight.iter_mut().find(|probe| probe.answer.is_none()) {
+                        probe.answer = Some(Answer::ReceiveError(e.to_string()));
+                    }
+                }
             }
         }
 
-        if seq < count - 1 {
-            std::thread::sleep(Duration::from_secs(1));
+        let Some(keys) = controls else {
+            if !awaited {
+                thread::sleep(wait);
+            }
+            continue;
+        };
+        // Keys are handled while waiting; only for the rest of the slice when replies were waited for
+        let mut wait = if awaited { Duration::ZERO } else { wait };
+        while let Some(key) = keys.wait(wait) {
+            wait = Duration::ZERO;
+            let now = Instant::now();
+            let note = match key {
+                Key::Pause => {
+                    paused = !paused;
+                    if paused { "Paused, press p to resume".to_string() } else { "Resumed".to_string() }
+                }
+                Key::Faster => {
+                    interval = (interval / 2).max(Duration::from_millis(1));
+                    next_send = next_send.min(now + interval);
+                    format!("Interval {:.3}s", interval.as_secs_f64())
+                }
+                Key::Slower => {
+                    interval = (interval * 2).min(Duration::from_secs(3600));
+                    format!("Interval {:.3}s", interval.as_secs_f64())
+                }
+                Key::Reset => {
+                    rtt_stats = RttStats::new();
+                    sent = 0;
+                    counted_from = seq;
+                    max_rtt_estimate = 50.0;
+                    "Statistics reset".to_string()
+                }
+                Key::ToggleGraph => {
+                    show_graph = !show_graph;
+                    format!("Bar graph {}", if show_graph { "on" } else { "off" })
+                }
+                Key::ToggleLineGraph => {
+                    show_line = !show_line;
+                    format!("Line graph at the end {}", if show_line { "on" } else { "off" })
+                }
+                Key::Mark(name) => {
+                    add_marker(Marker::new(&name, seq, Local::now()), decorate, &mut marks, exporter.as_mut());
+                    continue;
+                }
+                Key::Quit => {
+                    stopping = true;
+                    continue;
+                }
+                Key::Help => {
+                    keys::print_help();
+                    continue;
+                }
+            };
+            if decorate {
+                println!("  {} {}", "⏵".cyan(), note.cyan());
+            }
+        }
+    }
+    if aggregate && bucket.sent > 0 {
+        let agg = bucket.finish();
+        if decorate {
+            print_aggregate(&agg, show_graph, max_rtt_estimate);
+        }
+        if let Some(exporter) = exporter.as_mut() {
+            exporter.record_aggregate(&agg)?;
         }
     }
 
+    if let Some(switch) = anycast.as_mut().and_then(Tracker::finish) {
+        add_site_switch(switch, decorate, &mut switches, exporter.as_mut());
+    }
+
     let timestamp_end: DateTime<Local> = Local::now();
+    let stats = rtt_stats.statistics(sent);
+    let phases = channel.http_phases();
+    let sizes = sweep.as_ref().map(Sweep::results);
+    let addresses = per_address.as_ref().map(PerAddress::results);
+    let outages = outages.results(timestamp_end);
+    let run_seconds = (timestamp_end - timestamp_start).to_std().unwrap_or_default().as_secs_f64();
     
-    // Statistics
-    let successful = times.len() as u32;
-    print_stats(&times, count, successful, addr);
-    
-    // Line graph
-    if show_line && !results.is_empty() {
-        draw_line_graph(&results);
+    if decorate {
+        if dots {
+            println!();
+        }
+        // Statistics (multi-host runs get a combined table instead)
+        if !multi_host {
+            print_stats(&stats, addr);
+        }
+        if dots {
+            let elapsed = (timestamp_end - timestamp_start).to_std().unwrap_or_default().as_secs_f64().max(f64::EPSILON);
+            println!(
+                "  Flood: {} sent/s, {} replies/s over {:.2}s",
+                format!("{:.0}", stats.packets_sent as f64 / elapsed).cyan(),
+                format!("{:.0}", stats.packets_received as f64 / elapsed).green(),
+                elapsed
+            );
+        }
+
+        // Line graph
+        if show_line && !results.is_empty() {
+            marks.extend(switches.iter().map(SiteSwitch::marker));
+            draw_line_graph(&results, &marks);
+        }
+
+        // Histogram
+        if show_graph || show_line {
+            draw_histogram(&rtt_stats);
+        }
+
+        if let Some(breakdown) = &breakdown {
+            breakdown.print(host);
+        }
+
+        if let Some(phases) = &phases {
+            http::print_phases(host, phases);
+        }
+
+        if let Some(sizes) = &sizes {
+            sweep::print_sweep(host, sizes);
+        }
+
+        // Only worth a table once the host has moved
+        if let Some(addresses) = addresses.as_ref().filter(|addresses| addresses.len() > 1) {
+            reresolve::print_addresses(host, addresses);
+        }
+
+        if !outages.is_empty() {
+            outages::print_outages(host, &outages, run_seconds);
+        }
+
+        if args.anycast {
+            let likely = switches.iter().filter(|switch| switch.likely).count();
+            match switches.len() {
+                0 => println!("\n  {} {}", "⇄".cyan(), "No anycast site switch detected".dimmed()),
+                n => println!("\n  {} Anycast site switches: {} ({} likely)", "⇄".yellow(), n, likely),
+            }
+        }
     }
-    
-    // Histogram
-    if (show_graph || show_line) && !times.is_empty() {
-        draw_histogram(&times);
+
+    if args.quiet && args.output == OutputFormat::Text {
+        print_quiet_stats(host, addr, &stats);
     }
 
-    // Calculate statistics for export
-    let stats = calculate_statistics(&times, count);
-    
-    // Export section header
-    if json_file.is_some() || csv_file.is_some() {
-        println!("\n{}", "╔════════════════════════════════════════════════════════════╗".yellow());
-        println!("{}", "║                    📁 EXPORT RESULTS                        ║".yellow());
-        println!("{}", "╚════════════════════════════════════════════════════════════╝".yellow());
+    // Finalize exports with the statistics
+    if let Some(mut exporter) = exporter {
+        if let Some(phases) = phases {
+            exporter.record_http_phases(phases);
+        }
+        if let Some(sizes) = sizes {
+            exporter.record_size_sweep(sizes);
+        }
+        if let Some(addresses) = addresses {
+            exporter.record_addresses(addresses);
+        }
+        exporter.record_outages(outages);
+        let written = exporter.finish(&timestamp_end.format("%Y-%m-%d %H:%M:%S").to_string())?;
+        if decorate {
+            println!("\n{}", "╔════════════════════════════════════════════════════════════╗".yellow());
+            println!("{}", "║                    📁 EXPORT RESULTS                        ║".yellow());
+            println!("{}", "╚════════════════════════════════════════════════════════════╝".yellow());
+            println!();
+            for (format, filename) in written {
+                println!("  {} Exported to {}: {}", "✓".green(), format, filename.cyan());
+            }
+        }
     }
-    
-    // JSON export
-    if let Some(filename) = json_file {
-        let report = PingReport {
-            host: host.to_string(),
-            ip_address: addr.to_string(),
-            timestamp_start: timestamp_start.format("%Y-%m-%d %H:%M:%S").to_string(),
-            timestamp_end: timestamp_end.format("%Y-%m-%d %H:%M:%S").to_string(),
-            timeout_seconds: timeout.as_secs(),
-            results: results.clone(),
-            statistics: calculate_statistics(&times, count),
+
+    sinks.lock().unwrap().summary(&target, &stats);
+
+    Ok(stats)
+}
+
+/// Bytes the payload is filled with
+#[derive(Clone, Debug)]
+struct Pattern(Vec<u8>);
+
+/// Parse up to 16 bytes written in hex, as ping -p takes them
+fn parse_pattern(value: &str) -> Result<Pattern, String> {
+    let digits = value.trim().trim_start_matches("0x");
+    if digits.is_empty() || digits.len() > 32 || !digits.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(format!("invalid pattern '{}': expected 1 to 16 bytes in hex, e.g. ff00", value));
+    }
+    // Pairs of digits, and an odd one out as a last byte of its own, as ping -p reads them
+    let bytes = digits
+        .as_bytes()
+        .chunks(2)
+        .map(|pair| u8::from_str_radix(std::str::from_utf8(pair).unwrap_or_default(), 16).unwrap_or_default())
+        .collect();
+    Ok(Pattern(bytes))
+}
+
+/// Parse a percentage from 0 to 100
+fn parse_percent(value: &str) -> Result<f64, String> {
+    match value.trim().trim_end_matches('%').parse::<f64>() {
+        Ok(percent) if (0.0..=100.0).contains(&percent) => Ok(percent),
+        _ => Err(format!("invalid percentage '{}' (expected 0 to 100)", value)),
+    }
+}
+
+fn parse_csv_delimiter(value: &str) -> Result<char, String> {
+    match value {
+        "," | ";" | "|" => Ok(value.chars().next().unwrap_or(',')),
+        "tab" | "\\t" | "\t" => Ok('\t'),
+        _ => Err(format!("invalid delimiter '{}' (expected , ; | or tab)", value)),
+    }
+}
+
+fn parse_csv_decimal(value: &str) -> Result<char, String> {
+    match value {
+        "." | "," => Ok(value.chars().next().unwrap_or('.')),
+        _ => Err(format!("invalid decimal separator '{}' (expected . or ,)", value)),
+    }
+}
+
+/// Parse a duration given in seconds ("2", "0.01") or with units ("500ms", "1.5s", "1h 30m")
+fn parse_duration(value: &str) -> Result<Duration, String> {
+    let value = value.trim();
+    let invalid = || format!("invalid duration '{}' (expected e.g. 2, 0.5, 500ms, 1.5s, 5m)", value);
+    if let Ok(seconds) = value.parse::<f64>() {
+        return Duration::try_from_secs_f64(seconds).map_err(|_| invalid());
+    }
+
+    // humantime only takes whole numbers, so a single fractional value is scaled here
+    let split = value.find(|c: char| !c.is_ascii_digit() && c != '.').unwrap_or(value.len());
+    let (number, unit) = value.split_at(split);
+    if number.contains('.') {
+        let scale = match unit.trim() {
+            "us" | "µs" => 1e-6,
+            "ms" => 1e-3,
+            "s" | "sec" => 1.0,
+            "m" | "min" => 60.0,
+            "h" => 3600.0,
+            "d" => 86400.0,
+            _ => return Err(invalid()),
         };
-        export_json(&report, &filename)?;
+        let number: f64 = number.parse().map_err(|_| invalid())?;
+        return Duration::try_from_secs_f64(number * scale).map_err(|_| invalid());
     }
-    
-    // CSV export
-    if let Some(filename) = csv_file {
-        export_csv(&results, &stats, host, addr, &filename)?;
+    humantime::parse_duration(value).map_err(|_| invalid())
+}
+
+/// Resolve a hostname or parse an IP address; IPv4 is preferred unless `ipv6` asks for IPv6 only
+fn resolve_host(host: &str, ipv6: bool) -> Result<IpAddr, String> {
+    if let Ok(ip) = host.parse::<IpAddr>() {
+        if ipv6 && ip.is_ipv4() {
+            return Err(format!("{} is not an IPv6 address", host));
+        }
+        return Ok(ip);
+    }
+    if host.split_once('/').is_some_and(|(addr, _)| addr.parse::<IpAddr>().is_ok()) {
+        return Err(format!("{} is a network; sweep it with `rust_ping scan {}`", host, host));
     }
 
-    Ok(())
+    use std::net::ToSocketAddrs;
+    let addrs: Vec<IpAddr> = match (host, 0).to_socket_addrs() {
+        Ok(addrs) => addrs.map(|socket_addr| socket_addr.ip()).collect(),
+        Err(e) => return Err(format!("DNS error: {}", e)),
+    };
+    let found = if ipv6 {
+        addrs.iter().find(|addr| addr.is_ipv6())
+    } else {
+        addrs.iter().find(|addr| addr.is_ipv4()).or(addrs.first())
+    };
+    match found {
+        Some(addr) => Ok(*addr),
+        None if ipv6 => Err(format!("No IPv6 address for {}", host)),
+        None => Err(format!("Could not resolve: {}", host)),
+    }
+}
+
+/// The command line, with `ping` inserted when no subcommand is named, and
+/// where the subcommand is in it
+fn command_line() -> (Vec<std::ffi::OsString>, usize) {
+    let mut argv: Vec<std::ffi::OsString> = std::env::args_os().collect();
+
+    // Skip global options to find the first word, which names the subcommand
+    let mut first = 1;
+    while let Some(arg) = argv.get(first).and_then(|a| a.to_str()) {
+        if arg == "--config" {
+            first += 2;
+        } else if arg.starts_with("--config=") {
+            first += 1;
+        } else {
+            break;
+        }
+    }
+    let named = match argv.get(first).and_then(|a| a.to_str()) {
+        None => true,
+        Some("-h" | "--help" | "-V" | "--version" | "help") => true,
+        Some(word) => Cli::command().get_subcommands().any(|c| c.get_name() == word),
+    };
+    if !named {
+        argv.insert(first, "ping".into());
+    }
+    (argv, first)
+}
+
+/// Parse the command line; an option given twice takes the later value
+fn parse_cli(argv: &[std::ffi::OsString]) -> (Cli, ArgMatches) {
+    let parsed = Cli::command()
+        .args_override_self(true)
+        .try_get_matches_from(argv)
+        .and_then(|matches| Ok((Cli::from_arg_matches(&matches)?, matches)));
+    parsed.unwrap_or_else(|e| {
+        // Usage errors are setup errors; clap's own status would read as total loss
+        let _ = e.print();
+        std::process::exit(if e.use_stderr() { RunStatus::Failed as i32 } else { 0 });
+    })
+}
+
+/// How a ping run went, as its exit status
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+enum RunStatus {
+    /// Every probe answered, or no more lost than --max-loss
+    Clean = 0,
+    /// More loss than --max-loss, or a regression against the baseline
+    Degraded = 1,
+    /// A target answered none of its probes
+    Down = 2,
+    /// The run couldn't be set up or a target couldn't be pinged
+    Failed = 3,
+}
+
+impl RunStatus {
+    fn of(stats: &PingStatistics, max_loss: f64) -> Self {
+        if stats.packets_sent > 0 && stats.packets_received == 0 {
+            RunStatus::Down
+        } else if stats.packet_loss_percent > max_loss {
+            RunStatus::Degraded
+        } else {
+            RunStatus::Clean
+        }
+    }
 }
 
 fn main() {
-    let args = Args::parse();
-
-    let addr: IpAddr = match args.host.parse() {
-        Ok(ip) => ip,
-        Err(_) => {
-            use std::net::ToSocketAddrs;
-            match (args.host.as_str(), 0).to_socket_addrs() {
-                Ok(mut addrs) => match addrs.next() {
-                    Some(socket_addr) => socket_addr.ip(),
-                    None => {
-                        eprintln!("{} Could not resolve: {}", "Error:".red(), args.host);
-                        return;
-                    }
-                },
-                Err(e) => {
-                    eprintln!("{} DNS error: {}", "Error:".red(), e);
-                    return;
+    let (mut argv, first) = command_line();
+    let (mut cli, mut matches) = parse_cli(&argv);
+
+    let path = cli.config.clone().or_else(|| Config::default_path().map(|p| p.display().to_string()));
+    let config = match path {
+        Some(path) => match Config::load(&path) {
+            Ok(config) => config,
+            Err(e) => {
+                eprintln!("{} {}", "Error:".red(), e);
+                std::process::exit(RunStatus::Failed as i32);
+            }
+        },
+        None => Config::default(),
+    };
+
+    // A profile's hosts and options go in front of those of the command line, which override them
+    let profile = match &cli.command {
+        Command::Ping(args) => args.profile.clone(),
+        Command::Monitor(monitor) => monitor.ping().profile.clone(),
+        Command::Serve(serve) => serve.ping().profile.clone(),
+        _ => None,
+    };
+    if let Some(name) = profile {
+        match profiles::expand(&config, &name) {
+            Ok(args) => {
+                argv.splice(first + 1..first + 1, args);
+                (cli, matches) = parse_cli(&argv);
+            }
+            Err(e) => {
+                eprintln!("{} {}", "Error:".red(), e);
+                std::process::exit(RunStatus::Failed as i32);
+            }
+        }
+    }
+
+    let _ = COLORS.set(config.colors);
+    // Options left out of the command line are taken from the config file
+    let given = matches.subcommand().map(|(_, matches)| matches);
+    match (&mut cli.command, given) {
+        (Command::Ping(args), Some(given)) => apply_defaults(args, &config.defaults, given),
+        (Command::Monitor(monitor), Some(given)) => apply_defaults(monitor.ping_mut(), &config.defaults, given),
+        (Command::Serve(serve), Some(given)) => apply_defaults(serve.ping_mut(), &config.defaults, given),
+        _ => {}
+    }
+
+    // Pings exit with how the targets fared; errors of the other commands with 1
+    let pinging = matches!(cli.command, Command::Ping(_) | Command::Monitor(_) | Command::Serve(_));
+    let outcome = match cli.command {
+        Command::Ping(args) => ping_command(*args, &config),
+        Command::Monitor(args) => monitor::run(*args, &config),
+        Command::Serve(args) => serve::run(*args, &config),
+        Command::Trace(args) => trace::run(&args, &config).map(|()| RunStatus::Clean),
+        Command::Pmtu(args) => pmtu::run(&args, &config).map(|()| RunStatus::Clean),
+        Command::Scan(args) => scan::run(&args).map(|()| RunStatus::Clean),
+        Command::Profiles(args) => profiles::run(&args, &config).map(|()| RunStatus::Clean),
+        Command::History(args) => recent::run(&args).map(|()| RunStatus::Clean),
+        Command::Report(args) => report::run(&args).map(|()| RunStatus::Clean),
+        Command::Replay(args) => report::render(&args).map(|()| RunStatus::Clean),
+        Command::Diff(args) => diff::run(&args).map(|()| RunStatus::Clean),
+        Command::Merge(args) => merge::run(&args).map(|()| RunStatus::Clean),
+        Command::Selftest(args) => selftest::run(&args).map(|()| RunStatus::Clean),
+    };
+    match outcome {
+        Ok(RunStatus::Clean) => {}
+        Ok(status) => std::process::exit(status as i32),
+        Err(e) => {
+            eprintln!("{} {}", "Error:".red(), e);
+            std::process::exit(if pinging { RunStatus::Failed as i32 } else { 1 });
+        }
+    }
+}
+
+/// The `ping` subcommand as given on the command line: aliases expanded and
+/// the output flags folded into --output
+fn ping_command(mut args: Args, config: &Config) -> Result<RunStatus, String> {
+    if let Some(path) = &args.targets_file {
+        args.hosts.extend(read_targets(path)?);
+    }
+    args.hosts = config.expand_hosts(&args.hosts)?;
+    if args.hosts.is_empty() && args.replay.is_none() {
+        return Err(match args.targets_file {
+            Some(path) => format!("No hosts to ping: {} lists none", if path == "-" { "stdin" } else { &path }),
+            None => "No hosts to ping: the profile lists none, so give them on the command line".to_string(),
+        });
+    }
+    if args.tui {
+        args.output = OutputFormat::Dashboard;
+    }
+    if args.ndjson {
+        args.output = OutputFormat::Ndjson;
+    }
+    if args.influx.as_deref() == Some("-") && args.output == OutputFormat::Text {
+        args.output = OutputFormat::InfluxStdout;
+    }
+    let to_stdout: Vec<&str> = [("--json", &args.json), ("--csv", &args.csv)]
+        .into_iter()
+        .filter(|(_, file)| file.as_deref() == Some(STDOUT))
+        .map(|(option, _)| option)
+        .collect();
+    if let Some(option) = to_stdout.first() {
+        if to_stdout.len() > 1 {
+            return Err("Only one of --json and --csv can write to stdout".to_string());
+        }
+        if args.output != OutputFormat::Text {
+            return Err(format!("{} - writes to stdout, which the --output format already uses", option));
+        }
+        if args.hosts.len() > 1 {
+            return Err(format!("{} - writes the report of a single target, but {} were given", option, args.hosts.len()));
+        }
+        args.output = OutputFormat::Export;
+    }
+    run_ping(&args, config)
+}
+
+/// Hosts of a targets file (or stdin for `-`), one per line, without comments and blank lines
+fn read_targets(path: &str) -> Result<Vec<String>, String> {
+    let text = if path == "-" {
+        io::read_to_string(io::stdin()).map_err(|e| format!("Failed to read targets from stdin: {}", e))?
+    } else {
+        std::fs::read_to_string(path).map_err(|e| format!("Failed to read targets file {}: {}", path, e))?
+    };
+    Ok(text
+        .lines()
+        .map(|line| line.split('#').next().unwrap_or_default().trim())
+        .filter(|host| !host.is_empty())
+        .map(str::to_string)
+        .collect())
+}
+
+/// Fill in the options not given on the command line from `[defaults]`
+fn apply_defaults(args: &mut Args, defaults: &Defaults, given: &ArgMatches) {
+    let unset = |id: &str| given.value_source(id).is_none_or(|source| source == ValueSource::DefaultValue);
+    if let Some(count) = defaults.count.filter(|_| unset("count")) {
+        args.count = count;
+    }
+    if let Some(interval) = defaults.interval.filter(|_| unset("interval")) {
+        args.interval = interval;
+    }
+    if let Some(timeout) = defaults.timeout.filter(|_| unset("timeout")) {
+        args.timeout = timeout;
+    }
+    if defaults.resolve == Some(true) && !args.numeric {
+        args.resolve = true;
+    }
+    for (id, path, default) in [
+        ("json", &mut args.json, &defaults.json),
+        ("csv", &mut args.csv, &defaults.csv),
+        ("xlsx", &mut args.xlsx, &defaults.xlsx),
+        ("yaml", &mut args.yaml, &defaults.yaml),
+        ("cbor", &mut args.cbor, &defaults.cbor),
+        ("msgpack", &mut args.msgpack, &defaults.msgpack),
+        ("html", &mut args.html, &defaults.html),
+    ] {
+        if unset(id) && default.is_some() {
+            path.clone_from(default);
+        }
+    }
+}
+
+/// Whether the process runs as root, which flood pings are restricted to
+#[cfg(unix)]
+fn is_root() -> bool {
+    unsafe { libc::geteuid() == 0 }
+}
+
+// Raw sockets need administrator rights on Windows anyway
+#[cfg(not(unix))]
+fn is_root() -> bool {
+    true
+}
+
+/// The `ping` subcommand
+fn run_ping(args: &Args, config: &Config) -> Result<RunStatus, String> {
+    // A replay runs the recorded target with the recorded count and timing
+    let replay = match &args.replay {
+        Some(path) => Some(replay::Replay::load(path, args.speed)?),
+        None => None,
+    };
+    let replayed;
+    let args = match &replay {
+        Some(replay) => {
+            replayed = replay.args(args);
+            &replayed
+        }
+        None => args,
+    };
+    // The file is the payload, repeated as a pattern would be but exactly as long
+    let loaded;
+    let args = match &args.payload_file {
+        Some(path) => {
+            let content = std::fs::read(path).map_err(|e| format!("Failed to read payload file {}: {}", path, e))?;
+            if content.len() > sweep::MAX_PAYLOAD {
+                return Err(format!("Payload file {} is {} bytes; payloads go up to {}", path, content.len(), sweep::MAX_PAYLOAD));
+            }
+            loaded = Args { size: content.len() as u16, pattern: Some(Pattern(content)), ..args.clone() };
+            &loaded
+        }
+        None => args,
+    };
+    // A flood keeps to its rate; without one the interval is only the longest wait between probes
+    let flooded;
+    let args = match args.flood {
+        Some(rate) => {
+            if !is_root() {
+                return Err("--flood is restricted to root, like the system ping".to_string());
+            }
+            let interval = match rate {
+                Some(rate) if rate > 0.0 => Duration::from_secs_f64(1.0 / rate),
+                Some(_) => return Err("Flood rate must be greater than zero".to_string()),
+                None => FLOOD_INTERVAL,
+            };
+            flooded = Args { interval, ..args.clone() };
+            &flooded
+        }
+        None => args,
+    };
+
+    if args.interval.is_zero() {
+        return Err("Interval must be greater than zero".to_string());
+    }
+    if args.timeout.is_zero() {
+        return Err("Timeout must be greater than zero".to_string());
+    }
+    if args.re_resolve.is_some_and(|every| every.is_zero()) {
+        return Err("Re-resolve interval must be greater than zero".to_string());
+    }
+    if args.csv_delimiter == args.csv_decimal {
+        return Err("A decimal comma needs another CSV delimiter, e.g. --csv-delimiter ';'".to_string());
+    }
+
+    let mut sinks = Sinks::from_args(args, config)?;
+
+    let mut targets = Vec::new();
+    // Targets that can't be pinged make the run fail once the others are done
+    let failed = AtomicBool::new(false);
+    for host in &args.hosts {
+        if let Some(replay) = &replay {
+            targets.push((host, replay.addr));
+            continue;
+        }
+        let ipv6 = args.ipv6 || args.source.is_some_and(|source| source.is_ipv6());
+        let resolved = if args.http {
+            http::resolve(host, args.ipv6)
+        } else if ipv6 {
+            resolve_host(host, true)
+        } else {
+            // Probes from an IPv4 source can only go to the host's IPv4 address
+            let preference = if args.source.is_some() { Preference::Ipv4 } else { args.preference() };
+            dualstack::resolve(host, preference, args.timeout).map(|choice| {
+                if choice.other.is_some() && args.output == OutputFormat::Text && !args.quiet {
+                    println!("  {} {} has IPv4 and IPv6 addresses: pinging {} ({})", "⇆".cyan(), host, choice.addr, choice);
                 }
+                choice.addr
+            })
+        };
+        match resolved {
+            Ok(addr) => targets.push((host, addr)),
+            Err(e) => {
+                failed.store(true, Ordering::Relaxed);
+                eprintln!("{} {}", "Error:".red(), e);
             }
         }
+    }
+    if args.resolve {
+        // The targets' own names are in by their first replies
+        rdns::start();
+        for (_, addr) in &targets {
+            rdns::prefetch(*addr);
+        }
+    }
+
+    // Loaded up front so a missing baseline doesn't waste a run
+    let baselines = match &args.baseline {
+        Some(template) => Some(
+            targets
+                .iter()
+                .map(|(host, addr)| baseline::load(template, host, *addr))
+                .collect::<Result<Vec<_>, _>>()?,
+        ),
+        None => None,
     };
 
-    let timeout = Duration::from_secs(args.timeout);
-    
-    if let Err(e) = ping(
-        &args.host,
-        addr,
-        args.count,
-        timeout,
-        args.graph,
-        args.line_graph,
-        args.json,
-        args.csv,
-    ) {
-        eprintln!("{} {}", "Error:".red(), e);
+    // A shared socket for each address family among the targets
+    let mut families: Vec<bool> = targets.iter().map(|(_, addr)| addr.is_ipv6()).collect();
+    families.sort();
+    families.dedup();
+    let binding = args.binding();
+    let shared = match (args.socket, &replay) {
+        (SocketMode::Shared, None) if !args.http => {
+            families.into_iter().map(|ipv6| SharedSocket::open(ipv6, &binding)).collect::<Result<Vec<_>, _>>()?
+        }
+        _ => Vec::new(),
+    };
+    let (sockets, routers): (Vec<SharedSocket>, Vec<_>) = shared.into_iter().unzip();
+
+    if args.mark_on_signal.is_some() {
+        markers::watch_signal()?;
+    }
+    interrupt::watch()?;
+
+    // Measured once, before any target is pinged, so the loopback probes don't mix with the run
+    let overhead = if args.calibrate || args.subtract_overhead {
+        let overhead = selftest::calibrate()?;
+        if args.output == OutputFormat::Text && !args.quiet {
+            println!(
+                "  {} Local overhead: {:.3}ms (median loopback RTT of {} probes)",
+                "⚙".cyan(),
+                overhead,
+                selftest::CALIBRATION_PROBES
+            );
+        }
+        Some(overhead)
+    } else {
+        None
+    };
+
+    if args.latency_breakdown {
+        let addrs: Vec<IpAddr> = targets.iter().map(|(_, addr)| *addr).collect();
+        let interfaces = breakdown::attach(&addrs)?;
+        if args.output == OutputFormat::Text && !args.quiet {
+            println!("  {} Stamping replies at the driver (XDP) on {}", "⚙".cyan(), interfaces.join(", "));
+        }
+    }
+
+    // Keys only make sense when the run prints to the terminal
+    let keys = match (args.interactive, args.output) {
+        (true, OutputFormat::Text) => Some(Keys::start()?),
+        (_, OutputFormat::Dashboard) => Some(Keys::shared()),
+        _ => None,
+    };
+
+    // Every target has a pane, so they are all pinged at once
+    let dashboard = (args.output == OutputFormat::Dashboard).then(|| Dashboard::new(&targets, args.down_after));
+    let parallel = match &dashboard {
+        Some(dashboard) => {
+            sinks.push(dashboard.sink());
+            targets.len()
+        }
+        None => args.parallel as usize,
+    };
+    let done = AtomicBool::new(false);
+
+    // Workers take the next target until none are left
+    let sinks = Mutex::new(sinks);
+    let next = AtomicUsize::new(0);
+    let finished = Mutex::new(Vec::new());
+    thread::scope(|scope| {
+        for (socket, receiver) in sockets.iter().zip(routers) {
+            scope.spawn(move || socket.route(receiver));
+        }
+
+        if let (Some(dashboard), Some(keys)) = (&dashboard, &keys) {
+            scope.spawn(|| dashboard.run(keys, &done));
+        }
+
+        let workers: Vec<_> = (0..parallel.min(targets.len()))
+            .map(|_| {
+                scope.spawn(|| loop {
+                    let index = next.fetch_add(1, Ordering::Relaxed);
+                    let Some(&(host, addr)) = targets.get(index) else {
+                        break;
+                    };
+                    if keys.as_ref().is_some_and(Keys::quit_requested) || interrupt::requested() {
+                        break;
+                    }
+                    // Each target has its own identifier so replies can be told apart
+                    let outcome = Identifier::claim()
+                        .and_then(|identifier| match &replay {
+                            Some(replay) => Ok(Channel::replay(replay.player(), identifier)),
+                            None if args.http => http::Prober::new(host, addr, args.head, args.timeout, identifier.value())
+                                .map(|prober| Channel::http(prober, identifier)),
+                            None => Channel::open(sockets.iter().find(|socket| socket.serves(addr)), identifier, addr)
+                                .and_then(|channel| channel.bind(addr, &binding))
+                                .and_then(|channel| match args.udp {
+                                    Some(port) => channel.udp(addr, port, &binding),
+                                    None => Ok(channel),
+                                })
+                                .and_then(|channel| match args.ttl {
+                                    Some(ttl) => channel.ttl(addr, ttl),
+                                    None => Ok(channel),
+                                }),
+                        })
+                        .and_then(|channel| ping(args, &sinks, keys.as_ref(), overhead, channel, host, addr));
+                    match outcome {
+                        Ok(stats) => finished.lock().unwrap().push((index, TargetSummary {
+                            host: host.clone(),
+                            addr,
+                            stats,
+                        })),
+                        Err(e) => {
+                            failed.store(true, Ordering::Relaxed);
+                            eprintln!("{} {}", "Error:".red(), e);
+                        }
+                    }
+                })
+            })
+            .collect();
+        for worker in workers {
+            let _ = worker.join();
+        }
+        done.store(true, Ordering::Relaxed);
+        for socket in &sockets {
+            socket.stop();
+        }
+    });
+
+    let mut sinks = sinks.into_inner().unwrap();
+    // Targets finish in any order in parallel runs
+    let mut finished = finished.into_inner().unwrap();
+    finished.sort_by_key(|(index, _)| *index);
+    let tolerance = baseline::Tolerance {
+        percent: args.tolerance,
+        ms: args.tolerance_ms,
+        loss: args.loss_tolerance,
+    };
+    let comparisons: Vec<(String, Vec<baseline::Check>)> = match &baselines {
+        Some(baselines) => finished
+            .iter()
+            .map(|(index, summary)| {
+                let checks = baseline::compare(&baselines[*index].statistics, &summary.stats, tolerance);
+                (summary.host.clone(), checks)
+            })
+            .collect(),
+        None => Vec::new(),
+    };
+    let mut summaries: Vec<TargetSummary> = finished.into_iter().map(|(_, summary)| summary).collect();
+
+    sinks.close();
+
+    // Replays are not runs of their own
+    if !args.forget && replay.is_none() && !summaries.is_empty() {
+        let stored = recent::Store::open().and_then(|store| summaries.iter().try_for_each(|s| store.record(s)));
+        if let Err(e) = stored {
+            eprintln!("  {} History store error: {}", "⚠".yellow(), e);
+        }
+    }
+
+    let terminal = matches!(args.output, OutputFormat::Text | OutputFormat::Dashboard) && !args.quiet;
+    let table = args.hosts.len() > 1 || args.output == OutputFormat::Dashboard;
+    if terminal && table && !summaries.is_empty() {
+        print_summary_table(&mut summaries, args.sort_by);
+    }
+    if let Some(filename) = &args.summary_json {
+        summary::write_json(filename, &summaries)?;
+        if terminal {
+            println!("  {} Exported summary to JSON: {}", "✓".green(), filename.cyan());
+        }
+    }
+    if let Some(filename) = &args.summary_csv {
+        summary::write_csv(filename, &summaries)?;
+        if terminal {
+            println!("  {} Exported summary to CSV: {}", "✓".green(), filename.cyan());
+        }
+    }
+
+    let mut status = summaries
+        .iter()
+        .map(|summary| RunStatus::of(&summary.stats, args.max_loss))
+        .max()
+        .unwrap_or(RunStatus::Clean);
+    if failed.into_inner() {
+        status = RunStatus::Failed;
+    }
+
+    let mut regressed = Vec::new();
+    for (host, checks) in &comparisons {
+        if terminal {
+            baseline::print(host, checks);
+        }
+        if baseline::regressed(checks) {
+            regressed.push(host.as_str());
+        }
+    }
+    if !regressed.is_empty() {
+        eprintln!("{} Regressed against the baseline: {}", "Error:".red(), regressed.join(", "));
+        status = status.max(RunStatus::Degraded);
     }
+    Ok(status)
 }
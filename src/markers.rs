@@ -0,0 +1,76 @@
+//! Event markers.
+//!
+//! A marker is a named point in time recorded while a run is in progress
+//! ("rebooted router"), so cause and effect can be lined up afterwards. They
+//! come from the `m` key in interactive runs or from SIGUSR1 with
+//! `--mark-on-signal`, are shown on the graphs and stored in the reports.
+
+use chrono::{DateTime, Local};
+
+#[cfg(target_os = "linux")]
+mod signal {
+    use chrono::{DateTime, Local};
+    use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+    use std::time::{Duration, SystemTime};
+
+    static RECEIVED: AtomicUsize = AtomicUsize::new(0);
+    // When the last one arrived, in nanoseconds since the epoch
+    static LAST: AtomicU64 = AtomicU64::new(0);
+
+    extern "C" fn record(_signal: libc::c_int) {
+        // Only atomics and clock_gettime, which are safe in a signal handler
+        if let Ok(now) = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH) {
+            LAST.store(now.as_nanos() as u64, Ordering::Relaxed);
+        }
+        RECEIVED.fetch_add(1, Ordering::Release);
+    }
+
+    pub fn watch() -> Result<(), String> {
+        let handler = record as extern "C" fn(libc::c_int);
+        if unsafe { libc::signal(libc::SIGUSR1, handler as libc::sighandler_t) } == libc::SIG_ERR {
+            return Err("Failed to install the SIGUSR1 handler".to_string());
+        }
+        Ok(())
+    }
+
+    pub fn received() -> usize {
+        RECEIVED.load(Ordering::Acquire)
+    }
+
+    pub fn last() -> DateTime<Local> {
+        let nanos = LAST.load(Ordering::Relaxed);
+        (SystemTime::UNIX_EPOCH + Duration::from_nanos(nanos)).into()
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+mod signal {
+    use chrono::{DateTime, Local};
+
+    pub fn watch() -> Result<(), String> {
+        Err("--mark-on-signal is only supported on Linux".to_string())
+    }
+
+    pub fn received() -> usize {
+        0
+    }
+
+    pub fn last() -> DateTime<Local> {
+        Local::now()
+    }
+}
+
+/// Count SIGUSR1 signals from now on
+pub fn watch_signal() -> Result<(), String> {
+    signal::watch()
+}
+
+/// SIGUSR1 signals seen since [`watch_signal`]; every target keeps its own position
+pub fn signals_received() -> usize {
+    signal::received()
+}
+
+/// When the most recent SIGUSR1 arrived
+pub fn last_signal() -> DateTime<Local> {
+    signal::last()
+}
@@ -0,0 +1,170 @@
+//! `merge` subcommand: several saved reports of a target as one.
+//!
+//! Runs of the same target, such as hourly runs from cron, are joined into
+//! one report in the order of their probes, numbered again from 0, and the
+//! statistics are counted again over all of them. Probes found in more than
+//! one report (a report given twice, or the temporary file of a killed run
+//! next to the report of the same run) are kept once. Markers and outages
+//! are carried over; what only makes sense for one run, like the HTTP phases
+//! or the size sweep, is left out. The merged report is written to
+//! `--output`, or drawn in the terminal like `report render` does.
+
+use colored::*;
+use std::collections::{HashMap, HashSet};
+use rust_ping::{Outage, PingReport, SiteSwitch, SCHEMA_VERSION};
+
+use crate::export::{load_report, new_run_id, platform, save_report, statistics_of};
+use crate::report;
+use crate::PingResult;
+
+/// Options of the `merge` subcommand
+#[derive(clap::Args, Debug)]
+pub struct MergeArgs {
+    /// Reports of the same target (JSON, YAML, CBOR or MessagePack), in any order
+    #[arg(value_name = "FILE", num_args = 2.., required = true)]
+    files: Vec<String>,
+
+    /// Write the merged report to FILE, in the format of its extension, instead of drawing it
+    #[arg(short, long, value_name = "FILE")]
+    output: Option<String>,
+}
+
+pub fn run(args: &MergeArgs) -> Result<(), String> {
+    let mut reports = Vec::new();
+    for path in &args.files {
+        let report = load_report(path)?;
+        if report.results.is_empty() {
+            return Err(format!("{} kept only per-second aggregates, which can't be merged", path));
+        }
+        reports.push((path, report));
+    }
+    let (first_path, first) = &reports[0];
+    if let Some((path, other)) = reports.iter().find(|(_, report)| report.host != first.host || report.ip_address != first.ip_address) {
+        return Err(format!(
+            "{} is of {} ({}) but {} of {} ({})",
+            path, other.host, other.ip_address, first_path, first.host, first.ip_address
+        ));
+    }
+    reports.sort_by(|(_, a), (_, b)| a.timestamp_start.cmp(&b.timestamp_start));
+
+    // Every probe once, tagged with the report it came from; the replies to
+    // a probe are told apart by their order, so every DUP is kept
+    let mut seen = HashSet::new();
+    let mut results: Vec<(usize, PingResult)> = Vec::new();
+    for (index, (_, report)) in reports.iter().enumerate() {
+        let mut replies: HashMap<(Option<_>, u32), usize> = HashMap::new();
+        for result in &report.results {
+            let reply = replies.entry((result.timestamp, result.seq)).or_default();
+            let key = (result.timestamp, result.seq, *reply);
+            *reply += 1;
+            if seen.insert(key) {
+                results.push((index, result.clone()));
+            }
+        }
+    }
+    let total: usize = reports.iter().map(|(_, report)| report.results.len()).sum();
+    let overlap = total - results.len();
+    results.sort_by_key(|(_, result)| result.timestamp);
+
+    // Probes are numbered again; duplicates, markers and the like follow the probe they belong to
+    let mut numbers: HashMap<(usize, u32), u32> = HashMap::new();
+    let mut next = 0;
+    for (index, result) in &mut results {
+        if result.duplicate {
+            result.seq = numbers.get(&(*index, result.seq)).copied().unwrap_or(result.seq);
+            continue;
+        }
+        numbers.insert((*index, result.seq), next);
+        result.seq = next;
+        next += 1;
+    }
+    // Where the probes of each report start; a marker after the last probe
+    // of its report goes before the first of the report that follows
+    let mut starts: Vec<Option<u32>> = vec![None; reports.len()];
+    for (index, result) in results.iter().filter(|(_, result)| !result.duplicate) {
+        starts[*index] = Some(starts[*index].map_or(result.seq, |start| start.min(result.seq)));
+    }
+    let mut markers = Vec::new();
+    let mut outages: Vec<Outage> = Vec::new();
+    let mut site_switches: Vec<SiteSwitch> = Vec::new();
+    for (index, (_, report)) in reports.iter().enumerate() {
+        for marker in &report.markers {
+            let mut marker = marker.clone();
+            marker.seq = numbers
+                .get(&(index, marker.seq))
+                .copied()
+                .or_else(|| starts[index + 1..].iter().find_map(|start| *start))
+                .unwrap_or(next);
+            markers.push(marker);
+        }
+        for outage in &report.outages {
+            let mut outage = outage.clone();
+            if outages.iter().any(|known| known.start == outage.start) {
+                continue;
+            }
+            outage.first_seq = numbers.get(&(index, outage.first_seq)).copied().unwrap_or(outage.first_seq);
+            outages.push(outage);
+        }
+        for switch in &report.site_switches {
+            if site_switches.iter().any(|known| known.timestamp == switch.timestamp) {
+                continue;
+            }
+            let mut switch = switch.clone();
+            switch.seq = numbers.get(&(index, switch.seq)).copied().unwrap_or(switch.seq);
+            site_switches.push(switch);
+        }
+    }
+    markers.sort_by(|a, b| a.timestamp.cmp(&b.timestamp));
+    markers.dedup_by(|a, b| a.timestamp == b.timestamp && a.name == b.name);
+    outages.sort_by(|a, b| a.start.cmp(&b.start));
+    site_switches.sort_by(|a, b| a.timestamp.cmp(&b.timestamp));
+
+    let results: Vec<PingResult> = results.into_iter().map(|(_, result)| result).collect();
+    let first = &reports[0].1;
+    let merged = PingReport {
+        schema_version: SCHEMA_VERSION,
+        tool_version: env!("CARGO_PKG_VERSION").to_string(),
+        os: platform(),
+        command_line: std::env::args_os().map(|arg| arg.to_string_lossy().into_owned()).collect(),
+        run_id: new_run_id(),
+        host: first.host.clone(),
+        ip_address: first.ip_address.clone(),
+        timestamp_start: first.timestamp_start.clone(),
+        timeout_ms: first.timeout_ms,
+        interval_seconds: first.interval_seconds,
+        overhead_ms: first.overhead_ms,
+        overhead_subtracted: first.overhead_subtracted,
+        ttl: first.ttl,
+        interface: first.interface.clone(),
+        source_address: first.source_address.clone(),
+        statistics: statistics_of(&results),
+        results,
+        aggregates: None,
+        markers,
+        site_switches,
+        http_phases: Vec::new(),
+        size_sweep: Vec::new(),
+        addresses: Vec::new(),
+        outages,
+        timestamp_end: reports.iter().map(|(_, report)| report.timestamp_end.clone()).max().unwrap_or_default(),
+        complete: reports.iter().all(|(_, report)| report.complete),
+    };
+
+    match &args.output {
+        Some(path) => {
+            save_report(&merged, path)?;
+            println!(
+                "  {} Merged {} reports, {} probes, into {}",
+                "✓".green(),
+                reports.len(),
+                next,
+                path.cyan()
+            );
+        }
+        None => report::render_terminal(&merged, &report::probes(&merged)),
+    }
+    if overlap > 0 {
+        println!("  {} results found in more than one report were kept once", overlap);
+    }
+    Ok(())
+}
@@ -0,0 +1,52 @@
+//! `monitor` subcommand: endless pings kept in a database.
+//!
+//! `monitor HOST...` pings like `ping --forever` and stores every result in
+//! a SQLite database (`--db`, in the data directory by default), so loss and
+//! latency of any past window can be looked up with `report query` while it
+//! keeps running. Every option of `ping` applies; `--duration` or
+//! `--deadline` still end it on time.
+
+use std::path::PathBuf;
+
+use crate::config::Config;
+use crate::{Args, RunStatus};
+
+/// Options of the `monitor` subcommand
+#[derive(clap::Args, Debug)]
+pub struct MonitorArgs {
+    /// SQLite database to store the results in (default: monitor.db in the data directory)
+    #[arg(long, value_name = "FILE")]
+    db: Option<String>,
+
+    #[command(flatten)]
+    ping: Args,
+}
+
+/// Database monitor runs write to and `report query` reads by default
+pub fn default_db() -> Result<PathBuf, String> {
+    dirs::data_dir()
+        .map(|dir| dir.join("rust_ping").join("monitor.db"))
+        .ok_or_else(|| "No data directory for the monitor database; pass --db".to_string())
+}
+
+impl MonitorArgs {
+    /// The options shared with `ping`
+    pub fn ping(&self) -> &Args {
+        &self.ping
+    }
+
+    pub fn ping_mut(&mut self) -> &mut Args {
+        &mut self.ping
+    }
+}
+
+pub fn run(args: MonitorArgs, config: &Config) -> Result<RunStatus, String> {
+    let mut ping = args.ping;
+    let db = match args.db {
+        Some(db) => db,
+        None => default_db()?.display().to_string(),
+    };
+    ping.count = 0;
+    ping.sqlite = Some(db);
+    crate::ping_command(ping, config)
+}
@@ -0,0 +1,108 @@
+//! Outage timeline and downtime accounting.
+//!
+//! A target is down once `--down-after` probes in a row were lost, from the
+//! first of them, and up again with the next probe answered, as the alert
+//! detector has it. Every outage of a run is kept with its start, end and
+//! duration, shown at the end and written to the exports, with the total
+//! downtime and how much of the run the target was available: evidence that
+//! holds up better than a loss percentage when taking a flaky line up with
+//! the provider.
+
+use chrono::{DateTime, Local};
+use colored::*;
+use comfy_table::modifiers::UTF8_ROUND_CORNERS;
+use comfy_table::presets::UTF8_FULL_CONDENSED;
+use comfy_table::{Cell, CellAlignment, ContentArrangement, Table};
+use rust_ping::Outage;
+
+use crate::alerts::human_duration;
+use crate::{PingResult, TIMESTAMP_FORMAT};
+
+/// The outages of a target as its probes come in
+pub struct OutageLog {
+    down_after: u32,
+    // The losses in a row so far: first probe, when it was sent and how many
+    streak: Option<(u32, DateTime<Local>, u32)>,
+    outages: Vec<Outage>,
+}
+
+impl OutageLog {
+    pub fn new(down_after: u32) -> Self {
+        OutageLog { down_after: down_after.max(1), streak: None, outages: Vec::new() }
+    }
+
+    pub fn record(&mut self, result: &PingResult) {
+        let time = result.timestamp.unwrap_or_else(Local::now);
+        if !result.success() {
+            self.streak.get_or_insert((result.seq, time, 0)).2 += 1;
+            return;
+        }
+        if let Some((first_seq, start, lost)) = self.streak.take().filter(|&(_, _, lost)| lost >= self.down_after) {
+            self.outages.push(outage(first_seq, start, Some(time), lost));
+        }
+    }
+
+    /// Every outage, one still going on at `end` included
+    pub fn results(&self, end: DateTime<Local>) -> Vec<Outage> {
+        let mut outages = self.outages.clone();
+        if let Some((first_seq, start, lost)) = self.streak.filter(|&(_, _, lost)| lost >= self.down_after) {
+            let mut ongoing = outage(first_seq, start, None, lost);
+            ongoing.duration_seconds = seconds(start, end);
+            outages.push(ongoing);
+        }
+        outages
+    }
+}
+
+fn outage(first_seq: u32, start: DateTime<Local>, end: Option<DateTime<Local>>, lost: u32) -> Outage {
+    Outage {
+        start: start.format(TIMESTAMP_FORMAT).to_string(),
+        end: end.map(|end| end.format(TIMESTAMP_FORMAT).to_string()),
+        duration_seconds: end.map_or(0.0, |end| seconds(start, end)),
+        first_seq,
+        lost,
+    }
+}
+
+fn seconds(start: DateTime<Local>, end: DateTime<Local>) -> f64 {
+    (end - start).num_milliseconds().max(0) as f64 / 1000.0
+}
+
+/// Downtime in seconds, and the percentage of a run of `run_seconds` the target was up
+pub fn downtime(outages: &[Outage], run_seconds: f64) -> (f64, f64) {
+    let down: f64 = outages.iter().map(|outage| outage.duration_seconds).sum();
+    let available = if run_seconds > 0.0 { (1.0 - down / run_seconds).clamp(0.0, 1.0) * 100.0 } else { 100.0 };
+    (down, available)
+}
+
+pub fn print_outages(host: &str, outages: &[Outage], run_seconds: f64) {
+    println!("\n{}", "╔════════════════════════════════════════════════════════════╗".red());
+    println!("{}", "║                      🔌 OUTAGES                             ║".red());
+    println!("{}", "╚════════════════════════════════════════════════════════════╝".red());
+    println!("  Host: {}", host.yellow());
+
+    let mut table = Table::new();
+    table
+        .load_preset(UTF8_FULL_CONDENSED)
+        .apply_modifier(UTF8_ROUND_CORNERS)
+        .set_content_arrangement(ContentArrangement::Dynamic)
+        .set_header(vec!["Start", "End", "Duration", "From seq", "Lost"]);
+    for outage in outages {
+        table.add_row(vec![
+            Cell::new(&outage.start),
+            Cell::new(outage.end.as_deref().unwrap_or("still down")),
+            Cell::new(human_duration(outage.duration_seconds)).set_alignment(CellAlignment::Right),
+            Cell::new(outage.first_seq).set_alignment(CellAlignment::Right),
+            Cell::new(outage.lost).set_alignment(CellAlignment::Right),
+        ]);
+    }
+    println!("{}", table);
+    let (down, available) = downtime(outages, run_seconds);
+    println!(
+        "  Downtime: {} in {} outage{} ({} available)",
+        human_duration(down).red().bold(),
+        outages.len(),
+        if outages.len() == 1 { "" } else { "s" },
+        format!("{:.2}%", available).yellow()
+    );
+}
@@ -0,0 +1,210 @@
+//! Path MTU discovery.
+//!
+//! `pmtu HOST` looks for the largest packet that reaches the host whole.
+//! Echo requests go out with the don't fragment bit (routers never fragment
+//! IPv6), and their size is binary-searched between the smallest MTU of the
+//! family and `--max`. A router whose next link is too small answers with
+//! fragmentation needed (packet too big for IPv6) and the MTU of that link,
+//! which is tried next; one that drops the probe without a word costs a
+//! timeout, so each size gets `--tries` probes before it counts as too large.
+//! Sizes are whole IP packets, headers included, as MTUs are.
+
+use colored::*;
+use std::io;
+use std::net::IpAddr;
+use std::time::{Duration, Instant, SystemTime};
+
+use crate::config::Config;
+use crate::identifier::Identifier;
+use crate::trace::wait_answer;
+use crate::transport::{self, IcmpReceiver, IcmpSender, DEST_UNREACHABLE, ECHO_REPLY};
+use crate::{get_latency_color, parse_duration, resolve_host, unreachable_reason, write_icmp_packet, ICMP_HEADER_LEN};
+
+/// Options of the `pmtu` subcommand
+#[derive(clap::Args, Debug)]
+pub struct PmtuArgs {
+    /// IP address or hostname to find the path MTU to
+    #[arg(value_name = "HOST")]
+    host: String,
+
+    /// Resolve the host to an IPv6 address only (IPv4 is preferred otherwise)
+    #[arg(short = '6', long)]
+    ipv6: bool,
+
+    /// Largest MTU tried, in bytes (9000 covers jumbo frames)
+    #[arg(long, value_name = "BYTES", default_value_t = 9000, value_parser = clap::value_parser!(u16).range(MIN_MTU_V4 as i64..))]
+    max: u16,
+
+    /// Probes of each size before it counts as too large
+    #[arg(short = 'q', long, default_value_t = 2, value_parser = clap::value_parser!(u16).range(1..=10))]
+    tries: u16,
+
+    /// Timeout for each probe, in seconds or with a unit (e.g. 2, 500ms)
+    #[arg(short, long, default_value = "1", value_parser = parse_duration)]
+    timeout: Duration,
+}
+
+// Smallest MTU of each family, which every link has to carry
+const MIN_MTU_V4: u16 = 68;
+const MIN_MTU_V6: u16 = 1280;
+
+/// What became of the probes of one size
+enum Outcome {
+    Fits { rtt: f64 },
+    /// Fragmentation needed from a router, with the MTU of its next link when it gave one
+    TooBig { from: IpAddr, mtu: Option<u16> },
+    /// Larger than the MTU of the interface it would leave by
+    TooBigLocally,
+    Lost,
+}
+
+pub fn run(args: &PmtuArgs, config: &Config) -> Result<(), String> {
+    let host = match config.expand_hosts(std::slice::from_ref(&args.host))?.as_slice() {
+        [host] => host.clone(),
+        _ => return Err(format!("Alias {} names several hosts; probe one of them", args.host)),
+    };
+    let addr = resolve_host(&host, args.ipv6)?;
+    let (mut sender, mut receiver) = transport::open(addr.is_ipv6())?;
+    sender.set_dont_fragment().map_err(|e| format!("Failed to set the don't fragment bit: {}", e))?;
+    let identifier = Identifier::claim()?;
+    let min = if addr.is_ipv6() { MIN_MTU_V6 } else { MIN_MTU_V4 };
+    let max = args.max.max(min);
+
+    println!("\n{}", "╔════════════════════════════════════════════════════════════╗".cyan());
+    println!("{}       PMTU {} - {} bytes max                {}",
+        "║".cyan(),
+        addr.to_string().yellow().bold(),
+        max.to_string().green(),
+        "║".cyan()
+    );
+    println!("{}", "╚════════════════════════════════════════════════════════════╝".cyan());
+
+    let mut seq: u16 = 0;
+    let mut try_size = |size| probe(sender.as_mut(), receiver.as_mut(), identifier.value(), addr, size, args, &mut seq);
+
+    // Without an answer to the smallest size there is nothing to search
+    let outcome = try_size(min)?;
+    print_step(min, &outcome);
+    if !matches!(outcome, Outcome::Fits { .. }) {
+        return Err(format!("{} doesn't answer {}-byte probes", addr, min));
+    }
+
+    // Largest size known to fit, and smallest known not to
+    let mut fits = min;
+    let mut too_big = max as u32 + 1;
+    let mut reported = None;
+    let mut silent = false;
+    let mut next = (max > min).then_some(max);
+    while let Some(size) = next {
+        let outcome = try_size(size)?;
+        print_step(size, &outcome);
+        let mut hint = None;
+        match outcome {
+            Outcome::Fits { .. } => fits = size,
+            Outcome::TooBig { from, mtu } => {
+                too_big = size as u32;
+                reported = Some(from);
+                // Nothing larger than the link's MTU gets across it
+                if let Some(mtu) = mtu.filter(|&mtu| mtu < size) {
+                    too_big = mtu as u32 + 1;
+                    hint = Some(mtu).filter(|&mtu| mtu > fits);
+                }
+            }
+            Outcome::TooBigLocally => too_big = size as u32,
+            Outcome::Lost => {
+                too_big = size as u32;
+                silent = true;
+            }
+        }
+        next = hint.or_else(|| (too_big - fits as u32 > 1).then(|| ((fits as u32 + too_big) / 2) as u16));
+    }
+
+    let header = if addr.is_ipv6() { 40 } else { 20 };
+    println!(
+        "\n  {} Path MTU to {}: {} ({} bytes of ICMP payload)",
+        "✓".green(),
+        host.yellow(),
+        format!("{} bytes", fits).green().bold(),
+        fits as usize - header - ICMP_HEADER_LEN
+    );
+    if fits == max {
+        println!("  {}", format!("Every size up to --max {} got through", max).dimmed());
+    } else if let Some(router) = reported {
+        println!("  Larger packets are refused by {}, in front of the smaller link", router.to_string().cyan());
+    } else if silent {
+        println!(
+            "  {} Larger probes were dropped without a fragmentation needed answer (an ICMP black hole?)",
+            "⚠".yellow()
+        );
+    }
+    Ok(())
+}
+
+/// Send up to `--tries` probes of `size` bytes and tell what became of them
+fn probe(
+    sender: &mut dyn IcmpSender,
+    receiver: &mut dyn IcmpReceiver,
+    identifier: u16,
+    addr: IpAddr,
+    size: u16,
+    args: &PmtuArgs,
+    seq: &mut u16,
+) -> Result<Outcome, String> {
+    let header = if addr.is_ipv6() { 40 } else { 20 };
+    let mut packet = vec![vec![0u8; size as usize - header]];
+    for _ in 0..args.tries {
+        let expected = *seq;
+        write_icmp_packet(&mut packet[0], expected, identifier, addr.is_ipv6());
+        *seq = seq.wrapping_add(1);
+
+        let sent_at = SystemTime::now();
+        if let (_, Some(e)) = sender.send_batch(&packet, addr) {
+            if too_large(&e) {
+                return Ok(Outcome::TooBigLocally);
+            }
+            return Err(format!("Send error: {}", e));
+        }
+
+        let deadline = Instant::now() + args.timeout;
+        while let Some(reply) = wait_answer(receiver, identifier, deadline.saturating_duration_since(Instant::now()))? {
+            // Late answers to a size tried before
+            if reply.sequence != Some(expected) {
+                continue;
+            }
+            match (reply.icmp_type, reply.code) {
+                (ECHO_REPLY, _) => {
+                    let rtt = reply.received.duration_since(sent_at).unwrap_or_default().as_secs_f64() * 1000.0;
+                    return Ok(Outcome::Fits { rtt });
+                }
+                (DEST_UNREACHABLE, 4) => return Ok(Outcome::TooBig { from: reply.source, mtu: reply.mtu }),
+                (DEST_UNREACHABLE, code) => return Err(format!("{} from {}", unreachable_reason(code), reply.source)),
+                _ => {}
+            }
+        }
+    }
+    Ok(Outcome::Lost)
+}
+
+/// The kernel refused a packet larger than the interface's MTU
+#[cfg(target_os = "linux")]
+fn too_large(e: &io::Error) -> bool {
+    e.raw_os_error() == Some(libc::EMSGSIZE)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn too_large(_: &io::Error) -> bool {
+    false
+}
+
+fn print_step(size: u16, outcome: &Outcome) {
+    let size = format!("{:>5} bytes", size);
+    match outcome {
+        Outcome::Fits { rtt } => println!("  {} {} {}", "✓".green(), size, get_latency_color(*rtt)),
+        Outcome::TooBig { from, mtu: Some(mtu) } => {
+            println!("  {} {} Fragmentation needed from {} (next-hop MTU {})", "✗".red(), size, from, mtu)
+        }
+        Outcome::TooBig { from, mtu: None } => println!("  {} {} Fragmentation needed from {}", "✗".red(), size, from),
+        Outcome::TooBigLocally => println!("  {} {} Larger than the local interface's MTU", "✗".red(), size),
+        Outcome::Lost => println!("  {} {} {}", "✗".red(), size, "No answer".dimmed()),
+    }
+}
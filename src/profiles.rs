@@ -0,0 +1,103 @@
+//! Named profiles.
+//!
+//! A `[profile.NAME]` section of the config file saves the hosts and options
+//! of a run that is repeated often. `--profile NAME` puts them in front of
+//! the command line, so options given there still win and hosts given there
+//! are pinged as well. Options are the long names of `ping`'s, without the
+//! dashes: `interval = "500ms"`, `graph = true`, `json = "office.json"`.
+//! `profiles list` shows what is defined.
+
+use clap::CommandFactory;
+use colored::*;
+use comfy_table::modifiers::UTF8_ROUND_CORNERS;
+use comfy_table::presets::UTF8_FULL_CONDENSED;
+use comfy_table::{Cell, Color, ContentArrangement, Table};
+use std::ffi::OsString;
+
+use crate::config::{Config, Profile};
+use crate::Cli;
+
+/// Options of the `profiles` subcommand
+#[derive(clap::Args, Debug)]
+pub struct ProfilesArgs {
+    #[command(subcommand)]
+    command: ProfilesCommand,
+}
+
+#[derive(clap::Subcommand, Debug)]
+enum ProfilesCommand {
+    /// List the profiles of the config file with their hosts and options
+    List,
+}
+
+pub fn run(args: &ProfilesArgs, config: &Config) -> Result<(), String> {
+    match args.command {
+        ProfilesCommand::List => list(config),
+    }
+}
+
+/// The arguments `--profile NAME` stands for, hosts first
+pub fn expand(config: &Config, name: &str) -> Result<Vec<OsString>, String> {
+    let profile = config
+        .profile
+        .get(name)
+        .ok_or_else(|| format!("Unknown profile '{}' (define it as [profile.{}] in the config file)", name, name))?;
+    let mut args: Vec<OsString> = profile.hosts.iter().map(OsString::from).collect();
+    args.extend(options(name, profile)?.into_iter().map(OsString::from));
+    Ok(args)
+}
+
+/// A profile's options as command-line arguments
+fn options(name: &str, profile: &Profile) -> Result<Vec<String>, String> {
+    let ping = Cli::command();
+    let ping = ping.find_subcommand("ping").expect("ping subcommand");
+    let mut args = Vec::new();
+    for (key, value) in &profile.options {
+        let option = key.replace('_', "-");
+        if option == "profile" || !ping.get_arguments().any(|arg| arg.get_long() == Some(option.as_str())) {
+            return Err(format!("Profile '{}': '{}' is not an option of ping", name, key));
+        }
+        let values = match value {
+            toml::Value::Array(values) => values.iter().collect(),
+            value => vec![value],
+        };
+        for value in values {
+            match value {
+                toml::Value::Boolean(true) => args.push(format!("--{}", option)),
+                toml::Value::Boolean(false) => {}
+                toml::Value::String(text) => args.push(format!("--{}={}", option, text)),
+                toml::Value::Integer(_) | toml::Value::Float(_) => args.push(format!("--{}={}", option, value)),
+                _ => return Err(format!("Profile '{}': '{}' must be a string, number or true/false", name, key)),
+            }
+        }
+    }
+    Ok(args)
+}
+
+fn list(config: &Config) -> Result<(), String> {
+    if config.profile.is_empty() {
+        println!("  {}", "No profiles in the config file; add them as [profile.NAME] sections".dimmed());
+        return Ok(());
+    }
+    let mut table = Table::new();
+    table
+        .load_preset(UTF8_FULL_CONDENSED)
+        .apply_modifier(UTF8_ROUND_CORNERS)
+        .set_content_arrangement(ContentArrangement::Dynamic)
+        .set_header(vec!["Profile", "Hosts", "Options", "Description"]);
+    for (name, profile) in &config.profile {
+        // A broken profile is shown with what is wrong with it
+        let options = match options(name, profile) {
+            Ok(options) => Cell::new(options.join(" ")),
+            Err(e) => Cell::new(e).fg(Color::Red),
+        };
+        table.add_row(vec![
+            Cell::new(name),
+            Cell::new(profile.hosts.join(" ")),
+            options,
+            Cell::new(profile.description.as_deref().unwrap_or("")),
+        ]);
+    }
+    println!("{}", table);
+    Ok(())
+}
@@ -0,0 +1,100 @@
+//! Reverse DNS of reply addresses.
+//!
+//! With `--resolve` the addresses that answer are shown with their PTR name,
+//! in the per-probe lines of a ping and the hops of a trace. Lookups run on
+//! threads of their own and every answer, name or none, is kept for the rest
+//! of the run, so a slow or missing name server never holds up a probe: an
+//! address is shown as a number until its name is in.
+
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::mpsc::{self, Sender};
+use std::sync::{Arc, Condvar, Mutex, OnceLock};
+use std::thread;
+use std::time::{Duration, Instant};
+
+// Lookups made at the same time
+const WORKERS: usize = 4;
+
+static RESOLVER: OnceLock<Resolver> = OnceLock::new();
+
+struct Resolver {
+    // `None` while the lookup is under way, then the name if the address has one
+    names: Mutex<HashMap<IpAddr, Option<Option<String>>>>,
+    answered: Condvar,
+    requests: Mutex<Sender<IpAddr>>,
+}
+
+impl Resolver {
+    /// The name of `addr` if it's known by now, looking it up the first time it's asked for
+    fn name(&self, addr: IpAddr) -> Option<String> {
+        let mut names = self.names.lock().unwrap();
+        match names.get(&addr) {
+            Some(known) => known.clone().flatten(),
+            None => {
+                names.insert(addr, None);
+                let _ = self.requests.lock().unwrap().send(addr);
+                None
+            }
+        }
+    }
+
+    fn answer(&self, addr: IpAddr, name: Option<String>) {
+        self.names.lock().unwrap().insert(addr, Some(name));
+        self.answered.notify_all();
+    }
+}
+
+/// Look up the names of the addresses shown from now on
+pub fn start() {
+    RESOLVER.get_or_init(|| {
+        let (requests, queue) = mpsc::channel::<IpAddr>();
+        let queue = Arc::new(Mutex::new(queue));
+        for _ in 0..WORKERS {
+            let queue = Arc::clone(&queue);
+            let _ = thread::Builder::new().name("rdns".to_string()).spawn(move || loop {
+                let Ok(addr) = queue.lock().unwrap().recv() else {
+                    break;
+                };
+                // Addresses without a PTR record are an error, and keep their number
+                let name = dns_lookup::lookup_addr(&addr).ok().filter(|name| !name.is_empty());
+                if let Some(resolver) = RESOLVER.get() {
+                    resolver.answer(addr, name);
+                }
+            });
+        }
+        Resolver { names: Mutex::new(HashMap::new()), answered: Condvar::new(), requests: Mutex::new(requests) }
+    });
+}
+
+/// Start looking up `addr` ahead of its first reply
+pub fn prefetch(addr: IpAddr) {
+    if let Some(resolver) = RESOLVER.get() {
+        resolver.name(addr);
+    }
+}
+
+/// `name (addr)` once the name is known, or just the address
+pub fn label(addr: IpAddr) -> String {
+    match RESOLVER.get().and_then(|resolver| resolver.name(addr)) {
+        Some(name) => format!("{} ({})", name, addr),
+        None => addr.to_string(),
+    }
+}
+
+/// Like [`label`], waiting up to `wait` for a lookup that is under way
+pub fn label_within(addr: IpAddr, wait: Duration) -> String {
+    let Some(resolver) = RESOLVER.get() else {
+        return addr.to_string();
+    };
+    resolver.name(addr);
+    let deadline = Instant::now() + wait;
+    let mut names = resolver.names.lock().unwrap();
+    while let (Some(None), Some(left)) = (names.get(&addr), deadline.checked_duration_since(Instant::now())) {
+        names = resolver.answered.wait_timeout(names, left).unwrap().0;
+    }
+    match names.get(&addr).cloned().flatten().flatten() {
+        Some(name) => format!("{} ({})", name, addr),
+        None => addr.to_string(),
+    }
+}
@@ -0,0 +1,220 @@
+//! Recently pinged targets and the `history` subcommand.
+//!
+//! The final statistics of every target are added to an SQLite database in
+//! the user's data directory (`rust_ping/history.db`), so what was pinged
+//! lately and how it did can be looked up without keeping report files.
+//! Runs under sudo are stored in root's directory.
+
+use chrono::{Local, TimeZone};
+use colored::*;
+use comfy_table::modifiers::UTF8_ROUND_CORNERS;
+use comfy_table::presets::UTF8_FULL_CONDENSED;
+use comfy_table::{Cell, CellAlignment, ContentArrangement, Table};
+use rusqlite::{params, Connection};
+use std::fs;
+use std::path::PathBuf;
+
+use crate::summary::{loss_cell, ms_cell, TargetSummary};
+
+// Older runs are dropped once the store holds this many
+const MAX_RUNS: i64 = 10_000;
+
+const SCHEMA: &str = "CREATE TABLE IF NOT EXISTS runs (
+    id INTEGER PRIMARY KEY,
+    host TEXT NOT NULL,
+    ip TEXT NOT NULL,
+    ended_at INTEGER NOT NULL,
+    sent INTEGER NOT NULL,
+    received INTEGER NOT NULL,
+    loss_percent REAL NOT NULL,
+    min_ms REAL,
+    avg_ms REAL,
+    max_ms REAL,
+    p95_ms REAL
+);
+CREATE INDEX IF NOT EXISTS runs_host ON runs (host, id);";
+
+/// Options of the `history` subcommand
+#[derive(clap::Args, Debug)]
+pub struct HistoryArgs {
+    /// Show the past runs of this host instead of the latest run of every host
+    #[arg(value_name = "HOST")]
+    host: Option<String>,
+
+    /// Number of rows to show
+    #[arg(short = 'n', long, default_value_t = 20)]
+    limit: u32,
+}
+
+pub fn store_path() -> Result<PathBuf, String> {
+    dirs::data_dir()
+        .map(|dir| dir.join("rust_ping").join("history.db"))
+        .ok_or_else(|| "No data directory for the history store".to_string())
+}
+
+/// Past runs, newest first
+pub struct Store {
+    conn: Connection,
+}
+
+/// One stored run; `runs` is how many the host has when listing all hosts
+struct PastRun {
+    host: String,
+    ip: String,
+    ended_at: i64,
+    runs: Option<u32>,
+    sent: u32,
+    received: u32,
+    loss_percent: f64,
+    min_ms: Option<f64>,
+    avg_ms: Option<f64>,
+    max_ms: Option<f64>,
+    p95_ms: Option<f64>,
+}
+
+impl Store {
+    pub fn open() -> Result<Self, String> {
+        let path = store_path()?;
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+        }
+        let conn = Connection::open(&path).map_err(|e| format!("Failed to open {}: {}", path.display(), e))?;
+        conn.execute_batch(SCHEMA).map_err(|e| format!("Failed to create the history store: {}", e))?;
+        Ok(Store { conn })
+    }
+
+    /// Add the final statistics of a target
+    pub fn record(&self, summary: &TargetSummary) -> Result<(), String> {
+        let stats = &summary.stats;
+        self.conn
+            .execute(
+                "INSERT INTO runs (host, ip, ended_at, sent, received, loss_percent, min_ms, avg_ms, max_ms, p95_ms)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
+                params![
+                    summary.host,
+                    summary.addr.to_string(),
+                    Local::now().timestamp(),
+                    stats.packets_sent,
+                    stats.packets_received,
+                    stats.packet_loss_percent,
+                    stats.min_ms,
+                    stats.avg_ms,
+                    stats.max_ms,
+                    stats.p95_ms,
+                ],
+            )
+            .and_then(|_| {
+                self.conn.execute(
+                    "DELETE FROM runs WHERE id <= (SELECT MAX(id) FROM runs) - ?1",
+                    params![MAX_RUNS],
+                )
+            })
+            .map(|_| ())
+            .map_err(|e| format!("Failed to store the run: {}", e))
+    }
+
+    fn runs(&self, host: Option<&str>, limit: u32) -> rusqlite::Result<Vec<PastRun>> {
+        match host {
+            Some(host) => {
+                let sql = format!("SELECT {}, NULL FROM runs r WHERE r.host = ?1 ORDER BY r.id DESC LIMIT ?2", COLUMNS);
+                let mut statement = self.conn.prepare(&sql)?;
+                let runs = statement.query_map(params![host, limit], read_run)?.collect();
+                runs
+            }
+            None => {
+                // The latest run of every host, with how many it has
+                let sql = format!(
+                    "SELECT {}, latest.runs FROM runs r
+                     JOIN (SELECT MAX(id) AS id, COUNT(*) AS runs FROM runs GROUP BY host) latest ON r.id = latest.id
+                     ORDER BY r.id DESC LIMIT ?1",
+                    COLUMNS
+                );
+                let mut statement = self.conn.prepare(&sql)?;
+                let runs = statement.query_map(params![limit], read_run)?.collect();
+                runs
+            }
+        }
+    }
+}
+
+const COLUMNS: &str = "r.host, r.ip, r.ended_at, r.sent, r.received, r.loss_percent, r.min_ms, r.avg_ms, r.max_ms, r.p95_ms";
+
+fn read_run(row: &rusqlite::Row) -> rusqlite::Result<PastRun> {
+    Ok(PastRun {
+        host: row.get(0)?,
+        ip: row.get(1)?,
+        ended_at: row.get(2)?,
+        sent: row.get(3)?,
+        received: row.get(4)?,
+        loss_percent: row.get(5)?,
+        min_ms: row.get(6)?,
+        avg_ms: row.get(7)?,
+        max_ms: row.get(8)?,
+        p95_ms: row.get(9)?,
+        runs: row.get(10)?,
+    })
+}
+
+pub fn run(args: &HistoryArgs) -> Result<(), String> {
+    if !store_path()?.exists() {
+        println!("{}", "No runs recorded yet".dimmed());
+        return Ok(());
+    }
+    let store = Store::open()?;
+    let rows = store
+        .runs(args.host.as_deref(), args.limit)
+        .map_err(|e| format!("Failed to read the history store: {}", e))?;
+    if rows.is_empty() {
+        match &args.host {
+            Some(host) => println!("{}", format!("No runs recorded for {}", host).dimmed()),
+            None => println!("{}", "No runs recorded yet".dimmed()),
+        }
+        return Ok(());
+    }
+
+    let title = match &args.host {
+        Some(_) => "║                    🕘 PAST RUNS                              ║",
+        None => "║                  🕘 RECENT TARGETS                           ║",
+    };
+    println!("\n{}", "╔════════════════════════════════════════════════════════════╗".blue());
+    println!("{}", title.blue());
+    println!("{}", "╚════════════════════════════════════════════════════════════╝".blue());
+
+    let mut header = vec!["Host", "IP"];
+    match args.host {
+        Some(_) => header.push("Ended"),
+        None => header.extend(["Last run", "Runs"]),
+    }
+    header.extend(["Sent", "Recv", "Loss", "Min ms", "Avg ms", "Max ms", "p95 ms"]);
+
+    let mut table = Table::new();
+    table
+        .load_preset(UTF8_FULL_CONDENSED)
+        .apply_modifier(UTF8_ROUND_CORNERS)
+        .set_content_arrangement(ContentArrangement::Dynamic)
+        .set_header(header);
+
+    for row in rows {
+        let ended = Local
+            .timestamp_opt(row.ended_at, 0)
+            .single()
+            .map_or("-".to_string(), |t| t.format("%Y-%m-%d %H:%M").to_string());
+        let mut cells = vec![Cell::new(&row.host), Cell::new(&row.ip), Cell::new(ended)];
+        if let Some(runs) = row.runs {
+            cells.push(Cell::new(runs).set_alignment(CellAlignment::Right));
+        }
+        cells.extend([
+            Cell::new(row.sent).set_alignment(CellAlignment::Right),
+            Cell::new(row.received).set_alignment(CellAlignment::Right),
+            loss_cell(row.loss_percent),
+            ms_cell(row.min_ms),
+            ms_cell(row.avg_ms),
+            ms_cell(row.max_ms),
+            ms_cell(row.p95_ms),
+        ]);
+        table.add_row(cells);
+    }
+
+    println!("{}", table);
+    Ok(())
+}
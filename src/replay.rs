@@ -0,0 +1,170 @@
+//! Replay of saved reports.
+//!
+//! `--replay` runs the recorded target again with the probes of an exported
+//! report standing in for the network: every probe sent is answered with the
+//! outcome recorded for it, after its recorded RTT. Output, graphs, alert
+//! rules, sinks and exports work as they did live, without root or a network,
+//! which makes them easy to develop and demo. `--speed` shortens the interval
+//! and the waits, not the RTTs that are reported. Duplicate replies that were
+//! recorded come again after the probe they repeat, and replies whose payload
+//! came back corrupted come back corrupted again.
+
+use std::collections::VecDeque;
+use std::io;
+use std::mem;
+use std::net::IpAddr;
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+use crate::export::load_report;
+use crate::transport::{Reply, DEST_UNREACHABLE, ECHO_REPLY, PARAMETER_PROBLEM, TIME_EXCEEDED};
+use crate::{Args, PingResult, ProbeStatus};
+
+// Timeout of reports written before it was recorded in milliseconds
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// A saved run to play back
+pub struct Replay {
+    pub host: String,
+    pub addr: IpAddr,
+    probes: Vec<PingResult>,
+    duplicates: Vec<PingResult>,
+    interval: Duration,
+    timeout: Duration,
+    speed: f64,
+}
+
+impl Replay {
+    pub fn load(path: &str, speed: f64) -> Result<Self, String> {
+        if !speed.is_finite() || speed <= 0.0 {
+            return Err("Speed must be greater than zero".to_string());
+        }
+        let report = load_report(path)?;
+        if report.results.is_empty() {
+            return Err(format!("{} has no per-probe results to replay (only aggregates were saved)", path));
+        }
+        let addr = report
+            .ip_address
+            .parse()
+            .map_err(|_| format!("{}: invalid IP address '{}'", path, report.ip_address))?;
+        let interval = Duration::try_from_secs_f64(report.interval_seconds / speed)
+            .ok()
+            .filter(|interval| !interval.is_zero())
+            .ok_or_else(|| format!("{}: invalid interval {}", path, report.interval_seconds))?;
+        let timeout = if report.timeout_ms > 0.0 {
+            Duration::from_secs_f64(report.timeout_ms / 1000.0)
+        } else {
+            DEFAULT_TIMEOUT
+        };
+        let (duplicates, probes) = report.results.into_iter().partition(|result| result.duplicate);
+        Ok(Replay {
+            host: report.host,
+            addr,
+            probes,
+            duplicates,
+            interval,
+            timeout: timeout.div_f64(speed),
+            speed,
+        })
+    }
+
+    /// Options of a run of the recorded target at the replay speed
+    pub fn args(&self, args: &Args) -> Args {
+        Args {
+            hosts: vec![self.host.clone()],
+            count: self.probes.len() as u32,
+            interval: self.interval,
+            timeout: self.timeout,
+            ..args.clone()
+        }
+    }
+
+    pub fn player(&self) -> Player {
+        Player {
+            addr: self.addr,
+            probes: self.probes.iter().cloned().collect(),
+            duplicates: self.duplicates.clone(),
+            in_flight: VecDeque::new(),
+            speed: self.speed,
+        }
+    }
+}
+
+/// Answers the probes of one run with the recorded outcomes, in order
+pub struct Player {
+    addr: IpAddr,
+    probes: VecDeque<PingResult>,
+    // Further replies still to come, by the probe they repeat
+    duplicates: Vec<PingResult>,
+    // Probes "sent" and not answered yet, with when they went out
+    in_flight: VecDeque<(SystemTime, PingResult)>,
+    speed: f64,
+}
+
+impl Player {
+    /// Take the next recorded probes; the first one recorded as a send error fails the batch there
+    pub fn send_batch(&mut self, packets: &[Vec<u8>]) -> (usize, Option<io::Error>) {
+        for i in 0..packets.len() {
+            let Some(probe) = self.probes.pop_front() else {
+                return (i, Some(io::Error::other("no recorded probes left")));
+            };
+            if probe.status == ProbeStatus::SendError {
+                return (i, Some(io::Error::other("send error (recorded)")));
+            }
+            self.in_flight.push_back((SystemTime::now(), probe));
+        }
+        (packets.len(), None)
+    }
+
+    /// The recorded answer to the oldest probe in flight once it is due, `None` if none is within `timeout`
+    pub fn next_with_timeout(&mut self, identifier: u16, timeout: Duration) -> io::Result<Option<Reply>> {
+        // Lost probes get no answer; the run times them out
+        while self.in_flight.front().is_some_and(|(_, probe)| matches!(probe.status, ProbeStatus::Timeout | ProbeStatus::SendError)) {
+            self.in_flight.pop_front();
+        }
+        let Some((sent, probe)) = self.in_flight.front() else {
+            thread::sleep(timeout);
+            return Ok(None);
+        };
+
+        // Answered as long after it went out as the probe took, sped up
+        let rtt = Duration::from_secs_f64(probe.raw_rtt_ms.or(probe.rtt_ms).unwrap_or(0.0) / 1000.0);
+        let wait = rtt.div_f64(self.speed).saturating_sub(sent.elapsed().unwrap_or_default());
+        if wait > timeout {
+            thread::sleep(timeout);
+            return Ok(None);
+        }
+        thread::sleep(wait);
+        let Some((sent, probe)) = self.in_flight.pop_front() else {
+            return Ok(None);
+        };
+        if probe.status == ProbeStatus::Success && !probe.duplicate {
+            let (repeats, rest): (Vec<_>, Vec<_>) =
+                mem::take(&mut self.duplicates).into_iter().partition(|duplicate| duplicate.seq == probe.seq);
+            self.duplicates = rest;
+            for duplicate in repeats.into_iter().rev() {
+                self.in_flight.push_front((sent, duplicate));
+            }
+        }
+        let reply = |icmp_type, code| Reply {
+            source: self.addr,
+            icmp_type,
+            code,
+            identifier,
+            sequence: Some(probe.seq as u16),
+            checksum_ok: true,
+            received: sent + rtt,
+            ttl: probe.ttl,
+            mtu: None,
+            // No data stands for data that didn't match what was sent
+            payload: probe.corrupted.then(Vec::new),
+        };
+        Ok(match probe.status {
+            ProbeStatus::Success => Some(reply(ECHO_REPLY, 0)),
+            ProbeStatus::Unreachable { code } => Some(reply(DEST_UNREACHABLE, code)),
+            ProbeStatus::TtlExceeded => Some(reply(TIME_EXCEEDED, 0)),
+            ProbeStatus::ParameterProblem { code } => Some(reply(PARAMETER_PROBLEM, code)),
+            ProbeStatus::Timeout | ProbeStatus::SendError => None,
+        })
+    }
+}
@@ -0,0 +1,671 @@
+//! `report` subcommand: working with saved reports.
+//!
+//! `report render` redraws the statistics, line graph and latency
+//! distribution of an exported report in the terminal, or writes them as an
+//! SVG chart or a self-contained HTML page, without probing again; `replay`
+//! is the same at the top level.
+//! `report query` tells the loss and latency stored by `monitor` (or
+//! `--sqlite`) over any time window, as a whole or in steps of `--every`.
+
+use chrono::{DateTime, Local, NaiveDate, NaiveDateTime, TimeZone};
+use colored::*;
+use comfy_table::modifiers::UTF8_ROUND_CORNERS;
+use comfy_table::presets::UTF8_FULL_CONDENSED;
+use comfy_table::{Cell, CellAlignment, ContentArrangement, Table};
+use rusqlite::{params, Connection, OpenFlags};
+use std::fmt::Write as _;
+use std::fs;
+use std::net::IpAddr;
+use std::path::Path;
+use std::time::Duration;
+use rust_ping::{Marker, PingReport, SiteSwitch};
+
+use crate::alerts::human_duration;
+use crate::anycast;
+use crate::downsample;
+use crate::export::load_report;
+use crate::history::History;
+use crate::outages;
+use crate::monitor;
+use crate::stats::RttStats;
+use crate::summary::{self, loss_cell, ms_cell, TargetSummary};
+use crate::{draw_histogram, draw_line_graph, parse_duration, print_stats, PingResult, ProbeStatus};
+
+/// Options of the `report` subcommand
+#[derive(clap::Args, Debug)]
+pub struct ReportArgs {
+    #[command(subcommand)]
+    command: ReportCommand,
+}
+
+#[derive(clap::Subcommand, Debug)]
+enum ReportCommand {
+    /// Draw a saved report again (JSON, YAML, CBOR or MessagePack)
+    Render(RenderArgs),
+    /// Loss and latency stored by `monitor` over a time window
+    Query(QueryArgs),
+}
+
+/// Options of `report render` and `replay`
+#[derive(clap::Args, Debug)]
+pub struct RenderArgs {
+    /// Report exported with --json, --yaml, --cbor or --msgpack
+    #[arg(value_name = "FILE")]
+    file: String,
+
+    /// Write the latency chart as SVG instead of drawing in the terminal
+    #[arg(long, value_name = "FILE")]
+    svg: Option<String>,
+
+    /// Write an HTML page with the statistics and the chart instead of drawing in the terminal
+    #[arg(long, value_name = "FILE")]
+    html: Option<String>,
+}
+
+#[derive(clap::Args, Debug)]
+struct QueryArgs {
+    /// Hosts to report on, as they were given to `monitor` (all of them by default)
+    #[arg(value_name = "HOST")]
+    hosts: Vec<String>,
+
+    /// SQLite database written by `monitor` or --sqlite (default: the one `monitor` uses)
+    #[arg(long, value_name = "FILE")]
+    db: Option<String>,
+
+    /// Start of the window: a duration ago (e.g. 2h, 7d) or a local time (e.g. "2024-05-01 08:00")
+    #[arg(long, value_name = "WHEN", default_value = "24h", value_parser = parse_when)]
+    since: DateTime<Local>,
+
+    /// End of the window, in the same forms as --since (default: now)
+    #[arg(long, value_name = "WHEN", value_parser = parse_when)]
+    until: Option<DateTime<Local>>,
+
+    /// Break the window into steps of this long (e.g. 1h, 15m), one row each
+    #[arg(long, value_name = "DURATION", value_parser = parse_duration)]
+    every: Option<Duration>,
+}
+
+// Size of the SVG chart and its margins for the axis labels
+const SVG_WIDTH: usize = 900;
+const SVG_HEIGHT: usize = 300;
+const SVG_LEFT: usize = 60;
+const SVG_BOTTOM: usize = 30;
+const SVG_TOP: usize = 20;
+// Parts of the run the loss timeline shows, and its height
+const LOSS_CELLS: usize = 120;
+const LOSS_HEIGHT: usize = 24;
+
+pub fn run(args: &ReportArgs) -> Result<(), String> {
+    match &args.command {
+        ReportCommand::Render(args) => render(args),
+        ReportCommand::Query(args) => query(args),
+    }
+}
+
+/// Probes to draw: the results, or one point per second for aggregate-only reports
+pub fn probes(report: &PingReport) -> Vec<PingResult> {
+    if !report.results.is_empty() {
+        // Duplicate replies aren't probes of their own
+        return report.results.iter().filter(|result| !result.duplicate).cloned().collect();
+    }
+    let aggregates = report.aggregates.as_deref().unwrap_or_default();
+    aggregates
+        .iter()
+        .map(|agg| PingResult {
+            seq: agg.second as u32,
+            rtt_ms: agg.avg_ms,
+            raw_rtt_ms: None,
+            status: if agg.received > 0 { ProbeStatus::Success } else { ProbeStatus::Timeout },
+            ttl: None,
+            timestamp: None,
+            duplicate: false,
+            reordered: false,
+            corrupted: false,
+        })
+        .collect()
+}
+
+pub fn render(args: &RenderArgs) -> Result<(), String> {
+    let report = load_report(&args.file)?;
+    let probes = probes(&report);
+
+    if args.svg.is_none() && args.html.is_none() {
+        render_terminal(&report, &probes);
+        return Ok(());
+    }
+    if let Some(path) = &args.svg {
+        fs::write(path, svg_chart(&report, &probes)).map_err(|e| format!("Failed to write {}: {}", path, e))?;
+        println!("  {} Rendered to SVG: {}", "✓".green(), path.cyan());
+    }
+    if let Some(path) = &args.html {
+        fs::write(path, html_page(&report, &probes)).map_err(|e| format!("Failed to write {}: {}", path, e))?;
+        println!("  {} Rendered to HTML: {}", "✓".green(), path.cyan());
+    }
+    Ok(())
+}
+
+/// Statistics, graphs and markers of a report, as at the end of its run
+pub fn render_terminal(report: &PingReport, probes: &[PingResult]) {
+    println!("\n{}", "╔════════════════════════════════════════════════════════════╗".cyan());
+    println!("{}       REPORT {} - run {}                {}",
+        "║".cyan(),
+        report.host.yellow().bold(),
+        report.run_id.green(),
+        "║".cyan()
+    );
+    println!("{}", "╚════════════════════════════════════════════════════════════╝".cyan());
+    println!("  {}", format!("{} -> {}", report.timestamp_start, report.timestamp_end).dimmed());
+    if !report.complete {
+        println!("  {}", "Incomplete run: the report was closed before the run finished".yellow());
+    }
+    if let Some(source) = report.source() {
+        println!("  Source: {}", source.cyan());
+    }
+    if report.results.is_empty() && !probes.is_empty() {
+        println!("  {}", "Only per-second aggregates were saved; graphs show the average of each second".dimmed());
+    }
+
+    let addr: IpAddr = report.ip_address.parse().unwrap_or(IpAddr::from([0, 0, 0, 0]));
+    print_stats(&report.statistics, addr);
+    for switch in &report.site_switches {
+        anycast::print_switch(switch);
+    }
+
+    if probes.is_empty() {
+        return;
+    }
+    let mut history = History::new(probes.len());
+    let mut rtt = RttStats::new();
+    for probe in probes {
+        if let Some(ms) = probe.rtt_ms {
+            rtt.add(ms);
+        }
+        history.push(probe.clone());
+    }
+    draw_line_graph(&history, &marks(report));
+    draw_histogram(&rtt);
+}
+
+/// The markers of a report with its site switches among them
+fn marks(report: &PingReport) -> Vec<Marker> {
+    let mut marks = report.markers.clone();
+    marks.extend(report.site_switches.iter().map(SiteSwitch::marker));
+    marks.sort_by_key(|marker| marker.seq);
+    marks
+}
+
+pub fn escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+/// Line chart of the RTT over the run, with losses and markers
+fn svg_chart(report: &PingReport, probes: &[PingResult]) -> String {
+    let plot_width = SVG_WIDTH - SVG_LEFT - 10;
+    let plot_height = SVG_HEIGHT - SVG_TOP - SVG_BOTTOM;
+    let rtts: Vec<Option<f64>> = probes.iter().map(|p| p.rtt_ms).collect();
+    let max_rtt = rtts.iter().flatten().cloned().fold(0.0_f64, f64::max).max(0.1) * 1.1;
+
+    // One point per pixel column at most, spikes kept
+    let columns = downsample::lttb(&rtts, plot_width);
+    let step = plot_width as f64 / columns.len().max(1) as f64;
+    let x = |column: usize| SVG_LEFT as f64 + (column as f64 + 0.5) * step;
+    let y = |rtt: f64| SVG_TOP as f64 + plot_height as f64 * (1.0 - rtt / max_rtt);
+
+    let mut svg = String::new();
+    let _ = writeln!(
+        svg,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{w}" height="{h}" viewBox="0 0 {w} {h}" font-family="sans-serif" font-size="11">"#,
+        w = SVG_WIDTH,
+        h = SVG_HEIGHT
+    );
+    let _ = writeln!(svg, r#"<title>{} ({})</title>"#, escape(&report.host), escape(&report.ip_address));
+    let _ = writeln!(svg, r##"<rect width="100%" height="100%" fill="#ffffff"/>"##);
+
+    // Horizontal grid with RTT labels
+    for i in 0..=4 {
+        let rtt = max_rtt * i as f64 / 4.0;
+        let decimals = if max_rtt < 10.0 { 2 } else { 1 };
+        let _ = writeln!(
+            svg,
+            r##"<line x1="{x1}" y1="{y:.1}" x2="{x2}" y2="{y:.1}" stroke="#e0e0e0"/><text x="{lx}" y="{ty:.1}" text-anchor="end" fill="#666">{rtt:.decimals$} ms</text>"##,
+            x1 = SVG_LEFT,
+            x2 = SVG_LEFT + plot_width,
+            y = y(rtt),
+            lx = SVG_LEFT - 6,
+            ty = y(rtt) + 4.0,
+        );
+    }
+
+    // Replies as a line, broken where a whole column was lost
+    let mut path = String::new();
+    let mut pen_down = false;
+    for (c, column) in columns.iter().enumerate() {
+        match column.rtt {
+            Some(rtt) => {
+                let _ = write!(path, "{}{:.1},{:.1} ", if pen_down { "L" } else { "M" }, x(c), y(rtt));
+                pen_down = true;
+            }
+            None => pen_down = false,
+        }
+    }
+    let _ = writeln!(svg, r##"<path d="{}" fill="none" stroke="#2a7ab0" stroke-width="1.5"/>"##, path.trim_end());
+
+    // Losses as red ticks along the bottom
+    let bottom = SVG_TOP + plot_height;
+    for (c, column) in columns.iter().enumerate() {
+        if let Some(lost) = column.lost {
+            let _ = writeln!(
+                svg,
+                r##"<line x1="{x:.1}" y1="{y1}" x2="{x:.1}" y2="{y2}" stroke="#d03030" stroke-width="2"><title>seq {seq}: {status}</title></line>"##,
+                x = x(c),
+                y1 = bottom - 8,
+                y2 = bottom,
+                seq = probes[lost].seq,
+                status = probes[lost].status.name(),
+            );
+        }
+    }
+
+    // Markers as dashed vertical lines at the first probe sent after them
+    for marker in &marks(report) {
+        let Some(index) = probes.iter().position(|p| p.seq >= marker.seq) else {
+            continue;
+        };
+        let column = index * columns.len() / probes.len().max(1);
+        let _ = writeln!(
+            svg,
+            r##"<line x1="{x:.1}" y1="{top}" x2="{x:.1}" y2="{bottom}" stroke="#a040a0" stroke-dasharray="4 3"/><text x="{tx:.1}" y="{ty}" fill="#a040a0">{name}</text>"##,
+            x = x(column),
+            top = SVG_TOP,
+            bottom = bottom,
+            tx = x(column) + 3.0,
+            ty = SVG_TOP + 10,
+            name = escape(&marker.name),
+        );
+    }
+
+    // Axes and the range of the run
+    let _ = writeln!(
+        svg,
+        r##"<line x1="{l}" y1="{t}" x2="{l}" y2="{b}" stroke="#333"/><line x1="{l}" y1="{b}" x2="{r}" y2="{b}" stroke="#333"/>"##,
+        l = SVG_LEFT,
+        t = SVG_TOP,
+        b = bottom,
+        r = SVG_LEFT + plot_width,
+    );
+    if let (Some(first), Some(last)) = (probes.first(), probes.last()) {
+        let unit = if report.results.is_empty() { "second" } else { "seq" };
+        let _ = writeln!(
+            svg,
+            r##"<text x="{l}" y="{ty}" fill="#666">{unit} {first}</text><text x="{r}" y="{ty}" text-anchor="end" fill="#666">{unit} {last}</text>"##,
+            l = SVG_LEFT,
+            r = SVG_LEFT + plot_width,
+            ty = bottom + 16,
+            first = first.seq,
+            last = last.seq,
+        );
+    }
+    svg.push_str("</svg>\n");
+    svg
+}
+
+/// Self-contained page with the statistics, the latency chart (pointing at
+/// it tells the RTT of each column), a loss timeline and the outages of a run
+pub fn html_page(report: &PingReport, probes: &[PingResult]) -> String {
+    let ms = |value: Option<f64>| value.map_or("-".to_string(), |v| format!("{:.2} ms", v));
+    let stats = &report.statistics;
+    let mut rows = vec![
+        ("Host", format!("{} ({})", report.host, report.ip_address)),
+        ("Run", report.run_id.clone()),
+        ("Start", report.timestamp_start.clone()),
+        ("End", format!("{}{}", report.timestamp_end, if report.complete { "" } else { " (incomplete)" })),
+        ("Packets", format!("{} sent, {} received, {} lost ({:.1}%)", stats.packets_sent, stats.packets_received, stats.packets_lost, stats.packet_loss_percent)),
+        ("Min / Avg / Max", format!("{} / {} / {}", ms(stats.min_ms), ms(stats.avg_ms), ms(stats.max_ms))),
+        ("StdDev", ms(stats.std_dev_ms)),
+        ("p50 / p95 / p99", format!("{} / {} / {}", ms(stats.p50_ms), ms(stats.p95_ms), ms(stats.p99_ms))),
+        ("Jitter", ms(stats.jitter_ms)),
+    ];
+
+    if let Some(source) = report.source() {
+        rows.insert(1, ("Source", source));
+    }
+    if stats.packets_lost > 0 {
+        rows.push((
+            "Lost",
+            format!(
+                "{} timeout, {} unreachable, {} TTL exceeded, {} parameter problems, {} send errors",
+                stats.packets_timeout,
+                stats.packets_unreachable,
+                stats.packets_ttl_exceeded,
+                stats.packets_parameter_problem,
+                stats.packets_send_error
+            ),
+        ));
+    }
+    if let Some(bursts) = crate::loss_bursts(&stats.loss_bursts) {
+        rows.push(("Lost in a row", format!("{} at most; bursts {}", stats.longest_loss_streak, bursts)));
+    }
+    if stats.packets_duplicate > 0 || stats.packets_reordered > 0 {
+        rows.push(("Duplicates / out of order", format!("{} / {}", stats.packets_duplicate, stats.packets_reordered)));
+    }
+
+    let mut html = String::new();
+    let _ = writeln!(html, "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">");
+    let _ = writeln!(html, "<title>Ping report: {}</title>", escape(&report.host));
+    let _ = writeln!(html, "<style>body {{ font-family: sans-serif; margin: 2em; }} td, th {{ padding: 2px 12px 2px 0; text-align: left; }} th {{ color: #666; font-weight: normal; }} #latency {{ position: relative; width: fit-content; }} #tip {{ position: absolute; display: none; pointer-events: none; background: #333; color: #fff; font-size: 12px; padding: 2px 6px; border-radius: 3px; white-space: nowrap; }}</style>");
+    let _ = writeln!(html, "</head>\n<body>\n<h1>Ping report: {}</h1>\n<table>", escape(&report.host));
+    for (label, value) in rows {
+        let _ = writeln!(html, "<tr><th>{}</th><td>{}</td></tr>", label, escape(&value));
+    }
+    let _ = writeln!(html, "</table>");
+    if !probes.is_empty() {
+        let _ = writeln!(html, "<h2>Latency</h2>\n<div id=\"latency\">\n{}<div id=\"tip\"></div>\n</div>", svg_chart(report, probes));
+        html.push_str(&chart_script(report, probes));
+        let _ = writeln!(html, "<h2>Loss</h2>\n{}", loss_timeline(report, probes));
+    }
+    if !report.outages.is_empty() {
+        let (down, _) = outages::downtime(&report.outages, 0.0);
+        let _ = writeln!(html, "<h2>Outages</h2>\n<table>\n<tr><th>Start</th><th>End</th><th>Duration</th><th>From seq</th><th>Lost</th></tr>");
+        for outage in &report.outages {
+            let _ = writeln!(
+                html,
+                "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+                escape(&outage.start),
+                escape(outage.end.as_deref().unwrap_or("still down")),
+                human_duration(outage.duration_seconds),
+                outage.first_seq,
+                outage.lost
+            );
+        }
+        let _ = writeln!(
+            html,
+            "</table>\n<p>Downtime: {} in {} outage{}</p>",
+            human_duration(down),
+            report.outages.len(),
+            if report.outages.len() == 1 { "" } else { "s" }
+        );
+    }
+    if !report.markers.is_empty() {
+        let _ = writeln!(html, "<h2>Markers</h2>\n<table>");
+        for marker in &report.markers {
+            let _ = writeln!(
+                html,
+                "<tr><td>{}</td><td>{}</td><td>before seq {}</td></tr>",
+                escape(&marker.timestamp),
+                escape(&marker.name),
+                marker.seq
+            );
+        }
+        let _ = writeln!(html, "</table>");
+    }
+    if !report.site_switches.is_empty() {
+        let _ = writeln!(html, "<h2>Anycast site switches</h2>\n<table>");
+        for switch in &report.site_switches {
+            let _ = writeln!(
+                html,
+                "<tr><td>{}</td><td>{}</td><td>at seq {}</td><td>{}</td></tr>",
+                escape(&switch.timestamp),
+                if switch.likely { "likely" } else { "possible" },
+                switch.seq,
+                escape(&switch.evidence.join(", "))
+            );
+        }
+        let _ = writeln!(html, "</table>");
+    }
+    html.push_str("</body>\n</html>\n");
+    html
+}
+
+/// What the chart shows at each column, and the script that tells it where
+/// the pointer is
+fn chart_script(report: &PingReport, probes: &[PingResult]) -> String {
+    let plot_width = SVG_WIDTH - SVG_LEFT - 10;
+    let rtts: Vec<Option<f64>> = probes.iter().map(|p| p.rtt_ms).collect();
+    let columns = downsample::lttb(&rtts, plot_width);
+    let step = plot_width as f64 / columns.len().max(1) as f64;
+    let unit = if report.results.is_empty() { "second" } else { "seq" };
+
+    let points: Vec<String> = columns
+        .iter()
+        .enumerate()
+        .map(|(c, column)| {
+            let (first, last) = (probes[c * probes.len() / columns.len()].seq, probes[(c + 1) * probes.len() / columns.len() - 1].seq);
+            let range = if first == last { format!("{} {}", unit, first) } else { format!("{} {}–{}", unit, first, last) };
+            let text = match (column.rtt, column.lost) {
+                (Some(rtt), None) => format!("{}: {:.2} ms", range, rtt),
+                (Some(rtt), Some(_)) => format!("{}: {:.2} ms, some lost", range, rtt),
+                (None, _) => format!("{}: lost", range),
+            };
+            format!("[{:.1},{}]", SVG_LEFT as f64 + (c as f64 + 0.5) * step, serde_json::Value::from(text))
+        })
+        .collect();
+
+    format!(
+        r##"<script>
+(() => {{
+  const points = [{points}];
+  const box = document.getElementById("latency"), svg = box.querySelector("svg"), tip = document.getElementById("tip");
+  const guide = document.createElementNS("http://www.w3.org/2000/svg", "line");
+  guide.setAttribute("y1", {top}); guide.setAttribute("y2", {bottom}); guide.setAttribute("stroke", "#999"); guide.style.display = "none";
+  svg.appendChild(guide);
+  svg.addEventListener("mousemove", (event) => {{
+    const rect = svg.getBoundingClientRect(), x = (event.clientX - rect.left) * {width} / rect.width;
+    let nearest = points[0];
+    for (const point of points) if (Math.abs(point[0] - x) < Math.abs(nearest[0] - x)) nearest = point;
+    guide.setAttribute("x1", nearest[0]); guide.setAttribute("x2", nearest[0]); guide.style.display = "";
+    tip.textContent = nearest[1]; tip.style.display = "block";
+    tip.style.left = (nearest[0] * rect.width / {width} + 8) + "px"; tip.style.top = (event.clientY - rect.top - 28) + "px";
+  }});
+  svg.addEventListener("mouseleave", () => {{ guide.style.display = "none"; tip.style.display = "none"; }});
+}})();
+</script>
+"##,
+        points = points.join(","),
+        top = SVG_TOP,
+        bottom = SVG_HEIGHT - SVG_BOTTOM,
+        width = SVG_WIDTH,
+    )
+}
+
+/// Strip of the run in equal parts, each colored by the share of its probes lost
+fn loss_timeline(report: &PingReport, probes: &[PingResult]) -> String {
+    let plot_width = SVG_WIDTH - SVG_LEFT - 10;
+    let cells = probes.len().min(LOSS_CELLS);
+    let width = plot_width as f64 / cells as f64;
+    let unit = if report.results.is_empty() { "second" } else { "seq" };
+
+    let mut svg = String::new();
+    let _ = writeln!(
+        svg,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{w}" height="{h}" viewBox="0 0 {w} {h}" font-family="sans-serif" font-size="11">"#,
+        w = SVG_WIDTH,
+        h = LOSS_HEIGHT + 20
+    );
+    for cell in 0..cells {
+        let part = &probes[cell * probes.len() / cells..(cell + 1) * probes.len() / cells];
+        let lost = part.iter().filter(|p| p.rtt_ms.is_none()).count();
+        let share = lost as f64 / part.len() as f64;
+        // From pale green with no loss to red with all of it lost
+        let color = if lost == 0 {
+            "#cfe8d5".to_string()
+        } else {
+            let mix = |from: f64, to: f64| (from + (to - from) * (0.3 + 0.7 * share)).round() as u8;
+            format!("#{:02x}{:02x}{:02x}", mix(240.0, 208.0), mix(200.0, 48.0), mix(160.0, 48.0))
+        };
+        let (first, last) = (part[0].seq, part[part.len() - 1].seq);
+        let range = if first == last { format!("{} {}", unit, first) } else { format!("{} {}–{}", unit, first, last) };
+        let _ = writeln!(
+            svg,
+            r#"<rect x="{x:.1}" y="0" width="{w:.1}" height="{h}" fill="{color}"><title>{range}: {lost} of {n} lost ({pct:.1}%)</title></rect>"#,
+            x = SVG_LEFT as f64 + cell as f64 * width,
+            w = width,
+            h = LOSS_HEIGHT,
+            n = part.len(),
+            pct = share * 100.0,
+        );
+    }
+    let _ = writeln!(
+        svg,
+        r##"<text x="{l}" y="{ty}" fill="#666">{unit} {first}</text><text x="{r}" y="{ty}" text-anchor="end" fill="#666">{unit} {last}</text>"##,
+        l = SVG_LEFT,
+        r = SVG_LEFT + plot_width,
+        ty = LOSS_HEIGHT + 16,
+        first = probes[0].seq,
+        last = probes[probes.len() - 1].seq,
+    );
+    svg.push_str("</svg>\n");
+    svg
+}
+
+/// Parse a duration ago or a local date and time
+fn parse_when(value: &str) -> Result<DateTime<Local>, String> {
+    if let Ok(ago) = parse_duration(value) {
+        let ago = chrono::Duration::from_std(ago).map_err(|_| format!("'{}' is too long ago", value))?;
+        return Ok(Local::now() - ago);
+    }
+    let value = value.trim();
+    let time = ["%Y-%m-%d %H:%M:%S", "%Y-%m-%d %H:%M", "%Y-%m-%dT%H:%M:%S", "%Y-%m-%dT%H:%M"]
+        .iter()
+        .find_map(|format| NaiveDateTime::parse_from_str(value, format).ok())
+        .or_else(|| NaiveDate::parse_from_str(value, "%Y-%m-%d").ok().and_then(|date| date.and_hms_opt(0, 0, 0)))
+        .ok_or_else(|| format!("invalid time '{}': expected a duration ago (e.g. 2h) or YYYY-MM-DD[ HH:MM[:SS]]", value))?;
+    Local
+        .from_local_datetime(&time)
+        .earliest()
+        .ok_or_else(|| format!("{} doesn't exist in the local time zone", value))
+}
+
+/// Probes of one host within a stretch of time
+struct Window {
+    sent: u32,
+    rtt: RttStats,
+}
+
+impl Window {
+    fn new() -> Self {
+        Window { sent: 0, rtt: RttStats::new() }
+    }
+
+    fn add(&mut self, rtt_ms: Option<f64>, status: &str, code: Option<u8>) {
+        self.sent += 1;
+        match rtt_ms {
+            Some(rtt) => self.rtt.add(rtt),
+            None => self.rtt.add_failure(ProbeStatus::from_name(status, code).unwrap_or(ProbeStatus::Timeout)),
+        }
+    }
+}
+
+fn query(args: &QueryArgs) -> Result<(), String> {
+    let path = match &args.db {
+        Some(path) => path.clone(),
+        None => monitor::default_db()?.display().to_string(),
+    };
+    if !Path::new(&path).exists() {
+        return Err(format!("No database at {}; results are stored by `monitor` or --sqlite", path));
+    }
+    let conn = Connection::open_with_flags(&path, OpenFlags::SQLITE_OPEN_READ_ONLY)
+        .map_err(|e| format!("Failed to open {}: {}", path, e))?;
+    let failed = |e: rusqlite::Error| format!("Failed to query {}: {}", path, e);
+
+    let since = args.since.timestamp_millis();
+    let until = args.until.unwrap_or_else(Local::now).timestamp_millis();
+    if until <= since {
+        return Err("--until must be later than --since".to_string());
+    }
+    let hosts = if args.hosts.is_empty() {
+        let mut stmt = conn
+            .prepare("SELECT DISTINCT host FROM probes WHERE time_ms >= ?1 AND time_ms < ?2 ORDER BY host")
+            .map_err(failed)?;
+        let hosts = stmt.query_map(params![since, until], |row| row.get(0)).map_err(failed)?;
+        hosts.collect::<Result<Vec<String>, _>>().map_err(failed)?
+    } else {
+        args.hosts.clone()
+    };
+
+    println!("\n{}", "╔════════════════════════════════════════════════════════════╗".cyan());
+    println!("{}       QUERY {} hosts                                    {}",
+        "║".cyan(),
+        hosts.len().to_string().yellow().bold(),
+        "║".cyan()
+    );
+    println!("{}", "╚════════════════════════════════════════════════════════════╝".cyan());
+    println!("  {}", format!("{} -> {}", format_ms(since), format_ms(until)).dimmed());
+
+    let step = args.every.map(|every| (every.as_millis() as i64).max(1));
+    let mut stmt = conn
+        .prepare(
+            "SELECT ip, time_ms, rtt_ms, status, icmp_code FROM probes
+             WHERE host = ?1 AND time_ms >= ?2 AND time_ms < ?3 ORDER BY time_ms",
+        )
+        .map_err(failed)?;
+    let mut summaries = Vec::new();
+    for host in &hosts {
+        let mut rows = stmt.query(params![host, since, until]).map_err(failed)?;
+        let mut whole = Window::new();
+        // Steps with probes in them, by their start
+        let mut steps: Vec<(i64, Window)> = Vec::new();
+        let mut ip = String::new();
+        while let Some(row) = rows.next().map_err(failed)? {
+            ip = row.get(0).map_err(failed)?;
+            let time: i64 = row.get(1).map_err(failed)?;
+            let rtt: Option<f64> = row.get(2).map_err(failed)?;
+            let status: String = row.get(3).map_err(failed)?;
+            let code: Option<u8> = row.get(4).map_err(failed)?;
+            whole.add(rtt, &status, code);
+            if let Some(step) = step {
+                let start = since + (time - since) / step * step;
+                if steps.last().is_none_or(|(last, _)| *last != start) {
+                    steps.push((start, Window::new()));
+                }
+                steps.last_mut().unwrap().1.add(rtt, &status, code);
+            }
+        }
+        if whole.sent == 0 {
+            println!("  {} No results for {} in this window", "⚠".yellow(), host);
+            continue;
+        }
+        if step.is_some() {
+            print_steps(host, &ip, &steps);
+        }
+        let addr: IpAddr = ip.parse().unwrap_or(IpAddr::from([0, 0, 0, 0]));
+        summaries.push(TargetSummary { host: host.clone(), addr, stats: whole.rtt.statistics(whole.sent) });
+    }
+    if !summaries.is_empty() {
+        summary::print_summary_table(&mut summaries, None);
+    }
+    Ok(())
+}
+
+/// Local time of a Unix timestamp in milliseconds
+fn format_ms(ms: i64) -> String {
+    Local
+        .timestamp_millis_opt(ms)
+        .single()
+        .map(|time| time.format("%Y-%m-%d %H:%M:%S").to_string())
+        .unwrap_or_default()
+}
+
+/// One row per step of a host's window; steps without probes are left out
+fn print_steps(host: &str, ip: &str, steps: &[(i64, Window)]) {
+    println!("\n  Host: {} ({})", host.yellow(), ip);
+    let mut table = Table::new();
+    table
+        .load_preset(UTF8_FULL_CONDENSED)
+        .apply_modifier(UTF8_ROUND_CORNERS)
+        .set_content_arrangement(ContentArrangement::Dynamic)
+        .set_header(vec!["From", "Sent", "Recv", "Loss", "Min ms", "Avg ms", "Max ms", "p95 ms"]);
+    for (start, window) in steps {
+        let stats = window.rtt.statistics(window.sent);
+        table.add_row(vec![
+            Cell::new(format_ms(*start)),
+            Cell::new(stats.packets_sent).set_alignment(CellAlignment::Right),
+            Cell::new(stats.packets_received).set_alignment(CellAlignment::Right),
+            loss_cell(stats.packet_loss_percent),
+            ms_cell(stats.min_ms),
+            ms_cell(stats.avg_ms),
+            ms_cell(stats.max_ms),
+            ms_cell(stats.p95_ms),
+        ]);
+    }
+    println!("{}", table);
+}
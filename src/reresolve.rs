@@ -0,0 +1,166 @@
+//! Following DNS changes in long runs.
+//!
+//! A hostname is resolved once, before its first probe, so a failover that
+//! moves it to another address (a round-robin record rotating, a DNS load
+//! balancer steering away from a site) goes unseen. With `--re-resolve`
+//! the name is looked up again every INTERVAL on a thread of its own; when
+//! it resolves to a new address the probes follow it, the change is marked
+//! in the output and the reports, and the statistics of every address are
+//! kept apart as well as together.
+
+use colored::*;
+use comfy_table::modifiers::UTF8_ROUND_CORNERS;
+use comfy_table::presets::UTF8_FULL_CONDENSED;
+use comfy_table::{Cell, CellAlignment, ContentArrangement, Table};
+use std::net::IpAddr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+use rust_ping::AddressStats;
+
+use crate::resolve_host;
+use crate::stats::RttStats;
+use crate::summary::{loss_cell, ms_cell};
+
+/// Resolves a hostname again in the background
+pub struct DnsWatch {
+    answers: Receiver<Result<IpAddr, String>>,
+    stop: Arc<AtomicBool>,
+    // Warned about already, so a lasting failure or family change is told once
+    failing: bool,
+    ignored: Option<IpAddr>,
+}
+
+impl DnsWatch {
+    pub fn start(host: &str, ipv6: bool, every: Duration) -> Self {
+        let (tx, answers) = mpsc::channel();
+        let stop = Arc::new(AtomicBool::new(false));
+        let stopped = stop.clone();
+        let host = host.to_string();
+
+        thread::spawn(move || {
+            let mut round = Instant::now();
+            while !stopped.load(Ordering::Relaxed) {
+                if round.elapsed() < every {
+                    thread::sleep(Duration::from_millis(100).min(every));
+                    continue;
+                }
+                round = Instant::now();
+                if tx.send(resolve_host(&host, ipv6)).is_err() {
+                    break;
+                }
+            }
+        });
+        DnsWatch { answers, stop, failing: false, ignored: None }
+    }
+
+    /// The new address of the host, when a lookup since the last call moved
+    /// it away from `current`
+    pub fn moved(&mut self, current: IpAddr) -> Option<IpAddr> {
+        let mut moved = None;
+        while let Ok(answer) = self.answers.try_recv() {
+            match answer {
+                Ok(addr) => {
+                    if self.failing {
+                        eprintln!("  {} Resolving again succeeded", "✓".green());
+                        self.failing = false;
+                    }
+                    // The socket was opened for the family of the first address
+                    if addr.is_ipv6() != current.is_ipv6() {
+                        if self.ignored != Some(addr) {
+                            eprintln!("  {} Now resolves to {}, of another address family; still pinging {}", "⚠".yellow(), addr, current);
+                            self.ignored = Some(addr);
+                        }
+                        continue;
+                    }
+                    moved = (addr != current).then_some(addr);
+                }
+                Err(e) => {
+                    if !self.failing {
+                        eprintln!("  {} Resolving again failed, still pinging {}: {}", "⚠".yellow(), current, e);
+                        self.failing = true;
+                    }
+                }
+            }
+        }
+        moved
+    }
+}
+
+impl Drop for DnsWatch {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+}
+
+/// The probes of a target by the address they were sent to
+#[derive(Default)]
+pub struct PerAddress {
+    addrs: Vec<(IpAddr, u32, u32, RttStats)>,
+}
+
+impl PerAddress {
+    /// Count probe `seq` sent to `addr`, with its RTT when it was answered
+    pub fn record(&mut self, addr: IpAddr, seq: u32, rtt: Option<f64>) {
+        let i = match self.addrs.iter().position(|(known, ..)| *known == addr) {
+            Some(i) => i,
+            None => {
+                self.addrs.push((addr, seq, 0, RttStats::new()));
+                self.addrs.len() - 1
+            }
+        };
+        let (_, _, sent, rtts) = &mut self.addrs[i];
+        *sent += 1;
+        if let Some(rtt) = rtt {
+            rtts.add(rtt);
+        }
+    }
+
+    /// Every address probed, in the order it was first used
+    pub fn results(&self) -> Vec<AddressStats> {
+        self.addrs
+            .iter()
+            .map(|&(ip, first_seq, sent, ref rtts)| {
+                let stats = rtts.statistics(sent);
+                AddressStats {
+                    ip,
+                    first_seq,
+                    sent,
+                    received: stats.packets_received,
+                    loss_percent: stats.packet_loss_percent,
+                    min_ms: stats.min_ms,
+                    avg_ms: stats.avg_ms,
+                    max_ms: stats.max_ms,
+                }
+            })
+            .collect()
+    }
+}
+
+pub fn print_addresses(host: &str, addrs: &[AddressStats]) {
+    println!("\n{}", "╔════════════════════════════════════════════════════════════╗".magenta());
+    println!("{}", "║                   🔀 PER ADDRESS                            ║".magenta());
+    println!("{}", "╚════════════════════════════════════════════════════════════╝".magenta());
+    println!("  Host: {}", host.yellow());
+
+    let mut table = Table::new();
+    table
+        .load_preset(UTF8_FULL_CONDENSED)
+        .apply_modifier(UTF8_ROUND_CORNERS)
+        .set_content_arrangement(ContentArrangement::Dynamic)
+        .set_header(vec!["Address", "From seq", "Sent", "Loss", "Min ms", "Avg ms", "Max ms"]);
+    for addr in addrs {
+        table.add_row(vec![
+            Cell::new(addr.ip),
+            Cell::new(addr.first_seq).set_alignment(CellAlignment::Right),
+            Cell::new(addr.sent).set_alignment(CellAlignment::Right),
+            loss_cell(addr.loss_percent),
+            ms_cell(addr.min_ms),
+            ms_cell(addr.avg_ms),
+            ms_cell(addr.max_ms),
+        ]);
+    }
+    println!("{}", table);
+}
@@ -0,0 +1,291 @@
+//! Subnet sweep.
+//!
+//! `scan 192.168.1.0/24` sends echo requests to every address of a network
+//! and lists those that answer with their best RTT, as `fping -g` or
+//! `nmap -sn` do. Up to `--concurrency` probes are out at once over a single
+//! socket, and each address gets `--count` of them; one that answers none
+//! within `-t` counts as down. The network and broadcast addresses of IPv4
+//! networks are skipped, except for /31 and /32 which have none. The alive
+//! hosts can be saved with `--json` and `--csv`.
+
+use chrono::Local;
+use colored::*;
+use comfy_table::modifiers::UTF8_ROUND_CORNERS;
+use comfy_table::presets::UTF8_FULL_CONDENSED;
+use comfy_table::{Cell, CellAlignment, ContentArrangement, Table};
+use serde::Serialize;
+use std::collections::{HashMap, VecDeque};
+use std::io::{self, IsTerminal, Write};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::time::{Duration, Instant, SystemTime};
+
+use crate::export::write_atomic;
+use crate::identifier::Identifier;
+use crate::summary::ms_cell;
+use crate::trace::wait_answer;
+use crate::transport::{self, DEST_UNREACHABLE, ECHO_REPLY};
+use crate::{interrupt, parse_duration, write_icmp_packet, PACKET_LEN, QUIT_POLL};
+
+/// Options of the `scan` subcommand
+#[derive(clap::Args, Debug)]
+pub struct ScanArgs {
+    /// Network to sweep, as an address and prefix length (e.g. 192.168.1.0/24)
+    #[arg(value_name = "CIDR")]
+    network: String,
+
+    /// Probes sent to each address; the best RTT of those answered is shown
+    #[arg(short, long, default_value_t = 1, value_parser = clap::value_parser!(u16).range(1..=10))]
+    count: u16,
+
+    /// Probes out at once
+    #[arg(long, default_value_t = 256, value_parser = clap::value_parser!(u16).range(1..=MAX_CONCURRENCY as i64))]
+    concurrency: u16,
+
+    /// Timeout for each probe, in seconds or with a unit (e.g. 2, 500ms)
+    #[arg(short, long, default_value = "1", value_parser = parse_duration)]
+    timeout: Duration,
+
+    /// Save the alive hosts to a JSON file
+    #[arg(long, value_name = "FILE")]
+    json: Option<String>,
+
+    /// Save the alive hosts to a CSV file
+    #[arg(long, value_name = "FILE")]
+    csv: Option<String>,
+}
+
+// Sequence numbers of the probes in flight must stay unique
+const MAX_CONCURRENCY: u16 = 4096;
+
+// Largest network swept: a /16 of IPv4, a /112 of IPv6
+const MAX_HOST_BITS: u32 = 16;
+
+/// An address that answered
+#[derive(Serialize)]
+struct Alive {
+    ip: IpAddr,
+    best_rtt_ms: f64,
+    replies: u16,
+}
+
+/// What is saved with `--json`
+#[derive(Serialize)]
+struct ScanReport<'a> {
+    network: &'a str,
+    timestamp: String,
+    addresses: usize,
+    probes_per_address: u16,
+    complete: bool,
+    alive: &'a [Alive],
+}
+
+/// A probe waiting for its answer
+struct InFlight {
+    host: usize,
+    sent_at: SystemTime,
+    deadline: Instant,
+}
+
+pub fn run(args: &ScanArgs) -> Result<(), String> {
+    let hosts = addresses(&args.network)?;
+    let ipv6 = hosts[0].is_ipv6();
+    let (mut sender, mut receiver) = transport::open(ipv6)?;
+    let identifier = Identifier::claim()?;
+    interrupt::watch()?;
+
+    println!("\n{}", "╔════════════════════════════════════════════════════════════╗".cyan());
+    println!("{}       SCAN {} - {} addresses                {}",
+        "║".cyan(),
+        args.network.yellow().bold(),
+        hosts.len().to_string().green(),
+        "║".cyan()
+    );
+    println!("{}", "╚════════════════════════════════════════════════════════════╝".cyan());
+
+    let started = Instant::now();
+    let live = io::stderr().is_terminal();
+    let total = hosts.len() * args.count as usize;
+    let mut packet = vec![vec![0u8; PACKET_LEN]];
+    let mut best: Vec<Option<f64>> = vec![None; hosts.len()];
+    let mut replies = vec![0u16; hosts.len()];
+    let mut in_flight: HashMap<u16, InFlight> = HashMap::new();
+    // Sequence numbers in the order sent, which is the order they time out in
+    let mut expiry: VecDeque<(u16, Instant)> = VecDeque::new();
+    let mut seq: u16 = 0;
+    let mut next = 0;
+    let mut send_errors = 0;
+    let mut last_error = None;
+    let mut last_progress = Instant::now();
+
+    while next < total || !in_flight.is_empty() {
+        if interrupt::requested() {
+            break;
+        }
+        let now = Instant::now();
+        while let Some(&(expired, deadline)) = expiry.front().filter(|(_, deadline)| *deadline <= now) {
+            expiry.pop_front();
+            if in_flight.get(&expired).is_some_and(|probe| probe.deadline == deadline) {
+                in_flight.remove(&expired);
+            }
+        }
+
+        // Every address gets its first probe before any gets a second
+        while next < total && in_flight.len() < args.concurrency as usize {
+            let host = next % hosts.len();
+            next += 1;
+            write_icmp_packet(&mut packet[0], seq, identifier.value(), ipv6);
+            let sent_at = SystemTime::now();
+            if let (_, Some(e)) = sender.send_batch(&packet, hosts[host]) {
+                send_errors += 1;
+                last_error = Some(e);
+                continue;
+            }
+            let deadline = Instant::now() + args.timeout;
+            in_flight.insert(seq, InFlight { host, sent_at, deadline });
+            expiry.push_back((seq, deadline));
+            seq = seq.wrapping_add(1);
+        }
+
+        if live && last_progress.elapsed() >= QUIT_POLL {
+            last_progress = Instant::now();
+            let alive = replies.iter().filter(|&&n| n > 0).count();
+            eprint!("\r  {} {}/{} probes sent, {} alive", "…".dimmed(), next, total, alive);
+            let _ = io::stderr().flush();
+        }
+
+        let Some(&(_, deadline)) = expiry.front() else {
+            continue;
+        };
+        let wait = deadline.saturating_duration_since(Instant::now()).min(QUIT_POLL);
+        let Some(reply) = wait_answer(receiver.as_mut(), identifier.value(), wait)? else {
+            continue;
+        };
+        let Some(probe) = reply.sequence.and_then(|sequence| in_flight.get(&sequence)) else {
+            continue;
+        };
+        match reply.icmp_type {
+            // Only the address probed counts, not a host answering for a broadcast
+            ECHO_REPLY if reply.source == hosts[probe.host] => {
+                let rtt = reply.received.duration_since(probe.sent_at).unwrap_or_default().as_secs_f64() * 1000.0;
+                best[probe.host] = Some(best[probe.host].map_or(rtt, |b: f64| b.min(rtt)));
+                replies[probe.host] += 1;
+            }
+            // A router telling the address can't be reached settles it as well
+            DEST_UNREACHABLE => {}
+            _ => continue,
+        }
+        if let Some(sequence) = reply.sequence {
+            in_flight.remove(&sequence);
+        }
+    }
+    if live {
+        eprint!("\r\x1b[K");
+    }
+
+    let complete = next == total && in_flight.is_empty();
+    let alive: Vec<Alive> = hosts
+        .iter()
+        .zip(best.iter().zip(&replies))
+        .filter_map(|(&ip, (best, &replies))| best.map(|best| Alive { ip, best_rtt_ms: (best * 1000.0).round() / 1000.0, replies }))
+        .collect();
+
+    println!();
+    if alive.is_empty() {
+        println!("  {} No address answered", "✗".red());
+    } else {
+        println!("{}", alive_table(&alive, args.count));
+    }
+    println!(
+        "\n  {} of {} addresses alive ({:.1}s){}",
+        alive.len().to_string().green().bold(),
+        hosts.len(),
+        started.elapsed().as_secs_f64(),
+        if complete { String::new() } else { " - interrupted".yellow().to_string() }
+    );
+    if let Some(e) = last_error {
+        println!("  {} {} probes couldn't be sent: {}", "⚠".yellow(), send_errors, e);
+    }
+
+    let report = ScanReport {
+        network: &args.network,
+        timestamp: Local::now().format("%Y-%m-%d %H:%M:%S").to_string(),
+        addresses: hosts.len(),
+        probes_per_address: args.count,
+        complete,
+        alive: &alive,
+    };
+    if let Some(filename) = &args.json {
+        let json = serde_json::to_string_pretty(&report).map_err(|e| format!("Failed to serialize JSON: {}", e))?;
+        write_atomic(filename, format!("{}\n", json).as_bytes())?;
+        println!("  {} Exported to JSON: {}", "✓".green(), filename.cyan());
+    }
+    if let Some(filename) = &args.csv {
+        write_atomic(filename, csv(&report).as_bytes())?;
+        println!("  {} Exported to CSV: {}", "✓".green(), filename.cyan());
+    }
+    Ok(())
+}
+
+/// Every address of the network to probe
+fn addresses(network: &str) -> Result<Vec<IpAddr>, String> {
+    let (addr, prefix) = match network.split_once('/') {
+        Some((addr, prefix)) => (addr, Some(prefix)),
+        None => (network, None),
+    };
+    let addr: IpAddr = addr.trim().parse().map_err(|_| format!("Invalid network {}: expected e.g. 192.168.1.0/24", network))?;
+    let bits = if addr.is_ipv6() { 128 } else { 32 };
+    let prefix = match prefix {
+        Some(prefix) => prefix.trim().parse::<u32>().ok().filter(|&p| p <= bits),
+        None => Some(bits),
+    }
+    .ok_or_else(|| format!("Invalid prefix length in {}: expected 0 to {}", network, bits))?;
+    if bits - prefix > MAX_HOST_BITS {
+        return Err(format!("{} is too large to sweep; at most a /{} is", network, bits - MAX_HOST_BITS));
+    }
+    let size = 1u32 << (bits - prefix);
+
+    Ok(match addr {
+        IpAddr::V4(v4) => {
+            let first = u32::from(v4) & (u32::MAX.checked_shl(32 - prefix).unwrap_or(0));
+            let mut range: Vec<u32> = (0..size).map(|i| first + i).collect();
+            // The network and broadcast addresses don't belong to a host
+            if prefix < 31 {
+                range.pop();
+                range.remove(0);
+            }
+            range.into_iter().map(|a| IpAddr::V4(Ipv4Addr::from(a))).collect()
+        }
+        IpAddr::V6(v6) => {
+            let first = u128::from(v6) & (u128::MAX.checked_shl(128 - prefix).unwrap_or(0));
+            (0..size).map(|i| IpAddr::V6(Ipv6Addr::from(first + i as u128))).collect()
+        }
+    })
+}
+
+fn alive_table(alive: &[Alive], count: u16) -> Table {
+    let mut table = Table::new();
+    table
+        .load_preset(UTF8_FULL_CONDENSED)
+        .apply_modifier(UTF8_ROUND_CORNERS)
+        .set_content_arrangement(ContentArrangement::Dynamic)
+        .set_header(vec!["Host", "Best ms", "Replies"]);
+    for host in alive {
+        table.add_row(vec![
+            Cell::new(host.ip),
+            ms_cell(Some(host.best_rtt_ms)),
+            Cell::new(format!("{}/{}", host.replies, count)).set_alignment(CellAlignment::Right),
+        ]);
+    }
+    table
+}
+
+fn csv(report: &ScanReport) -> String {
+    let mut out = format!(
+        "# Scan Report\n# Network: {}\n# Generated: {}\n# Addresses: {}\n# Probes per address: {}\n#\nip,best_rtt_ms,replies\n",
+        report.network, report.timestamp, report.addresses, report.probes_per_address
+    );
+    for host in report.alive {
+        out.push_str(&format!("{},{:.3},{}\n", host.ip, host.best_rtt_ms, host.replies));
+    }
+    out
+}
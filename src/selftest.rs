@@ -0,0 +1,310 @@
+//! `selftest` subcommand: checks and benchmarks the tool on this machine.
+//!
+//! Packets are built and parsed in memory first, covering echo requests,
+//! replies, damaged replies and ICMP errors quoting a probe, for ICMP and
+//! ICMPv6. Then loopback is pinged as fast as replies come back, which measures what the tool itself
+//! adds to an RTT: the send call, and the time from the kernel stamping a
+//! reply to the tool seeing it. Differences between RTTs smaller than the
+//! loopback spread are measurement noise on this machine.
+
+use colored::*;
+use comfy_table::modifiers::UTF8_ROUND_CORNERS;
+use comfy_table::presets::UTF8_FULL_CONDENSED;
+use comfy_table::{Cell, CellAlignment, ContentArrangement, Table};
+use std::net::{IpAddr, Ipv6Addr};
+use std::time::{Duration, Instant, SystemTime};
+
+use crate::identifier::Identifier;
+use crate::transport::{
+    self, checksum6, checksum_valid, parse_icmp, parse_icmp6, DEST_UNREACHABLE, ECHO_REPLY, ICMP6_ECHO_REQUEST, PARAMETER_PROBLEM,
+    REDIRECT, TIME_EXCEEDED,
+};
+use crate::{checksum, write_icmp_packet, PACKET_LEN};
+
+const ECHO_REQUEST: u8 = 8;
+// A loopback reply slower than this is lost
+const TIMEOUT: Duration = Duration::from_secs(1);
+/// Loopback probes timed by [`calibrate`]
+pub const CALIBRATION_PROBES: u32 = 200;
+
+/// Options of the `selftest` subcommand
+#[derive(clap::Args, Debug)]
+pub struct SelftestArgs {
+    /// Loopback probes to time
+    #[arg(short, long, default_value_t = 1000, value_parser = clap::value_parser!(u32).range(1..))]
+    count: u32,
+}
+
+/// Rewrite the checksum of an ICMP message after changing it
+fn seal(message: &mut [u8]) {
+    message[2..4].fill(0);
+    let sum = checksum(message);
+    message[2..4].copy_from_slice(&sum.to_be_bytes());
+}
+
+/// Rewrite the checksum of an ICMPv6 message sent from ::1 to ::1
+fn seal6(message: &mut [u8]) {
+    message[2..4].fill(0);
+    let sum = checksum6(Ipv6Addr::LOCALHOST, Ipv6Addr::LOCALHOST, message);
+    message[2..4].copy_from_slice(&sum.to_be_bytes());
+}
+
+/// ICMPv6 error of `icmp_type` quoting `probe` behind an IPv6 header, as a router sends it
+fn icmp6_error(icmp_type: u8, code: u8, probe: &[u8]) -> Vec<u8> {
+    let mut message = vec![icmp_type, code, 0, 0, 0, 0, 0, 0];
+    message.extend_from_slice(&[0x60, 0, 0, 0, 0, 8, 58, 64]);
+    message.extend_from_slice(&Ipv6Addr::LOCALHOST.octets());
+    message.extend_from_slice(&Ipv6Addr::LOCALHOST.octets());
+    message.extend_from_slice(&probe[..8]);
+    seal6(&mut message);
+    message
+}
+
+/// ICMP error of `icmp_type` quoting `probe` behind a minimal IP header, as a router sends it
+fn icmp_error(icmp_type: u8, code: u8, probe: &[u8]) -> Vec<u8> {
+    let mut message = vec![icmp_type, code, 0, 0, 0, 0, 0, 0];
+    message.extend_from_slice(&[0x45, 0, 0, 28, 0, 0, 0, 0, 64, 1, 0, 0, 127, 0, 0, 1, 127, 0, 0, 1]);
+    message.extend_from_slice(&probe[..8]);
+    seal(&mut message);
+    message
+}
+
+/// Build and parse packets the way a run does
+fn round_trips() -> Vec<(&'static str, bool)> {
+    let (sequence, identifier) = (0xbeef_u16, 0x1234_u16);
+    let mut request = vec![0u8; PACKET_LEN];
+    write_icmp_packet(&mut request, sequence, identifier, false);
+
+    let mut reply = request.clone();
+    reply[0] = ECHO_REPLY;
+    seal(&mut reply);
+
+    let mut damaged = reply.clone();
+    damaged[PACKET_LEN - 1] ^= 0x01;
+
+    let unreachable = icmp_error(DEST_UNREACHABLE, 1, &request);
+    let exceeded = icmp_error(TIME_EXCEEDED, 0, &request);
+    let redirect = icmp_error(REDIRECT, 1, &request);
+    let problem = icmp_error(PARAMETER_PROBLEM, 0, &request);
+
+    // The kernel fills in the checksum of ICMPv6 requests; replies are checked against the addresses
+    let mut request6 = vec![0u8; PACKET_LEN];
+    write_icmp_packet(&mut request6, sequence, identifier, true);
+    let mut reply6 = request6.clone();
+    reply6[0] = 129;
+    seal6(&mut reply6);
+    let mut damaged6 = reply6.clone();
+    damaged6[PACKET_LEN - 1] ^= 0x01;
+    let checksum6_ok = |message: &[u8]| checksum6(Ipv6Addr::LOCALHOST, Ipv6Addr::LOCALHOST, message) == 0;
+    // Port unreachable (4) and hop limit exceeded (0) in ICMPv6 terms
+    let unreachable6 = icmp6_error(1, 4, &request6);
+    let exceeded6 = icmp6_error(3, 0, &request6);
+
+    vec![
+        ("Echo request checksum", checksum_valid(&request)),
+        (
+            "Echo request fields",
+            parse_icmp(&request) == (ECHO_REQUEST, 0, identifier, Some(sequence)),
+        ),
+        ("Echo reply checksum", checksum_valid(&reply)),
+        ("Echo reply fields", parse_icmp(&reply) == (ECHO_REPLY, 0, identifier, Some(sequence))),
+        ("Damaged reply rejected", !checksum_valid(&damaged)),
+        ("Unreachable quotes the probe", checksum_valid(&unreachable) && parse_icmp(&unreachable) == (DEST_UNREACHABLE, 1, identifier, Some(sequence))),
+        ("Time exceeded quotes the probe", checksum_valid(&exceeded) && parse_icmp(&exceeded) == (TIME_EXCEEDED, 0, identifier, Some(sequence))),
+        ("Redirect quotes the probe", checksum_valid(&redirect) && parse_icmp(&redirect) == (REDIRECT, 1, identifier, Some(sequence))),
+        ("Parameter problem quotes the probe", checksum_valid(&problem) && parse_icmp(&problem) == (PARAMETER_PROBLEM, 0, identifier, Some(sequence))),
+        (
+            "ICMPv6 echo request fields",
+            request6[0] == ICMP6_ECHO_REQUEST && parse_icmp6(&request6).is_none() && request6[4..8] == request[4..8],
+        ),
+        ("ICMPv6 echo reply checksum", checksum6_ok(&reply6)),
+        ("ICMPv6 echo reply fields", parse_icmp6(&reply6) == Some((ECHO_REPLY, 0, identifier, Some(sequence)))),
+        ("ICMPv6 damaged reply rejected", !checksum6_ok(&damaged6)),
+        ("ICMPv6 checksum covers the addresses", checksum6(Ipv6Addr::UNSPECIFIED, Ipv6Addr::LOCALHOST, &reply6) != 0),
+        (
+            "ICMPv6 unreachable quotes the probe",
+            checksum6_ok(&unreachable6) && parse_icmp6(&unreachable6) == Some((DEST_UNREACHABLE, 3, identifier, Some(sequence))),
+        ),
+        (
+            "ICMPv6 time exceeded quotes the probe",
+            checksum6_ok(&exceeded6) && parse_icmp6(&exceeded6) == Some((TIME_EXCEEDED, 0, identifier, Some(sequence))),
+        ),
+    ]
+}
+
+/// Timings of one loopback probe, in microseconds
+struct Sample {
+    rtt: f64,
+    send: f64,
+    wakeup: f64,
+}
+
+struct Benchmark {
+    samples: Vec<Sample>,
+    lost: u32,
+    elapsed: Duration,
+}
+
+fn micros(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1e6
+}
+
+/// Ping `addr` one probe at a time, each sent as soon as the last reply is in
+fn benchmark(addr: IpAddr, count: u32) -> Result<Benchmark, String> {
+    let (mut sender, mut receiver) = transport::open(addr.is_ipv6())?;
+    let identifier = Identifier::claim()?;
+    let mut packet = vec![vec![0u8; PACKET_LEN]];
+    let mut samples = Vec::with_capacity(count as usize);
+    let mut lost = 0;
+
+    let start = Instant::now();
+    for seq in 0..count {
+        write_icmp_packet(&mut packet[0], seq as u16, identifier.value(), addr.is_ipv6());
+        let sent_at = SystemTime::now();
+        let before_send = Instant::now();
+        if let (_, Some(e)) = sender.send_batch(&packet, addr) {
+            return Err(format!("Send error: {}", e));
+        }
+        let send = micros(before_send.elapsed());
+
+        // The raw socket also sees the request itself on loopback
+        let deadline = Instant::now() + TIMEOUT;
+        loop {
+            let reply = receiver
+                .next_with_timeout(deadline.saturating_duration_since(Instant::now()))
+                .map_err(|e| format!("Receive error: {}", e))?;
+            match reply {
+                Some(reply) if reply.icmp_type == ECHO_REPLY && reply.identifier == identifier.value() && reply.checksum_ok => {
+                    let seen = SystemTime::now();
+                    samples.push(Sample {
+                        rtt: micros(reply.received.duration_since(sent_at).unwrap_or_default()),
+                        send,
+                        wakeup: micros(seen.duration_since(reply.received).unwrap_or_default()),
+                    });
+                    break;
+                }
+                Some(_) => {}
+                None => {
+                    lost += 1;
+                    break;
+                }
+            }
+        }
+    }
+    Ok(Benchmark { samples, lost, elapsed: start.elapsed() })
+}
+
+/// Local overhead in milliseconds: the median loopback RTT, from the send call to the kernel's arrival stamp
+pub fn calibrate() -> Result<f64, String> {
+    let bench = benchmark(IpAddr::from([127, 0, 0, 1]), CALIBRATION_PROBES)?;
+    if bench.samples.is_empty() {
+        return Err("Calibration failed: no replies from 127.0.0.1".to_string());
+    }
+    let [_, median, _, _] = spread(bench.samples.iter().map(|s| s.rtt).collect());
+    Ok(median.round() / 1000.0)
+}
+
+/// Min, median, p99 and max
+pub fn spread(mut values: Vec<f64>) -> [f64; 4] {
+    values.sort_by(f64::total_cmp);
+    let at = |q: f64| values[((values.len() - 1) as f64 * q).round() as usize];
+    [at(0.0), at(0.5), at(0.99), at(1.0)]
+}
+
+fn us_cell(value: f64) -> Cell {
+    Cell::new(format!("{:.1}", value)).set_alignment(CellAlignment::Right)
+}
+
+pub fn run(args: &SelftestArgs) -> Result<(), String> {
+    println!("\n{}", "╔════════════════════════════════════════════════════════════╗".cyan());
+    println!("{}", "║                      🔧 SELF-TEST                           ║".cyan());
+    println!("{}", "╚════════════════════════════════════════════════════════════╝".cyan());
+
+    println!("\n  {}", "Packet construction and parsing".bold());
+    let checks = round_trips();
+    for (name, passed) in &checks {
+        if *passed {
+            println!("  {} {}", "✓".green(), name);
+        } else {
+            println!("  {} {}", "✗".red(), name.red());
+        }
+    }
+    let failed = checks.iter().filter(|(_, passed)| !passed).count();
+
+    let addr = IpAddr::from([127, 0, 0, 1]);
+    println!("\n  {}", format!("Loopback benchmark: {} probes to {}", args.count, addr).bold());
+    let bench = benchmark(addr, args.count)?;
+    println!(
+        "  {} received, {} lost, {:.0} probes/s",
+        bench.samples.len(),
+        bench.lost,
+        bench.samples.len() as f64 / bench.elapsed.as_secs_f64()
+    );
+
+    // Without IPv6 on loopback there is nothing to compare with
+    let loopback6 = IpAddr::from(Ipv6Addr::LOCALHOST);
+    let lost6 = match benchmark(loopback6, args.count) {
+        Ok(bench6) => {
+            let median = match bench6.samples.is_empty() {
+                true => "-".to_string(),
+                false => format!("{:.1} µs", spread(bench6.samples.iter().map(|s| s.rtt).collect())[1]),
+            };
+            println!(
+                "  {}: {} received, {} lost, median RTT {}",
+                loopback6,
+                bench6.samples.len(),
+                bench6.lost,
+                median
+            );
+            bench6.lost
+        }
+        Err(e) => {
+            println!("  {}", format!("{} skipped: {}", loopback6, e).dimmed());
+            0
+        }
+    };
+
+    if !bench.samples.is_empty() {
+        let rtt = spread(bench.samples.iter().map(|s| s.rtt).collect());
+        let send = spread(bench.samples.iter().map(|s| s.send).collect());
+        let wakeup = spread(bench.samples.iter().map(|s| s.wakeup).collect());
+
+        let mut table = Table::new();
+        table
+            .load_preset(UTF8_FULL_CONDENSED)
+            .apply_modifier(UTF8_ROUND_CORNERS)
+            .set_content_arrangement(ContentArrangement::Dynamic)
+            .set_header(vec!["µs", "Min", "Median", "p99", "Max"]);
+        let rows = [
+            ("Loopback RTT", rtt),
+            ("Send call", send),
+            ("Reply arrival to seen", wakeup),
+        ];
+        for (name, [min, median, p99, max]) in rows {
+            table.add_row(vec![Cell::new(name), us_cell(min), us_cell(median), us_cell(p99), us_cell(max)]);
+        }
+        println!("{}", table);
+
+        println!(
+            "  Tool overhead per probe: ~{:.1} µs in the send call (part of the RTT), ~{:.1} µs from a reply's arrival until it is seen (not part of it)",
+            send[1], wakeup[1]
+        );
+        println!(
+            "  {}",
+            format!(
+                "RTTs are measured from the kernel's arrival stamp; differences below ~{:.0} µs (loopback p99 - min) are noise on this machine",
+                rtt[2] - rtt[0]
+            )
+            .dimmed()
+        );
+    }
+
+    match (failed, bench.lost + lost6) {
+        (0, 0) => {
+            println!("\n  {} {}", "✓".green(), "All checks passed".green());
+            Ok(())
+        }
+        (0, lost) => Err(format!("{} loopback probes lost", lost)),
+        (failed, _) => Err(format!("{} packet checks failed", failed)),
+    }
+}
@@ -0,0 +1,39 @@
+//! `serve` subcommand: endless pings behind a JSON API.
+//!
+//! `serve HOST...` pings like `ping --forever` with `--serve` listening on
+//! `--listen`, turning the tool into a small monitoring agent that Grafana's
+//! JSON datasource, a script or a browser can ask for the current statistics,
+//! the recent results and a live stream of new ones. Every option of `ping`
+//! applies; `--duration` or `--deadline` still end it on time.
+
+use crate::config::Config;
+use crate::{Args, RunStatus};
+
+/// Options of the `serve` subcommand
+#[derive(clap::Args, Debug)]
+pub struct ServeArgs {
+    /// Address to answer HTTP requests on; 0.0.0.0:9899 makes the API reachable from other hosts
+    #[arg(long, value_name = "ADDR", default_value = "127.0.0.1:9899")]
+    listen: String,
+
+    #[command(flatten)]
+    ping: Args,
+}
+
+impl ServeArgs {
+    /// The options shared with `ping`
+    pub fn ping(&self) -> &Args {
+        &self.ping
+    }
+
+    pub fn ping_mut(&mut self) -> &mut Args {
+        &mut self.ping
+    }
+}
+
+pub fn run(args: ServeArgs, config: &Config) -> Result<RunStatus, String> {
+    let mut ping = args.ping;
+    ping.count = 0;
+    ping.serve = Some(args.listen);
+    crate::ping_command(ping, config)
+}
@@ -0,0 +1,454 @@
+//! JSON API for dashboards and web pages.
+//!
+//! `--serve ADDR` answers HTTP requests while the run goes on, from a thread
+//! of its own like the Prometheus endpoint:
+//!
+//! - `GET /api/stats`: every target with its statistics so far and last result
+//! - `GET /api/results`: the last results of the targets (`?host=` and `?limit=`)
+//! - `GET /api/stream`: server-sent events of new results, alerts and summaries
+//! - `POST /search`, `/metrics` and `/query`: the Grafana JSON datasource API,
+//!   with the series `rtt:<host>` and `loss:<host>` over the recent results
+//! - `GET /`: a page showing the targets as their probes come in
+//!
+//! Only the last results of each target are kept, so a `--forever` run
+//! serves from constant memory; the statistics cover the whole run.
+
+use chrono::{DateTime, Local};
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::collections::{BTreeMap, VecDeque};
+use std::io::{self, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use super::{AlertEvent, ProbeEvent, Sink, SummaryEvent, Target};
+use crate::alerts::Alert;
+use crate::stats::RttStats;
+use crate::{PingResult, PingStatistics};
+
+// Results kept per target for /api/results and Grafana, an hour at one per second
+const RECENT: usize = 3600;
+// Results returned by /api/results without ?limit=
+const DEFAULT_LIMIT: usize = 100;
+// A client that doesn't send its request within this long is dropped
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+// Largest request taken, headers and body together
+const MAX_REQUEST: usize = 64 * 1024;
+// Comment sent on idle event streams so proxies don't close them
+const KEEPALIVE: Duration = Duration::from_secs(15);
+// Event streams open at once; more are refused until one closes
+const MAX_STREAMS: usize = 32;
+
+// Page on /, the targets' statistics redrawn as events come in
+const PAGE: &str = r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<title>rust_ping</title>
+<style>body { font-family: sans-serif; margin: 2em; } td, th { padding: 2px 16px 2px 0; text-align: right; } th { color: #666; font-weight: normal; } td:first-child, th:first-child { text-align: left; } .down { color: #c00; }</style>
+</head>
+<body>
+<h1>rust_ping</h1>
+<table>
+<thead><tr><th>Host</th><th>IP</th><th>Sent</th><th>Loss %</th><th>Last ms</th><th>Avg ms</th><th>p95 ms</th><th>Max ms</th></tr></thead>
+<tbody id="targets"></tbody>
+</table>
+<p id="state">Connecting...</p>
+<script>
+const ms = v => v == null ? "-" : v.toFixed(2);
+const cell = text => { const td = document.createElement("td"); td.textContent = text; return td; };
+let pending = false;
+async function refresh() {
+  pending = false;
+  const targets = await (await fetch("/api/stats")).json();
+  const rows = targets.map(t => {
+    const tr = document.createElement("tr");
+    const s = t.statistics;
+    if (t.last && t.last.rtt_ms == null) tr.className = "down";
+    [t.host, t.ip, s.packets_sent, s.packet_loss_percent.toFixed(1), ms(t.last && t.last.rtt_ms), ms(s.avg_ms), ms(s.p95_ms), ms(s.max_ms)]
+      .forEach(v => tr.appendChild(cell(v)));
+    return tr;
+  });
+  document.getElementById("targets").replaceChildren(...rows);
+}
+function later() {
+  if (!pending) { pending = true; setTimeout(refresh, 500); }
+}
+const events = new EventSource("/api/stream");
+events.onopen = () => document.getElementById("state").textContent = "Live";
+events.onerror = () => { document.getElementById("state").textContent = "Run over or unreachable"; events.close(); };
+events.addEventListener("result", later);
+events.addEventListener("summary", later);
+refresh();
+</script>
+</body>
+</html>
+"#;
+
+/// What is known of one target
+struct Series {
+    run_id: String,
+    host: String,
+    ip: String,
+    sent: u32,
+    rtt: RttStats,
+    recent: VecDeque<PingResult>,
+    // Set once the target is done, with exact percentiles
+    summary: Option<PingStatistics>,
+}
+
+#[derive(Default)]
+struct State {
+    // Keyed by host and ip
+    series: BTreeMap<(String, String), Series>,
+    // One per open event stream, fed with ready-to-send events
+    streams: Vec<Sender<String>>,
+}
+
+impl State {
+    fn publish(&mut self, kind: &str, event: &impl Serialize) {
+        if self.streams.is_empty() {
+            return;
+        }
+        let Ok(data) = serde_json::to_string(event) else {
+            return;
+        };
+        let message = format!("event: {}\ndata: {}\n\n", kind, data);
+        // Streams whose client went away are dropped here
+        self.streams.retain(|stream| stream.send(message.clone()).is_ok());
+    }
+}
+
+/// One target as shown by /api/stats
+#[derive(Serialize)]
+struct TargetStats<'a> {
+    run_id: &'a str,
+    host: &'a str,
+    ip: &'a str,
+    done: bool,
+    last: Option<&'a PingResult>,
+    statistics: PingStatistics,
+}
+
+pub struct ApiSink {
+    state: Arc<Mutex<State>>,
+}
+
+impl ApiSink {
+    /// Listen on `addr` (e.g. 127.0.0.1:9899) and answer requests from then on
+    pub fn listen(addr: &str) -> Result<Self, String> {
+        let listener = TcpListener::bind(addr).map_err(|e| format!("Failed to listen on {} for the API: {}", addr, e))?;
+        let state = Arc::new(Mutex::new(State::default()));
+        let shared = state.clone();
+        thread::Builder::new()
+            .name("api".to_string())
+            .spawn(move || {
+                for stream in listener.incoming().flatten() {
+                    let state = shared.clone();
+                    // Event streams stay open, so every client gets a thread; clients retry failed requests
+                    let _ = thread::Builder::new().name("api-client".to_string()).spawn(move || answer(stream, &state));
+                }
+            })
+            .map_err(|e| format!("Failed to start the API: {}", e))?;
+        Ok(ApiSink { state })
+    }
+}
+
+impl Sink for ApiSink {
+    fn name(&self) -> &'static str {
+        "API"
+    }
+
+    fn send_result(&mut self, target: &Target, result: &PingResult) -> Result<(), String> {
+        let mut state = self.state.lock().unwrap();
+        let ip = target.addr.to_string();
+        let series = state.series.entry((target.host.to_string(), ip.clone())).or_insert_with(|| Series {
+            run_id: target.run_id.to_string(),
+            host: target.host.to_string(),
+            ip,
+            sent: 0,
+            rtt: RttStats::new(),
+            recent: VecDeque::new(),
+            summary: None,
+        });
+        // Counted as the exports count them
+        if result.duplicate {
+            series.rtt.add_duplicate();
+        } else {
+            series.sent += 1;
+            match result.rtt_ms {
+                Some(ms) => series.rtt.add(ms),
+                None => series.rtt.add_failure(result.status),
+            }
+            if result.reordered {
+                series.rtt.add_reordered();
+            }
+            if result.corrupted {
+                series.rtt.add_payload_corrupted();
+            }
+        }
+        if series.recent.len() == RECENT {
+            series.recent.pop_front();
+        }
+        series.recent.push_back(result.clone());
+        state.publish("result", &ProbeEvent::new(target, result));
+        Ok(())
+    }
+
+    fn send_alert(&mut self, target: &Target, alert: &Alert) -> Result<(), String> {
+        self.state.lock().unwrap().publish("alert", &AlertEvent::new(target, alert));
+        Ok(())
+    }
+
+    fn send_summary(&mut self, target: &Target, stats: &PingStatistics) -> Result<(), String> {
+        let mut state = self.state.lock().unwrap();
+        if let Some(series) = state.series.get_mut(&(target.host.to_string(), target.addr.to_string())) {
+            series.summary = Some(stats.clone());
+        }
+        state.publish("summary", &SummaryEvent::new(target, stats));
+        Ok(())
+    }
+
+    fn close(&mut self) {
+        // Ends the event streams, so clients see the run is over
+        self.state.lock().unwrap().streams.clear();
+    }
+}
+
+/// A request as far as it matters here
+struct Request {
+    method: String,
+    path: String,
+    query: Vec<(String, String)>,
+    body: Vec<u8>,
+}
+
+/// `%xx` escapes and `+` of a query string decoded
+fn decode(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'+' => out.push(b' '),
+            b'%' => match value.get(i + 1..i + 3).and_then(|hex| u8::from_str_radix(hex, 16).ok()) {
+                Some(byte) => {
+                    out.push(byte);
+                    i += 2;
+                }
+                None => out.push(b'%'),
+            },
+            byte => out.push(byte),
+        }
+        i += 1;
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+fn read_request(stream: &mut TcpStream) -> io::Result<Request> {
+    stream.set_read_timeout(Some(REQUEST_TIMEOUT))?;
+    let mut data = Vec::new();
+    let mut chunk = [0u8; 4096];
+    let header_end = loop {
+        if let Some(end) = data.windows(4).position(|w| w == b"\r\n\r\n") {
+            break end + 4;
+        }
+        let n = stream.read(&mut chunk)?;
+        if n == 0 || data.len() > MAX_REQUEST {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "incomplete request"));
+        }
+        data.extend_from_slice(&chunk[..n]);
+    };
+
+    let head = String::from_utf8_lossy(&data[..header_end]).into_owned();
+    let mut lines = head.lines();
+    let mut parts = lines.next().unwrap_or_default().split_whitespace();
+    let method = parts.next().unwrap_or_default().to_string();
+    let target = parts.next().unwrap_or_default();
+    let length = lines
+        .filter_map(|line| line.split_once(':'))
+        .find(|(name, _)| name.trim().eq_ignore_ascii_case("content-length"))
+        .and_then(|(_, value)| value.trim().parse::<usize>().ok())
+        .unwrap_or(0);
+    if length > MAX_REQUEST {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "request too large"));
+    }
+    while data.len() < header_end + length {
+        let n = stream.read(&mut chunk)?;
+        if n == 0 {
+            break;
+        }
+        data.extend_from_slice(&chunk[..n]);
+    }
+
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+    let query = query
+        .split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let (name, value) = pair.split_once('=').unwrap_or((pair, ""));
+            (decode(name), decode(value))
+        })
+        .collect();
+    let end = data.len().min(header_end + length);
+    Ok(Request { method, path: path.to_string(), query, body: data[header_end..end].to_vec() })
+}
+
+fn respond(stream: &mut TcpStream, status: &str, content_type: &str, body: &str) -> io::Result<()> {
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nAccess-Control-Allow-Origin: *\r\nConnection: close\r\n\r\n{}",
+        status,
+        content_type,
+        body.len(),
+        body
+    )
+}
+
+fn respond_json(stream: &mut TcpStream, value: &impl Serialize) -> io::Result<()> {
+    let body = serde_json::to_string(value).map_err(io::Error::other)?;
+    respond(stream, "200 OK", "application/json", &body)
+}
+
+/// Answer one request; event streams are served until the client or the run goes away
+fn answer(mut stream: TcpStream, state: &Mutex<State>) -> io::Result<()> {
+    let request = read_request(&mut stream)?;
+    match (request.method.as_str(), request.path.as_str()) {
+        ("GET", "/") => respond(&mut stream, "200 OK", "text/html; charset=utf-8", PAGE),
+        ("GET", "/api/stats") => respond_json(&mut stream, &stats(&state.lock().unwrap())),
+        ("GET", "/api/results") => {
+            let host = request.query.iter().find(|(name, _)| name == "host").map(|(_, value)| value.as_str());
+            let limit = request
+                .query
+                .iter()
+                .find(|(name, _)| name == "limit")
+                .and_then(|(_, value)| value.parse().ok())
+                .unwrap_or(DEFAULT_LIMIT);
+            respond_json(&mut stream, &results(&state.lock().unwrap(), host, limit))
+        }
+        ("GET", "/api/stream") => stream_events(stream, state),
+        // Grafana's JSON datasource: series names, then their points
+        ("POST", "/search") => respond_json(&mut stream, &series_names(&state.lock().unwrap())),
+        ("POST", "/metrics") => {
+            let names: Vec<Value> =
+                series_names(&state.lock().unwrap()).into_iter().map(|name| json!({ "label": name, "value": name })).collect();
+            respond_json(&mut stream, &names)
+        }
+        ("POST", "/query") => match serde_json::from_slice::<Value>(&request.body) {
+            Ok(query) => respond_json(&mut stream, &grafana_query(&state.lock().unwrap(), &query)),
+            Err(e) => respond(&mut stream, "400 Bad Request", "text/plain", &format!("Invalid query: {}\n", e)),
+        },
+        // Preflight of pages on other origins
+        ("OPTIONS", _) => write!(
+            stream,
+            "HTTP/1.1 204 No Content\r\nAccess-Control-Allow-Origin: *\r\nAccess-Control-Allow-Methods: GET, POST\r\nAccess-Control-Allow-Headers: Content-Type\r\nConnection: close\r\n\r\n"
+        ),
+        _ => respond(
+            &mut stream,
+            "404 Not Found",
+            "text/plain",
+            "Served: /api/stats, /api/results, /api/stream, and /search, /metrics and /query for Grafana\n",
+        ),
+    }
+}
+
+fn stats(state: &State) -> Vec<TargetStats<'_>> {
+    state
+        .series
+        .values()
+        .map(|series| TargetStats {
+            run_id: &series.run_id,
+            host: &series.host,
+            ip: &series.ip,
+            done: series.summary.is_some(),
+            last: series.recent.back(),
+            statistics: series.summary.clone().unwrap_or_else(|| series.rtt.statistics(series.sent)),
+        })
+        .collect()
+}
+
+/// The last `limit` results of every target, or of those named `host`, oldest first
+fn results<'a>(state: &'a State, host: Option<&str>, limit: usize) -> Vec<ProbeEvent<'a>> {
+    let mut events = Vec::new();
+    for series in state.series.values().filter(|series| host.is_none_or(|host| series.host == host || series.ip == host)) {
+        let target = Target { run_id: &series.run_id, host: &series.host, addr: series.ip.parse().expect("stored from an IpAddr") };
+        let skip = series.recent.len().saturating_sub(limit);
+        events.extend(series.recent.iter().skip(skip).map(|result| ProbeEvent::new(&target, result)));
+    }
+    events.sort_by_key(|event| event.result.timestamp);
+    events
+}
+
+fn stream_events(mut stream: TcpStream, state: &Mutex<State>) -> io::Result<()> {
+    let (sender, events) = mpsc::channel();
+    {
+        let mut state = state.lock().unwrap();
+        if state.streams.len() >= MAX_STREAMS {
+            return respond(&mut stream, "503 Service Unavailable", "text/plain", "Too many event streams open\n");
+        }
+        state.streams.push(sender);
+    }
+    write!(
+        stream,
+        "HTTP/1.1 200 OK\r\nContent-Type: text/event-stream\r\nCache-Control: no-cache\r\nAccess-Control-Allow-Origin: *\r\nConnection: close\r\n\r\n"
+    )?;
+    loop {
+        match events.recv_timeout(KEEPALIVE) {
+            Ok(message) => stream.write_all(message.as_bytes())?,
+            Err(RecvTimeoutError::Timeout) => stream.write_all(b": keepalive\n\n")?,
+            Err(RecvTimeoutError::Disconnected) => return Ok(()),
+        }
+        stream.flush()?;
+    }
+}
+
+fn series_names(state: &State) -> Vec<String> {
+    let mut names = Vec::new();
+    for series in state.series.values() {
+        names.push(format!("rtt:{}", series.host));
+        names.push(format!("loss:{}", series.host));
+    }
+    names
+}
+
+/// Points of the series asked for within the query's time range: the RTT of
+/// every reply, and 0 or 100 per probe for loss, so averages read as percent
+fn grafana_query(state: &State, query: &Value) -> Vec<Value> {
+    let time = |key: &str| {
+        query["range"][key].as_str().and_then(|time| DateTime::parse_from_rfc3339(time).ok()).map(|time| time.with_timezone(&Local))
+    };
+    let (from, to) = (time("from"), time("to"));
+    let wanted = query["targets"].as_array().cloned().unwrap_or_default();
+
+    let mut answer = Vec::new();
+    for target in wanted.iter().filter_map(|target| target["target"].as_str()) {
+        let Some((metric, host)) = target.split_once(':') else {
+            continue;
+        };
+        for series in state.series.values().filter(|series| series.host == host) {
+            let points: Vec<Value> = series
+                .recent
+                .iter()
+                .filter(|result| !result.duplicate)
+                .filter_map(|result| {
+                    let at = result.timestamp?;
+                    if from.is_some_and(|from| at < from) || to.is_some_and(|to| at > to) {
+                        return None;
+                    }
+                    let value = match metric {
+                        "rtt" => result.rtt_ms?,
+                        "loss" if result.success() => 0.0,
+                        "loss" => 100.0,
+                        _ => return None,
+                    };
+                    Some(json!([value, at.timestamp_millis()]))
+                })
+                .collect();
+            answer.push(json!({ "target": target, "datapoints": points }));
+        }
+    }
+    answer
+}
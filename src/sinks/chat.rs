@@ -0,0 +1,130 @@
+//! Slack, Discord and Telegram notifications.
+//!
+//! Slack and Discord are reached through incoming webhooks, Telegram through
+//! the bot API. As with email, messages are posted from a sender thread so a
+//! slow chat service never delays probes.
+
+use serde_json::json;
+
+use super::{Poster, Sink, Target};
+use crate::alerts::{human_duration, Alert, AlertKind};
+use crate::config::{TelegramConfig, WebhookConfig};
+use crate::PingResult;
+
+enum Service {
+    Slack,
+    Discord,
+    Telegram { chat_id: String },
+}
+
+impl Service {
+    fn name(&self) -> &'static str {
+        match self {
+            Service::Slack => "Slack",
+            Service::Discord => "Discord",
+            Service::Telegram { .. } => "Telegram",
+        }
+    }
+}
+
+pub struct ChatSink {
+    service: Service,
+    url: String,
+    events: Option<Vec<AlertKind>>,
+    poster: Poster,
+}
+
+/// Message text; `bold` wraps the headline in the service's markup
+pub(super) fn message(target: &Target, alert: &Alert, bold: &str) -> String {
+    let icon = match alert.kind {
+        AlertKind::Down | AlertKind::LossHigh => "🔴",
+        AlertKind::RttHigh => "🟠",
+        AlertKind::Up | AlertKind::RttNormal | AlertKind::LossNormal => "🟢",
+    };
+    let addr = target.addr.to_string();
+    let name = if target.host == addr {
+        addr
+    } else {
+        format!("{} ({})", target.host, addr)
+    };
+    let mut text = format!("{} {b}{} {}{b}", icon, name, alert.kind.title(), b = bold);
+
+    match (alert.kind, alert.threshold) {
+        (AlertKind::RttHigh | AlertKind::RttNormal, Some(limit)) => {
+            text.push_str(&format!("\nThreshold: {} ms average RTT", limit));
+        }
+        (AlertKind::LossHigh | AlertKind::LossNormal, Some(limit)) => {
+            text.push_str(&format!("\nThreshold: {}% loss", limit));
+        }
+        _ => {}
+    }
+    if let Some(duration) = alert.duration_seconds {
+        let what = if alert.kind == AlertKind::Up { "Outage" } else { "Lasted" };
+        text.push_str(&format!("\n{}: {}", what, human_duration(duration)));
+    }
+
+    let ms = |v: Option<f64>| v.map_or_else(|| "-".to_string(), |v| format!("{:.2} ms", v));
+    text.push_str(&format!(
+        "\nRecent: loss {:.1}%, avg {}, last {}",
+        alert.loss_percent,
+        ms(alert.avg_rtt_ms),
+        ms(alert.last_rtt_ms)
+    ));
+    text.push_str(&format!("\n{} · run {}", alert.timestamp, target.run_id));
+    text
+}
+
+impl ChatSink {
+    fn spawn(service: Service, url: String, events: Option<Vec<AlertKind>>) -> Result<Self, String> {
+        let poster = Poster::spawn(service.name())?;
+        Ok(ChatSink { service, url, events, poster })
+    }
+
+    pub fn slack(config: &WebhookConfig) -> Result<Self, String> {
+        Self::spawn(Service::Slack, config.webhook.clone(), config.events.clone())
+    }
+
+    pub fn discord(config: &WebhookConfig) -> Result<Self, String> {
+        Self::spawn(Service::Discord, config.webhook.clone(), config.events.clone())
+    }
+
+    pub fn telegram(config: &TelegramConfig) -> Result<Self, String> {
+        let url = format!("https://api.telegram.org/bot{}/sendMessage", config.bot_token);
+        let chat_id = config.chat_id.to_string();
+        Self::spawn(Service::Telegram { chat_id }, url, config.events.clone())
+    }
+}
+
+impl Sink for ChatSink {
+    fn name(&self) -> &'static str {
+        self.service.name()
+    }
+
+    fn send_result(&mut self, _target: &Target, _result: &PingResult) -> Result<(), String> {
+        Ok(())
+    }
+
+    fn send_alert(&mut self, target: &Target, alert: &Alert) -> Result<(), String> {
+        if let Some(events) = &self.events {
+            if !events.contains(&alert.kind) {
+                return Ok(());
+            }
+        }
+
+        let payload = match &self.service {
+            Service::Slack => json!({ "text": message(target, alert, "*") }),
+            Service::Discord => json!({ "content": message(target, alert, "**") }),
+            Service::Telegram { chat_id } => json!({
+                "chat_id": chat_id,
+                "text": message(target, alert, ""),
+                "disable_web_page_preview": true,
+            }),
+        };
+
+        self.poster.post(&self.url, payload)
+    }
+
+    fn close(&mut self) {
+        self.poster.close();
+    }
+}
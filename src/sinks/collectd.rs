@@ -0,0 +1,104 @@
+//! collectd exec plugin protocol on stdout.
+//!
+//! Every `COLLECTD_INTERVAL` seconds (10 when not run by collectd) one
+//! PUTVAL line per value and target is written, under the host name collectd
+//! passes in `COLLECTD_HOSTNAME`. Values use the types of collectd's own ping
+//! plugin from the stock types.db (`ping`, `ping_stddev`, `ping_droprate`),
+//! so existing RRD and graphing setups pick them up as they are.
+
+use std::collections::HashMap;
+use std::io::{self, Write};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use super::{Sink, Target};
+use crate::{PingResult, PingStatistics};
+
+// collectd's default interval, used when not run by collectd
+const DEFAULT_INTERVAL: f64 = 10.0;
+
+/// Probes of one target collected since its last update
+struct Window {
+    started: Instant,
+    sent: u64,
+    times: Vec<f64>,
+}
+
+impl Window {
+    fn new() -> Self {
+        Window { started: Instant::now(), sent: 0, times: Vec::new() }
+    }
+}
+
+pub struct CollectdSink {
+    hostname: String,
+    interval: f64,
+    windows: HashMap<String, Window>,
+}
+
+/// Identifier part derived from a name: '/' separates parts and '"' ends the quoted identifier
+fn identifier_part(name: &str) -> String {
+    name.chars()
+        .map(|c| if c == '/' || c == '"' || c.is_whitespace() { '_' } else { c })
+        .collect()
+}
+
+impl CollectdSink {
+    pub fn new() -> Self {
+        let hostname = std::env::var("COLLECTD_HOSTNAME").unwrap_or_else(|_| "localhost".to_string());
+        let interval = std::env::var("COLLECTD_INTERVAL")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .filter(|&v: &f64| v > 0.0)
+            .unwrap_or(DEFAULT_INTERVAL);
+        CollectdSink { hostname: identifier_part(&hostname), interval, windows: HashMap::new() }
+    }
+
+    fn update(&self, out: &mut impl Write, target: &Target, w: &Window) -> io::Result<()> {
+        let time = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs_f64();
+        let prefix = format!("PUTVAL \"{}/rust_ping/", self.hostname);
+        let instance = identifier_part(target.host);
+
+        // Windows without replies have no latency; collectd's "U" leaves a gap
+        let (avg, stddev) = if w.times.is_empty() {
+            ("U".to_string(), "U".to_string())
+        } else {
+            let n = w.times.len() as f64;
+            let avg = w.times.iter().sum::<f64>() / n;
+            let variance = w.times.iter().map(|t| (t - avg).powi(2)).sum::<f64>() / n;
+            (format!("{:.3}", avg), format!("{:.3}", variance.sqrt()))
+        };
+        let droprate = (w.sent - w.times.len() as u64) as f64 / w.sent as f64;
+
+        writeln!(out, "{}ping-{}\" interval={} {:.3}:{}", prefix, instance, self.interval, time, avg)?;
+        writeln!(out, "{}ping_stddev-{}\" interval={} {:.3}:{}", prefix, instance, self.interval, time, stddev)?;
+        writeln!(out, "{}ping_droprate-{}\" interval={} {:.3}:{:.4}", prefix, instance, self.interval, time, droprate)?;
+        out.flush()
+    }
+}
+
+impl Sink for CollectdSink {
+    fn name(&self) -> &'static str {
+        "collectd"
+    }
+
+    fn send_result(&mut self, target: &Target, result: &PingResult) -> Result<(), String> {
+        let w = self.windows.entry(target.host.to_string()).or_insert_with(Window::new);
+        w.sent += 1;
+        if let Some(rtt) = result.rtt_ms {
+            w.times.push(rtt);
+        }
+
+        if w.started.elapsed() >= Duration::from_secs_f64(self.interval) {
+            let w = std::mem::replace(w, Window::new());
+            self.update(&mut io::stdout().lock(), target, &w).map_err(|e| e.to_string())?;
+        }
+        Ok(())
+    }
+
+    fn send_summary(&mut self, target: &Target, _stats: &PingStatistics) -> Result<(), String> {
+        match self.windows.remove(target.host) {
+            Some(w) if w.sent > 0 => self.update(&mut io::stdout().lock(), target, &w).map_err(|e| e.to_string()),
+            _ => Ok(()),
+        }
+    }
+}
@@ -0,0 +1,101 @@
+//! `--on-alert` commands.
+//!
+//! Every alert runs the command through the shell (`sh -c`, `cmd /C` on
+//! Windows) with the event described in its environment:
+//!
+//! - `RUST_PING_EVENT`: down, up, rtt_high, rtt_normal, loss_high or loss_normal
+//! - `RUST_PING_PROBLEM`: down, rtt or loss, the same for an event and its recovery
+//! - `RUST_PING_STATE`: problem or recovery
+//! - `RUST_PING_HOST`, `RUST_PING_IP`, `RUST_PING_RUN_ID`, `RUST_PING_TIMESTAMP`
+//! - `RUST_PING_LOSS_PERCENT`, `RUST_PING_AVG_RTT_MS`, `RUST_PING_LAST_RTT_MS`
+//!   over the alert window, `RUST_PING_THRESHOLD` and, for recoveries,
+//!   `RUST_PING_DURATION_SECONDS`; empty when they don't apply
+//! - `RUST_PING_MESSAGE`: the alert in one line
+//!
+//! Commands run one at a time on a thread of their own, so a slow script
+//! never delays probes; a command that fails is reported on stderr.
+
+use colored::*;
+use std::process::Command;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread;
+
+use super::{Sink, Target, CLOSE_TIMEOUT};
+use crate::alerts::{describe, Alert};
+use crate::PingResult;
+
+pub struct CommandSink {
+    // Dropped on close so the thread runs what's queued and exits
+    queue: Option<Sender<Vec<(&'static str, String)>>>,
+    done: Receiver<()>,
+}
+
+impl CommandSink {
+    pub fn spawn(command: &str) -> Result<Self, String> {
+        let (queue, events) = mpsc::channel::<Vec<(&'static str, String)>>();
+        let (done_tx, done) = mpsc::channel();
+        let command = command.to_string();
+
+        thread::Builder::new()
+            .name("on-alert".to_string())
+            .spawn(move || {
+                for env in events {
+                    let mut shell = if cfg!(windows) { Command::new("cmd") } else { Command::new("sh") };
+                    shell.arg(if cfg!(windows) { "/C" } else { "-c" }).arg(&command).envs(env);
+                    match shell.status() {
+                        Ok(status) if status.success() => {}
+                        Ok(status) => eprintln!("  {} --on-alert command failed: {}", "⚠".yellow(), status),
+                        Err(e) => eprintln!("  {} --on-alert command couldn't run: {}", "⚠".yellow(), e),
+                    }
+                }
+                let _ = done_tx.send(());
+            })
+            .map_err(|e| format!("Failed to start the --on-alert runner: {}", e))?;
+
+        Ok(CommandSink { queue: Some(queue), done })
+    }
+}
+
+/// The environment a command is run with for `alert`
+fn environment(target: &Target, alert: &Alert) -> Vec<(&'static str, String)> {
+    let num = |v: Option<f64>| v.map_or_else(String::new, |v| v.to_string());
+    vec![
+        ("RUST_PING_EVENT", alert.kind.name().to_string()),
+        ("RUST_PING_PROBLEM", alert.kind.problem().to_string()),
+        ("RUST_PING_STATE", if alert.kind.is_problem() { "problem" } else { "recovery" }.to_string()),
+        ("RUST_PING_HOST", target.host.to_string()),
+        ("RUST_PING_IP", target.addr.to_string()),
+        ("RUST_PING_RUN_ID", target.run_id.to_string()),
+        ("RUST_PING_TIMESTAMP", alert.timestamp.clone()),
+        ("RUST_PING_LOSS_PERCENT", alert.loss_percent.to_string()),
+        ("RUST_PING_AVG_RTT_MS", num(alert.avg_rtt_ms)),
+        ("RUST_PING_LAST_RTT_MS", num(alert.last_rtt_ms)),
+        ("RUST_PING_THRESHOLD", num(alert.threshold)),
+        ("RUST_PING_DURATION_SECONDS", num(alert.duration_seconds)),
+        ("RUST_PING_MESSAGE", format!("{} {}", target.host, describe(alert))),
+    ]
+}
+
+impl Sink for CommandSink {
+    fn name(&self) -> &'static str {
+        "--on-alert"
+    }
+
+    fn send_result(&mut self, _target: &Target, _result: &PingResult) -> Result<(), String> {
+        Ok(())
+    }
+
+    fn send_alert(&mut self, target: &Target, alert: &Alert) -> Result<(), String> {
+        self.queue
+            .as_ref()
+            .ok_or("runner already closed")?
+            .send(environment(target, alert))
+            .map_err(|_| "runner stopped".to_string())
+    }
+
+    fn close(&mut self) {
+        if self.queue.take().is_some() {
+            let _ = self.done.recv_timeout(CLOSE_TIMEOUT);
+        }
+    }
+}
@@ -0,0 +1,150 @@
+//! Email notifications over SMTP.
+//!
+//! Messages are rendered on the probing thread and handed to a sender
+//! thread, so a slow or unreachable mail server never delays probes.
+
+use colored::*;
+use lettre::message::Mailbox;
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{Message, SmtpTransport, Transport};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread;
+use std::time::Duration;
+
+use super::{Sink, Target};
+use crate::alerts::{check_template, render, Alert, AlertKind};
+use crate::config::{EmailConfig, SmtpTls};
+use crate::PingResult;
+
+const SMTP_TIMEOUT: Duration = Duration::from_secs(10);
+// How long to wait for queued messages to go out on exit
+const CLOSE_TIMEOUT: Duration = Duration::from_secs(30);
+
+const DEFAULT_SUBJECT: &str = "[rust_ping] {host}: {event}";
+const DEFAULT_BODY: &str = "\
+Host:           {host} ({ip})
+Event:          {event}
+Time:           {timestamp}
+Loss (%):       {loss}
+Avg RTT (ms):   {avg_rtt}
+Last RTT (ms):  {last_rtt}
+Threshold:      {threshold}
+Duration (s):   {duration}
+Run:            {run_id}
+";
+
+pub struct EmailSink {
+    // Dropped on close so the sender thread finishes its queue and exits
+    queue: Option<Sender<Message>>,
+    done: Receiver<()>,
+    from: Mailbox,
+    to: Vec<Mailbox>,
+    events: Option<Vec<AlertKind>>,
+    subject: String,
+    body: String,
+}
+
+fn mailbox(address: &str) -> Result<Mailbox, String> {
+    address
+        .parse()
+        .map_err(|e| format!("Invalid email address '{}': {}", address, e))
+}
+
+fn transport(config: &EmailConfig) -> Result<SmtpTransport, String> {
+    let builder = match config.tls {
+        SmtpTls::Starttls => SmtpTransport::starttls_relay(&config.server),
+        SmtpTls::Tls => SmtpTransport::relay(&config.server),
+        SmtpTls::None => Ok(SmtpTransport::builder_dangerous(&config.server)),
+    }
+    .map_err(|e| format!("Invalid SMTP server '{}': {}", config.server, e))?;
+
+    let mut builder = builder.timeout(Some(SMTP_TIMEOUT));
+    if let Some(port) = config.port {
+        builder = builder.port(port);
+    }
+    if let Some(username) = &config.username {
+        let password = config.password.clone().unwrap_or_default();
+        builder = builder.credentials(Credentials::new(username.clone(), password));
+    }
+    Ok(builder.build())
+}
+
+impl EmailSink {
+    pub fn new(config: &EmailConfig) -> Result<Self, String> {
+        if config.to.is_empty() {
+            return Err("No email recipients configured ([alerting.email] to)".to_string());
+        }
+        let subject = config.subject.clone().unwrap_or_else(|| DEFAULT_SUBJECT.to_string());
+        let body = config.body.clone().unwrap_or_else(|| DEFAULT_BODY.to_string());
+        check_template(&subject)?;
+        check_template(&body)?;
+
+        let from = mailbox(&config.from)?;
+        let to = config.to.iter().map(|a| mailbox(a)).collect::<Result<_, _>>()?;
+        let transport = transport(config)?;
+
+        let (queue, messages) = mpsc::channel::<Message>();
+        let (done_tx, done) = mpsc::channel();
+        thread::Builder::new()
+            .name("email".to_string())
+            .spawn(move || {
+                for message in messages {
+                    if let Err(e) = transport.send(&message) {
+                        eprintln!("  {} Email sink error: {}", "⚠".yellow(), e);
+                    }
+                }
+                let _ = done_tx.send(());
+            })
+            .map_err(|e| format!("Failed to start email sender: {}", e))?;
+
+        Ok(EmailSink {
+            queue: Some(queue),
+            done,
+            from,
+            to,
+            events: config.events.clone(),
+            subject,
+            body,
+        })
+    }
+}
+
+impl Sink for EmailSink {
+    fn name(&self) -> &'static str {
+        "Email"
+    }
+
+    fn send_result(&mut self, _target: &Target, _result: &PingResult) -> Result<(), String> {
+        Ok(())
+    }
+
+    fn send_alert(&mut self, target: &Target, alert: &Alert) -> Result<(), String> {
+        if let Some(events) = &self.events {
+            if !events.contains(&alert.kind) {
+                return Ok(());
+            }
+        }
+
+        let mut message = Message::builder()
+            .from(self.from.clone())
+            .subject(render(&self.subject, target, alert)?);
+        for to in &self.to {
+            message = message.to(to.clone());
+        }
+        let message = message
+            .body(render(&self.body, target, alert)?)
+            .map_err(|e| e.to_string())?;
+
+        self.queue
+            .as_ref()
+            .ok_or("email sender already closed")?
+            .send(message)
+            .map_err(|_| "email sender stopped".to_string())
+    }
+
+    fn close(&mut self) {
+        if self.queue.take().is_some() {
+            let _ = self.done.recv_timeout(CLOSE_TIMEOUT);
+        }
+    }
+}
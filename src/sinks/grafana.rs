@@ -0,0 +1,91 @@
+//! Grafana Live push.
+//!
+//! Results are posted as InfluxDB line protocol to Grafana's Live push
+//! endpoint, `/api/live/push/<stream>`, about once a second, so panels
+//! subscribed to `stream/<stream>/rust_ping` update while the run is in
+//! progress, with no time-series database in between.
+
+use std::time::{Duration, Instant};
+
+use super::influx::{result_line, summary_line};
+use super::{http_agent, Sink, Target};
+use crate::{PingResult, PingStatistics};
+
+// How often buffered lines are pushed; Live panels don't redraw faster than this anyway
+const PUSH_INTERVAL: Duration = Duration::from_secs(1);
+
+// Buffered lines are dropped beyond this while Grafana is unreachable
+const MAX_PENDING_LINES: usize = 10_000;
+
+pub struct GrafanaLiveSink {
+    url: String,
+    token: String,
+    agent: ureq::Agent,
+    pending: Vec<String>,
+    last_push: Instant,
+}
+
+impl GrafanaLiveSink {
+    /// `token` is a service account token allowed to publish; falls back to `GRAFANA_TOKEN`
+    pub fn new(url: &str, stream: &str, token: Option<&str>) -> Result<Self, String> {
+        let token = match token {
+            Some(token) => token.to_string(),
+            None => std::env::var("GRAFANA_TOKEN").map_err(|_| "Grafana Live needs --grafana-token or GRAFANA_TOKEN".to_string())?,
+        };
+        if stream.is_empty() || !stream.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.')) {
+            return Err(format!("Invalid Grafana Live stream '{}': use letters, digits, '_', '-' and '.'", stream));
+        }
+
+        Ok(GrafanaLiveSink {
+            url: format!("{}/api/live/push/{}", url.trim_end_matches('/'), stream),
+            token,
+            agent: http_agent(),
+            pending: Vec::new(),
+            last_push: Instant::now(),
+        })
+    }
+
+    fn push(&mut self) -> Result<(), String> {
+        self.last_push = Instant::now();
+        if self.pending.is_empty() {
+            return Ok(());
+        }
+
+        let body = self.pending.join("\n");
+        let sent = self
+            .agent
+            .post(&self.url)
+            .header("Authorization", &format!("Bearer {}", self.token))
+            .header("Content-Type", "text/plain")
+            .send(body.as_bytes());
+        // Lines are kept and retried with the next push if sending failed
+        if let Err(e) = sent {
+            if self.pending.len() > MAX_PENDING_LINES {
+                self.pending.clear();
+            }
+            return Err(e.to_string());
+        }
+
+        self.pending.clear();
+        Ok(())
+    }
+}
+
+impl Sink for GrafanaLiveSink {
+    fn name(&self) -> &'static str {
+        "Grafana Live"
+    }
+
+    fn send_result(&mut self, target: &Target, result: &PingResult) -> Result<(), String> {
+        self.pending.push(result_line(target, result));
+        if self.last_push.elapsed() >= PUSH_INTERVAL {
+            self.push()?;
+        }
+        Ok(())
+    }
+
+    fn send_summary(&mut self, target: &Target, stats: &PingStatistics) -> Result<(), String> {
+        self.pending.push(summary_line(target, stats));
+        self.push()
+    }
+}
@@ -0,0 +1,214 @@
+//! InfluxDB line protocol output.
+//!
+//! Points go to stdout with `--output influx-stdout`, and with `--influx` to
+//! a file, appended so Telegraf's tail input can follow it, or to the HTTP
+//! write endpoint of InfluxDB in batches about once a second.
+
+use chrono::Utc;
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufWriter, Write};
+use std::time::{Duration, Instant};
+
+use super::{http_agent, Sink, Target};
+use crate::{PingResult, PingStatistics};
+
+/// Escape a tag value (commas, spaces and equals signs)
+fn escape_tag(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+    for c in value.chars() {
+        if matches!(c, ',' | ' ' | '=') {
+            out.push('\\');
+        }
+        out.push(c);
+    }
+    out
+}
+
+fn tags(target: &Target) -> String {
+    format!("host={},ip={}", escape_tag(target.host), escape_tag(&target.addr.to_string()))
+}
+
+fn now_ns() -> i64 {
+    Utc::now().timestamp_nanos_opt().unwrap_or_default()
+}
+
+/// Time a probe was sent, so lost probes and replayed reports land where they were measured
+fn probe_ns(result: &PingResult) -> i64 {
+    result.timestamp.and_then(|time| time.timestamp_nanos_opt()).unwrap_or_else(now_ns)
+}
+
+/// `rust_ping` point for one probe
+pub fn result_line(target: &Target, result: &PingResult) -> String {
+    let mut fields = format!("seq={}i,success={},status=\"{}\"", result.seq, result.success(), result.status.name());
+    if let Some(rtt) = result.rtt_ms {
+        fields.push_str(&format!(",rtt_ms={}", rtt));
+    }
+    if result.reordered {
+        fields.push_str(",reordered=true");
+    }
+    if result.corrupted {
+        fields.push_str(",corrupted=true");
+    }
+    format!("rust_ping,{} {} {}", tags(target), fields, probe_ns(result))
+}
+
+/// `rust_ping_summary` point with the final statistics of a target, at the time they are known
+pub fn summary_line(target: &Target, stats: &PingStatistics) -> String {
+    let mut fields = format!(
+        "packets_sent={}i,packets_received={}i,packets_lost={}i,packet_loss_percent={},packets_corrupted={}i,\
+         packets_payload_corrupted={}i,packets_duplicate={}i,packets_reordered={}i,packets_timeout={}i,packets_unreachable={}i,packets_ttl_exceeded={}i,packets_parameter_problem={}i,packets_send_error={}i,\
+         longest_loss_streak={}i",
+        stats.packets_sent,
+        stats.packets_received,
+        stats.packets_lost,
+        stats.packet_loss_percent,
+        stats.packets_corrupted,
+        stats.packets_payload_corrupted,
+        stats.packets_duplicate,
+        stats.packets_reordered,
+        stats.packets_timeout,
+        stats.packets_unreachable,
+        stats.packets_ttl_exceeded,
+        stats.packets_parameter_problem,
+        stats.packets_send_error,
+        stats.longest_loss_streak
+    );
+    let rtts = [
+        ("min_ms", stats.min_ms),
+        ("avg_ms", stats.avg_ms),
+        ("max_ms", stats.max_ms),
+        ("std_dev_ms", stats.std_dev_ms),
+        ("p50_ms", stats.p50_ms),
+        ("p95_ms", stats.p95_ms),
+        ("p99_ms", stats.p99_ms),
+        ("jitter_ms", stats.jitter_ms),
+    ];
+    for (name, value) in rtts {
+        if let Some(v) = value {
+            fields.push_str(&format!(",{}={}", name, v));
+        }
+    }
+    format!("rust_ping_summary,{} {} {}", tags(target), fields, now_ns())
+}
+
+/// Line protocol on stdout, e.g. for Telegraf's `inputs.exec`
+pub struct InfluxStdoutSink;
+
+impl InfluxStdoutSink {
+    fn emit(line: &str) -> Result<(), String> {
+        let mut out = io::stdout().lock();
+        writeln!(out, "{}", line)
+            .and_then(|_| out.flush())
+            .map_err(|e| e.to_string())
+    }
+}
+
+impl Sink for InfluxStdoutSink {
+    fn name(&self) -> &'static str {
+        "influx-stdout"
+    }
+
+    fn send_result(&mut self, target: &Target, result: &PingResult) -> Result<(), String> {
+        Self::emit(&result_line(target, result))
+    }
+
+    fn send_summary(&mut self, target: &Target, stats: &PingStatistics) -> Result<(), String> {
+        Self::emit(&summary_line(target, stats))
+    }
+}
+
+// How often points are written out to the file or pushed to the endpoint
+const FLUSH_INTERVAL: Duration = Duration::from_secs(1);
+
+// Buffered lines are dropped beyond this while InfluxDB is unreachable
+const MAX_PENDING_LINES: usize = 100_000;
+
+enum Destination {
+    File(BufWriter<File>),
+    Http { url: String, token: Option<String>, agent: ureq::Agent, pending: Vec<String> },
+}
+
+/// Line protocol to a file or an InfluxDB write endpoint
+pub struct InfluxSink {
+    destination: Destination,
+    last_flush: Instant,
+}
+
+impl InfluxSink {
+    /// `dest` is an http(s) write URL (e.g. .../api/v2/write?org=ORG&bucket=BUCKET) or a file path;
+    /// the endpoint's token falls back to `INFLUX_TOKEN`
+    pub fn new(dest: &str, token: Option<&str>) -> Result<Self, String> {
+        let destination = if dest.starts_with("http://") || dest.starts_with("https://") {
+            Destination::Http {
+                url: dest.to_string(),
+                token: token.map(str::to_string).or_else(|| std::env::var("INFLUX_TOKEN").ok()),
+                agent: http_agent(),
+                pending: Vec::new(),
+            }
+        } else {
+            let file = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(dest)
+                .map_err(|e| format!("Failed to open {} for InfluxDB points: {}", dest, e))?;
+            Destination::File(BufWriter::new(file))
+        };
+        Ok(InfluxSink { destination, last_flush: Instant::now() })
+    }
+
+    fn add(&mut self, line: String) -> Result<(), String> {
+        match &mut self.destination {
+            Destination::File(out) => writeln!(out, "{}", line).map_err(|e| e.to_string()),
+            Destination::Http { pending, .. } => {
+                pending.push(line);
+                Ok(())
+            }
+        }
+    }
+
+    fn flush(&mut self) -> Result<(), String> {
+        self.last_flush = Instant::now();
+        match &mut self.destination {
+            Destination::File(out) => out.flush().map_err(|e| e.to_string()),
+            Destination::Http { pending, .. } if pending.is_empty() => Ok(()),
+            Destination::Http { url, token, agent, pending } => {
+                let mut request = agent.post(url.as_str()).header("Content-Type", "text/plain; charset=utf-8");
+                if let Some(token) = token {
+                    request = request.header("Authorization", &format!("Token {}", token));
+                }
+                // Lines are kept and retried with the next push if sending failed
+                if let Err(e) = request.send(pending.join("\n").as_bytes()) {
+                    if pending.len() > MAX_PENDING_LINES {
+                        pending.clear();
+                    }
+                    return Err(e.to_string());
+                }
+                pending.clear();
+                Ok(())
+            }
+        }
+    }
+}
+
+impl Sink for InfluxSink {
+    fn name(&self) -> &'static str {
+        "InfluxDB"
+    }
+
+    fn send_result(&mut self, target: &Target, result: &PingResult) -> Result<(), String> {
+        self.add(result_line(target, result))?;
+        if self.last_flush.elapsed() >= FLUSH_INTERVAL {
+            self.flush()?;
+        }
+        Ok(())
+    }
+
+    fn send_summary(&mut self, target: &Target, stats: &PingStatistics) -> Result<(), String> {
+        self.add(summary_line(target, stats))?;
+        self.flush()
+    }
+
+    fn close(&mut self) {
+        let _ = self.flush();
+    }
+}
@@ -0,0 +1,444 @@
+//! Live result sinks.
+//!
+//! Sinks receive every probe result as it happens, the alerts raised by the
+//! detector and the final statistics of each target. A failing sink never
+//! stops the measurement: the error is reported once, the sink keeps being
+//! retried, and recovery is announced.
+
+mod api;
+mod chat;
+mod collectd;
+mod command;
+mod email;
+mod grafana;
+mod influx;
+mod mqtt;
+mod ndjson;
+mod netdata;
+mod otlp;
+mod pagerduty;
+mod postgres;
+mod prometheus;
+mod redis;
+mod remote_write;
+mod snmp;
+mod sqlite;
+mod statsd;
+mod terminal;
+mod webhook;
+mod zabbix;
+
+use colored::*;
+use serde::Serialize;
+use serde_json::Value;
+use std::net::{IpAddr, SocketAddr, ToSocketAddrs};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread;
+use std::time::Duration;
+
+use crate::alerts::{describe, Alert, AlertDetector, AlertRules};
+use crate::config::Config;
+use crate::{Args, OutputFormat, PingResult, PingStatistics};
+
+pub use self::api::ApiSink;
+pub use self::chat::ChatSink;
+pub use self::collectd::CollectdSink;
+pub use self::command::CommandSink;
+pub use self::email::EmailSink;
+pub use self::grafana::GrafanaLiveSink;
+pub use self::influx::{InfluxSink, InfluxStdoutSink};
+pub use self::mqtt::{MqttSink, MqttTopics};
+pub use self::ndjson::NdjsonSink;
+pub use self::netdata::NetdataSink;
+pub use self::otlp::OtlpSink;
+pub use self::pagerduty::PagerDutySink;
+pub use self::postgres::PostgresSink;
+pub use self::prometheus::PrometheusSink;
+pub use self::redis::RedisSink;
+pub use self::remote_write::RemoteWriteSink;
+pub use self::snmp::SnmpTrapSink;
+pub use self::sqlite::SqliteSink;
+pub use self::statsd::StatsdSink;
+pub use self::terminal::TerminalAlertSink;
+pub use self::webhook::WebhookSink;
+pub use self::zabbix::ZabbixSink;
+
+/// Timeout for requests made by HTTP-based sinks
+const HTTP_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// HTTP client shared by the HTTP-based sinks
+pub fn http_agent() -> ureq::Agent {
+    ureq::Agent::config_builder()
+        .timeout_global(Some(HTTP_TIMEOUT))
+        .build()
+        .into()
+}
+
+/// Split `Name: value` headers given on the command line
+pub fn parse_headers(headers: &[String]) -> Result<Vec<(String, String)>, String> {
+    headers
+        .iter()
+        .map(|h| {
+            h.split_once(':')
+                .map(|(name, value)| (name.trim().to_string(), value.trim().to_string()))
+                .ok_or_else(|| format!("Invalid header '{}', expected 'Name: value'", h))
+        })
+        .collect()
+}
+
+/// Resolve `host[:port]` of a sink's server, with IPv6 literals written as
+/// `[addr]:port`; `what` names the server in errors
+pub fn resolve(dest: &str, default_port: u16, what: &str) -> Result<SocketAddr, String> {
+    let with_port = match dest.parse::<IpAddr>() {
+        Ok(ip) => SocketAddr::new(ip, default_port).to_string(),
+        Err(_) if dest.contains(':') => dest.to_string(),
+        Err(_) => format!("{}:{}", dest, default_port),
+    };
+    with_port
+        .to_socket_addrs()
+        .map_err(|e| format!("Failed to resolve {} '{}': {}", what, dest, e))?
+        .next()
+        .ok_or_else(|| format!("No address found for {} '{}'", what, dest))
+}
+
+// How long to wait for queued background posts to go out on exit
+const CLOSE_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// JSON POSTs sent from a background thread, for notification sinks that
+/// must not delay probes. Failures are reported on stderr by the thread.
+pub struct Poster {
+    // Dropped on close so the thread finishes its queue and exits
+    queue: Option<Sender<(String, Value)>>,
+    done: Receiver<()>,
+}
+
+impl Poster {
+    pub fn spawn(name: &'static str) -> Result<Self, String> {
+        let agent = http_agent();
+        let (queue, requests) = mpsc::channel::<(String, Value)>();
+        let (done_tx, done) = mpsc::channel();
+
+        thread::Builder::new()
+            .name(name.to_lowercase())
+            .spawn(move || {
+                for (url, payload) in requests {
+                    if let Err(e) = agent.post(&url).send_json(&payload) {
+                        eprintln!("  {} {} sink error: {}", "⚠".yellow(), name, e);
+                    }
+                }
+                let _ = done_tx.send(());
+            })
+            .map_err(|e| format!("Failed to start {} sender: {}", name, e))?;
+
+        Ok(Poster { queue: Some(queue), done })
+    }
+
+    pub fn post(&self, url: &str, payload: Value) -> Result<(), String> {
+        self.queue
+            .as_ref()
+            .ok_or("sender already closed")?
+            .send((url.to_string(), payload))
+            .map_err(|_| "sender stopped".to_string())
+    }
+
+    pub fn close(&mut self) {
+        if self.queue.take().is_some() {
+            let _ = self.done.recv_timeout(CLOSE_TIMEOUT);
+        }
+    }
+}
+
+/// The run a result belongs to
+pub struct Target<'a> {
+    pub run_id: &'a str,
+    pub host: &'a str,
+    pub addr: IpAddr,
+}
+
+/// One probe result as published to sinks
+#[derive(Serialize)]
+pub struct ProbeEvent<'a> {
+    pub event: &'static str,
+    pub run_id: &'a str,
+    pub host: &'a str,
+    pub ip: String,
+    #[serde(flatten)]
+    pub result: &'a PingResult,
+}
+
+/// Final statistics of one target as published to sinks
+#[derive(Serialize)]
+pub struct SummaryEvent<'a> {
+    pub event: &'static str,
+    pub run_id: &'a str,
+    pub host: &'a str,
+    pub ip: String,
+    #[serde(flatten)]
+    pub statistics: &'a PingStatistics,
+}
+
+/// One alert as posted to generic webhooks
+#[derive(Serialize)]
+pub struct AlertEvent<'a> {
+    pub event: &'static str,
+    pub run_id: &'a str,
+    pub host: &'a str,
+    pub ip: String,
+    #[serde(flatten)]
+    pub alert: &'a Alert,
+    pub message: String,
+}
+
+impl<'a> ProbeEvent<'a> {
+    pub fn new(target: &Target<'a>, result: &'a PingResult) -> Self {
+        ProbeEvent {
+            event: "result",
+            run_id: target.run_id,
+            host: target.host,
+            ip: target.addr.to_string(),
+            result,
+        }
+    }
+}
+
+impl<'a> AlertEvent<'a> {
+    pub fn new(target: &Target<'a>, alert: &'a Alert) -> Self {
+        AlertEvent {
+            event: "alert",
+            run_id: target.run_id,
+            host: target.host,
+            ip: target.addr.to_string(),
+            alert,
+            message: describe(alert),
+        }
+    }
+}
+
+impl<'a> SummaryEvent<'a> {
+    pub fn new(target: &Target<'a>, statistics: &'a PingStatistics) -> Self {
+        SummaryEvent {
+            event: "summary",
+            run_id: target.run_id,
+            host: target.host,
+            ip: target.addr.to_string(),
+            statistics,
+        }
+    }
+}
+
+/// Destination for live results; targets pinged in parallel share the sinks
+pub trait Sink: Send {
+    /// Short name used in warnings
+    fn name(&self) -> &'static str;
+
+    /// Called for every probe as soon as its outcome is known
+    fn send_result(&mut self, target: &Target, result: &PingResult) -> Result<(), String>;
+
+    /// Called when a target goes down, recovers or crosses a threshold
+    fn send_alert(&mut self, _target: &Target, _alert: &Alert) -> Result<(), String> {
+        Ok(())
+    }
+
+    /// Called once per target after its last probe
+    fn send_summary(&mut self, _target: &Target, _stats: &PingStatistics) -> Result<(), String> {
+        Ok(())
+    }
+
+    /// Called once after the last target; flush anything still queued
+    fn close(&mut self) {}
+}
+
+struct SinkSlot {
+    sink: Box<dyn Sink>,
+    failing: bool,
+}
+
+impl SinkSlot {
+    fn report(&mut self, outcome: Result<(), String>) {
+        match outcome {
+            Ok(()) if self.failing => {
+                self.failing = false;
+                eprintln!("  {} {} sink recovered", "✓".green(), self.sink.name());
+            }
+            Ok(()) => {}
+            Err(e) if !self.failing => {
+                self.failing = true;
+                eprintln!("  {} {} sink error: {}", "⚠".yellow(), self.sink.name(), e);
+            }
+            Err(_) => {}
+        }
+    }
+}
+
+/// All sinks configured for this run
+pub struct Sinks {
+    slots: Vec<SinkSlot>,
+    detector: AlertDetector,
+}
+
+impl Sinks {
+    /// Connect the sinks requested on the command line and in the config file
+    pub fn from_args(args: &Args, config: &Config) -> Result<Self, String> {
+        let mut sinks: Vec<Box<dyn Sink>> = Vec::new();
+
+        match args.output {
+            // The dashboard's sink is added once the targets are known
+            OutputFormat::Text | OutputFormat::Dashboard | OutputFormat::Export => {}
+            OutputFormat::Netdata => sinks.push(Box::new(NetdataSink::new())),
+            OutputFormat::InfluxStdout => sinks.push(Box::new(InfluxStdoutSink)),
+            OutputFormat::Collectd => sinks.push(Box::new(CollectdSink::new())),
+            OutputFormat::Ndjson => sinks.push(Box::new(NdjsonSink)),
+        }
+
+        if let Some(url) = &args.redis {
+            sinks.push(Box::new(RedisSink::connect(
+                url,
+                &args.redis_channel,
+                args.redis_timeseries.as_deref(),
+            )?));
+        }
+
+        if let Some(dsn) = &args.postgres {
+            sinks.push(Box::new(PostgresSink::connect(dsn)?));
+        }
+
+        if let Some(path) = &args.sqlite {
+            sinks.push(Box::new(SqliteSink::new(path)?));
+        }
+
+        if let Some(url) = &args.remote_write {
+            sinks.push(Box::new(RemoteWriteSink::new(
+                url,
+                &args.remote_write_header,
+                Duration::from_secs(args.remote_write_interval),
+            )?));
+        }
+
+        if let Some(url) = &args.otlp {
+            sinks.push(Box::new(OtlpSink::new(
+                url,
+                &args.otlp_header,
+                Duration::from_secs(args.otlp_interval),
+                args.timeout,
+                args.otlp_spans,
+            )?));
+        }
+
+        match args.influx.as_deref() {
+            // Stdout is taken as --output influx-stdout
+            Some("-") if args.output != OutputFormat::InfluxStdout => {
+                return Err("--influx - writes to stdout, which the --output format already uses".to_string());
+            }
+            Some("-") | None => {}
+            Some(dest) => sinks.push(Box::new(InfluxSink::new(dest, args.influx_token.as_deref())?)),
+        }
+
+        if let Some(addr) = &args.prometheus {
+            sinks.push(Box::new(PrometheusSink::listen(addr)?));
+        }
+
+        if let Some(addr) = &args.serve {
+            sinks.push(Box::new(ApiSink::listen(addr)?));
+        }
+
+        if let Some(url) = &args.grafana {
+            sinks.push(Box::new(GrafanaLiveSink::new(url, &args.grafana_stream, args.grafana_token.as_deref())?));
+        }
+
+        if let Some(url) = &args.mqtt {
+            let discovery = args.mqtt_ha_discovery.then_some(args.mqtt_discovery_prefix.as_str());
+            let topics = MqttTopics {
+                prefix: &args.mqtt_topic,
+                state: &args.mqtt_state_topic,
+                summary: &args.mqtt_summary_topic,
+                summary_interval: (args.mqtt_summary_interval > 0).then(|| Duration::from_secs(args.mqtt_summary_interval)),
+            };
+            sinks.push(Box::new(MqttSink::connect(url, &topics, discovery)?));
+        }
+
+        if let Some(dest) = &args.statsd {
+            sinks.push(Box::new(StatsdSink::new(dest, &args.statsd_prefix, &args.statsd_tag, args.statsd_plain)?));
+        }
+
+        if let Some(server) = &args.zabbix {
+            let host = args.zabbix_host.as_deref().ok_or("--zabbix needs --zabbix-host, the Zabbix host of the items")?;
+            sinks.push(Box::new(ZabbixSink::new(server, host)?));
+        }
+
+        if let Some(dest) = &args.snmp_trap {
+            sinks.push(Box::new(SnmpTrapSink::new(dest, &args.snmp_community)?));
+        }
+
+        if let Some(email) = &config.alerting.email {
+            sinks.push(Box::new(EmailSink::new(email)?));
+        }
+        if let Some(slack) = &config.alerting.slack {
+            sinks.push(Box::new(ChatSink::slack(slack)?));
+        }
+        if let Some(discord) = &config.alerting.discord {
+            sinks.push(Box::new(ChatSink::discord(discord)?));
+        }
+        if let Some(telegram) = &config.alerting.telegram {
+            sinks.push(Box::new(ChatSink::telegram(telegram)?));
+        }
+        if let Some(pagerduty) = &config.alerting.pagerduty {
+            sinks.push(Box::new(PagerDutySink::new(pagerduty)?));
+        }
+        if !args.webhook.is_empty() {
+            sinks.push(Box::new(WebhookSink::new(&args.webhook, args.webhook_debounce)?));
+        }
+
+        // Alerts are shown among the probe lines of text output
+        let show = args.output == OutputFormat::Text && !args.quiet;
+        if show || args.alert_bell {
+            sinks.push(Box::new(TerminalAlertSink::new(show, args.alert_bell)));
+        }
+        if let Some(command) = &args.on_alert {
+            sinks.push(Box::new(CommandSink::spawn(command)?));
+        }
+
+        Ok(Sinks {
+            slots: sinks
+                .into_iter()
+                .map(|sink| SinkSlot { sink, failing: false })
+                .collect(),
+            detector: AlertDetector::new(AlertRules {
+                down_after: args.down_after,
+                rtt_ms: args.alert_rtt,
+                loss_percent: args.alert_loss,
+                window: args.alert_window,
+            }),
+        })
+    }
+
+    pub fn push(&mut self, sink: Box<dyn Sink>) {
+        self.slots.push(SinkSlot { sink, failing: false });
+    }
+
+    pub fn result(&mut self, target: &Target, result: &PingResult) {
+        for slot in &mut self.slots {
+            let outcome = slot.sink.send_result(target, result);
+            slot.report(outcome);
+        }
+
+        for alert in self.detector.observe(target.host, result) {
+            for slot in &mut self.slots {
+                let outcome = slot.sink.send_alert(target, &alert);
+                slot.report(outcome);
+            }
+        }
+    }
+
+    pub fn summary(&mut self, target: &Target, stats: &PingStatistics) {
+        for slot in &mut self.slots {
+            let outcome = slot.sink.send_summary(target, stats);
+            slot.report(outcome);
+        }
+    }
+
+    pub fn close(&mut self) {
+        for slot in &mut self.slots {
+            slot.sink.close();
+        }
+    }
+}
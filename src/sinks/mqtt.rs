@@ -0,0 +1,326 @@
+//! MQTT publishing with optional Home Assistant discovery.
+//!
+//! Probe results go to `<prefix>/<host>/state` and the final statistics to
+//! `<prefix>/<host>/summary`, or to the topics of `--mqtt-state-topic` and
+//! `--mqtt-summary-topic` templates. With `--mqtt-summary-interval` the
+//! statistics so far are also published every so often, so long runs keep
+//! dashboards current. With discovery enabled, retained config topics are
+//! published the first time a target reports so it shows up in Home
+//! Assistant as a latency sensor, a connectivity binary sensor and a packet
+//! loss sensor.
+
+use colored::*;
+use rumqttc::{Client, Connection, Event, MqttOptions, Outgoing, QoS};
+use serde_json::json;
+use std::collections::{HashMap, HashSet};
+use std::net::{IpAddr, Ipv4Addr};
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use super::{ProbeEvent, Sink, SummaryEvent, Target};
+use crate::stats::RttStats;
+use crate::{PingResult, PingStatistics};
+
+const DEFAULT_PORT: u16 = 1883;
+const KEEP_ALIVE: Duration = Duration::from_secs(30);
+// Pending publishes held while the broker is unreachable
+const QUEUE_CAPACITY: usize = 1000;
+// How long to wait for queued messages to go out on exit
+const CLOSE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Where results and statistics go and how often statistics are published
+pub struct MqttTopics<'a> {
+    pub prefix: &'a str,
+    pub state: &'a str,
+    pub summary: &'a str,
+    pub summary_interval: Option<Duration>,
+}
+
+/// Statistics of a target since its first result, for interim summaries
+struct Running {
+    rtt: RttStats,
+    sent: u32,
+    published: Instant,
+}
+
+pub struct MqttSink {
+    client: Client,
+    // Signalled when the network thread has finished
+    done: Receiver<()>,
+    prefix: String,
+    state_topic: String,
+    summary_topic: String,
+    summary_interval: Option<Duration>,
+    running: HashMap<String, Running>,
+    // Discovery prefix when Home Assistant discovery is enabled
+    discovery: Option<String>,
+    announced: HashSet<String>,
+}
+
+/// Split `mqtt://[user[:pass]@]host[:port]` into options for the client
+fn parse_url(url: &str) -> Result<MqttOptions, String> {
+    let rest = url
+        .strip_prefix("mqtt://")
+        .or_else(|| url.strip_prefix("tcp://"))
+        .ok_or_else(|| format!("Invalid MQTT URL '{}': expected mqtt://host[:port]", url))?;
+    let rest = rest.trim_end_matches('/');
+
+    let (credentials, address) = match rest.rsplit_once('@') {
+        Some((creds, addr)) => (Some(creds), addr),
+        None => (None, rest),
+    };
+
+    // IPv6 literals are bracketed: [::1]:1883
+    let (host, port) = match address.strip_prefix('[') {
+        Some(bracketed) => match bracketed.split_once(']') {
+            Some((host, "")) => (host, None),
+            Some((host, port)) => (host, port.strip_prefix(':')),
+            None => return Err(format!("Invalid MQTT URL '{}': unclosed '['", url)),
+        },
+        None => match address.split_once(':') {
+            Some((host, port)) => (host, Some(port)),
+            None => (address, None),
+        },
+    };
+    let port = match port {
+        Some(port) => port
+            .parse::<u16>()
+            .map_err(|_| format!("Invalid MQTT URL '{}': bad port '{}'", url, port))?,
+        None => DEFAULT_PORT,
+    };
+    if host.is_empty() {
+        return Err(format!("Invalid MQTT URL '{}': missing host", url));
+    }
+
+    let client_id = format!("rust_ping-{}", std::process::id());
+    let mut options = MqttOptions::new(client_id, host, port);
+    options.set_keep_alive(KEEP_ALIVE);
+    if let Some(creds) = credentials {
+        let (user, pass) = creds.split_once(':').unwrap_or((creds, ""));
+        options.set_credentials(user, pass);
+    }
+    Ok(options)
+}
+
+/// Topic and unique-id component derived from the host name
+fn object_id(host: &str) -> String {
+    host.chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c.to_ascii_lowercase() } else { '_' })
+        .collect()
+}
+
+/// Expand `{prefix}`, `{host}`, `{ip}` and `{run_id}` in a topic template;
+/// the host is made topic-safe as for the default topics
+fn topic(template: &str, prefix: &str, target: &Target) -> Result<String, String> {
+    let mut out = String::new();
+    let mut rest = template;
+
+    while let Some(open) = rest.find('{') {
+        out.push_str(&rest[..open]);
+        let close = rest[open..]
+            .find('}')
+            .ok_or_else(|| format!("Unclosed '{{' in MQTT topic '{}'", template))?;
+        let value = match &rest[open + 1..open + close] {
+            "prefix" => prefix.to_string(),
+            "host" => object_id(target.host),
+            "ip" => target.addr.to_string(),
+            "run_id" => target.run_id.to_string(),
+            other => {
+                return Err(format!(
+                    "Unknown placeholder '{{{}}}' in MQTT topic '{}' (use prefix, host, ip, run_id)",
+                    other, template
+                ))
+            }
+        };
+        out.push_str(&value);
+        rest = &rest[open + close + 1..];
+    }
+    out.push_str(rest);
+    Ok(out)
+}
+
+/// Keep the network event loop running; the client reconnects on its own
+fn drive(mut connection: Connection) {
+    let mut failing = false;
+    for event in connection.iter() {
+        match event {
+            Ok(Event::Outgoing(Outgoing::Disconnect)) => break,
+            Ok(_) => failing = false,
+            Err(e) => {
+                if !failing {
+                    eprintln!("  {} MQTT connection error: {}", "⚠".yellow(), e);
+                    failing = true;
+                }
+                thread::sleep(Duration::from_secs(1));
+            }
+        }
+    }
+}
+
+impl MqttSink {
+    pub fn connect(url: &str, topics: &MqttTopics, discovery: Option<&str>) -> Result<Self, String> {
+        let options = parse_url(url)?;
+        // Templates are checked before anything is published
+        let example = Target { run_id: "", host: "", addr: IpAddr::V4(Ipv4Addr::UNSPECIFIED) };
+        topic(topics.state, "", &example)?;
+        topic(topics.summary, "", &example)?;
+
+        let (client, connection) = Client::new(options, QUEUE_CAPACITY);
+        let (done_tx, done) = mpsc::channel();
+        thread::Builder::new()
+            .name("mqtt".to_string())
+            .spawn(move || {
+                drive(connection);
+                let _ = done_tx.send(());
+            })
+            .map_err(|e| format!("Failed to start MQTT client: {}", e))?;
+
+        Ok(MqttSink {
+            client,
+            done,
+            prefix: topics.prefix.trim_end_matches('/').to_string(),
+            state_topic: topics.state.to_string(),
+            summary_topic: topics.summary.to_string(),
+            summary_interval: topics.summary_interval,
+            running: HashMap::new(),
+            discovery: discovery.map(|p| p.trim_end_matches('/').to_string()),
+            announced: HashSet::new(),
+        })
+    }
+
+    fn publish(&self, topic: String, qos: QoS, retain: bool, payload: Vec<u8>) -> Result<(), String> {
+        self.client
+            .try_publish(topic, qos, retain, payload)
+            .map_err(|e| e.to_string())
+    }
+
+    /// Retained Home Assistant config for the sensors of one target
+    fn announce(&mut self, target: &Target) -> Result<(), String> {
+        let Some(discovery) = &self.discovery else {
+            return Ok(());
+        };
+        let id = object_id(target.host);
+        if self.announced.contains(&id) {
+            return Ok(());
+        }
+
+        let state_topic = topic(&self.state_topic, &self.prefix, target)?;
+        let summary_topic = topic(&self.summary_topic, &self.prefix, target)?;
+        let device = json!({
+            "identifiers": [format!("rust_ping_{}", id)],
+            "name": format!("Ping {}", target.host),
+            "model": target.addr.to_string(),
+            "manufacturer": "rust_ping",
+            "sw_version": env!("CARGO_PKG_VERSION"),
+        });
+
+        let configs = [
+            (
+                format!("{}/sensor/rust_ping_{}/latency/config", discovery, id),
+                json!({
+                    "name": "Latency",
+                    "unique_id": format!("rust_ping_{}_latency", id),
+                    "state_topic": state_topic,
+                    "value_template": "{{ value_json.rtt_ms | round(2) if value_json.success else None }}",
+                    "unit_of_measurement": "ms",
+                    "device_class": "duration",
+                    "state_class": "measurement",
+                    "icon": "mdi:timer-outline",
+                    "device": device,
+                }),
+            ),
+            (
+                format!("{}/binary_sensor/rust_ping_{}/reachable/config", discovery, id),
+                json!({
+                    "name": "Reachable",
+                    "unique_id": format!("rust_ping_{}_reachable", id),
+                    "state_topic": state_topic,
+                    "value_template": "{{ 'ON' if value_json.success else 'OFF' }}",
+                    "device_class": "connectivity",
+                    "device": device,
+                }),
+            ),
+            (
+                format!("{}/sensor/rust_ping_{}/packet_loss/config", discovery, id),
+                json!({
+                    "name": "Packet loss",
+                    "unique_id": format!("rust_ping_{}_packet_loss", id),
+                    "state_topic": summary_topic,
+                    "value_template": "{{ value_json.packet_loss_percent | round(1) }}",
+                    "unit_of_measurement": "%",
+                    "state_class": "measurement",
+                    "icon": "mdi:lan-disconnect",
+                    "device": device,
+                }),
+            ),
+        ];
+
+        for (topic, config) in configs {
+            let payload = serde_json::to_vec(&config).map_err(|e| e.to_string())?;
+            self.publish(topic, QoS::AtLeastOnce, true, payload)?;
+        }
+        self.announced.insert(id);
+        Ok(())
+    }
+
+    /// Statistics of a target as they stand, once the summary interval has passed
+    fn interim(&mut self, target: &Target, result: &PingResult) -> Result<(), String> {
+        let Some(interval) = self.summary_interval else {
+            return Ok(());
+        };
+        let running = self.running.entry(target.host.to_string()).or_insert_with(|| Running {
+            rtt: RttStats::new(),
+            sent: 0,
+            published: Instant::now(),
+        });
+        running.sent += 1;
+        match result.rtt_ms {
+            Some(rtt) => running.rtt.add(rtt),
+            None => running.rtt.add_failure(result.status),
+        }
+        if running.published.elapsed() < interval {
+            return Ok(());
+        }
+        running.published = Instant::now();
+
+        let stats = running.rtt.statistics(running.sent);
+        let event = SummaryEvent { event: "interim", ..SummaryEvent::new(target, &stats) };
+        let payload = serde_json::to_vec(&event).map_err(|e| e.to_string())?;
+        let topic = topic(&self.summary_topic, &self.prefix, target)?;
+        self.publish(topic, QoS::AtLeastOnce, true, payload)
+    }
+}
+
+impl Sink for MqttSink {
+    fn name(&self) -> &'static str {
+        "MQTT"
+    }
+
+    fn send_result(&mut self, target: &Target, result: &PingResult) -> Result<(), String> {
+        self.announce(target)?;
+        let payload = serde_json::to_vec(&ProbeEvent::new(target, result))
+            .map_err(|e| e.to_string())?;
+        let topic = topic(&self.state_topic, &self.prefix, target)?;
+        self.publish(topic, QoS::AtMostOnce, false, payload)?;
+        self.interim(target, result)
+    }
+
+    fn send_summary(&mut self, target: &Target, stats: &PingStatistics) -> Result<(), String> {
+        self.announce(target)?;
+        self.running.remove(target.host);
+        let payload = serde_json::to_vec(&SummaryEvent::new(target, stats))
+            .map_err(|e| e.to_string())?;
+        let topic = topic(&self.summary_topic, &self.prefix, target)?;
+        // Retained so dashboards show the last run's numbers until the next one
+        self.publish(topic, QoS::AtLeastOnce, true, payload)
+    }
+
+    fn close(&mut self) {
+        // Queued publishes are sent before the disconnect request is handled;
+        // an unreachable broker must not keep the process alive
+        if self.client.try_disconnect().is_ok() {
+            let _ = self.done.recv_timeout(CLOSE_TIMEOUT);
+        }
+    }
+}
@@ -0,0 +1,37 @@
+//! Newline-delimited JSON on stdout.
+//!
+//! One object per probe as soon as its outcome is known, and one with the
+//! final statistics of each target, flushed line by line so `jq`, Vector or
+//! Fluentd can follow a run while it goes on. The objects are the events the
+//! Redis sink publishes.
+
+use std::io::{self, Write};
+
+use super::{ProbeEvent, Sink, SummaryEvent, Target};
+use crate::{PingResult, PingStatistics};
+
+pub struct NdjsonSink;
+
+impl NdjsonSink {
+    fn emit(event: &impl serde::Serialize) -> Result<(), String> {
+        let line = serde_json::to_string(event).map_err(|e| e.to_string())?;
+        let mut out = io::stdout().lock();
+        writeln!(out, "{}", line)
+            .and_then(|_| out.flush())
+            .map_err(|e| e.to_string())
+    }
+}
+
+impl Sink for NdjsonSink {
+    fn name(&self) -> &'static str {
+        "ndjson"
+    }
+
+    fn send_result(&mut self, target: &Target, result: &PingResult) -> Result<(), String> {
+        Self::emit(&ProbeEvent::new(target, result))
+    }
+
+    fn send_summary(&mut self, target: &Target, stats: &PingStatistics) -> Result<(), String> {
+        Self::emit(&SummaryEvent::new(target, stats))
+    }
+}
@@ -0,0 +1,131 @@
+//! netdata external plugin protocol on stdout.
+//!
+//! Charts are declared the first time a target reports; afterwards one
+//! BEGIN/SET/END block per chart is written every `update_every` seconds
+//! (taken from `NETDATA_UPDATE_EVERY` when run by netdata, 1 otherwise).
+
+use std::collections::{HashMap, HashSet};
+use std::io::{self, Write};
+use std::time::{Duration, Instant};
+
+use super::{Sink, Target};
+use crate::{PingResult, PingStatistics};
+
+/// Probes of one target collected since its last update
+struct Window {
+    started: Instant,
+    sent: u64,
+    received: u64,
+    times: Vec<f64>,
+}
+
+impl Window {
+    fn new() -> Self {
+        Window { started: Instant::now(), sent: 0, received: 0, times: Vec::new() }
+    }
+}
+
+pub struct NetdataSink {
+    update_every: u64,
+    declared: HashSet<String>,
+    windows: HashMap<String, Window>,
+}
+
+/// Chart id component derived from the host name
+fn chart_id(host: &str) -> String {
+    host.chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect()
+}
+
+impl NetdataSink {
+    pub fn new() -> Self {
+        let update_every = std::env::var("NETDATA_UPDATE_EVERY")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .filter(|&v| v > 0)
+            .unwrap_or(1);
+        NetdataSink { update_every, declared: HashSet::new(), windows: HashMap::new() }
+    }
+
+    fn declare(&mut self, out: &mut impl Write, target: &Target) -> io::Result<()> {
+        let id = chart_id(target.host);
+        if !self.declared.insert(id.clone()) {
+            return Ok(());
+        }
+        let host = target.host;
+        let every = self.update_every;
+
+        writeln!(out, "CHART rust_ping.{id}_latency '' 'Latency to {host}' 'milliseconds' '{host}' 'rust_ping.latency' line 70000 {every} '' 'rust_ping'")?;
+        writeln!(out, "DIMENSION min '' absolute 1 1000")?;
+        writeln!(out, "DIMENSION avg '' absolute 1 1000")?;
+        writeln!(out, "DIMENSION max '' absolute 1 1000")?;
+        writeln!(out, "CHART rust_ping.{id}_packets '' 'Packets to {host}' 'packets' '{host}' 'rust_ping.packets' line 70001 {every} '' 'rust_ping'")?;
+        writeln!(out, "DIMENSION sent '' absolute 1 1")?;
+        writeln!(out, "DIMENSION received '' absolute 1 1")?;
+        writeln!(out, "CHART rust_ping.{id}_loss '' 'Packet loss to {host}' 'percentage' '{host}' 'rust_ping.loss' area 70002 {every} '' 'rust_ping'")?;
+        writeln!(out, "DIMENSION loss '' absolute 1 1000")?;
+        Ok(())
+    }
+
+    fn update(&self, out: &mut impl Write, target: &Target, w: &Window) -> io::Result<()> {
+        let id = chart_id(target.host);
+        let micros = w.started.elapsed().as_micros();
+
+        // Latency is only reported for windows that saw replies
+        if !w.times.is_empty() {
+            let min = w.times.iter().cloned().fold(f64::INFINITY, f64::min);
+            let max = w.times.iter().cloned().fold(f64::NEG_INFINITY, f64::max);
+            let avg = w.times.iter().sum::<f64>() / w.times.len() as f64;
+            writeln!(out, "BEGIN rust_ping.{}_latency {}", id, micros)?;
+            writeln!(out, "SET min = {}", (min * 1000.0).round() as i64)?;
+            writeln!(out, "SET avg = {}", (avg * 1000.0).round() as i64)?;
+            writeln!(out, "SET max = {}", (max * 1000.0).round() as i64)?;
+            writeln!(out, "END")?;
+        }
+
+        writeln!(out, "BEGIN rust_ping.{}_packets {}", id, micros)?;
+        writeln!(out, "SET sent = {}", w.sent)?;
+        writeln!(out, "SET received = {}", w.received)?;
+        writeln!(out, "END")?;
+
+        if w.sent > 0 {
+            let loss = (w.sent - w.received) as f64 / w.sent as f64 * 100.0;
+            writeln!(out, "BEGIN rust_ping.{}_loss {}", id, micros)?;
+            writeln!(out, "SET loss = {}", (loss * 1000.0).round() as i64)?;
+            writeln!(out, "END")?;
+        }
+        out.flush()
+    }
+}
+
+impl Sink for NetdataSink {
+    fn name(&self) -> &'static str {
+        "netdata"
+    }
+
+    fn send_result(&mut self, target: &Target, result: &PingResult) -> Result<(), String> {
+        let mut out = io::stdout().lock();
+        self.declare(&mut out, target).map_err(|e| e.to_string())?;
+
+        let w = self.windows.entry(target.host.to_string()).or_insert_with(Window::new);
+        w.sent += 1;
+        if let Some(rtt) = result.rtt_ms {
+            w.received += 1;
+            w.times.push(rtt);
+        }
+
+        if w.started.elapsed() >= Duration::from_secs(self.update_every) {
+            let w = std::mem::replace(w, Window::new());
+            self.update(&mut out, target, &w).map_err(|e| e.to_string())?;
+        }
+        Ok(())
+    }
+
+    fn send_summary(&mut self, target: &Target, _stats: &PingStatistics) -> Result<(), String> {
+        match self.windows.remove(target.host) {
+            Some(w) if w.sent > 0 => self.update(&mut io::stdout().lock(), target, &w).map_err(|e| e.to_string()),
+            _ => Ok(()),
+        }
+    }
+}
@@ -0,0 +1,272 @@
+//! OpenTelemetry (OTLP/HTTP) export.
+//!
+//! `--otlp URL` pushes metrics to `<URL>/v1/metrics` of an OpenTelemetry
+//! collector every `--otlp-interval` seconds and when a target finishes, in
+//! the OTLP JSON encoding:
+//!
+//! - `rust_ping.rtt`: histogram of reply RTTs in ms
+//! - `rust_ping.packets.sent`, `rust_ping.packets.received` and
+//!   `rust_ping.packets.lost`: counters
+//!
+//! all cumulative since the target's first probe and with the attributes
+//! `host` and `ip`, under a resource with `service.name` "rust_ping". With
+//! `--otlp-spans` every probe is also sent to `<URL>/v1/traces` as a span of
+//! its own, from its send to its reply (or timeout), with an error status
+//! when it was lost. A push that fails loses nothing: counters are sent in
+//! full every time and spans are kept for the next one.
+
+use chrono::{DateTime, Local, Utc};
+use serde_json::{json, Value};
+use std::collections::hash_map::RandomState;
+use std::collections::BTreeMap;
+use std::hash::{BuildHasher, Hasher};
+use std::time::{Duration, Instant};
+
+use super::{http_agent, parse_headers, Sink, Target};
+use crate::{PingResult, PingStatistics};
+
+// Upper bounds of the RTT histogram buckets, in ms
+const BUCKETS: [f64; 13] = [0.5, 1.0, 2.5, 5.0, 10.0, 25.0, 50.0, 100.0, 250.0, 500.0, 1000.0, 2500.0, 5000.0];
+
+// Spans are dropped beyond this while the collector is unreachable
+const MAX_PENDING_SPANS: usize = 100_000;
+
+// Span kind and status codes of the OTLP protobuf enums
+const SPAN_KIND_CLIENT: u32 = 3;
+const STATUS_OK: u32 = 1;
+const STATUS_ERROR: u32 = 2;
+
+/// Metrics of one target since its first probe
+struct Series {
+    start: i64,
+    sent: u64,
+    received: u64,
+    // Replies per bucket, the last one for those above every bound
+    buckets: [u64; BUCKETS.len() + 1],
+    sum: f64,
+    min: f64,
+    max: f64,
+}
+
+pub struct OtlpSink {
+    url: String,
+    headers: Vec<(String, String)>,
+    agent: ureq::Agent,
+    interval: Duration,
+    // Span end for probes that got no reply
+    timeout: Duration,
+    spans: bool,
+    // Keyed by host and ip, the attributes of every series
+    series: BTreeMap<(String, String), Series>,
+    pending_spans: Vec<Value>,
+    last_push: Instant,
+}
+
+fn nanos(time: DateTime<Utc>) -> i64 {
+    time.timestamp_nanos_opt().unwrap_or_default()
+}
+
+/// `count` random bytes in hex, for trace and span ids
+fn random_id(count: usize) -> String {
+    let mut id = String::new();
+    while id.len() < count * 2 {
+        let mut hasher = RandomState::new().build_hasher();
+        hasher.write_usize(id.len());
+        id.push_str(&format!("{:016x}", hasher.finish()));
+    }
+    id.truncate(count * 2);
+    id
+}
+
+fn attribute(key: &str, value: Value) -> Value {
+    json!({ "key": key, "value": value })
+}
+
+fn text(value: &str) -> Value {
+    json!({ "stringValue": value })
+}
+
+/// `host` and `ip` attributes of a target's series and spans
+fn target_attributes(host: &str, ip: &str) -> Vec<Value> {
+    vec![attribute("host", text(host)), attribute("ip", text(ip))]
+}
+
+/// Resource and scope around the metrics or spans of a request
+fn envelope(resource_key: &str, scope_key: &str, items_key: &str, items: Value) -> Value {
+    json!({
+        resource_key: [{
+            "resource": { "attributes": [attribute("service.name", text("rust_ping"))] },
+            scope_key: [{
+                "scope": { "name": "rust_ping", "version": env!("CARGO_PKG_VERSION") },
+                items_key: items,
+            }],
+        }],
+    })
+}
+
+impl OtlpSink {
+    pub fn new(url: &str, headers: &[String], interval: Duration, timeout: Duration, spans: bool) -> Result<Self, String> {
+        Ok(OtlpSink {
+            url: url.trim_end_matches('/').to_string(),
+            headers: parse_headers(headers)?,
+            agent: http_agent(),
+            interval,
+            timeout,
+            spans,
+            series: BTreeMap::new(),
+            pending_spans: Vec::new(),
+            last_push: Instant::now(),
+        })
+    }
+
+    fn post(&self, path: &str, body: &Value) -> Result<(), String> {
+        let mut req = self.agent.post(&format!("{}{}", self.url, path));
+        for (name, value) in &self.headers {
+            req = req.header(name, value);
+        }
+        req.send_json(body).map(|_| ()).map_err(|e| e.to_string())
+    }
+
+    fn span(&self, target: &Target, result: &PingResult) -> Value {
+        let start = result.timestamp.unwrap_or_else(Local::now).with_timezone(&Utc);
+        let took = result.rtt_ms.map_or(self.timeout, |rtt| Duration::from_secs_f64(rtt / 1000.0));
+        let end = start + chrono::Duration::from_std(took).unwrap_or_default();
+
+        let mut attributes = target_attributes(target.host, &target.addr.to_string());
+        attributes.push(attribute("run_id", text(target.run_id)));
+        attributes.push(attribute("seq", json!({ "intValue": result.seq.to_string() })));
+        attributes.push(attribute("status", text(result.status.name())));
+        if let Some(rtt) = result.rtt_ms {
+            attributes.push(attribute("rtt_ms", json!({ "doubleValue": rtt })));
+        }
+        let status = if result.success() {
+            json!({ "code": STATUS_OK })
+        } else {
+            json!({ "code": STATUS_ERROR, "message": result.status.name() })
+        };
+        json!({
+            "traceId": random_id(16),
+            "spanId": random_id(8),
+            "name": "ping",
+            "kind": SPAN_KIND_CLIENT,
+            "startTimeUnixNano": nanos(start).to_string(),
+            "endTimeUnixNano": nanos(end).to_string(),
+            "attributes": attributes,
+            "status": status,
+        })
+    }
+
+    fn metrics(&self) -> Value {
+        let now = nanos(Utc::now()).to_string();
+        let mut histogram = Vec::new();
+        let mut sent = Vec::new();
+        let mut received = Vec::new();
+        let mut lost = Vec::new();
+        for ((host, ip), series) in &self.series {
+            let attributes = target_attributes(host, ip);
+            let start = series.start.to_string();
+            let replies: u64 = series.buckets.iter().sum();
+            let mut point = json!({
+                "attributes": attributes,
+                "startTimeUnixNano": start,
+                "timeUnixNano": now,
+                "count": replies.to_string(),
+                "sum": series.sum,
+                "bucketCounts": series.buckets.iter().map(u64::to_string).collect::<Vec<_>>(),
+                "explicitBounds": BUCKETS,
+            });
+            if replies > 0 {
+                point["min"] = json!(series.min);
+                point["max"] = json!(series.max);
+            }
+            histogram.push(point);
+            let counter = |value: u64| {
+                json!({ "attributes": attributes, "startTimeUnixNano": start, "timeUnixNano": now, "asInt": value.to_string() })
+            };
+            sent.push(counter(series.sent));
+            received.push(counter(series.received));
+            lost.push(counter(series.sent - series.received));
+        }
+
+        // Aggregation temporality 2 is cumulative
+        let sum = |name: &str, description: &str, points: Vec<Value>| {
+            json!({
+                "name": name,
+                "description": description,
+                "unit": "{packet}",
+                "sum": { "aggregationTemporality": 2, "isMonotonic": true, "dataPoints": points },
+            })
+        };
+        let metrics = json!([
+            {
+                "name": "rust_ping.rtt",
+                "description": "Round-trip time of echo replies",
+                "unit": "ms",
+                "histogram": { "aggregationTemporality": 2, "dataPoints": histogram },
+            },
+            sum("rust_ping.packets.sent", "Probes sent", sent),
+            sum("rust_ping.packets.received", "Probes answered", received),
+            sum("rust_ping.packets.lost", "Probes that got no reply", lost),
+        ]);
+        envelope("resourceMetrics", "scopeMetrics", "metrics", metrics)
+    }
+
+    fn push(&mut self) -> Result<(), String> {
+        self.last_push = Instant::now();
+        if self.series.is_empty() {
+            return Ok(());
+        }
+        self.post("/v1/metrics", &self.metrics())?;
+
+        if !self.pending_spans.is_empty() {
+            let spans = envelope("resourceSpans", "scopeSpans", "spans", Value::Array(self.pending_spans.clone()));
+            if let Err(e) = self.post("/v1/traces", &spans) {
+                if self.pending_spans.len() > MAX_PENDING_SPANS {
+                    self.pending_spans.clear();
+                }
+                return Err(e);
+            }
+            self.pending_spans.clear();
+        }
+        Ok(())
+    }
+}
+
+impl Sink for OtlpSink {
+    fn name(&self) -> &'static str {
+        "OTLP"
+    }
+
+    fn send_result(&mut self, target: &Target, result: &PingResult) -> Result<(), String> {
+        let series = self.series.entry((target.host.to_string(), target.addr.to_string())).or_insert_with(|| Series {
+            start: nanos(Utc::now()),
+            sent: 0,
+            received: 0,
+            buckets: [0; BUCKETS.len() + 1],
+            sum: 0.0,
+            min: f64::INFINITY,
+            max: f64::NEG_INFINITY,
+        });
+        series.sent += 1;
+        if let Some(rtt) = result.rtt_ms {
+            series.received += 1;
+            series.buckets[BUCKETS.iter().position(|&bound| rtt <= bound).unwrap_or(BUCKETS.len())] += 1;
+            series.sum += rtt;
+            series.min = series.min.min(rtt);
+            series.max = series.max.max(rtt);
+        }
+        if self.spans {
+            let span = self.span(target, result);
+            self.pending_spans.push(span);
+        }
+
+        if self.last_push.elapsed() >= self.interval {
+            self.push()?;
+        }
+        Ok(())
+    }
+
+    fn send_summary(&mut self, _target: &Target, _stats: &PingStatistics) -> Result<(), String> {
+        self.push()
+    }
+}
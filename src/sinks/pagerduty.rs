@@ -0,0 +1,97 @@
+//! PagerDuty incidents through the Events API v2.
+//!
+//! Each problem of a target (down, high RTT, high loss) has its own dedup
+//! key, so it opens one incident that is resolved by the matching recovery
+//! alert.
+
+use serde_json::json;
+
+use super::{Poster, Sink, Target};
+use crate::alerts::{Alert, AlertKind};
+use crate::config::PagerDutyConfig;
+use crate::PingResult;
+
+const EVENTS_URL: &str = "https://events.pagerduty.com/v2/enqueue";
+
+pub struct PagerDutySink {
+    routing_key: String,
+    url: String,
+    events: Option<Vec<AlertKind>>,
+    poster: Poster,
+}
+
+impl PagerDutySink {
+    pub fn new(config: &PagerDutyConfig) -> Result<Self, String> {
+        Ok(PagerDutySink {
+            routing_key: config.routing_key.clone(),
+            url: config.url.clone().unwrap_or_else(|| EVENTS_URL.to_string()),
+            events: config.events.clone(),
+            poster: Poster::spawn("PagerDuty")?,
+        })
+    }
+}
+
+impl Sink for PagerDutySink {
+    fn name(&self) -> &'static str {
+        "PagerDuty"
+    }
+
+    fn send_result(&mut self, _target: &Target, _result: &PingResult) -> Result<(), String> {
+        Ok(())
+    }
+
+    fn send_alert(&mut self, target: &Target, alert: &Alert) -> Result<(), String> {
+        // Listing either side of a problem selects both trigger and resolve
+        if let Some(events) = &self.events {
+            if !events.iter().any(|kind| kind.problem() == alert.kind.problem()) {
+                return Ok(());
+            }
+        }
+
+        let dedup_key = format!("rust_ping/{}/{}", target.host, alert.kind.problem());
+        let event = if alert.kind.is_problem() {
+            let summary = match alert.kind {
+                AlertKind::RttHigh => format!(
+                    "{} average RTT above {} ms",
+                    target.host,
+                    alert.threshold.unwrap_or_default()
+                ),
+                AlertKind::LossHigh => format!(
+                    "{} packet loss above {}%",
+                    target.host,
+                    alert.threshold.unwrap_or_default()
+                ),
+                _ => format!("{} ({}) is not responding to ping", target.host, target.addr),
+            };
+            let severity = if alert.kind == AlertKind::Down { "critical" } else { "warning" };
+            json!({
+                "routing_key": self.routing_key,
+                "event_action": "trigger",
+                "dedup_key": dedup_key,
+                "payload": {
+                    "summary": summary,
+                    "source": target.host,
+                    "severity": severity,
+                    "component": target.addr.to_string(),
+                    "class": alert.kind.problem(),
+                    "custom_details": {
+                        "alert": alert,
+                        "run_id": target.run_id,
+                    },
+                },
+            })
+        } else {
+            json!({
+                "routing_key": self.routing_key,
+                "event_action": "resolve",
+                "dedup_key": dedup_key,
+            })
+        };
+
+        self.poster.post(&self.url, event)
+    }
+
+    fn close(&mut self) {
+        self.poster.close();
+    }
+}
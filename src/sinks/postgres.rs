@@ -0,0 +1,173 @@
+//! PostgreSQL / TimescaleDB storage.
+//!
+//! Tables are created on first connect; when the timescaledb extension is
+//! installed the probes table is turned into a hypertable. A lost connection
+//! is opened again at most every few seconds, with a bounded wait, so a
+//! database that went away costs the probes no more than that.
+
+use chrono::Utc;
+use postgres::{Client, Config, NoTls, Statement};
+use std::time::{Duration, Instant};
+
+use super::{Sink, Target};
+use crate::{PingResult, PingStatistics};
+
+// Wait for the server when the DSN doesn't set connect_timeout
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+// Wait after a failed connect before trying again
+const RETRY: Duration = Duration::from_secs(10);
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS ping_probes (
+    time        TIMESTAMPTZ      NOT NULL,
+    run_id      TEXT             NOT NULL,
+    host        TEXT             NOT NULL,
+    ip          TEXT             NOT NULL,
+    seq         INTEGER          NOT NULL,
+    rtt_ms      DOUBLE PRECISION,
+    success     BOOLEAN          NOT NULL
+);
+CREATE INDEX IF NOT EXISTS ping_probes_host_time ON ping_probes (host, time DESC);
+CREATE TABLE IF NOT EXISTS ping_runs (
+    finished_at         TIMESTAMPTZ      NOT NULL,
+    run_id              TEXT             NOT NULL,
+    host                TEXT             NOT NULL,
+    ip                  TEXT             NOT NULL,
+    packets_sent        INTEGER          NOT NULL,
+    packets_received    INTEGER          NOT NULL,
+    packets_lost        INTEGER          NOT NULL,
+    packet_loss_percent DOUBLE PRECISION NOT NULL,
+    min_ms              DOUBLE PRECISION,
+    avg_ms              DOUBLE PRECISION,
+    max_ms              DOUBLE PRECISION,
+    std_dev_ms          DOUBLE PRECISION
+);
+";
+
+const INSERT_PROBE: &str = "INSERT INTO ping_probes (time, run_id, host, ip, seq, rtt_ms, success)
+     VALUES ($1, $2, $3, $4, $5, $6, $7)";
+
+const INSERT_RUN: &str = "INSERT INTO ping_runs (finished_at, run_id, host, ip, packets_sent,
+     packets_received, packets_lost, packet_loss_percent, min_ms, avg_ms, max_ms, std_dev_ms)
+     VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12)";
+
+struct Session {
+    client: Client,
+    insert_probe: Statement,
+    insert_run: Statement,
+}
+
+impl Session {
+    fn open(dsn: &str) -> Result<Self, postgres::Error> {
+        let mut config: Config = dsn.parse()?;
+        if config.get_connect_timeout().is_none() {
+            config.connect_timeout(CONNECT_TIMEOUT);
+        }
+        let mut client = config.connect(NoTls)?;
+        client.batch_execute(SCHEMA)?;
+
+        let timescale = client
+            .query_opt("SELECT 1 FROM pg_extension WHERE extname = 'timescaledb'", &[])?
+            .is_some();
+        if timescale {
+            client.execute(
+                "SELECT create_hypertable('ping_probes', 'time', if_not_exists => TRUE, migrate_data => TRUE)",
+                &[],
+            )?;
+        }
+
+        let insert_probe = client.prepare(INSERT_PROBE)?;
+        let insert_run = client.prepare(INSERT_RUN)?;
+        Ok(Session { client, insert_probe, insert_run })
+    }
+}
+
+pub struct PostgresSink {
+    dsn: String,
+    // Dropped after an error and re-established on a later send
+    session: Option<Session>,
+    // No connect is tried before this after one failed
+    retry_at: Instant,
+}
+
+impl PostgresSink {
+    pub fn connect(dsn: &str) -> Result<Self, String> {
+        let session = Session::open(dsn)
+            .map_err(|e| format!("Failed to connect to PostgreSQL: {}", e))?;
+        Ok(PostgresSink {
+            dsn: dsn.to_string(),
+            session: Some(session),
+            retry_at: Instant::now(),
+        })
+    }
+
+    fn session(&mut self) -> Result<&mut Session, String> {
+        if self.session.is_none() {
+            if Instant::now() < self.retry_at {
+                return Err("not connected, waiting to try again".to_string());
+            }
+            match Session::open(&self.dsn) {
+                Ok(session) => self.session = Some(session),
+                Err(e) => {
+                    self.retry_at = Instant::now() + RETRY;
+                    return Err(e.to_string());
+                }
+            }
+        }
+        Ok(self.session.as_mut().expect("session was just opened"))
+    }
+
+    fn check<T>(&mut self, outcome: Result<T, postgres::Error>) -> Result<(), String> {
+        outcome.map(|_| ()).map_err(|e| {
+            if e.is_closed() {
+                self.session = None;
+            }
+            e.to_string()
+        })
+    }
+}
+
+impl Sink for PostgresSink {
+    fn name(&self) -> &'static str {
+        "PostgreSQL"
+    }
+
+    fn send_result(&mut self, target: &Target, result: &PingResult) -> Result<(), String> {
+        let session = self.session()?;
+        let outcome = session.client.execute(
+            &session.insert_probe,
+            &[
+                &result.timestamp.map_or_else(Utc::now, |time| time.with_timezone(&Utc)),
+                &target.run_id,
+                &target.host,
+                &target.addr.to_string(),
+                &(result.seq as i32),
+                &result.rtt_ms,
+                &result.success(),
+            ],
+        );
+        self.check(outcome)
+    }
+
+    fn send_summary(&mut self, target: &Target, stats: &PingStatistics) -> Result<(), String> {
+        let session = self.session()?;
+        let outcome = session.client.execute(
+            &session.insert_run,
+            &[
+                &Utc::now(),
+                &target.run_id,
+                &target.host,
+                &target.addr.to_string(),
+                &(stats.packets_sent as i32),
+                &(stats.packets_received as i32),
+                &(stats.packets_lost as i32),
+                &stats.packet_loss_percent,
+                &stats.min_ms,
+                &stats.avg_ms,
+                &stats.max_ms,
+                &stats.std_dev_ms,
+            ],
+        );
+        self.check(outcome)
+    }
+}
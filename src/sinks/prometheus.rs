@@ -0,0 +1,161 @@
+//! Prometheus scrape endpoint.
+//!
+//! `--prometheus ADDR` serves `/metrics` in the text exposition format while
+//! the run goes on: an RTT histogram, packet counters and the loss ratio of
+//! every target, labelled with its host and ip. Scrapes are answered by a
+//! thread of their own from the metrics the probes keep up to date, so a
+//! slow scraper never holds up the measurement.
+
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use super::{Sink, Target};
+use crate::PingResult;
+
+// Upper bounds of the RTT histogram buckets, in seconds
+const BUCKETS: [f64; 13] = [0.0005, 0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0];
+
+// A scraper that doesn't send its request within this long is dropped
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// What is exported for one target
+#[derive(Default)]
+struct Series {
+    sent: u64,
+    received: u64,
+    up: bool,
+    // Replies per bucket (not cumulative), and the sum of their RTTs in seconds
+    buckets: [u64; BUCKETS.len()],
+    rtt_sum: f64,
+}
+
+// Keyed by host and ip, the labels of every series
+type Metrics = BTreeMap<(String, String), Series>;
+
+pub struct PrometheusSink {
+    metrics: Arc<Mutex<Metrics>>,
+}
+
+impl PrometheusSink {
+    /// Listen on `addr` (e.g. 0.0.0.0:9898) and answer scrapes from then on
+    pub fn listen(addr: &str) -> Result<Self, String> {
+        let listener = TcpListener::bind(addr).map_err(|e| format!("Failed to listen on {} for Prometheus: {}", addr, e))?;
+        let metrics = Arc::new(Mutex::new(Metrics::new()));
+        let shared = metrics.clone();
+        thread::Builder::new()
+            .name("prometheus".to_string())
+            .spawn(move || {
+                for stream in listener.incoming().flatten() {
+                    // Scrapers retry, so a failed answer is only their loss
+                    let _ = answer(stream, &shared);
+                }
+            })
+            .map_err(|e| format!("Failed to start Prometheus endpoint: {}", e))?;
+        Ok(PrometheusSink { metrics })
+    }
+}
+
+impl Sink for PrometheusSink {
+    fn name(&self) -> &'static str {
+        "Prometheus"
+    }
+
+    fn send_result(&mut self, target: &Target, result: &PingResult) -> Result<(), String> {
+        let mut metrics = self.metrics.lock().unwrap();
+        let series = metrics.entry((target.host.to_string(), target.addr.to_string())).or_default();
+        series.sent += 1;
+        series.up = result.success();
+        if let Some(rtt) = result.rtt_ms {
+            let seconds = rtt / 1000.0;
+            series.received += 1;
+            series.rtt_sum += seconds;
+            if let Some(i) = BUCKETS.iter().position(|&bound| seconds <= bound) {
+                series.buckets[i] += 1;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Answer one HTTP request: the metrics on /metrics, 404 elsewhere
+fn answer(mut stream: TcpStream, metrics: &Mutex<Metrics>) -> std::io::Result<()> {
+    stream.set_read_timeout(Some(REQUEST_TIMEOUT))?;
+    let mut request = Vec::new();
+    let mut chunk = [0u8; 1024];
+    // Only the request line matters; the headers are read so the client isn't reset
+    while !request.windows(4).any(|w| w == b"\r\n\r\n") && request.len() < 16 * 1024 {
+        let n = stream.read(&mut chunk)?;
+        if n == 0 {
+            break;
+        }
+        request.extend_from_slice(&chunk[..n]);
+    }
+    let line = String::from_utf8_lossy(&request);
+    let mut parts = line.split_whitespace();
+    let (status, body) = match (parts.next(), parts.next()) {
+        (Some("GET"), Some(path)) if path == "/metrics" || path.starts_with("/metrics?") => {
+            ("200 OK", render(&metrics.lock().unwrap()))
+        }
+        _ => ("404 Not Found", "Metrics are served on /metrics\n".to_string()),
+    };
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: text/plain; version=0.0.4; charset=utf-8\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    )
+}
+
+/// Label values with backslashes, quotes and newlines escaped
+fn escape(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+/// All metrics in the text exposition format
+fn render(metrics: &Metrics) -> String {
+    let mut out = String::new();
+
+    out.push_str("# HELP rust_ping_rtt_seconds Round-trip time of the replies.\n# TYPE rust_ping_rtt_seconds histogram\n");
+    for ((host, ip), series) in metrics {
+        let labels = labels(host, ip);
+        let mut cumulative = 0;
+        for (bound, count) in BUCKETS.iter().zip(series.buckets) {
+            cumulative += count;
+            let _ = writeln!(out, "rust_ping_rtt_seconds_bucket{{{},le=\"{}\"}} {}", labels, bound, cumulative);
+        }
+        let _ = writeln!(out, "rust_ping_rtt_seconds_bucket{{{},le=\"+Inf\"}} {}", labels, series.received);
+        let _ = writeln!(out, "rust_ping_rtt_seconds_sum{{{}}} {}", labels, series.rtt_sum);
+        let _ = writeln!(out, "rust_ping_rtt_seconds_count{{{}}} {}", labels, series.received);
+    }
+
+    counter_or_gauge(&mut out, metrics, "rust_ping_packets_sent_total", "counter", "Probes sent.", |s| s.sent as f64);
+    counter_or_gauge(&mut out, metrics, "rust_ping_packets_received_total", "counter", "Probes answered.", |s| {
+        s.received as f64
+    });
+    counter_or_gauge(&mut out, metrics, "rust_ping_packet_loss_ratio", "gauge", "Share of the probes sent that were lost.", |s| {
+        if s.sent == 0 { 0.0 } else { (s.sent - s.received) as f64 / s.sent as f64 }
+    });
+    counter_or_gauge(&mut out, metrics, "rust_ping_up", "gauge", "Whether the last probe was answered.", |s| {
+        if s.up { 1.0 } else { 0.0 }
+    });
+    out
+}
+
+/// One value per target of a metric that is a single number
+fn counter_or_gauge(out: &mut String, metrics: &Metrics, name: &str, kind: &str, help: &str, value: impl Fn(&Series) -> f64) {
+    let _ = writeln!(out, "# HELP {} {}\n# TYPE {} {}", name, help, name, kind);
+    for ((host, ip), series) in metrics {
+        let _ = writeln!(out, "{}{{{}}} {}", name, labels(host, ip), value(series));
+    }
+}
+
+/// Labels of a target's series
+fn labels(host: &str, ip: &str) -> String {
+    format!("host=\"{}\",ip=\"{}\"", escape(host), escape(ip))
+}
@@ -0,0 +1,116 @@
+//! Redis pub/sub publishing with optional RedisTimeSeries samples.
+//!
+//! Samples carry the time their probe was sent. A lost connection is opened
+//! again at most every few seconds, so an unreachable server doesn't hold up
+//! every probe for the connect timeout.
+
+use chrono::Utc;
+use std::time::{Duration, Instant};
+
+use super::{ProbeEvent, Sink, SummaryEvent, Target};
+use crate::{PingResult, PingStatistics};
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+// Wait after a failed connect before trying again
+const RETRY: Duration = Duration::from_secs(10);
+
+pub struct RedisSink {
+    client: ::redis::Client,
+    // Dropped after an error and re-established on a later send
+    conn: Option<::redis::Connection>,
+    // No connect is tried before this after one failed
+    retry_at: Instant,
+    channel: String,
+    ts_prefix: Option<String>,
+}
+
+impl RedisSink {
+    pub fn connect(url: &str, channel: &str, ts_prefix: Option<&str>) -> Result<Self, String> {
+        let client = ::redis::Client::open(url)
+            .map_err(|e| format!("Invalid Redis URL '{}': {}", url, e))?;
+        let conn = client
+            .get_connection_with_timeout(CONNECT_TIMEOUT)
+            .map_err(|e| format!("Failed to connect to Redis at '{}': {}", url, e))?;
+
+        Ok(RedisSink {
+            client,
+            conn: Some(conn),
+            retry_at: Instant::now(),
+            channel: channel.to_string(),
+            ts_prefix: ts_prefix.map(str::to_string),
+        })
+    }
+
+    fn run(&mut self, commands: &::redis::Pipeline) -> Result<(), String> {
+        if self.conn.is_none() {
+            if Instant::now() < self.retry_at {
+                return Err("not connected, waiting to try again".to_string());
+            }
+            match self.client.get_connection_with_timeout(CONNECT_TIMEOUT) {
+                Ok(conn) => self.conn = Some(conn),
+                Err(e) => {
+                    self.retry_at = Instant::now() + RETRY;
+                    return Err(e.to_string());
+                }
+            }
+        }
+        let conn = self.conn.as_mut().expect("connection was just established");
+        commands.exec(conn).map_err(|e| {
+            self.conn = None;
+            e.to_string()
+        })
+    }
+}
+
+/// `TS.ADD` creating the series with descriptive labels on first use
+fn ts_add(pipe: &mut ::redis::Pipeline, key: String, ts: i64, value: f64, host: &str, ip: &str, metric: &str) {
+    pipe.cmd("TS.ADD")
+        .arg(key)
+        .arg(ts)
+        .arg(value)
+        .arg("ON_DUPLICATE")
+        .arg("LAST")
+        .arg("LABELS")
+        .arg("host")
+        .arg(host)
+        .arg("ip")
+        .arg(ip)
+        .arg("metric")
+        .arg(metric)
+        .ignore();
+}
+
+impl Sink for RedisSink {
+    fn name(&self) -> &'static str {
+        "Redis"
+    }
+
+    fn send_result(&mut self, target: &Target, result: &PingResult) -> Result<(), String> {
+        let payload = serde_json::to_string(&ProbeEvent::new(target, result))
+            .map_err(|e| e.to_string())?;
+
+        let mut pipe = ::redis::pipe();
+        pipe.cmd("PUBLISH").arg(&self.channel).arg(payload).ignore();
+
+        if let Some(prefix) = &self.ts_prefix {
+            let ts = result.timestamp.map_or_else(|| Utc::now().timestamp_millis(), |time| time.timestamp_millis());
+            let (host, ip) = (target.host, target.addr.to_string());
+            if let Some(rtt) = result.rtt_ms {
+                ts_add(&mut pipe, format!("{}:{}:rtt", prefix, host), ts, rtt, host, &ip, "rtt_ms");
+            }
+            let up = if result.success() { 1.0 } else { 0.0 };
+            ts_add(&mut pipe, format!("{}:{}:up", prefix, host), ts, up, host, &ip, "up");
+        }
+
+        self.run(&pipe)
+    }
+
+    fn send_summary(&mut self, target: &Target, stats: &PingStatistics) -> Result<(), String> {
+        let payload = serde_json::to_string(&SummaryEvent::new(target, stats))
+            .map_err(|e| e.to_string())?;
+
+        let mut pipe = ::redis::pipe();
+        pipe.cmd("PUBLISH").arg(&self.channel).arg(payload).ignore();
+        self.run(&pipe)
+    }
+}
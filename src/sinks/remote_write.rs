@@ -0,0 +1,206 @@
+//! Prometheus remote_write push.
+//!
+//! Samples are buffered and pushed as a snappy-compressed protobuf
+//! `WriteRequest` every few seconds and when a target finishes, so probes
+//! behind NAT or firewalls can feed Prometheus-compatible storage directly.
+//! Samples carry the time their probe was sent, and pushes go out from a
+//! thread of their own, so a slow endpoint never holds up the probes.
+
+use chrono::Utc;
+use prost::Message;
+use std::collections::BTreeMap;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use super::{http_agent, parse_headers, Sink, Target, CLOSE_TIMEOUT};
+use crate::{PingResult, PingStatistics};
+
+#[derive(Clone, PartialEq, Message)]
+struct WriteRequest {
+    #[prost(message, repeated, tag = "1")]
+    timeseries: Vec<TimeSeries>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct TimeSeries {
+    #[prost(message, repeated, tag = "1")]
+    labels: Vec<Label>,
+    #[prost(message, repeated, tag = "2")]
+    samples: Vec<Sample>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct Label {
+    #[prost(string, tag = "1")]
+    name: String,
+    #[prost(string, tag = "2")]
+    value: String,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct Sample {
+    #[prost(double, tag = "1")]
+    value: f64,
+    #[prost(int64, tag = "2")]
+    timestamp: i64,
+}
+
+// Buffered samples are dropped beyond this while the endpoint is unreachable
+const MAX_PENDING_SAMPLES: usize = 100_000;
+
+// Series identity: metric name, host, ip (sorted label order is name, host, ip)
+type SeriesKey = (&'static str, String, String);
+type Samples = BTreeMap<SeriesKey, Vec<Sample>>;
+
+/// Sends the samples handed over by the sink, from a thread of its own
+struct Pusher {
+    url: String,
+    headers: Vec<(String, String)>,
+    agent: ureq::Agent,
+    pending: Samples,
+}
+
+impl Pusher {
+    fn push(&mut self) -> Result<(), String> {
+        if self.pending.is_empty() {
+            return Ok(());
+        }
+
+        let request = WriteRequest {
+            timeseries: self
+                .pending
+                .iter()
+                .map(|((metric, host, ip), samples)| TimeSeries {
+                    labels: vec![
+                        Label { name: "__name__".into(), value: metric.to_string() },
+                        Label { name: "host".into(), value: host.clone() },
+                        Label { name: "ip".into(), value: ip.clone() },
+                    ],
+                    samples: samples.clone(),
+                })
+                .collect(),
+        };
+        let body = snap::raw::Encoder::new()
+            .compress_vec(&request.encode_to_vec())
+            .map_err(|e| e.to_string())?;
+
+        let mut req = self
+            .agent
+            .post(&self.url)
+            .header("Content-Encoding", "snappy")
+            .header("Content-Type", "application/x-protobuf")
+            .header("X-Prometheus-Remote-Write-Version", "0.1.0");
+        for (name, value) in &self.headers {
+            req = req.header(name, value);
+        }
+        // Samples are kept and retried with the next push if sending failed
+        if let Err(e) = req.send(&body[..]) {
+            let buffered: usize = self.pending.values().map(Vec::len).sum();
+            if buffered > MAX_PENDING_SAMPLES {
+                self.pending.clear();
+            }
+            return Err(e.to_string());
+        }
+
+        self.pending.clear();
+        Ok(())
+    }
+}
+
+pub struct RemoteWriteSink {
+    interval: Duration,
+    // Samples since the last hand-over to the pusher
+    batch: Samples,
+    last_push: Instant,
+    // Dropped on close so the pusher sends what it has and exits
+    queue: Option<Sender<Samples>>,
+    done: Receiver<()>,
+    // Why the last push failed, until one succeeds
+    failing: Arc<Mutex<Option<String>>>,
+}
+
+impl RemoteWriteSink {
+    pub fn new(url: &str, headers: &[String], interval: Duration) -> Result<Self, String> {
+        let mut pusher = Pusher {
+            url: url.to_string(),
+            headers: parse_headers(headers)?,
+            agent: http_agent(),
+            pending: BTreeMap::new(),
+        };
+        let failing = Arc::new(Mutex::new(None));
+        let outcome = failing.clone();
+        let (queue, batches) = mpsc::channel::<Samples>();
+        let (done_tx, done) = mpsc::channel();
+        thread::Builder::new()
+            .name("remote_write".to_string())
+            .spawn(move || {
+                for batch in batches {
+                    for (key, samples) in batch {
+                        pusher.pending.entry(key).or_default().extend(samples);
+                    }
+                    *outcome.lock().unwrap() = pusher.push().err();
+                }
+                let _ = done_tx.send(());
+            })
+            .map_err(|e| format!("Failed to start remote_write sender: {}", e))?;
+
+        Ok(RemoteWriteSink {
+            interval,
+            batch: BTreeMap::new(),
+            last_push: Instant::now(),
+            queue: Some(queue),
+            done,
+            failing,
+        })
+    }
+
+    fn add(&mut self, metric: &'static str, target: &Target, timestamp: i64, value: f64) {
+        let key = (metric, target.host.to_string(), target.addr.to_string());
+        self.batch.entry(key).or_default().push(Sample { value, timestamp });
+    }
+
+    /// Hand the batch to the pusher; the outcome of the push before is returned
+    fn push(&mut self) -> Result<(), String> {
+        self.last_push = Instant::now();
+        if !self.batch.is_empty() {
+            let batch = std::mem::take(&mut self.batch);
+            if let Some(queue) = &self.queue {
+                queue.send(batch).map_err(|_| "sender stopped".to_string())?;
+            }
+        }
+        self.failing.lock().unwrap().clone().map_or(Ok(()), Err)
+    }
+}
+
+impl Sink for RemoteWriteSink {
+    fn name(&self) -> &'static str {
+        "remote_write"
+    }
+
+    fn send_result(&mut self, target: &Target, result: &PingResult) -> Result<(), String> {
+        // Samples are placed at the time their probe was sent
+        let timestamp = result.timestamp.map_or_else(|| Utc::now().timestamp_millis(), |time| time.timestamp_millis());
+        if let Some(rtt) = result.rtt_ms {
+            self.add("rust_ping_rtt_seconds", target, timestamp, rtt / 1000.0);
+        }
+        self.add("rust_ping_up", target, timestamp, if result.success() { 1.0 } else { 0.0 });
+
+        if self.last_push.elapsed() >= self.interval {
+            return self.push();
+        }
+        Ok(())
+    }
+
+    fn send_summary(&mut self, target: &Target, stats: &PingStatistics) -> Result<(), String> {
+        self.add("rust_ping_packet_loss_ratio", target, Utc::now().timestamp_millis(), stats.packet_loss_percent / 100.0);
+        self.push()
+    }
+
+    fn close(&mut self) {
+        if self.queue.take().is_some() {
+            let _ = self.done.recv_timeout(CLOSE_TIMEOUT);
+        }
+    }
+}
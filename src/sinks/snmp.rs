@@ -0,0 +1,182 @@
+//! SNMPv2c traps for alerts.
+//!
+//! Objects live under net-snmp's experimental arc
+//! (NET-SNMP-MIB::netSnmpPlaypen, 1.3.6.1.4.1.8072.9999.9999) until the
+//! project has an enterprise number of its own:
+//!
+//! - `.1.0.<n>` notifications: 1 down, 2 up, 3 rttHigh, 4 rttNormal,
+//!   5 lossHigh, 6 lossNormal
+//! - `.1.1.<n>` varbinds: 1 host, 2 ip, 3 loss percent, 4 average RTT (ms),
+//!   5 last RTT (ms), 6 threshold, 7 problem duration (s), 8 run id
+//!
+//! Numeric values are sent as DisplayStrings so trap receivers can show them
+//! without a MIB.
+
+use std::net::{SocketAddr, UdpSocket};
+use std::time::Instant;
+
+use super::{resolve, Sink, Target};
+use crate::alerts::{Alert, AlertKind};
+use crate::PingResult;
+
+const DEFAULT_PORT: u16 = 162;
+
+const SYS_UPTIME: &[u32] = &[1, 3, 6, 1, 2, 1, 1, 3, 0];
+const SNMP_TRAP_OID: &[u32] = &[1, 3, 6, 1, 6, 3, 1, 1, 4, 1, 0];
+const RUST_PING: &[u32] = &[1, 3, 6, 1, 4, 1, 8072, 9999, 9999, 1];
+
+// BER tags
+const INTEGER: u8 = 0x02;
+const OCTET_STRING: u8 = 0x04;
+const OBJECT_ID: u8 = 0x06;
+const SEQUENCE: u8 = 0x30;
+const TIME_TICKS: u8 = 0x43;
+const TRAP_V2: u8 = 0xa7;
+
+pub struct SnmpTrapSink {
+    socket: UdpSocket,
+    dest: SocketAddr,
+    community: String,
+    started: Instant,
+    request_id: i32,
+}
+
+fn tlv(tag: u8, content: &[u8]) -> Vec<u8> {
+    let mut out = vec![tag];
+    let len = content.len();
+    if len < 0x80 {
+        out.push(len as u8);
+    } else {
+        let bytes: Vec<u8> = len.to_be_bytes().into_iter().skip_while(|b| *b == 0).collect();
+        out.push(0x80 | bytes.len() as u8);
+        out.extend(bytes);
+    }
+    out.extend_from_slice(content);
+    out
+}
+
+/// Minimal two's complement encoding
+fn integer(tag: u8, value: i64) -> Vec<u8> {
+    let bytes = value.to_be_bytes();
+    let mut start = 0;
+    while start < 7 {
+        let (b, next) = (bytes[start], bytes[start + 1]);
+        if (b == 0x00 && next & 0x80 == 0) || (b == 0xff && next & 0x80 != 0) {
+            start += 1;
+        } else {
+            break;
+        }
+    }
+    tlv(tag, &bytes[start..])
+}
+
+fn oid(arcs: &[u32]) -> Vec<u8> {
+    let mut content = vec![(arcs[0] * 40 + arcs[1]) as u8];
+    for &arc in &arcs[2..] {
+        let mut chunk = vec![(arc & 0x7f) as u8];
+        let mut rest = arc >> 7;
+        while rest > 0 {
+            chunk.push((rest & 0x7f) as u8 | 0x80);
+            rest >>= 7;
+        }
+        content.extend(chunk.iter().rev());
+    }
+    tlv(OBJECT_ID, &content)
+}
+
+fn child(base: &[u32], suffix: &[u32]) -> Vec<u32> {
+    base.iter().chain(suffix).copied().collect()
+}
+
+fn varbind(name: &[u32], value: Vec<u8>) -> Vec<u8> {
+    let mut content = oid(name);
+    content.extend(value);
+    tlv(SEQUENCE, &content)
+}
+
+fn notification(kind: AlertKind) -> u32 {
+    match kind {
+        AlertKind::Down => 1,
+        AlertKind::Up => 2,
+        AlertKind::RttHigh => 3,
+        AlertKind::RttNormal => 4,
+        AlertKind::LossHigh => 5,
+        AlertKind::LossNormal => 6,
+    }
+}
+
+impl SnmpTrapSink {
+    pub fn new(dest: &str, community: &str) -> Result<Self, String> {
+        let dest = resolve(dest, DEFAULT_PORT, "SNMP trap receiver")?;
+        let bind = if dest.is_ipv6() { "[::]:0" } else { "0.0.0.0:0" };
+        let socket = UdpSocket::bind(bind)
+            .map_err(|e| format!("Failed to open SNMP trap socket: {}", e))?;
+
+        Ok(SnmpTrapSink {
+            socket,
+            dest,
+            community: community.to_string(),
+            started: Instant::now(),
+            request_id: 0,
+        })
+    }
+
+    fn trap(&mut self, target: &Target, alert: &Alert) -> Vec<u8> {
+        let objects = child(RUST_PING, &[1]);
+        let text = |arc: u32, value: String| {
+            varbind(&child(&objects, &[arc]), tlv(OCTET_STRING, value.as_bytes()))
+        };
+        let uptime = (self.started.elapsed().as_millis() / 10) as i64;
+        self.request_id = self.request_id.wrapping_add(1);
+
+        let mut varbinds = varbind(SYS_UPTIME, integer(TIME_TICKS, uptime & 0xffff_ffff));
+        varbinds.extend(varbind(
+            SNMP_TRAP_OID,
+            oid(&child(RUST_PING, &[0, notification(alert.kind)])),
+        ));
+        varbinds.extend(text(1, target.host.to_string()));
+        varbinds.extend(text(2, target.addr.to_string()));
+        varbinds.extend(text(3, format!("{:.2}", alert.loss_percent)));
+        if let Some(avg) = alert.avg_rtt_ms {
+            varbinds.extend(text(4, format!("{:.2}", avg)));
+        }
+        if let Some(last) = alert.last_rtt_ms {
+            varbinds.extend(text(5, format!("{:.2}", last)));
+        }
+        if let Some(threshold) = alert.threshold {
+            varbinds.extend(text(6, format!("{}", threshold)));
+        }
+        if let Some(duration) = alert.duration_seconds {
+            varbinds.extend(text(7, format!("{:.2}", duration)));
+        }
+        varbinds.extend(text(8, target.run_id.to_string()));
+
+        let mut pdu = integer(INTEGER, self.request_id as i64);
+        pdu.extend(integer(INTEGER, 0)); // error-status
+        pdu.extend(integer(INTEGER, 0)); // error-index
+        pdu.extend(tlv(SEQUENCE, &varbinds));
+
+        let mut message = integer(INTEGER, 1); // SNMPv2c
+        message.extend(tlv(OCTET_STRING, self.community.as_bytes()));
+        message.extend(tlv(TRAP_V2, &pdu));
+        tlv(SEQUENCE, &message)
+    }
+}
+
+impl Sink for SnmpTrapSink {
+    fn name(&self) -> &'static str {
+        "SNMP trap"
+    }
+
+    fn send_result(&mut self, _target: &Target, _result: &PingResult) -> Result<(), String> {
+        Ok(())
+    }
+
+    fn send_alert(&mut self, target: &Target, alert: &Alert) -> Result<(), String> {
+        let trap = self.trap(target, alert);
+        self.socket
+            .send_to(&trap, self.dest)
+            .map(|_| ())
+            .map_err(|e| e.to_string())
+    }
+}
@@ -0,0 +1,137 @@
+//! SQLite storage of every probe.
+//!
+//! With `--sqlite FILE` (and always in `monitor` runs) each result is stored
+//! with its target, time, RTT and outcome, so `report query` can tell loss
+//! and latency over any window later on. Rows are written in a transaction
+//! about once a second, and the database is in WAL mode so queries can read
+//! it while a monitor keeps writing.
+
+use chrono::Local;
+use rusqlite::{params, Connection};
+use std::fs;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use super::{Sink, Target};
+use crate::{PingResult, PingStatistics};
+
+// How often stored results are committed
+const COMMIT_INTERVAL: Duration = Duration::from_secs(1);
+
+// Rows are dropped beyond this while the database can't be written
+const MAX_PENDING_ROWS: usize = 100_000;
+
+const SCHEMA: &str = "PRAGMA journal_mode = WAL;
+CREATE TABLE IF NOT EXISTS probes (
+    id INTEGER PRIMARY KEY,
+    run_id TEXT NOT NULL,
+    host TEXT NOT NULL,
+    ip TEXT NOT NULL,
+    time_ms INTEGER NOT NULL,
+    seq INTEGER NOT NULL,
+    rtt_ms REAL,
+    status TEXT NOT NULL,
+    icmp_code INTEGER,
+    ttl INTEGER
+);
+CREATE INDEX IF NOT EXISTS probes_time ON probes (time_ms);
+CREATE INDEX IF NOT EXISTS probes_host ON probes (host, time_ms);";
+
+/// Open the database at `path`, creating it and its tables when missing
+pub fn open(path: &str) -> Result<Connection, String> {
+    if let Some(dir) = Path::new(path).parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        fs::create_dir_all(dir).map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+    }
+    let conn = Connection::open(path).map_err(|e| format!("Failed to open {}: {}", path, e))?;
+    conn.execute_batch(SCHEMA).map_err(|e| format!("Failed to create the tables in {}: {}", path, e))?;
+    Ok(conn)
+}
+
+/// A result waiting to be committed
+struct Row {
+    run_id: String,
+    host: String,
+    ip: String,
+    time_ms: i64,
+    result: PingResult,
+}
+
+pub struct SqliteSink {
+    conn: Connection,
+    pending: Vec<Row>,
+    last_commit: Instant,
+}
+
+impl SqliteSink {
+    pub fn new(path: &str) -> Result<Self, String> {
+        Ok(SqliteSink { conn: open(path)?, pending: Vec::new(), last_commit: Instant::now() })
+    }
+
+    fn commit(&mut self) -> Result<(), String> {
+        self.last_commit = Instant::now();
+        if self.pending.is_empty() {
+            return Ok(());
+        }
+        let stored = (|| {
+            let transaction = self.conn.transaction()?;
+            {
+                let mut insert = transaction.prepare_cached(
+                    "INSERT INTO probes (run_id, host, ip, time_ms, seq, rtt_ms, status, icmp_code, ttl)
+                     VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+                )?;
+                for row in &self.pending {
+                    insert.execute(params![
+                        row.run_id,
+                        row.host,
+                        row.ip,
+                        row.time_ms,
+                        row.result.seq,
+                        row.result.rtt_ms,
+                        row.result.status.name(),
+                        row.result.status.code(),
+                        row.result.ttl,
+                    ])?;
+                }
+            }
+            transaction.commit()
+        })();
+        // Rows are kept and retried with the next commit if the database was busy or full
+        if let Err(e) = stored {
+            if self.pending.len() > MAX_PENDING_ROWS {
+                self.pending.clear();
+            }
+            return Err(e.to_string());
+        }
+        self.pending.clear();
+        Ok(())
+    }
+}
+
+impl Sink for SqliteSink {
+    fn name(&self) -> &'static str {
+        "SQLite"
+    }
+
+    fn send_result(&mut self, target: &Target, result: &PingResult) -> Result<(), String> {
+        let time = result.timestamp.unwrap_or_else(Local::now);
+        self.pending.push(Row {
+            run_id: target.run_id.to_string(),
+            host: target.host.to_string(),
+            ip: target.addr.to_string(),
+            time_ms: time.timestamp_millis(),
+            result: result.clone(),
+        });
+        if self.last_commit.elapsed() >= COMMIT_INTERVAL {
+            self.commit()?;
+        }
+        Ok(())
+    }
+
+    fn send_summary(&mut self, _target: &Target, _stats: &PingStatistics) -> Result<(), String> {
+        self.commit()
+    }
+
+    fn close(&mut self) {
+        let _ = self.commit();
+    }
+}
@@ -0,0 +1,86 @@
+//! StatsD / DogStatsD metrics over UDP.
+//!
+//! Every probe sends one datagram to `--statsd` with
+//!
+//! - `<prefix>.rtt:<ms>|ms` for a reply
+//! - `<prefix>.sent:1|c` and `<prefix>.received:1|c` or `<prefix>.lost:1|c`
+//!
+//! tagged DogStatsD style with `host`, `ip` and the `--statsd-tag`s. For
+//! servers that don't take tags, `--statsd-plain` puts the host in the
+//! metric name instead (`<prefix>.<host>.rtt`), with dots and other
+//! punctuation made `_` so it stays one component.
+
+use std::net::{SocketAddr, UdpSocket};
+
+use super::{resolve, Sink, Target};
+use crate::PingResult;
+
+const DEFAULT_PORT: u16 = 8125;
+
+pub struct StatsdSink {
+    socket: UdpSocket,
+    dest: SocketAddr,
+    prefix: String,
+    // Tags of every metric besides host and ip, already joined; None for plain StatsD
+    tags: Option<String>,
+}
+
+/// Metric name component derived from the host name
+fn component(host: &str) -> String {
+    host.chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '-' { c } else { '_' })
+        .collect()
+}
+
+/// Tag value without the characters that separate tags or values
+fn tag_value(value: &str) -> String {
+    value.chars().map(|c| if matches!(c, ',' | '|' | '#') { '_' } else { c }).collect()
+}
+
+impl StatsdSink {
+    pub fn new(dest: &str, prefix: &str, tags: &[String], plain: bool) -> Result<Self, String> {
+        let dest = resolve(dest, DEFAULT_PORT, "StatsD server")?;
+        let bind = if dest.is_ipv6() { "[::]:0" } else { "0.0.0.0:0" };
+        let socket = UdpSocket::bind(bind).map_err(|e| format!("Failed to open StatsD socket: {}", e))?;
+
+        Ok(StatsdSink {
+            socket,
+            dest,
+            prefix: prefix.trim_end_matches('.').to_string(),
+            tags: (!plain).then(|| tags.iter().map(|tag| tag_value(tag)).collect::<Vec<_>>().join(",")),
+        })
+    }
+}
+
+impl Sink for StatsdSink {
+    fn name(&self) -> &'static str {
+        "StatsD"
+    }
+
+    fn send_result(&mut self, target: &Target, result: &PingResult) -> Result<(), String> {
+        let (prefix, suffix) = match &self.tags {
+            Some(tags) => {
+                let mut suffix = format!("|#host:{},ip:{}", tag_value(target.host), target.addr);
+                if !tags.is_empty() {
+                    suffix.push(',');
+                    suffix.push_str(tags);
+                }
+                (self.prefix.clone(), suffix)
+            }
+            None => (format!("{}.{}", self.prefix, component(target.host)), String::new()),
+        };
+
+        let mut lines = vec![format!("{}.sent:1|c{}", prefix, suffix)];
+        match result.rtt_ms {
+            Some(rtt) => {
+                lines.push(format!("{}.rtt:{}|ms{}", prefix, rtt, suffix));
+                lines.push(format!("{}.received:1|c{}", prefix, suffix));
+            }
+            None => lines.push(format!("{}.lost:1|c{}", prefix, suffix)),
+        }
+        self.socket
+            .send_to(lines.join("\n").as_bytes(), self.dest)
+            .map(|_| ())
+            .map_err(|e| e.to_string())
+    }
+}
@@ -0,0 +1,52 @@
+//! Alerts shown where the run is watched.
+//!
+//! In text output every alert is printed among the probe lines, problems
+//! highlighted in red and recoveries in green, so a threshold crossed in a
+//! long run stands out without a notification service. `--alert-bell` rings
+//! the terminal bell as a problem starts.
+
+use colored::*;
+use std::io::{self, Write};
+
+use super::{Sink, Target};
+use crate::alerts::{describe, Alert};
+use crate::PingResult;
+
+pub struct TerminalAlertSink {
+    show: bool,
+    bell: bool,
+}
+
+impl TerminalAlertSink {
+    pub fn new(show: bool, bell: bool) -> Self {
+        TerminalAlertSink { show, bell }
+    }
+}
+
+impl Sink for TerminalAlertSink {
+    fn name(&self) -> &'static str {
+        "Terminal"
+    }
+
+    fn send_result(&mut self, _target: &Target, _result: &PingResult) -> Result<(), String> {
+        Ok(())
+    }
+
+    fn send_alert(&mut self, target: &Target, alert: &Alert) -> Result<(), String> {
+        let addr = target.addr.to_string();
+        let name = if target.host == addr { addr } else { format!("{} ({})", target.host, addr) };
+        if self.show {
+            if alert.kind.is_problem() {
+                println!("  {} {} {}", "🚨".red(), format!(" ALERT {} ", name).on_red().white().bold(), describe(alert).red().bold());
+            } else {
+                println!("  {} {} {}", "✓".green(), name.green().bold(), describe(alert).green());
+            }
+        }
+        // The bell goes to stderr so it isn't taken into redirected output
+        if self.bell && alert.kind.is_problem() {
+            eprint!("\x07");
+            let _ = io::stderr().flush();
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,121 @@
+//! `--webhook` notifications.
+//!
+//! Every alert is POSTed as JSON to each URL given: Slack and Discord
+//! incoming webhooks (told by their address) get the message the chat sinks
+//! send, any other URL the alert as an event with the target it's about.
+//! With `--webhook-debounce` a problem is only posted once it has lasted that
+//! long, and one that's over sooner is never posted, nor its recovery, so a
+//! short blip doesn't page anyone.
+
+use serde_json::{json, Value};
+use std::collections::{HashMap, HashSet};
+use std::net::IpAddr;
+use std::time::{Duration, Instant};
+
+use super::chat::message;
+use super::{AlertEvent, Poster, Sink, Target};
+use crate::alerts::Alert;
+use crate::PingResult;
+
+enum Format {
+    Slack,
+    Discord,
+    Generic,
+}
+
+impl Format {
+    fn of(url: &str) -> Self {
+        let address = url.split_once("://").map_or(url, |(_, rest)| rest);
+        if address.starts_with("hooks.slack.com/") {
+            Format::Slack
+        } else if address.starts_with("discord.com/api/webhooks/") || address.starts_with("discordapp.com/api/webhooks/") {
+            Format::Discord
+        } else {
+            Format::Generic
+        }
+    }
+}
+
+/// A problem waiting out the debounce, with the target it's about
+struct Pending {
+    due: Instant,
+    run_id: String,
+    addr: IpAddr,
+    alert: Alert,
+}
+
+pub struct WebhookSink {
+    urls: Vec<(String, Format)>,
+    debounce: Duration,
+    // Problems of a host by their name ("down", "rtt", "loss"), until due
+    pending: HashMap<(String, &'static str), Pending>,
+    // Problems posted, whose recovery is posted too
+    posted: HashSet<(String, &'static str)>,
+    poster: Poster,
+}
+
+impl WebhookSink {
+    pub fn new(urls: &[String], debounce: Duration) -> Result<Self, String> {
+        Ok(WebhookSink {
+            urls: urls.iter().map(|url| (url.clone(), Format::of(url))).collect(),
+            debounce,
+            pending: HashMap::new(),
+            posted: HashSet::new(),
+            poster: Poster::spawn("Webhook")?,
+        })
+    }
+
+    fn post(&self, target: &Target, alert: &Alert) -> Result<(), String> {
+        for (url, format) in &self.urls {
+            let payload: Value = match format {
+                Format::Slack => json!({ "text": message(target, alert, "*") }),
+                Format::Discord => json!({ "content": message(target, alert, "**") }),
+                Format::Generic => serde_json::to_value(AlertEvent::new(target, alert)).map_err(|e| e.to_string())?,
+            };
+            self.poster.post(url, payload)?;
+        }
+        Ok(())
+    }
+}
+
+impl Sink for WebhookSink {
+    fn name(&self) -> &'static str {
+        "Webhook"
+    }
+
+    // Results only tell when a pending problem has lasted long enough
+    fn send_result(&mut self, _target: &Target, _result: &PingResult) -> Result<(), String> {
+        let now = Instant::now();
+        let due: Vec<_> = self.pending.iter().filter(|(_, pending)| pending.due <= now).map(|(key, _)| key.clone()).collect();
+        for key in due {
+            let Some(pending) = self.pending.remove(&key) else { continue };
+            let target = Target { run_id: &pending.run_id, host: &key.0, addr: pending.addr };
+            self.post(&target, &pending.alert)?;
+            self.posted.insert(key);
+        }
+        Ok(())
+    }
+
+    fn send_alert(&mut self, target: &Target, alert: &Alert) -> Result<(), String> {
+        let key = (target.host.to_string(), alert.kind.problem());
+        if alert.kind.is_problem() {
+            if self.debounce.is_zero() {
+                self.posted.insert(key);
+                return self.post(target, alert);
+            }
+            let pending =
+                Pending { due: Instant::now() + self.debounce, run_id: target.run_id.to_string(), addr: target.addr, alert: alert.clone() };
+            self.pending.insert(key, pending);
+            return Ok(());
+        }
+        // A problem over within the debounce is dropped along with its recovery
+        if self.pending.remove(&key).is_some() || !self.posted.remove(&key) {
+            return Ok(());
+        }
+        self.post(target, alert)
+    }
+
+    fn close(&mut self) {
+        self.poster.close();
+    }
+}
@@ -0,0 +1,168 @@
+//! Zabbix sender (trapper) protocol.
+//!
+//! Results are pushed to a Zabbix server or proxy as values of trapper items
+//! of the host named with `--zabbix-host`, two items per target:
+//!
+//! - `rust_ping.rtt[<target>]`: the RTT of each reply in ms
+//! - `rust_ping.loss[<target>]`: 0 for each reply and 100 for each lost
+//!   probe, so `avg()` over any period is the packet loss in percent
+//!
+//! Values carry the time of their probe and are sent after each probe, or in
+//! batches of half a second's worth at higher rates, and when a target ends.
+//! Values the server doesn't take (usually items that don't exist, or aren't
+//! of type "Zabbix trapper") are reported; while the server can't be reached
+//! they're kept and sent again every few seconds.
+
+use chrono::Local;
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::io::{self, Read, Write};
+use std::net::{SocketAddr, TcpStream};
+use std::time::{Duration, Instant};
+
+use super::{resolve, Sink, Target};
+use crate::{PingResult, PingStatistics};
+
+const DEFAULT_PORT: u16 = 10051;
+const TIMEOUT: Duration = Duration::from_secs(3);
+// Probes coming faster than this are sent together
+const BATCH: Duration = Duration::from_millis(500);
+// Wait after a failed send before trying again
+const RETRY: Duration = Duration::from_secs(10);
+// Values are dropped beyond this while the server is unreachable
+const MAX_PENDING_VALUES: usize = 100_000;
+// "ZBXD" and the protocol flags: plain JSON, no compression
+const HEADER: &[u8] = b"ZBXD\x01";
+
+/// One value of a trapper item
+#[derive(Serialize)]
+struct ItemValue {
+    host: String,
+    key: String,
+    value: String,
+    clock: i64,
+    ns: u32,
+}
+
+pub struct ZabbixSink {
+    server: SocketAddr,
+    host: String,
+    pending: Vec<ItemValue>,
+    next_push: Instant,
+    // Why the last send failed, until one succeeds
+    failing: Option<String>,
+}
+
+/// `rust_ping.<metric>[<target>]`, the target quoted when it has characters
+/// an unquoted key parameter can't
+fn key(metric: &str, target: &str) -> String {
+    if target.contains([',', '[', ']', '"']) || target.starts_with(' ') {
+        format!("rust_ping.{}[\"{}\"]", metric, target.replace('"', "\\\""))
+    } else {
+        format!("rust_ping.{}[{}]", metric, target)
+    }
+}
+
+/// Send one request and read the server's response
+fn exchange(server: SocketAddr, request: &[u8]) -> io::Result<Value> {
+    let mut stream = TcpStream::connect_timeout(&server, TIMEOUT)?;
+    stream.set_read_timeout(Some(TIMEOUT))?;
+    stream.set_write_timeout(Some(TIMEOUT))?;
+
+    let mut packet = HEADER.to_vec();
+    packet.extend_from_slice(&(request.len() as u64).to_le_bytes());
+    packet.extend_from_slice(request);
+    stream.write_all(&packet)?;
+
+    let mut header = [0u8; 13];
+    stream.read_exact(&mut header)?;
+    if &header[..4] != b"ZBXD" {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "not a Zabbix response"));
+    }
+    let len = u32::from_le_bytes(header[5..9].try_into().expect("4 bytes")) as usize;
+    let mut body = vec![0u8; len];
+    stream.read_exact(&mut body)?;
+    serde_json::from_slice(&body).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+impl ZabbixSink {
+    pub fn new(server: &str, host: &str) -> Result<Self, String> {
+        Ok(ZabbixSink { server: resolve(server, DEFAULT_PORT, "Zabbix server")?, host: host.to_string(), pending: Vec::new(), next_push: Instant::now(), failing: None })
+    }
+
+    fn add(&mut self, metric: &str, target: &Target, result: &PingResult, value: f64) {
+        let time = result.timestamp.unwrap_or_else(Local::now);
+        self.pending.push(ItemValue {
+            host: self.host.clone(),
+            key: key(metric, target.host),
+            value: value.to_string(),
+            clock: time.timestamp(),
+            ns: time.timestamp_subsec_nanos(),
+        });
+    }
+
+    fn push(&mut self) -> Result<(), String> {
+        if self.pending.is_empty() {
+            return self.failing.clone().map_or(Ok(()), Err);
+        }
+        let outcome = self.send();
+        self.failing = outcome.clone().err();
+        outcome
+    }
+
+    fn send(&mut self) -> Result<(), String> {
+        let request = json!({ "request": "sender data", "data": self.pending });
+        let request = serde_json::to_vec(&request).map_err(|e| e.to_string())?;
+
+        // Values are kept and sent again after a while if the server couldn't be reached
+        let response = match exchange(self.server, &request) {
+            Ok(response) => response,
+            Err(e) => {
+                self.next_push = Instant::now() + RETRY;
+                if self.pending.len() > MAX_PENDING_VALUES {
+                    self.pending.clear();
+                }
+                return Err(format!("{}: {}", self.server, e));
+            }
+        };
+        self.next_push = Instant::now() + BATCH;
+        let total = std::mem::take(&mut self.pending).len();
+
+        // "info" is like "processed: 2; failed: 0; total: 2; seconds spent: 0.000041"
+        let info = response["info"].as_str().unwrap_or_default();
+        if response["response"] != "success" {
+            return Err(format!("values refused: {}", info));
+        }
+        let failed = info.split(';').find_map(|part| part.trim().strip_prefix("failed: ")).unwrap_or("0");
+        if failed != "0" {
+            return Err(format!(
+                "{} of {} values refused, check that the trapper items exist on host '{}'",
+                failed, total, self.host
+            ));
+        }
+        Ok(())
+    }
+}
+
+impl Sink for ZabbixSink {
+    fn name(&self) -> &'static str {
+        "Zabbix"
+    }
+
+    fn send_result(&mut self, target: &Target, result: &PingResult) -> Result<(), String> {
+        if let Some(rtt) = result.rtt_ms {
+            self.add("rtt", target, result, rtt);
+        }
+        self.add("loss", target, result, if result.success() { 0.0 } else { 100.0 });
+
+        if Instant::now() >= self.next_push {
+            return self.push();
+        }
+        // Still failing while waiting to try again
+        self.failing.clone().map_or(Ok(()), Err)
+    }
+
+    fn send_summary(&mut self, _target: &Target, _stats: &PingStatistics) -> Result<(), String> {
+        self.push()
+    }
+}
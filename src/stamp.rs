@@ -0,0 +1,45 @@
+//! Send times carried in the payload.
+//!
+//! Every echo request of a ping carries the time it was written in the first
+//! 8 bytes of its payload, as iputils ping carries a timeval, on a monotonic
+//! clock that starts with the process. The reply echoes it back, so its RTT is
+//! taken from the request it really answers rather than from the probe it was
+//! matched to: a late reply to one probe is never timed against the send of
+//! another with the same sequence number, and replies need no send time kept
+//! for them to be timed. Payloads too short for a stamp, `--payload-file`
+//! payloads, which are sent as they are, and replies that don't echo the
+//! data are timed from the send of their probe as before.
+
+use std::sync::OnceLock;
+use std::time::{Duration, Instant, SystemTime};
+
+/// Bytes of the payload the stamp takes
+pub const STAMP_LEN: usize = 8;
+
+static EPOCH: OnceLock<Instant> = OnceLock::new();
+
+fn now() -> u64 {
+    EPOCH.get_or_init(Instant::now).elapsed().as_nanos() as u64
+}
+
+/// Stamp the start of `payload` with the time; false if it's too short for one
+pub fn write(payload: &mut [u8]) -> bool {
+    let Some(stamp) = payload.get_mut(..STAMP_LEN) else {
+        return false;
+    };
+    stamp.copy_from_slice(&now().to_be_bytes());
+    true
+}
+
+/// Time from the stamp an echoed payload starts with to `received`, the
+/// kernel's arrival time of the reply
+///
+/// Arrival times are on the wall clock, so the arrival is put on the
+/// monotonic one by how long ago it was; a stamp that isn't from before it
+/// was damaged on the way.
+pub fn rtt(payload: &[u8], received: SystemTime) -> Option<Duration> {
+    let stamp = u64::from_be_bytes(payload.get(..STAMP_LEN)?.try_into().ok()?);
+    let ago = SystemTime::now().duration_since(received).unwrap_or_default();
+    let arrived = now().checked_sub(ago.as_nanos() as u64)?;
+    arrived.checked_sub(stamp).map(Duration::from_nanos)
+}
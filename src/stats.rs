@@ -0,0 +1,257 @@
+//! Streaming RTT statistics.
+//!
+//! Mean and variance use Welford's online algorithm and percentiles come from
+//! a log-bucketed sketch with 1% relative error (the DDSketch layout), so the
+//! memory used stays constant however long a run lasts. Jitter follows
+//! RFC 3550: the difference between consecutive replies' RTTs, smoothed
+//! with a gain of 1/16. Lost probes are also counted by the run of losses
+//! in a row they belong to, since bursty loss has other causes than loss
+//! spread evenly.
+
+use std::collections::BTreeMap;
+
+use crate::{PingStatistics, ProbeStatus};
+
+/// Relative error of the percentiles
+const RELATIVE_ACCURACY: f64 = 0.01;
+/// RTTs below this (ms) share one bucket
+const MIN_TRACKED: f64 = 1e-6;
+
+fn round2(v: f64) -> f64 {
+    (v * 100.0).round() / 100.0
+}
+
+/// Counts per logarithmic bucket; bucket `i` holds values in (γ^(i-1), γ^i]
+#[derive(Clone, Debug)]
+pub struct QuantileSketch {
+    gamma: f64,
+    ln_gamma: f64,
+    buckets: BTreeMap<i32, u64>,
+    tiny: u64,
+    count: u64,
+}
+
+impl QuantileSketch {
+    pub fn new() -> Self {
+        let gamma = (1.0 + RELATIVE_ACCURACY) / (1.0 - RELATIVE_ACCURACY);
+        QuantileSketch { gamma, ln_gamma: gamma.ln(), buckets: BTreeMap::new(), tiny: 0, count: 0 }
+    }
+
+    pub fn add(&mut self, value: f64) {
+        self.count += 1;
+        if value <= MIN_TRACKED {
+            self.tiny += 1;
+        } else {
+            let index = (value.ln() / self.ln_gamma).ceil() as i32;
+            *self.buckets.entry(index).or_insert(0) += 1;
+        }
+    }
+
+    /// Value within 1% of every value in bucket `index`
+    fn representative(&self, index: i32) -> f64 {
+        2.0 * self.gamma.powi(index) / (self.gamma + 1.0)
+    }
+
+    /// Approximate value at quantile `q` (0.0..=1.0)
+    pub fn quantile(&self, q: f64) -> Option<f64> {
+        if self.count == 0 {
+            return None;
+        }
+        let rank = (q.clamp(0.0, 1.0) * (self.count - 1) as f64).floor() as u64;
+        if rank < self.tiny {
+            return Some(0.0);
+        }
+        let mut seen = self.tiny;
+        for (&index, &n) in &self.buckets {
+            seen += n;
+            if seen > rank {
+                return Some(self.representative(index));
+            }
+        }
+        None
+    }
+
+    /// Approximate number of values in `[low, high)`
+    pub fn count_between(&self, low: f64, high: f64) -> u64 {
+        let tiny = if low <= 0.0 && high > 0.0 { self.tiny } else { 0 };
+        tiny + self
+            .buckets
+            .iter()
+            .filter(|(&index, _)| {
+                let value = self.representative(index);
+                value >= low && value < high
+            })
+            .map(|(_, &n)| n)
+            .sum::<u64>()
+    }
+}
+
+/// Running min/max/mean/variance and percentiles of the replies of a run
+#[derive(Clone, Debug)]
+pub struct RttStats {
+    count: u64,
+    mean: f64,
+    m2: f64,
+    min: f64,
+    max: f64,
+    sketch: QuantileSketch,
+    // RTT of the previous reply, and the jitter and RTT deltas between consecutive replies
+    last: Option<f64>,
+    jitter: f64,
+    delta_sum: f64,
+    delta_max: f64,
+    corrupted: u32,
+    payload_corrupted: u32,
+    duplicate: u32,
+    reordered: u32,
+    timeout: u32,
+    unreachable: u32,
+    ttl_exceeded: u32,
+    parameter_problem: u32,
+    send_error: u32,
+    // Probes lost in a row up to now, and the runs of losses that ended, by length
+    streak: u32,
+    bursts: BTreeMap<u32, u32>,
+}
+
+impl RttStats {
+    pub fn new() -> Self {
+        RttStats {
+            count: 0,
+            mean: 0.0,
+            m2: 0.0,
+            min: f64::INFINITY,
+            max: f64::NEG_INFINITY,
+            sketch: QuantileSketch::new(),
+            last: None,
+            jitter: 0.0,
+            delta_sum: 0.0,
+            delta_max: 0.0,
+            corrupted: 0,
+            payload_corrupted: 0,
+            duplicate: 0,
+            reordered: 0,
+            timeout: 0,
+            unreachable: 0,
+            ttl_exceeded: 0,
+            parameter_problem: 0,
+            send_error: 0,
+            streak: 0,
+            bursts: BTreeMap::new(),
+        }
+    }
+
+    pub fn add(&mut self, rtt_ms: f64) {
+        self.count += 1;
+        let delta = rtt_ms - self.mean;
+        self.mean += delta / self.count as f64;
+        self.m2 += delta * (rtt_ms - self.mean);
+        self.min = self.min.min(rtt_ms);
+        self.max = self.max.max(rtt_ms);
+        self.sketch.add(rtt_ms);
+        // Lost probes in between don't count; the delta is to the previous reply
+        if let Some(last) = self.last {
+            let delta = (rtt_ms - last).abs();
+            self.jitter += (delta - self.jitter) / 16.0;
+            self.delta_sum += delta;
+            self.delta_max = self.delta_max.max(delta);
+        }
+        self.last = Some(rtt_ms);
+        if self.streak > 0 {
+            *self.bursts.entry(self.streak).or_default() += 1;
+            self.streak = 0;
+        }
+    }
+
+    /// Count a probe that got no reply, by cause
+    pub fn add_failure(&mut self, status: ProbeStatus) {
+        if status != ProbeStatus::Success {
+            self.streak += 1;
+        }
+        match status {
+            ProbeStatus::Success => {}
+            ProbeStatus::Timeout => self.timeout += 1,
+            ProbeStatus::Unreachable { .. } => self.unreachable += 1,
+            ProbeStatus::TtlExceeded => self.ttl_exceeded += 1,
+            ProbeStatus::ParameterProblem { .. } => self.parameter_problem += 1,
+            ProbeStatus::SendError => self.send_error += 1,
+        }
+    }
+
+    /// A reply was discarded because its checksum didn't add up
+    pub fn add_corrupted(&mut self) {
+        self.corrupted += 1;
+    }
+
+    /// A reply was counted, but its payload wasn't the one sent
+    pub fn add_payload_corrupted(&mut self) {
+        self.payload_corrupted += 1;
+    }
+
+    /// A further reply came to a probe that was answered already
+    pub fn add_duplicate(&mut self) {
+        self.duplicate += 1;
+    }
+
+    /// A reply came after that of a later probe
+    pub fn add_reordered(&mut self) {
+        self.reordered += 1;
+    }
+
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    /// Percentile, clamped to the exact min and max
+    pub fn quantile(&self, q: f64) -> Option<f64> {
+        self.sketch.quantile(q).map(|v| v.clamp(self.min, self.max))
+    }
+
+    pub fn sketch(&self) -> &QuantileSketch {
+        &self.sketch
+    }
+
+    /// Final statistics of a run that sent `sent` probes
+    pub fn statistics(&self, sent: u32) -> PingStatistics {
+        let received = self.count as u32;
+        let lost = sent.saturating_sub(received);
+        let loss = if sent == 0 { 100.0 } else { round2(lost as f64 / sent as f64 * 100.0) };
+        let replied = self.count > 0;
+        let value = |v: f64| replied.then(|| round2(v));
+        let deltas = self.count.saturating_sub(1);
+        let delta = |v: f64| (deltas > 0).then(|| round2(v));
+        // A run of losses the statistics end in counts as well
+        let mut bursts = self.bursts.clone();
+        if self.streak > 0 {
+            *bursts.entry(self.streak).or_default() += 1;
+        }
+
+        PingStatistics {
+            min_ms: value(self.min),
+            max_ms: value(self.max),
+            avg_ms: value(self.mean),
+            std_dev_ms: value((self.m2 / self.count.max(1) as f64).sqrt()),
+            p50_ms: self.quantile(0.50).map(round2),
+            p95_ms: self.quantile(0.95).map(round2),
+            p99_ms: self.quantile(0.99).map(round2),
+            jitter_ms: delta(self.jitter),
+            delta_avg_ms: delta(self.delta_sum / deltas.max(1) as f64),
+            delta_max_ms: delta(self.delta_max),
+            packets_sent: sent,
+            packets_received: received,
+            packets_lost: lost,
+            packets_timeout: self.timeout,
+            packets_unreachable: self.unreachable,
+            packets_ttl_exceeded: self.ttl_exceeded,
+            packets_parameter_problem: self.parameter_problem,
+            packets_send_error: self.send_error,
+            packets_corrupted: self.corrupted,
+            packets_payload_corrupted: self.payload_corrupted,
+            packets_duplicate: self.duplicate,
+            packets_reordered: self.reordered,
+            longest_loss_streak: bursts.keys().next_back().copied().unwrap_or(0),
+            loss_bursts: bursts,
+            packet_loss_percent: loss,
+        }
+    }
+}
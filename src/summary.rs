@@ -0,0 +1,171 @@
+//! Comparison table printed after pinging several targets, and the
+//! combined report of their statistics written with `--summary-json` and
+//! `--summary-csv`.
+
+use chrono::Local;
+use clap::ValueEnum;
+use colored::*;
+use comfy_table::presets::UTF8_FULL_CONDENSED;
+use comfy_table::modifiers::UTF8_ROUND_CORNERS;
+use comfy_table::{Cell, CellAlignment, Color, ContentArrangement, Table};
+use serde::Serialize;
+use std::cmp::Ordering;
+use std::net::IpAddr;
+
+use crate::export::{stats_values, write_atomic, CsvFormat, STATS_COLUMNS};
+use crate::PingStatistics;
+
+/// Column used to order the summary table
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum SortKey {
+    Host,
+    Sent,
+    Received,
+    Loss,
+    Min,
+    Avg,
+    Max,
+    Stddev,
+    Jitter,
+}
+
+/// Final statistics of one target
+pub struct TargetSummary {
+    pub host: String,
+    pub addr: IpAddr,
+    pub stats: PingStatistics,
+}
+
+// Targets without replies sort after every measured one
+fn cmp_opt(a: Option<f64>, b: Option<f64>) -> Ordering {
+    match (a, b) {
+        (Some(a), Some(b)) => a.total_cmp(&b),
+        (Some(_), None) => Ordering::Less,
+        (None, Some(_)) => Ordering::Greater,
+        (None, None) => Ordering::Equal,
+    }
+}
+
+/// Order of two targets, given as host and statistics, by `key`
+pub fn compare(key: SortKey, (a, x): (&str, &PingStatistics), (b, y): (&str, &PingStatistics)) -> Ordering {
+    match key {
+        SortKey::Host => a.cmp(b),
+        SortKey::Sent => y.packets_sent.cmp(&x.packets_sent),
+        SortKey::Received => y.packets_received.cmp(&x.packets_received),
+        SortKey::Loss => x.packet_loss_percent.total_cmp(&y.packet_loss_percent),
+        SortKey::Min => cmp_opt(x.min_ms, y.min_ms),
+        SortKey::Avg => cmp_opt(x.avg_ms, y.avg_ms),
+        SortKey::Max => cmp_opt(x.max_ms, y.max_ms),
+        SortKey::Stddev => cmp_opt(x.std_dev_ms, y.std_dev_ms),
+        SortKey::Jitter => cmp_opt(x.jitter_ms, y.jitter_ms),
+    }
+}
+
+fn sort_summaries(summaries: &mut [TargetSummary], key: SortKey) {
+    summaries.sort_by(|a, b| compare(key, (&a.host, &a.stats), (&b.host, &b.stats)));
+}
+
+pub fn ms_cell(value: Option<f64>) -> Cell {
+    match value {
+        Some(v) => Cell::new(format!("{:.2}", v)),
+        None => Cell::new("-"),
+    }
+    .set_alignment(CellAlignment::Right)
+}
+
+pub fn loss_cell(loss: f64) -> Cell {
+    let color = if loss == 0.0 {
+        Color::Green
+    } else if loss < 100.0 {
+        Color::Yellow
+    } else {
+        Color::Red
+    };
+    Cell::new(format!("{:.1}%", loss))
+        .fg(color)
+        .set_alignment(CellAlignment::Right)
+}
+
+/// Print all targets as one aligned table
+pub fn print_summary_table(summaries: &mut [TargetSummary], sort_by: Option<SortKey>) {
+    if let Some(key) = sort_by {
+        sort_summaries(summaries, key);
+    }
+
+    println!("\n{}", "╔════════════════════════════════════════════════════════════╗".blue());
+    println!("{}", "║                       📋 SUMMARY                            ║".blue());
+    println!("{}", "╚════════════════════════════════════════════════════════════╝".blue());
+
+    let mut table = Table::new();
+    table
+        .load_preset(UTF8_FULL_CONDENSED)
+        .apply_modifier(UTF8_ROUND_CORNERS)
+        .set_content_arrangement(ContentArrangement::Dynamic)
+        .set_header(vec![
+            "Host", "IP", "Sent", "Recv", "Loss", "Min ms", "Avg ms", "Max ms", "StdDev ms", "Jitter ms",
+        ]);
+
+    for summary in summaries.iter() {
+        let stats = &summary.stats;
+        table.add_row(vec![
+            Cell::new(&summary.host),
+            Cell::new(summary.addr),
+            Cell::new(stats.packets_sent).set_alignment(CellAlignment::Right),
+            Cell::new(stats.packets_received).set_alignment(CellAlignment::Right),
+            loss_cell(stats.packet_loss_percent),
+            ms_cell(stats.min_ms),
+            ms_cell(stats.avg_ms),
+            ms_cell(stats.max_ms),
+            ms_cell(stats.std_dev_ms),
+            ms_cell(stats.jitter_ms),
+        ]);
+    }
+
+    println!("{}", table);
+}
+
+/// One target of the combined JSON report
+#[derive(Serialize)]
+struct SummaryEntry<'a> {
+    host: &'a str,
+    ip_address: IpAddr,
+    statistics: &'a PingStatistics,
+}
+
+/// The combined JSON report
+#[derive(Serialize)]
+struct SummaryReport<'a> {
+    generated: String,
+    targets: Vec<SummaryEntry<'a>>,
+}
+
+/// Write the statistics of every target to one JSON file
+pub fn write_json(filename: &str, summaries: &[TargetSummary]) -> Result<(), String> {
+    let report = SummaryReport {
+        generated: Local::now().format("%Y-%m-%d %H:%M:%S").to_string(),
+        targets: summaries
+            .iter()
+            .map(|s| SummaryEntry { host: &s.host, ip_address: s.addr, statistics: &s.stats })
+            .collect(),
+    };
+    let json = serde_json::to_string_pretty(&report).map_err(|e| format!("Failed to serialize JSON: {}", e))?;
+    write_atomic(filename, format!("{}\n", json).as_bytes())
+}
+
+/// Write the statistics of every target to one CSV file, a row per target
+pub fn write_csv(filename: &str, summaries: &[TargetSummary]) -> Result<(), String> {
+    let mut csv = format!("host,ip_address,{}\n", STATS_COLUMNS);
+    for s in summaries {
+        csv.push_str(&format!("{},{},{}\n", csv_field(&s.host), s.addr, stats_values(&s.stats, &CsvFormat::default())));
+    }
+    write_atomic(filename, csv.as_bytes())
+}
+
+/// Quote a field holding a comma or quote, as URLs of --http may
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
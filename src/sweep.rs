@@ -0,0 +1,155 @@
+//! Payload size sweeps.
+//!
+//! With `--sweep MIN:MAX:STEP` each probe carries STEP bytes more payload
+//! than the one before, from MIN up to MAX and then from MIN again, and the
+//! RTT and loss of every size are shown at the end. Loss that starts at a
+//! size is where probes stop fitting through the path (or their fragments
+//! get dropped); the RTT growing with the size is the time the bytes take on
+//! the slowest link. That growth is fitted over the fastest RTT of each size,
+//! which queueing adds the least to, and an echo carries its payload both
+//! ways, so it gives a rough bandwidth of that link.
+
+use colored::*;
+use comfy_table::modifiers::UTF8_ROUND_CORNERS;
+use comfy_table::presets::UTF8_FULL_CONDENSED;
+use comfy_table::{Cell, CellAlignment, ContentArrangement, Table};
+use rust_ping::SizeStats;
+
+use crate::stats::RttStats;
+use crate::summary::{loss_cell, ms_cell};
+
+/// Largest echo payload that fits in an IPv4 datagram
+pub const MAX_PAYLOAD: usize = 65507;
+
+/// Payload sizes to sweep, as given on the command line
+#[derive(Clone, Copy, Debug)]
+pub struct SweepRange {
+    min: usize,
+    max: usize,
+    step: usize,
+}
+
+/// Parse MIN:MAX:STEP, in bytes of payload
+pub fn parse_sweep(value: &str) -> Result<SweepRange, String> {
+    let invalid = |reason: &str| format!("invalid sweep '{}': {}", value, reason);
+    let parts: Vec<&str> = value.split(':').collect();
+    let [min, max, step] = parts[..] else {
+        return Err(invalid("expected MIN:MAX:STEP, e.g. 56:1472:64"));
+    };
+    let bytes = |part: &str| part.trim().parse::<usize>().map_err(|_| invalid(&format!("'{}' is not a size in bytes", part)));
+    let range = SweepRange { min: bytes(min)?, max: bytes(max)?, step: bytes(step)? };
+    if range.min > range.max {
+        return Err(invalid("MIN is larger than MAX"));
+    }
+    if range.step == 0 {
+        return Err(invalid("STEP must be at least 1"));
+    }
+    if range.max > MAX_PAYLOAD {
+        return Err(invalid(&format!("payloads go up to {} bytes", MAX_PAYLOAD)));
+    }
+    Ok(range)
+}
+
+/// The sizes of a target's probes and how each fared
+pub struct Sweep {
+    sizes: Vec<usize>,
+    sent: Vec<u32>,
+    rtts: Vec<RttStats>,
+}
+
+impl Sweep {
+    pub fn new(range: SweepRange) -> Self {
+        let sizes: Vec<usize> = (range.min..=range.max).step_by(range.step).collect();
+        let n = sizes.len();
+        Sweep { sizes, sent: vec![0; n], rtts: vec![RttStats::new(); n] }
+    }
+
+    /// Payload of probe `seq`
+    pub fn size(&self, seq: u32) -> usize {
+        self.sizes[seq as usize % self.sizes.len()]
+    }
+
+    /// Count probe `seq`, with its RTT when it was answered
+    pub fn record(&mut self, seq: u32, rtt: Option<f64>) {
+        let i = seq as usize % self.sizes.len();
+        self.sent[i] += 1;
+        if let Some(rtt) = rtt {
+            self.rtts[i].add(rtt);
+        }
+    }
+
+    /// Every size that was sent, smallest first
+    pub fn results(&self) -> Vec<SizeStats> {
+        self.sizes
+            .iter()
+            .zip(self.sent.iter().zip(&self.rtts))
+            .filter(|(_, (&sent, _))| sent > 0)
+            .map(|(&size, (&sent, rtts))| {
+                let stats = rtts.statistics(sent);
+                SizeStats {
+                    size,
+                    sent,
+                    received: stats.packets_received,
+                    loss_percent: stats.packet_loss_percent,
+                    min_ms: stats.min_ms,
+                    avg_ms: stats.avg_ms,
+                    max_ms: stats.max_ms,
+                }
+            })
+            .collect()
+    }
+}
+
+/// Least squares slope of the fastest RTT over the size, in ms per byte
+fn slope(sizes: &[SizeStats]) -> Option<f64> {
+    let points: Vec<(f64, f64)> = sizes.iter().filter_map(|s| Some((s.size as f64, s.min_ms?))).collect();
+    if points.len() < 2 {
+        return None;
+    }
+    let n = points.len() as f64;
+    let mean_x = points.iter().map(|p| p.0).sum::<f64>() / n;
+    let mean_y = points.iter().map(|p| p.1).sum::<f64>() / n;
+    let sxx: f64 = points.iter().map(|p| (p.0 - mean_x).powi(2)).sum();
+    let sxy: f64 = points.iter().map(|p| (p.0 - mean_x) * (p.1 - mean_y)).sum();
+    (sxx > 0.0).then(|| sxy / sxx)
+}
+
+pub fn print_sweep(host: &str, sizes: &[SizeStats]) {
+    println!("\n{}", "╔════════════════════════════════════════════════════════════╗".magenta());
+    println!("{}", "║                     📏 SIZE SWEEP                           ║".magenta());
+    println!("{}", "╚════════════════════════════════════════════════════════════╝".magenta());
+    println!("  Host: {}", host.yellow());
+
+    let mut table = Table::new();
+    table
+        .load_preset(UTF8_FULL_CONDENSED)
+        .apply_modifier(UTF8_ROUND_CORNERS)
+        .set_content_arrangement(ContentArrangement::Dynamic)
+        .set_header(vec!["Payload", "Sent", "Loss", "Min ms", "Avg ms", "Max ms"]);
+    for size in sizes {
+        table.add_row(vec![
+            Cell::new(format!("{} B", size.size)).set_alignment(CellAlignment::Right),
+            Cell::new(size.sent).set_alignment(CellAlignment::Right),
+            loss_cell(size.loss_percent),
+            ms_cell(size.min_ms),
+            ms_cell(size.avg_ms),
+            ms_cell(size.max_ms),
+        ]);
+    }
+    println!("{}", table);
+
+    // Where the larger sizes all went unanswered
+    if let Some(lost) = sizes.iter().rposition(|s| s.received > 0).and_then(|i| sizes.get(i + 1)) {
+        println!("  {} No replies from {} bytes of payload up", "⚠".yellow(), lost.size);
+    }
+    match slope(sizes) {
+        // Both ways, in bits
+        Some(ms_per_byte) if ms_per_byte >= 1e-6 => println!(
+            "  RTT grows {:.3} ms per 1000 bytes (≈ {:.1} Mbit/s on the slowest link)",
+            ms_per_byte * 1000.0,
+            2.0 * 8.0 / (ms_per_byte / 1000.0) / 1e6
+        ),
+        Some(_) => println!("  {}", "RTT doesn't grow with the payload".dimmed()),
+        None => {}
+    }
+}
@@ -0,0 +1,357 @@
+//! `trace` subcommand: the route to a host.
+//!
+//! Echo requests are sent with increasing TTLs. The router where a probe's
+//! TTL runs out answers with ICMP time exceeded and the target itself with
+//! an echo reply, so the probes pass the same firewalls as a ping does.
+//! With `--watch` every hop is probed again each round, like mtr does, and
+//! its loss and RTTs are kept in a table redrawn after every round.
+
+use colored::*;
+use comfy_table::modifiers::UTF8_ROUND_CORNERS;
+use comfy_table::presets::UTF8_FULL_CONDENSED;
+use comfy_table::{Cell, CellAlignment, ContentArrangement, Table};
+use std::io::{self, IsTerminal, Write};
+use std::net::IpAddr;
+use std::time::{Duration, Instant, SystemTime};
+
+use crate::config::Config;
+use crate::identifier::Identifier;
+use crate::interrupt;
+use crate::keys::{self, Keys};
+use crate::rdns;
+use crate::stats::RttStats;
+use crate::summary::{loss_cell, ms_cell};
+use crate::transport::{self, IcmpReceiver, IcmpSender, Reply, DEST_UNREACHABLE, ECHO_REPLY, TIME_EXCEEDED};
+use crate::{get_latency_color, parse_duration, resolve_host, write_icmp_packet, PACKET_LEN, QUIT_POLL};
+
+/// Options of the `trace` subcommand
+#[derive(clap::Args, Debug)]
+pub struct TraceArgs {
+    /// IP address or hostname to trace
+    #[arg(value_name = "HOST")]
+    host: String,
+
+    /// Resolve the host to an IPv6 address only (IPv4 is preferred otherwise)
+    #[arg(short = '6', long)]
+    ipv6: bool,
+
+    /// Give up after this many hops
+    #[arg(short, long, default_value_t = 30, value_parser = clap::value_parser!(u8).range(1..))]
+    max_hops: u8,
+
+    /// Probes sent to each hop
+    #[arg(short, long, default_value_t = 3, value_parser = clap::value_parser!(u16).range(1..=100))]
+    queries: u16,
+
+    /// Timeout for each probe, in seconds or with a unit (e.g. 2, 500ms)
+    #[arg(short, long, default_value = "2", value_parser = parse_duration)]
+    timeout: Duration,
+
+    /// Keep probing every hop and show its loss and RTTs in a live table, like mtr
+    #[arg(short, long)]
+    watch: bool,
+
+    /// With --watch: time between rounds of probes, e.g. 1, 500ms
+    #[arg(short, long, default_value = "1", value_parser = parse_duration, requires = "watch")]
+    interval: Duration,
+
+    /// With --watch: stop after this many rounds instead of at q or Ctrl+C
+    #[arg(short, long, value_name = "N", requires = "watch", value_parser = clap::value_parser!(u32).range(1..))]
+    cycles: Option<u32>,
+
+    /// Show the names of the hops (reverse DNS, looked up in the background)
+    #[arg(long, overrides_with = "numeric")]
+    resolve: bool,
+
+    /// Show hops as numbers only, even with `resolve = true` in the config
+    #[arg(short = 'n', long, overrides_with = "resolve")]
+    numeric: bool,
+}
+
+// How long a hop's line waits for its name once its probes are done
+const NAME_WAIT: Duration = Duration::from_millis(500);
+
+/// Short marker for an unreachable code, as printed by traceroute
+fn unreachable_flag(code: u8) -> &'static str {
+    match code {
+        0 => "!N",
+        1 => "!H",
+        2 => "!P",
+        4 => "!F",
+        9 | 10 | 13 => "!X",
+        _ => "!U",
+    }
+}
+
+/// Wait for the answer to the probe with `identifier`, skipping anything else
+pub fn wait_answer(receiver: &mut dyn IcmpReceiver, identifier: u16, timeout: Duration) -> Result<Option<Reply>, String> {
+    let deadline = Instant::now() + timeout;
+    loop {
+        let reply = receiver
+            .next_with_timeout(deadline.saturating_duration_since(Instant::now()))
+            .map_err(|e| format!("Receive error: {}", e))?;
+        match reply {
+            Some(reply)
+                if reply.identifier == identifier
+                    && reply.checksum_ok
+                    && matches!(reply.icmp_type, ECHO_REPLY | TIME_EXCEEDED | DEST_UNREACHABLE) =>
+            {
+                return Ok(Some(reply));
+            }
+            Some(_) => {}
+            None => return Ok(None),
+        }
+    }
+}
+
+pub fn run(args: &TraceArgs, config: &Config) -> Result<(), String> {
+    let host = match config.expand_hosts(std::slice::from_ref(&args.host))?.as_slice() {
+        [host] => host.clone(),
+        _ => return Err(format!("Alias {} names several hosts; trace one of them", args.host)),
+    };
+    let addr = resolve_host(&host, args.ipv6)?;
+    let (mut sender, mut receiver) = transport::open(addr.is_ipv6())?;
+    if args.resolve || (config.defaults.resolve == Some(true) && !args.numeric) {
+        rdns::start();
+    }
+
+    println!("\n{}", "╔════════════════════════════════════════════════════════════╗".cyan());
+    println!("{}       TRACE {} - {} hops max                {}",
+        "║".cyan(),
+        addr.to_string().yellow().bold(),
+        args.max_hops.to_string().green(),
+        "║".cyan()
+    );
+    println!("{}", "╚════════════════════════════════════════════════════════════╝".cyan());
+
+    if args.watch {
+        return watch(args, addr, sender.as_mut(), receiver.as_mut());
+    }
+
+    let identifier = Identifier::claim()?;
+    let timeout = args.timeout;
+    let mut packet = vec![vec![0u8; PACKET_LEN]];
+    let mut seq: u16 = 0;
+    for ttl in 1..=args.max_hops {
+        sender.set_ttl(ttl).map_err(|e| format!("Failed to set TTL: {}", e))?;
+
+        let mut line = vec![Part::Text(format!("  {:>2} ", ttl))];
+        let mut last_hop: Option<IpAddr> = None;
+        let mut done = false;
+        for _ in 0..args.queries {
+            write_icmp_packet(&mut packet[0], seq, identifier.value(), addr.is_ipv6());
+            seq = seq.wrapping_add(1);
+
+            let sent_at = SystemTime::now();
+            if let (_, Some(e)) = sender.send_batch(&packet, addr) {
+                line.push(Part::Text(format!(" {}", format!("send error: {}", e).red())));
+                continue;
+            }
+
+            match wait_answer(receiver.as_mut(), identifier.value(), timeout)? {
+                Some(reply) => {
+                    // A hop's address is printed before the first time it answered
+                    if last_hop != Some(reply.source) {
+                        rdns::prefetch(reply.source);
+                        line.push(Part::Hop(reply.source));
+                        last_hop = Some(reply.source);
+                    }
+                    let rtt = reply.received.duration_since(sent_at).unwrap_or_default().as_secs_f64() * 1000.0;
+                    line.push(Part::Text(format!(" {}", get_latency_color(rtt))));
+                    match reply.icmp_type {
+                        ECHO_REPLY => done = true,
+                        DEST_UNREACHABLE => {
+                            line.push(Part::Text(format!(" {}", unreachable_flag(reply.code).red())));
+                            done = true;
+                        }
+                        _ => {}
+                    }
+                }
+                None => line.push(Part::Text(format!(" {}", "*".dimmed()))),
+            }
+        }
+        // Names looked up while the hop's probes were out are shown in its line
+        let line: String = line
+            .into_iter()
+            .map(|part| match part {
+                Part::Hop(hop) => format!(" {}", rdns::label_within(hop, NAME_WAIT).cyan()),
+                Part::Text(text) => text,
+            })
+            .collect();
+        println!("{}", line);
+
+        if done {
+            return Ok(());
+        }
+    }
+
+    println!("  {}", format!("{} not reached within {} hops", addr, args.max_hops).dimmed());
+    Ok(())
+}
+
+/// A piece of a hop's line, whose addresses get their names when it's printed
+enum Part {
+    Hop(IpAddr),
+    Text(String),
+}
+
+/// What was seen of one hop over the rounds of `--watch`
+struct Hop {
+    // Every address that answered, in the order they first did
+    addrs: Vec<IpAddr>,
+    sent: u32,
+    rtt: RttStats,
+    last: Option<f64>,
+    flag: Option<&'static str>,
+}
+
+/// Probe every hop each round until the rounds are done or `q` or Ctrl+C is pressed
+///
+/// Each hop's probes carry an identifier of their own, so all hops are probed
+/// at once and an answer is matched to its hop whatever order they come in.
+/// A probe not answered within the timeout, or before the next round, is lost.
+fn watch(args: &TraceArgs, addr: IpAddr, sender: &mut dyn IcmpSender, receiver: &mut dyn IcmpReceiver) -> Result<(), String> {
+    let identifiers = (0..args.max_hops).map(|_| Identifier::claim()).collect::<Result<Vec<_>, _>>()?;
+    let mut hops: Vec<Hop> = (0..args.max_hops)
+        .map(|_| Hop { addrs: Vec::new(), sent: 0, rtt: RttStats::new(), last: None, flag: None })
+        .collect();
+    // Hops up to the target, once it answered
+    let mut length = args.max_hops;
+
+    interrupt::watch()?;
+    let live = io::stdout().is_terminal();
+    let keys = if live { Some(Keys::start()?) } else { None };
+    if live {
+        keys::alternate_screen(true);
+    }
+
+    let mut packet = vec![vec![0u8; PACKET_LEN]];
+    let mut round: u32 = 0;
+    let outcome = 'rounds: loop {
+        let started = Instant::now();
+        let mut sent_at = vec![None; length as usize];
+        for ttl in 1..=length {
+            if let Err(e) = sender.set_ttl(ttl) {
+                break 'rounds Err(format!("Failed to set TTL: {}", e));
+            }
+            write_icmp_packet(&mut packet[0], round as u16, identifiers[ttl as usize - 1].value(), addr.is_ipv6());
+            let at = SystemTime::now();
+            hops[ttl as usize - 1].sent += 1;
+            if sender.send_batch(&packet, addr).1.is_none() {
+                sent_at[ttl as usize - 1] = Some(at);
+            }
+        }
+        round += 1;
+
+        let deadline = started + args.timeout.min(args.interval);
+        while let Some(wait) = deadline.checked_duration_since(Instant::now()) {
+            if sent_at.iter().all(Option::is_none) {
+                break;
+            }
+            let reply = match receiver.next_with_timeout(wait) {
+                Ok(Some(reply)) => reply,
+                Ok(None) => break,
+                Err(e) => break 'rounds Err(format!("Receive error: {}", e)),
+            };
+            if !reply.checksum_ok || !matches!(reply.icmp_type, ECHO_REPLY | TIME_EXCEEDED | DEST_UNREACHABLE) {
+                continue;
+            }
+            let Some(index) = identifiers.iter().position(|i| i.value() == reply.identifier) else {
+                continue;
+            };
+            // Answered once per round, and only probes of this round
+            let Some(at) = sent_at.get_mut(index).and_then(Option::take) else {
+                continue;
+            };
+            let hop = &mut hops[index];
+            let rtt = reply.received.duration_since(at).unwrap_or_default().as_secs_f64() * 1000.0;
+            hop.rtt.add(rtt);
+            hop.last = Some(rtt);
+            if !hop.addrs.contains(&reply.source) {
+                rdns::prefetch(reply.source);
+                hop.addrs.push(reply.source);
+            }
+            match reply.icmp_type {
+                ECHO_REPLY => length = length.min(index as u8 + 1),
+                DEST_UNREACHABLE => {
+                    hop.flag = Some(unreachable_flag(reply.code));
+                    length = length.min(index as u8 + 1);
+                }
+                _ => {}
+            }
+        }
+
+        if live {
+            let status = format!("{} · q or Ctrl+C to stop", round_line(addr, round)).dimmed();
+            let screen = format!("\x1b[H{}\n{}", status, hop_table(&hops[..length as usize]));
+            let mut stdout = io::stdout();
+            let _ = stdout.write_all(screen.replace('\n', "\x1b[K\n").as_bytes());
+            let _ = stdout.write_all(b"\x1b[K\x1b[J");
+            let _ = stdout.flush();
+        }
+        if args.cycles.is_some_and(|cycles| round >= cycles) {
+            break Ok(());
+        }
+        // Waiting for the next round in slices, so q and Ctrl+C act at once
+        let next = started + args.interval;
+        loop {
+            let wait = next.saturating_duration_since(Instant::now()).min(QUIT_POLL);
+            match &keys {
+                Some(keys) => {
+                    keys.wait(wait);
+                }
+                None => std::thread::sleep(wait),
+            }
+            if keys.as_ref().is_some_and(Keys::quit_requested) || interrupt::requested() {
+                break 'rounds Ok(());
+            }
+            if Instant::now() >= next {
+                break;
+            }
+        }
+    };
+
+    if live {
+        keys::alternate_screen(false);
+    }
+    println!("  {}", round_line(addr, round).dimmed());
+    println!("{}", hop_table(&hops[..length as usize]));
+    outcome
+}
+
+fn round_line(addr: IpAddr, round: u32) -> String {
+    format!("{} rounds to {}", round, addr)
+}
+
+/// Per-hop table as mtr shows it
+fn hop_table(hops: &[Hop]) -> Table {
+    let mut table = Table::new();
+    table
+        .load_preset(UTF8_FULL_CONDENSED)
+        .apply_modifier(UTF8_ROUND_CORNERS)
+        .set_content_arrangement(ContentArrangement::Dynamic)
+        .set_header(vec!["Hop", "Host", "Loss", "Sent", "Last ms", "Avg ms", "Best ms", "Worst ms", "StdDev ms"]);
+
+    for (i, hop) in hops.iter().enumerate() {
+        let stats = hop.rtt.statistics(hop.sent);
+        let mut host = match hop.addrs.as_slice() {
+            [] => "???".to_string(),
+            addrs => addrs.iter().map(|&addr| rdns::label(addr)).collect::<Vec<_>>().join("\n"),
+        };
+        if let Some(flag) = hop.flag {
+            host = format!("{} {}", host, flag);
+        }
+        table.add_row(vec![
+            Cell::new(i + 1).set_alignment(CellAlignment::Right),
+            Cell::new(host),
+            loss_cell(stats.packet_loss_percent),
+            Cell::new(hop.sent).set_alignment(CellAlignment::Right),
+            ms_cell(hop.last),
+            ms_cell(stats.avg_ms),
+            ms_cell(stats.min_ms),
+            ms_cell(stats.max_ms),
+            ms_cell(stats.std_dev_ms),
+        ]);
+    }
+    table
+}
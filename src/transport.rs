@@ -0,0 +1,1099 @@
+//! Batched ICMP send and receive.
+//!
+//! On Linux replies are read with `recvmmsg`, up to `BATCH` packets per
+//! syscall, each stamped by the kernel on arrival (`SO_TIMESTAMPNS`) so the
+//! RTT doesn't include the time a reply spent queued behind others. pnet's
+//! iterator instead costs four syscalls per packet (it saves, sets and
+//! restores the socket timeout around every read). Bursts of probes go out
+//! with a single `sendmmsg`. Other platforms use pnet one packet at a time.
+//!
+//! IPv6 targets get a raw ICMPv6 socket. Its messages are handed out as the
+//! ICMP messages they stand for (echo reply, destination unreachable, time
+//! exceeded), so the rest of the tool doesn't tell the families apart.
+//!
+//! Windows has no raw ICMP sockets without a packet capture driver. There
+//! each probe is an `IcmpSendEcho2Ex` (`Icmp6SendEcho2`) call of the IP
+//! Helper API on a thread of its own, and its outcome is handed out the same
+//! way. Both backends implement [`IcmpSender`] and [`IcmpReceiver`], and
+//! `open` picks the one of the platform, so the rest of the tool works with
+//! either through those traits.
+
+use pnet::packet::ip::IpNextHeaderProtocols;
+#[cfg(not(windows))]
+use pnet::transport::TransportChannelType::Layer4;
+#[cfg(not(windows))]
+use pnet::transport::TransportProtocol::{Ipv4, Ipv6};
+#[cfg(not(windows))]
+use pnet::transport::{transport_channel, TransportReceiver, TransportSender};
+use std::collections::VecDeque;
+use std::io;
+use std::net::{IpAddr, Ipv6Addr, UdpSocket};
+use std::time::{Duration, SystemTime};
+
+/// One received ICMP message
+#[derive(Clone, Debug)]
+pub struct Reply {
+    pub source: IpAddr,
+    pub icmp_type: u8,
+    pub code: u8,
+    /// Echo identifier, which tells concurrent targets' replies apart; for
+    /// errors it's taken from the echo request quoted in the message
+    pub identifier: u16,
+    /// Echo sequence, taken from the quoted request for errors as well;
+    /// `None` for answers to UDP probes, which have none
+    pub sequence: Option<u16>,
+    /// ICMP checksum (and IP header checksum where the header is seen) verified;
+    /// ICMPv6 checksums cover the addresses too
+    pub checksum_ok: bool,
+    /// Arrival time, from the kernel where available
+    pub received: SystemTime,
+    /// TTL of the IP header, or hop limit of the IPv6 one, where it is seen
+    pub ttl: Option<u8>,
+    /// MTU of the next hop, told by a fragmentation needed or packet too big error
+    pub mtu: Option<u16>,
+    /// What an echo reply carried after its header, when all of it was read
+    pub payload: Option<Vec<u8>>,
+}
+
+/// ICMP echo reply
+pub const ECHO_REPLY: u8 = 0;
+/// ICMP destination unreachable
+pub const DEST_UNREACHABLE: u8 = 3;
+/// ICMP redirect: a router points at a better next hop, and still forwards the probe
+pub const REDIRECT: u8 = 5;
+/// ICMP time exceeded (TTL ran out in transit)
+pub const TIME_EXCEEDED: u8 = 11;
+/// ICMP parameter problem: a router or the target couldn't process the probe's header
+pub const PARAMETER_PROBLEM: u8 = 12;
+
+/// ICMPv6 echo request
+pub const ICMP6_ECHO_REQUEST: u8 = 128;
+const ICMP6_ECHO_REPLY: u8 = 129;
+const ICMP6_DEST_UNREACHABLE: u8 = 1;
+const ICMP6_PACKET_TOO_BIG: u8 = 2;
+const ICMP6_TIME_EXCEEDED: u8 = 3;
+const ICMP6_PARAMETER_PROBLEM: u8 = 4;
+
+// Length of an IPv6 header, the part of a probe quoted by ICMPv6 errors before the probe itself
+const IPV6_HEADER_LEN: usize = 40;
+
+/// Identifier and sequence fields of an ICMP echo message (0 if too short to have them)
+fn echo_fields(icmp: &[u8]) -> (u16, Option<u16>) {
+    let field = |at: usize| icmp.get(at..at + 2).map_or(0, |b| u16::from_be_bytes([b[0], b[1]]));
+    (field(4), Some(field(6)))
+}
+
+/// Identifier and sequence of a probe quoted in an error: those of the echo
+/// request, or the source port of a UDP probe, which is sent from the
+/// identifier's port and has no sequence
+fn quoted_fields(protocol: Option<u8>, probe: &[u8]) -> (u16, Option<u16>) {
+    if protocol == Some(IpNextHeaderProtocols::Udp.0) {
+        (probe.get(0..2).map_or(0, |b| u16::from_be_bytes([b[0], b[1]])), None)
+    } else {
+        echo_fields(probe)
+    }
+}
+
+/// Type, code, echo identifier and sequence of an ICMP message. Error messages
+/// carry the IP header and first 8 bytes of the datagram that caused them.
+pub fn parse_icmp(icmp: &[u8]) -> (u8, u8, u16, Option<u16>) {
+    let icmp_type = icmp.first().copied().unwrap_or(0);
+    let code = icmp.get(1).copied().unwrap_or(0);
+    let (identifier, sequence) = match icmp_type {
+        DEST_UNREACHABLE | REDIRECT | TIME_EXCEEDED | PARAMETER_PROBLEM => {
+            let quoted = icmp.get(8..).unwrap_or_default();
+            let header_len = quoted.first().map_or(0, |b| (b & 0x0f) as usize * 4);
+            quoted.get(header_len..).map_or((0, None), |probe| quoted_fields(quoted.get(9).copied(), probe))
+        }
+        _ => echo_fields(icmp),
+    };
+    (icmp_type, code, identifier, sequence)
+}
+
+/// Type, code, echo identifier and sequence of the ICMP message an ICMPv6
+/// message stands for; `None` for messages that don't answer a probe (echo
+/// requests, neighbour discovery and the like). Errors carry the IPv6 header
+/// and the start of the probe that caused them.
+pub fn parse_icmp6(icmp: &[u8]) -> Option<(u8, u8, u16, Option<u16>)> {
+    let code = icmp.get(1).copied().unwrap_or(0);
+    let quoted = || {
+        let next_header = icmp.get(8 + 6).copied();
+        icmp.get(8 + IPV6_HEADER_LEN..).map_or((0, None), |probe| quoted_fields(next_header, probe))
+    };
+    let (icmp_type, code, (identifier, sequence)) = match *icmp.first()? {
+        ICMP6_ECHO_REPLY => (ECHO_REPLY, code, echo_fields(icmp)),
+        ICMP6_DEST_UNREACHABLE => (DEST_UNREACHABLE, unreachable_code(code), quoted()),
+        // The ICMP counterpart of "packet too big" is "fragmentation needed"
+        ICMP6_PACKET_TOO_BIG => (DEST_UNREACHABLE, 4, quoted()),
+        ICMP6_TIME_EXCEEDED => (TIME_EXCEEDED, code, quoted()),
+        ICMP6_PARAMETER_PROBLEM => (PARAMETER_PROBLEM, code, quoted()),
+        // ICMPv6 redirects are neighbour discovery and quote the probe in an option, if at all
+        _ => return None,
+    };
+    Some((icmp_type, code, identifier, sequence))
+}
+
+/// Data of an echo reply, which should be that of the request it answers
+pub fn echo_payload(icmp_type: u8, icmp: &[u8]) -> Option<Vec<u8>> {
+    (icmp_type == ECHO_REPLY).then(|| icmp.get(8..).unwrap_or_default().to_vec())
+}
+
+/// Next-hop MTU in a fragmentation needed error, or an ICMPv6 packet too
+/// big one; older routers send fragmentation needed with 0 there
+pub fn next_hop_mtu(icmp: &[u8], ipv6: bool) -> Option<u16> {
+    let (icmp_type, code) = (*icmp.first()?, *icmp.get(1)?);
+    let mtu = match (ipv6, icmp_type, code) {
+        (false, DEST_UNREACHABLE, 4) => u16::from_be_bytes(icmp.get(6..8)?.try_into().ok()?) as u32,
+        (true, ICMP6_PACKET_TOO_BIG, _) => u32::from_be_bytes(icmp.get(4..8)?.try_into().ok()?),
+        _ => return None,
+    };
+    (mtu > 0).then(|| mtu.min(u16::MAX as u32) as u16)
+}
+
+/// ICMP destination unreachable code closest to an ICMPv6 one
+fn unreachable_code(code: u8) -> u8 {
+    match code {
+        // No route to destination
+        0 => 0,
+        // Address unreachable
+        3 => 1,
+        // Port unreachable
+        4 => 3,
+        // Administratively prohibited, beyond the source's scope, policy, reject route
+        _ => 13,
+    }
+}
+
+/// Whether a checksummed header or message adds up, i.e. arrived intact
+pub fn checksum_valid(data: &[u8]) -> bool {
+    !data.is_empty() && crate::checksum(data) == 0
+}
+
+/// Checksum of an ICMPv6 message, which also covers a pseudo-header of the
+/// source and destination addresses, the length and the next header (RFC 4443)
+pub fn checksum6(source: Ipv6Addr, destination: Ipv6Addr, icmp: &[u8]) -> u16 {
+    let mut data = Vec::with_capacity(IPV6_HEADER_LEN + icmp.len());
+    data.extend_from_slice(&source.octets());
+    data.extend_from_slice(&destination.octets());
+    data.extend_from_slice(&(icmp.len() as u32).to_be_bytes());
+    data.extend_from_slice(&[0, 0, 0, IpNextHeaderProtocols::Icmpv6.0]);
+    data.extend_from_slice(icmp);
+    crate::checksum(&data)
+}
+
+/// Hop limit of the datagrams sent on an IPv6 UDP socket from now on
+#[cfg(target_os = "linux")]
+pub fn set_udp_hop_limit(socket: &UdpSocket, hops: u8) -> io::Result<()> {
+    use std::os::fd::AsRawFd;
+
+    linux::set_hop_limit(socket.as_raw_fd(), hops)
+}
+
+/// Hop limit of the datagrams sent on an IPv6 UDP socket from now on
+#[cfg(not(target_os = "linux"))]
+pub fn set_udp_hop_limit(_socket: &UdpSocket, _hops: u8) -> io::Result<()> {
+    Err(io::Error::new(io::ErrorKind::Unsupported, "the hop limit of UDP probes can only be set on Linux"))
+}
+
+/// Have a UDP socket send and receive on this interface only
+#[cfg(target_os = "linux")]
+pub fn bind_udp_interface(socket: &UdpSocket, name: &str) -> io::Result<()> {
+    use std::os::fd::AsRawFd;
+
+    linux::bind_to_device(socket.as_raw_fd(), name)
+}
+
+/// Have a UDP socket send and receive on this interface only
+#[cfg(not(target_os = "linux"))]
+pub fn bind_udp_interface(_socket: &UdpSocket, _name: &str) -> io::Result<()> {
+    Err(io::Error::new(io::ErrorKind::Unsupported, "probes can only be bound to an interface on Linux"))
+}
+
+/// Sends echo requests (or other ICMP messages) whose answers arrive at the
+/// paired [`IcmpReceiver`]
+pub trait IcmpSender: Send {
+    /// TTL (hop limit for ICMPv6) of the probes sent from now on
+    fn set_ttl(&mut self, ttl: u8) -> io::Result<()>;
+
+    /// Send the probes from now on with the don't fragment bit, and never fragment them locally
+    fn set_dont_fragment(&mut self) -> io::Result<()>;
+
+    /// Send the probes from now on through this interface (or VRF) only, whatever the routing table says
+    fn bind_interface(&mut self, name: &str) -> io::Result<()>;
+
+    /// Send the probes from now on from this local address
+    fn bind_source(&mut self, addr: IpAddr) -> io::Result<()>;
+
+    /// Send ICMP messages to `addr` in order. Returns how many went out and,
+    /// if not all of them did, the error that stopped the rest.
+    fn send_batch(&mut self, packets: &[Vec<u8>], addr: IpAddr) -> (usize, Option<io::Error>);
+}
+
+/// Hands out the ICMP messages that arrived, in the order they did
+pub trait IcmpReceiver: Send {
+    /// Next message, or `None` if nothing arrived within `timeout`
+    fn next_with_timeout(&mut self, timeout: Duration) -> io::Result<Option<Reply>>;
+}
+
+/// Both halves of a backend, as `open` hands them out
+pub type Halves = (Box<dyn IcmpSender>, Box<dyn IcmpReceiver>);
+
+/// Open the platform's backend for `ipv6` or IPv4 targets: raw sockets, or the IP Helper API on Windows
+pub fn open(ipv6: bool) -> Result<Halves, String> {
+    #[cfg(not(windows))]
+    let (sender, receiver) = open_raw(ipv6)?;
+    #[cfg(windows)]
+    let (sender, receiver) = windows::open(ipv6)?;
+    Ok((Box::new(sender), Box::new(receiver)))
+}
+
+/// Open a raw ICMP socket, or an ICMPv6 one for IPv6 targets
+#[cfg(not(windows))]
+fn open_raw(ipv6: bool) -> Result<(RawSender, RawReceiver), String> {
+    let protocol = if ipv6 {
+        Layer4(Ipv6(IpNextHeaderProtocols::Icmpv6))
+    } else {
+        Layer4(Ipv4(IpNextHeaderProtocols::Icmp))
+    };
+    // Room for the largest reply, so ones to a large --size aren't cut short
+    let (tx, rx) = transport_channel(65536, protocol)
+        .map_err(|e| format!("Error creating channel (root permissions?): {}", e))?;
+    Ok((RawSender::new(tx, ipv6), RawReceiver::new(rx, ipv6)?))
+}
+
+#[cfg(not(windows))]
+pub struct RawSender {
+    tx: TransportSender,
+    ipv6: bool,
+    #[cfg(target_os = "linux")]
+    batch: linux::SendBatch,
+}
+
+#[cfg(not(windows))]
+impl RawSender {
+    pub fn new(tx: TransportSender, ipv6: bool) -> Self {
+        RawSender {
+            tx,
+            ipv6,
+            #[cfg(target_os = "linux")]
+            batch: linux::SendBatch::default(),
+        }
+    }
+}
+
+#[cfg(not(windows))]
+impl IcmpSender for RawSender {
+    /// TTL (hop limit for ICMPv6) of the probes sent from now on
+    #[cfg(target_os = "linux")]
+    fn set_ttl(&mut self, ttl: u8) -> io::Result<()> {
+        if self.ipv6 {
+            return linux::set_hop_limit(self.tx.socket.fd, ttl);
+        }
+        self.tx.set_ttl(ttl)
+    }
+
+    /// TTL of the probes sent from now on
+    #[cfg(not(target_os = "linux"))]
+    fn set_ttl(&mut self, ttl: u8) -> io::Result<()> {
+        if self.ipv6 {
+            return Err(io::Error::new(io::ErrorKind::Unsupported, "the hop limit of ICMPv6 probes can only be set on Linux"));
+        }
+        self.tx.set_ttl(ttl)
+    }
+
+    /// Send the probes from now on with the don't fragment bit, and never fragment them locally
+    #[cfg(target_os = "linux")]
+    fn set_dont_fragment(&mut self) -> io::Result<()> {
+        linux::probe_mtu(self.tx.socket.fd, self.ipv6)
+    }
+
+    /// Send the probes from now on with the don't fragment bit
+    #[cfg(not(target_os = "linux"))]
+    fn set_dont_fragment(&mut self) -> io::Result<()> {
+        Err(io::Error::new(io::ErrorKind::Unsupported, "the don't fragment bit can only be set on Linux and Windows"))
+    }
+
+    /// Send the probes from now on through this interface (or VRF) only, whatever the routing table says
+    #[cfg(target_os = "linux")]
+    fn bind_interface(&mut self, name: &str) -> io::Result<()> {
+        linux::bind_to_device(self.tx.socket.fd, name)
+    }
+
+    /// Send the probes through this interface only
+    #[cfg(not(target_os = "linux"))]
+    fn bind_interface(&mut self, _name: &str) -> io::Result<()> {
+        Err(io::Error::new(io::ErrorKind::Unsupported, "probes can only be bound to an interface on Linux"))
+    }
+
+    /// Send the probes from now on from this local address
+    #[cfg(target_os = "linux")]
+    fn bind_source(&mut self, addr: IpAddr) -> io::Result<()> {
+        linux::bind(self.tx.socket.fd, addr)
+    }
+
+    /// Send the probes from now on from this local address
+    #[cfg(not(target_os = "linux"))]
+    fn bind_source(&mut self, _addr: IpAddr) -> io::Result<()> {
+        Err(io::Error::new(io::ErrorKind::Unsupported, "the source address can only be chosen on Linux and Windows"))
+    }
+
+    /// Send ICMP messages to `addr` in order. Returns how many went out and,
+    /// if not all of them did, the error that stopped the rest.
+    #[cfg(target_os = "linux")]
+    fn send_batch(&mut self, packets: &[Vec<u8>], addr: IpAddr) -> (usize, Option<io::Error>) {
+        self.batch.send(self.tx.socket.fd, packets, addr)
+    }
+
+    /// Send ICMP messages to `addr` in order. Returns how many went out and,
+    /// if not all of them did, the error that stopped the rest.
+    #[cfg(not(target_os = "linux"))]
+    fn send_batch(&mut self, packets: &[Vec<u8>], addr: IpAddr) -> (usize, Option<io::Error>) {
+        use pnet::packet::icmp::IcmpPacket;
+
+        for (sent, packet) in packets.iter().enumerate() {
+            let icmp = match IcmpPacket::new(packet) {
+                Some(icmp) => icmp,
+                None => return (sent, Some(io::Error::new(io::ErrorKind::InvalidInput, "packet too short"))),
+            };
+            if let Err(e) = self.tx.send_to(icmp, addr) {
+                return (sent, Some(e));
+            }
+        }
+        (packets.len(), None)
+    }
+}
+
+#[cfg(not(windows))]
+pub struct RawReceiver {
+    rx: TransportReceiver,
+    ipv6: bool,
+    // Messages read in an earlier batch and not handed out yet
+    queue: VecDeque<Reply>,
+    #[cfg(target_os = "linux")]
+    batch: linux::Batch,
+}
+
+#[cfg(not(windows))]
+impl RawReceiver {
+    pub fn new(rx: TransportReceiver, ipv6: bool) -> Result<Self, String> {
+        #[cfg(target_os = "linux")]
+        linux::enable_timestamps(rx.socket.fd)
+            .map_err(|e| format!("Failed to enable receive timestamps: {}", e))?;
+        #[cfg(target_os = "linux")]
+        if ipv6 {
+            linux::enable_ipv6_info(rx.socket.fd)
+                .map_err(|e| format!("Failed to set up the ICMPv6 socket: {}", e))?;
+        }
+
+        Ok(RawReceiver {
+            rx,
+            ipv6,
+            queue: VecDeque::new(),
+            #[cfg(target_os = "linux")]
+            batch: linux::Batch::new(),
+        })
+    }
+
+    #[cfg(target_os = "linux")]
+    fn fill(&mut self, timeout: Duration) -> io::Result<()> {
+        let fd = self.rx.socket.fd;
+        if !linux::wait_readable(fd, timeout)? {
+            return Ok(());
+        }
+        self.batch.receive(fd, self.ipv6, &mut self.queue)
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    fn fill(&mut self, timeout: Duration) -> io::Result<()> {
+        use pnet::packet::Packet;
+
+        // A zero socket timeout would block until something arrives
+        if timeout.is_zero() {
+            return Ok(());
+        }
+        if self.ipv6 {
+            let mut iter = pnet::transport::icmpv6_packet_iter(&mut self.rx);
+            if let Some((packet, source)) = iter.next_with_timeout(timeout)? {
+                // The kernel drops ICMPv6 messages with bad checksums before raw sockets see them
+                if let Some((icmp_type, code, identifier, sequence)) = parse_icmp6(packet.packet()) {
+                    let received = SystemTime::now();
+                    let mtu = next_hop_mtu(packet.packet(), true);
+                    let payload = echo_payload(icmp_type, packet.packet());
+                    self.queue.push_back(Reply { source, icmp_type, code, identifier, sequence, checksum_ok: true, received, ttl: None, mtu, payload });
+                }
+            }
+            return Ok(());
+        }
+        let mut iter = pnet::transport::icmp_packet_iter(&mut self.rx);
+        if let Some((packet, source)) = iter.next_with_timeout(timeout)? {
+            let (icmp_type, code, identifier, sequence) = parse_icmp(packet.packet());
+            let checksum_ok = checksum_valid(packet.packet());
+            let received = SystemTime::now();
+            let mtu = next_hop_mtu(packet.packet(), false);
+            let payload = echo_payload(icmp_type, packet.packet());
+            self.queue.push_back(Reply { source, icmp_type, code, identifier, sequence, checksum_ok, received, ttl: None, mtu, payload });
+        }
+        Ok(())
+    }
+}
+
+#[cfg(not(windows))]
+impl IcmpReceiver for RawReceiver {
+    fn next_with_timeout(&mut self, timeout: Duration) -> io::Result<Option<Reply>> {
+        if let Some(reply) = self.queue.pop_front() {
+            return Ok(Some(reply));
+        }
+        self.fill(timeout)?;
+        Ok(self.queue.pop_front())
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod linux {
+    use std::collections::VecDeque;
+    use std::io;
+    use std::mem;
+    use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+    use std::os::fd::RawFd;
+    use std::ptr;
+    use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+    use super::{checksum6, checksum_valid, echo_payload, next_hop_mtu, parse_icmp, parse_icmp6, Reply};
+    use super::{ICMP6_DEST_UNREACHABLE, ICMP6_ECHO_REPLY, ICMP6_PACKET_TOO_BIG, ICMP6_PARAMETER_PROBLEM, ICMP6_TIME_EXCEEDED};
+
+    /// Packets read per `recvmmsg` call
+    pub const BATCH: usize = 32;
+    const PACKET_SIZE: usize = 2048;
+    // Room for the arrival stamp and, on ICMPv6 sockets, the destination address and hop limit
+    const CONTROL_SIZE: usize = 128;
+
+    // Socket option of the ICMPv6 type filter (<netinet/icmp6.h>)
+    const ICMP6_FILTER: libc::c_int = 1;
+
+    fn set_option<T>(fd: RawFd, level: libc::c_int, name: libc::c_int, value: &T) -> io::Result<()> {
+        let ret = unsafe {
+            libc::setsockopt(fd, level, name, value as *const T as *const libc::c_void, mem::size_of::<T>() as libc::socklen_t)
+        };
+        if ret < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    pub fn enable_timestamps(fd: RawFd) -> io::Result<()> {
+        let on: libc::c_int = 1;
+        set_option(fd, libc::SOL_SOCKET, libc::SO_TIMESTAMPNS, &on)
+    }
+
+    /// Have ICMPv6 messages arrive with the address they were sent to, for the
+    /// checksum, and their hop limit; and only those that can answer a probe
+    pub fn enable_ipv6_info(fd: RawFd) -> io::Result<()> {
+        let on: libc::c_int = 1;
+        set_option(fd, libc::IPPROTO_IPV6, libc::IPV6_RECVPKTINFO, &on)?;
+        set_option(fd, libc::IPPROTO_IPV6, libc::IPV6_RECVHOPLIMIT, &on)?;
+
+        // A set bit blocks its type; neighbour discovery and the probes looped back on lo stay out
+        let mut filter = [u32::MAX; 8];
+        for icmp_type in [ICMP6_ECHO_REPLY, ICMP6_DEST_UNREACHABLE, ICMP6_PACKET_TOO_BIG, ICMP6_TIME_EXCEEDED, ICMP6_PARAMETER_PROBLEM] {
+            filter[icmp_type as usize >> 5] &= !(1 << (icmp_type & 31));
+        }
+        set_option(fd, libc::IPPROTO_ICMPV6, ICMP6_FILTER, &filter)
+    }
+
+    /// Hop limit of the ICMPv6 probes sent from now on
+    pub fn set_hop_limit(fd: RawFd, hops: u8) -> io::Result<()> {
+        set_option(fd, libc::IPPROTO_IPV6, libc::IPV6_UNICAST_HOPS, &(hops as libc::c_int))
+    }
+
+    /// Send with DF set and never fragment locally, whatever MTU the kernel
+    /// learned for the route; larger than the interface's fails with EMSGSIZE
+    pub fn probe_mtu(fd: RawFd, ipv6: bool) -> io::Result<()> {
+        if ipv6 {
+            set_option(fd, libc::IPPROTO_IPV6, libc::IPV6_MTU_DISCOVER, &libc::IPV6_PMTUDISC_PROBE)
+        } else {
+            set_option(fd, libc::IPPROTO_IP, libc::IP_MTU_DISCOVER, &libc::IP_PMTUDISC_PROBE)
+        }
+    }
+
+    /// Send and receive on this interface only (`SO_BINDTODEVICE`)
+    pub fn bind_to_device(fd: RawFd, name: &str) -> io::Result<()> {
+        let ret = unsafe {
+            libc::setsockopt(fd, libc::SOL_SOCKET, libc::SO_BINDTODEVICE, name.as_ptr().cast(), name.len() as libc::socklen_t)
+        };
+        if ret < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    /// Send from `addr`, one of this host's addresses
+    pub fn bind(fd: RawFd, addr: IpAddr) -> io::Result<()> {
+        let (storage, len) = sockaddr(addr);
+        let ret = unsafe { libc::bind(fd, &storage as *const _ as *const libc::sockaddr, len) };
+        if ret < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    /// Socket address of `addr`, with its length
+    fn sockaddr(addr: IpAddr) -> (libc::sockaddr_storage, libc::socklen_t) {
+        let mut storage: libc::sockaddr_storage = unsafe { mem::zeroed() };
+        match addr {
+            IpAddr::V4(v4) => {
+                let sin = unsafe { &mut *(&mut storage as *mut _ as *mut libc::sockaddr_in) };
+                sin.sin_family = libc::AF_INET as libc::sa_family_t;
+                sin.sin_addr.s_addr = u32::from(v4).to_be();
+                (storage, mem::size_of::<libc::sockaddr_in>() as libc::socklen_t)
+            }
+            IpAddr::V6(v6) => {
+                let sin6 = unsafe { &mut *(&mut storage as *mut _ as *mut libc::sockaddr_in6) };
+                sin6.sin6_family = libc::AF_INET6 as libc::sa_family_t;
+                sin6.sin6_addr.s6_addr = v6.octets();
+                (storage, mem::size_of::<libc::sockaddr_in6>() as libc::socklen_t)
+            }
+        }
+    }
+
+    /// Address in a socket address filled in by the kernel
+    fn address(storage: &libc::sockaddr_storage) -> Option<IpAddr> {
+        match storage.ss_family as libc::c_int {
+            libc::AF_INET => {
+                let sin = unsafe { &*(storage as *const _ as *const libc::sockaddr_in) };
+                Some(IpAddr::V4(Ipv4Addr::from(u32::from_be(sin.sin_addr.s_addr))))
+            }
+            libc::AF_INET6 => {
+                let sin6 = unsafe { &*(storage as *const _ as *const libc::sockaddr_in6) };
+                Some(IpAddr::V6(Ipv6Addr::from(sin6.sin6_addr.s6_addr)))
+            }
+            _ => None,
+        }
+    }
+
+    /// Wait until the socket has data, with sub-millisecond resolution
+    pub fn wait_readable(fd: RawFd, timeout: Duration) -> io::Result<bool> {
+        let mut pfd = libc::pollfd { fd, events: libc::POLLIN, revents: 0 };
+        let ts = libc::timespec {
+            tv_sec: timeout.as_secs() as libc::time_t,
+            tv_nsec: timeout.subsec_nanos() as libc::c_long,
+        };
+        loop {
+            let ret = unsafe { libc::ppoll(&mut pfd, 1, &ts, ptr::null()) };
+            if ret >= 0 {
+                return Ok(ret > 0);
+            }
+            let err = io::Error::last_os_error();
+            if err.kind() != io::ErrorKind::Interrupted {
+                return Err(err);
+            }
+        }
+    }
+
+    /// Message headers for `sendmmsg`, kept so bursts don't allocate
+    #[derive(Default)]
+    pub struct SendBatch {
+        iovecs: Vec<libc::iovec>,
+        headers: Vec<libc::mmsghdr>,
+    }
+
+    // SAFETY: the pointers in the headers are rebuilt before every send and
+    // never dereferenced after it returns, so they don't tie the batch to a thread
+    unsafe impl Send for SendBatch {}
+
+    impl SendBatch {
+        /// Send with `sendmmsg`, retrying after partial sends until done or failed
+        pub fn send(&mut self, fd: RawFd, packets: &[Vec<u8>], dest: IpAddr) -> (usize, Option<io::Error>) {
+            let (mut addr, addr_len) = sockaddr(dest);
+
+            // The kernel only reads the buffers, the mutable pointers are just the C signature
+            self.iovecs.clear();
+            self.iovecs.extend(
+                packets
+                    .iter()
+                    .map(|p| libc::iovec { iov_base: p.as_ptr() as *mut libc::c_void, iov_len: p.len() }),
+            );
+            self.headers.clear();
+            self.headers.extend(self.iovecs.iter_mut().map(|iov| {
+                let mut hdr: libc::msghdr = unsafe { mem::zeroed() };
+                hdr.msg_name = &mut addr as *mut _ as *mut libc::c_void;
+                hdr.msg_namelen = addr_len;
+                hdr.msg_iov = iov;
+                hdr.msg_iovlen = 1;
+                libc::mmsghdr { msg_hdr: hdr, msg_len: 0 }
+            }));
+
+            let total = self.headers.len();
+            let mut sent = 0;
+            while sent < total {
+                let n = unsafe {
+                    libc::sendmmsg(fd, self.headers[sent..].as_mut_ptr(), (total - sent) as libc::c_uint, 0)
+                };
+                if n < 0 {
+                    let err = io::Error::last_os_error();
+                    if err.kind() == io::ErrorKind::Interrupted {
+                        continue;
+                    }
+                    return (sent, Some(err));
+                }
+                sent += n as usize;
+            }
+            (sent, None)
+        }
+    }
+
+    /// Buffers for one `recvmmsg` call, allocated once per receiver
+    pub struct Batch {
+        packets: Vec<[u8; PACKET_SIZE]>,
+        controls: Vec<[u64; CONTROL_SIZE / 8]>,
+        addrs: Vec<libc::sockaddr_storage>,
+        iovecs: Vec<libc::iovec>,
+        headers: Vec<libc::mmsghdr>,
+    }
+
+    // SAFETY: the headers only point into the batch's own heap buffers and are
+    // rebuilt before every receive
+    unsafe impl Send for Batch {}
+
+    impl Batch {
+        pub fn new() -> Self {
+            Batch {
+                packets: vec![[0; PACKET_SIZE]; BATCH],
+                controls: vec![[0; CONTROL_SIZE / 8]; BATCH],
+                addrs: vec![unsafe { mem::zeroed() }; BATCH],
+                iovecs: Vec::with_capacity(BATCH),
+                headers: Vec::with_capacity(BATCH),
+            }
+        }
+
+        /// Read whatever is queued on the socket without blocking
+        pub fn receive(&mut self, fd: RawFd, ipv6: bool, out: &mut VecDeque<Reply>) -> io::Result<()> {
+            // Pointers are refreshed every call since the kernel rewrites the lengths
+            self.iovecs.clear();
+            self.iovecs.extend(
+                self.packets
+                    .iter_mut()
+                    .map(|p| libc::iovec { iov_base: p.as_mut_ptr() as *mut libc::c_void, iov_len: PACKET_SIZE }),
+            );
+            self.headers.clear();
+            for i in 0..BATCH {
+                let mut hdr: libc::msghdr = unsafe { mem::zeroed() };
+                hdr.msg_name = &mut self.addrs[i] as *mut _ as *mut libc::c_void;
+                hdr.msg_namelen = mem::size_of::<libc::sockaddr_storage>() as libc::socklen_t;
+                hdr.msg_iov = &mut self.iovecs[i];
+                hdr.msg_iovlen = 1;
+                hdr.msg_control = self.controls[i].as_mut_ptr() as *mut libc::c_void;
+                hdr.msg_controllen = CONTROL_SIZE as _;
+                self.headers.push(libc::mmsghdr { msg_hdr: hdr, msg_len: 0 });
+            }
+
+            let n = unsafe {
+                libc::recvmmsg(fd, self.headers.as_mut_ptr(), BATCH as libc::c_uint, libc::MSG_DONTWAIT, ptr::null_mut())
+            };
+            if n < 0 {
+                let err = io::Error::last_os_error();
+                return match err.kind() {
+                    io::ErrorKind::WouldBlock | io::ErrorKind::Interrupted => Ok(()),
+                    _ => Err(err),
+                };
+            }
+
+            for (i, header) in self.headers.iter().enumerate().take(n as usize) {
+                let control = control(&header.msg_hdr);
+                let received = control.received.unwrap_or_else(SystemTime::now);
+                let Some(source) = address(&self.addrs[i]) else {
+                    continue;
+                };
+                let packet = &self.packets[i][..(header.msg_len as usize).min(PACKET_SIZE)];
+                // Replies to a large --size don't fit; their headers still tell whose they are
+                let truncated = header.msg_hdr.msg_flags & libc::MSG_TRUNC != 0;
+
+                // Raw ICMPv6 sockets deliver the message alone
+                if ipv6 {
+                    let Some((icmp_type, code, identifier, sequence)) = parse_icmp6(packet) else {
+                        continue;
+                    };
+                    let checksum_ok = match (source, control.destination) {
+                        _ if truncated => true,
+                        (IpAddr::V6(source), Some(destination)) => checksum6(source, destination, packet) == 0,
+                        // The kernel verified it before handing the message over
+                        _ => true,
+                    };
+                    let (ttl, mtu) = (control.hop_limit, next_hop_mtu(packet, true));
+                    let payload = if truncated { None } else { echo_payload(icmp_type, packet) };
+                    out.push_back(Reply { source, icmp_type, code, identifier, sequence, checksum_ok, received, ttl, mtu, payload });
+                    continue;
+                }
+
+                // Raw IPv4 sockets deliver the IP header in front of the ICMP message
+                let header_len = packet.first().map_or(0, |b| (b & 0x0f) as usize * 4);
+                let (ip, icmp) = packet.split_at(header_len.min(packet.len()));
+                let (icmp_type, code, identifier, sequence) = parse_icmp(icmp);
+                let checksum_ok = checksum_valid(ip) && (truncated || checksum_valid(icmp));
+                let (ttl, mtu) = (ip.get(8).copied(), next_hop_mtu(icmp, false));
+                let payload = if truncated { None } else { echo_payload(icmp_type, icmp) };
+                out.push_back(Reply { source, icmp_type, code, identifier, sequence, checksum_ok, received, ttl, mtu, payload });
+            }
+            Ok(())
+        }
+    }
+
+    /// What the control messages of a received packet tell
+    #[derive(Default)]
+    struct Control {
+        /// Kernel receive time, from `SCM_TIMESTAMPNS`
+        received: Option<SystemTime>,
+        /// Address the packet was sent to, from `IPV6_PKTINFO`
+        destination: Option<Ipv6Addr>,
+        /// From `IPV6_HOPLIMIT`
+        hop_limit: Option<u8>,
+    }
+
+    fn control(hdr: &libc::msghdr) -> Control {
+        let mut control = Control::default();
+        unsafe {
+            let mut cmsg = libc::CMSG_FIRSTHDR(hdr);
+            while !cmsg.is_null() {
+                let data = libc::CMSG_DATA(cmsg);
+                match ((*cmsg).cmsg_level, (*cmsg).cmsg_type) {
+                    (libc::SOL_SOCKET, libc::SCM_TIMESTAMPNS) => {
+                        let ts = ptr::read_unaligned(data as *const libc::timespec);
+                        control.received = Some(UNIX_EPOCH + Duration::new(ts.tv_sec as u64, ts.tv_nsec as u32));
+                    }
+                    (libc::IPPROTO_IPV6, libc::IPV6_PKTINFO) => {
+                        let info = ptr::read_unaligned(data as *const libc::in6_pktinfo);
+                        control.destination = Some(Ipv6Addr::from(info.ipi6_addr.s6_addr));
+                    }
+                    (libc::IPPROTO_IPV6, libc::IPV6_HOPLIMIT) => {
+                        let hops = ptr::read_unaligned(data as *const libc::c_int);
+                        control.hop_limit = u8::try_from(hops).ok();
+                    }
+                    _ => {}
+                }
+                cmsg = libc::CMSG_NXTHDR(hdr, cmsg);
+            }
+        }
+        control
+    }
+}
+
+#[cfg(windows)]
+mod windows {
+    use std::ffi::c_void;
+    use std::io;
+    use std::mem;
+    use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+    use std::ptr;
+    use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+    use std::sync::Arc;
+    use std::thread;
+    use std::time::{Duration, SystemTime};
+
+    use super::{IcmpReceiver, IcmpSender, Reply, DEST_UNREACHABLE, ECHO_REPLY, PARAMETER_PROBLEM, TIME_EXCEEDED};
+
+    type Handle = *mut c_void;
+    const INVALID_HANDLE_VALUE: Handle = -1isize as Handle;
+    const AF_INET6: u16 = 23;
+
+    // How long a probe is waited for; later than the run's timeout it is dropped there
+    const WAIT_MS: u32 = 10_000;
+    // Room for one reply with the echoed data, plus what the API asks for on top
+    const REPLY_SIZE: usize = 1024;
+
+    // IP_STATUS values of <ipexport.h>; several have an IPv6 name as well
+    const IP_SUCCESS: u32 = 0;
+    const IP_DEST_NET_UNREACHABLE: u32 = 11002;
+    const IP_DEST_HOST_UNREACHABLE: u32 = 11003;
+    const IP_DEST_PROT_UNREACHABLE: u32 = 11004;
+    const IP_DEST_PORT_UNREACHABLE: u32 = 11005;
+    const IP_PACKET_TOO_BIG: u32 = 11009;
+    const IP_TTL_EXPIRED_TRANSIT: u32 = 11013;
+    const IP_TTL_EXPIRED_REASSEM: u32 = 11014;
+    const IP_PARAM_PROBLEM: u32 = 11015;
+
+    // Don't fragment, in IP_OPTION_INFORMATION
+    const IP_FLAG_DF: u8 = 0x2;
+
+    #[repr(C)]
+    struct IpOptionInformation {
+        ttl: u8,
+        tos: u8,
+        flags: u8,
+        options_size: u8,
+        options_data: *mut u8,
+    }
+
+    /// `ICMP_ECHO_REPLY32`, the layout replies have on 32- and 64-bit Windows alike
+    #[repr(C)]
+    struct IcmpEchoReply {
+        address: u32,
+        status: u32,
+        round_trip_time: u32,
+        data_size: u16,
+        reserved: u16,
+        data: u32,
+        ttl: u8,
+        tos: u8,
+        flags: u8,
+        options_size: u8,
+        options_data: u32,
+    }
+
+    #[repr(C)]
+    struct SockaddrIn6 {
+        family: u16,
+        port: u16,
+        flowinfo: u32,
+        addr: [u8; 16],
+        scope_id: u32,
+    }
+
+    #[repr(C, packed)]
+    struct Ipv6AddressEx {
+        port: u16,
+        flowinfo: u32,
+        addr: [u16; 8],
+        scope_id: u32,
+    }
+
+    #[repr(C)]
+    struct Icmpv6EchoReply {
+        address: Ipv6AddressEx,
+        status: u32,
+        round_trip_time: u32,
+    }
+
+    #[link(name = "iphlpapi")]
+    extern "system" {
+        fn IcmpCreateFile() -> Handle;
+        fn Icmp6CreateFile() -> Handle;
+        fn IcmpCloseHandle(handle: Handle) -> i32;
+        #[allow(clippy::too_many_arguments)]
+        fn IcmpSendEcho2Ex(
+            handle: Handle,
+            event: Handle,
+            apc_routine: *mut c_void,
+            apc_context: *mut c_void,
+            source: u32,
+            destination: u32,
+            request_data: *const c_void,
+            request_size: u16,
+            request_options: *const IpOptionInformation,
+            reply_buffer: *mut c_void,
+            reply_size: u32,
+            timeout: u32,
+        ) -> u32;
+        #[allow(clippy::too_many_arguments)]
+        fn Icmp6SendEcho2(
+            handle: Handle,
+            event: Handle,
+            apc_routine: *mut c_void,
+            apc_context: *mut c_void,
+            source: *const SockaddrIn6,
+            destination: *const SockaddrIn6,
+            request_data: *const c_void,
+            request_size: u16,
+            request_options: *const IpOptionInformation,
+            reply_buffer: *mut c_void,
+            reply_size: u32,
+            timeout: u32,
+        ) -> u32;
+    }
+
+    /// An ICMP handle of the IP Helper API, shared by the probes in flight
+    struct IcmpHandle(Handle);
+
+    // The API may be called from any thread on the same handle
+    unsafe impl Send for IcmpHandle {}
+    unsafe impl Sync for IcmpHandle {}
+
+    impl Drop for IcmpHandle {
+        fn drop(&mut self) {
+            unsafe {
+                IcmpCloseHandle(self.0);
+            }
+        }
+    }
+
+    /// Open an ICMP handle, or an ICMPv6 one for IPv6 targets; no administrator rights needed
+    pub fn open(ipv6: bool) -> Result<(HelperSender, HelperReceiver), String> {
+        let handle = unsafe { if ipv6 { Icmp6CreateFile() } else { IcmpCreateFile() } };
+        if handle == INVALID_HANDLE_VALUE {
+            return Err(format!("Error creating ICMP handle: {}", io::Error::last_os_error()));
+        }
+        let (tx, rx) = mpsc::channel();
+        let settings = Settings { ttl: 128, flags: 0, source: None };
+        let sender = HelperSender { handle: Arc::new(IcmpHandle(handle)), ipv6, settings, replies: tx };
+        Ok((sender, HelperReceiver { replies: rx }))
+    }
+
+    /// What the probes of a sender go out with
+    #[derive(Clone, Copy)]
+    struct Settings {
+        ttl: u8,
+        // IP_OPTION_INFORMATION flags of the probes
+        flags: u8,
+        source: Option<IpAddr>,
+    }
+
+    pub struct HelperSender {
+        handle: Arc<IcmpHandle>,
+        ipv6: bool,
+        settings: Settings,
+        replies: Sender<Reply>,
+    }
+
+    impl IcmpSender for HelperSender {
+        /// TTL (hop limit for ICMPv6) of the probes sent from now on
+        fn set_ttl(&mut self, ttl: u8) -> io::Result<()> {
+            self.settings.ttl = ttl;
+            Ok(())
+        }
+
+        /// Send the probes from now on with the don't fragment bit
+        fn set_dont_fragment(&mut self) -> io::Result<()> {
+            self.settings.flags |= IP_FLAG_DF;
+            Ok(())
+        }
+
+        /// Send the probes through this interface only
+        fn bind_interface(&mut self, _name: &str) -> io::Result<()> {
+            Err(io::Error::new(io::ErrorKind::Unsupported, "probes can only be bound to an interface on Linux"))
+        }
+
+        /// Send the probes from now on from this local address
+        fn bind_source(&mut self, addr: IpAddr) -> io::Result<()> {
+            self.settings.source = Some(addr);
+            Ok(())
+        }
+
+        /// Send each echo request's data to `addr`; the answers arrive at the receiver
+        fn send_batch(&mut self, packets: &[Vec<u8>], addr: IpAddr) -> (usize, Option<io::Error>) {
+            for (sent, packet) in packets.iter().enumerate() {
+                if packet.len() < 8 || addr.is_ipv6() != self.ipv6 {
+                    return (sent, Some(io::Error::new(io::ErrorKind::InvalidInput, "not an echo request for this handle")));
+                }
+                // Windows picks the identifier itself; replies carry ours so they are routed as usual
+                let identifier = u16::from_be_bytes([packet[4], packet[5]]);
+                let sequence = u16::from_be_bytes([packet[6], packet[7]]);
+                let data = packet[8..].to_vec();
+                let (handle, settings, replies) = (Arc::clone(&self.handle), self.settings, self.replies.clone());
+                let spawned = thread::Builder::new().name("echo".to_string()).spawn(move || {
+                    if let Some(reply) = echo(&handle, addr, &data, settings, identifier, sequence) {
+                        let _ = replies.send(reply);
+                    }
+                });
+                if let Err(e) = spawned {
+                    return (sent, Some(e));
+                }
+            }
+            (packets.len(), None)
+        }
+    }
+
+    /// One echo request and what answered it, `None` when nothing did
+    fn echo(handle: &IcmpHandle, addr: IpAddr, data: &[u8], settings: Settings, identifier: u16, sequence: u16) -> Option<Reply> {
+        let Settings { ttl, flags, source: bound } = settings;
+        let options = IpOptionInformation { ttl, tos: 0, flags, options_size: 0, options_data: ptr::null_mut() };
+        let mut buffer = vec![0u8; REPLY_SIZE + data.len()];
+        let (status, source, reply_ttl) = match addr {
+            IpAddr::V4(dest) => {
+                let count = unsafe {
+                    IcmpSendEcho2Ex(
+                        handle.0,
+                        ptr::null_mut(),
+                        ptr::null_mut(),
+                        ptr::null_mut(),
+                        // 0 lets the system pick the source
+                        match bound {
+                            Some(IpAddr::V4(bound)) => u32::from_ne_bytes(bound.octets()),
+                            _ => 0,
+                        },
+                        u32::from_ne_bytes(dest.octets()),
+                        data.as_ptr().cast(),
+                        data.len() as u16,
+                        &options,
+                        buffer.as_mut_ptr().cast(),
+                        buffer.len() as u32,
+                        WAIT_MS,
+                    )
+                };
+                if count == 0 {
+                    return None;
+                }
+                let reply = unsafe { ptr::read_unaligned(buffer.as_ptr() as *const IcmpEchoReply) };
+                (reply.status, IpAddr::V4(Ipv4Addr::from(reply.address.to_ne_bytes())), Some(reply.ttl))
+            }
+            IpAddr::V6(dest) => {
+                // The unspecified address lets the system pick the source
+                let from = match bound {
+                    Some(IpAddr::V6(bound)) => bound.octets(),
+                    _ => [0; 16],
+                };
+                let source = SockaddrIn6 { family: AF_INET6, port: 0, flowinfo: 0, addr: from, scope_id: 0 };
+                let destination = SockaddrIn6 { addr: dest.octets(), ..source };
+                let count = unsafe {
+                    Icmp6SendEcho2(
+                        handle.0,
+                        ptr::null_mut(),
+                        ptr::null_mut(),
+                        ptr::null_mut(),
+                        &source,
+                        &destination,
+                        data.as_ptr().cast(),
+                        data.len() as u16,
+                        &options,
+                        buffer.as_mut_ptr().cast(),
+                        buffer.len() as u32,
+                        WAIT_MS,
+                    )
+                };
+                if count == 0 {
+                    return None;
+                }
+                let reply = unsafe { ptr::read_unaligned(buffer.as_ptr() as *const Icmpv6EchoReply) };
+                let words = reply.address.addr;
+                let octets: Vec<u8> = words.iter().flat_map(|word| word.to_ne_bytes()).collect();
+                let octets: [u8; 16] = octets.try_into().unwrap_or([0; 16]);
+                // The hop limit of ICMPv6 replies isn't reported
+                (reply.status, IpAddr::V6(Ipv6Addr::from(octets)), None)
+            }
+        };
+
+        let (icmp_type, code) = match status {
+            IP_SUCCESS => (ECHO_REPLY, 0),
+            IP_DEST_NET_UNREACHABLE => (DEST_UNREACHABLE, 0),
+            IP_DEST_HOST_UNREACHABLE => (DEST_UNREACHABLE, 1),
+            // "Administratively prohibited" under its IPv6 name
+            IP_DEST_PROT_UNREACHABLE if addr.is_ipv6() => (DEST_UNREACHABLE, 13),
+            IP_DEST_PROT_UNREACHABLE => (DEST_UNREACHABLE, 2),
+            IP_DEST_PORT_UNREACHABLE => (DEST_UNREACHABLE, 3),
+            IP_PACKET_TOO_BIG => (DEST_UNREACHABLE, 4),
+            IP_TTL_EXPIRED_TRANSIT => (TIME_EXCEEDED, 0),
+            IP_TTL_EXPIRED_REASSEM => (TIME_EXCEEDED, 1),
+            IP_PARAM_PROBLEM => (PARAMETER_PROBLEM, 0),
+            // Timed out, or failed without an answer from the network
+            _ => return None,
+        };
+        let ttl = if icmp_type == ECHO_REPLY { reply_ttl } else { None };
+        let received = SystemTime::now();
+        // The API doesn't pass on the MTU of a packet too big answer, and the data isn't checked here
+        Some(Reply { source, icmp_type, code, identifier, sequence: Some(sequence), checksum_ok: true, received, ttl, mtu: None, payload: None })
+    }
+
+    pub struct HelperReceiver {
+        replies: Receiver<Reply>,
+    }
+
+    impl IcmpReceiver for HelperReceiver {
+        fn next_with_timeout(&mut self, timeout: Duration) -> io::Result<Option<Reply>> {
+            match self.replies.recv_timeout(timeout) {
+                Ok(reply) => Ok(Some(reply)),
+                Err(RecvTimeoutError::Timeout) => Ok(None),
+                Err(RecvTimeoutError::Disconnected) => Err(io::Error::new(io::ErrorKind::BrokenPipe, "ICMP handle closed")),
+            }
+        }
+    }
+
+    // The reply layout is fixed by the API, whatever the pointer size
+    const _: () = assert!(mem::size_of::<IcmpEchoReply>() == 28);
+}
@@ -0,0 +1,203 @@
+//! Excel workbook export.
+//!
+//! The workbook has a summary/statistics sheet followed by the raw results
+//! and, for aggregated runs, the per-second rows.
+
+use rust_xlsxwriter::{Format, FormatAlign, Workbook, Worksheet, XlsxError};
+
+use crate::export::ReportHeader;
+use crate::{PingResult, PingStatistics, SecondAggregate, TIMESTAMP_FORMAT};
+
+fn xlsx_err(e: XlsxError) -> String {
+    format!("Failed to build XLSX workbook: {}", e)
+}
+
+fn write_header_row(sheet: &mut Worksheet, columns: &[&str], bold: &Format) -> Result<(), XlsxError> {
+    for (col, name) in columns.iter().enumerate() {
+        sheet.write_string_with_format(0, col as u16, *name, bold)?;
+    }
+    sheet.set_freeze_panes(1, 0)?;
+    Ok(())
+}
+
+fn write_opt(sheet: &mut Worksheet, row: u32, col: u16, value: Option<f64>, fmt: &Format) -> Result<(), XlsxError> {
+    if let Some(v) = value {
+        sheet.write_number_with_format(row, col, v, fmt)?;
+    }
+    Ok(())
+}
+
+fn statistics_sheet(
+    sheet: &mut Worksheet,
+    header: &ReportHeader,
+    timestamp_end: &str,
+    complete: bool,
+    stats: &PingStatistics,
+) -> Result<(), XlsxError> {
+    let title = Format::new().set_bold().set_font_size(14);
+    let bold = Format::new().set_bold();
+    let ms = Format::new().set_num_format("0.00 \"ms\"").set_align(FormatAlign::Right);
+    let pct = Format::new().set_num_format("0.00\"%\"").set_align(FormatAlign::Right);
+
+    sheet.set_name("Statistics")?;
+    sheet.write_string_with_format(0, 0, "Ping Report", &title)?;
+
+    let info = [
+        ("Host", header.host.clone()),
+        ("IP", header.ip_address.clone()),
+        ("Run", header.run_id.clone()),
+        ("Start", header.timestamp_start.clone()),
+        ("End", timestamp_end.to_string()),
+        ("Timeout (ms)", header.timeout_ms.to_string()),
+        ("Interval (s)", header.interval_seconds.to_string()),
+        ("Complete", if complete { "yes" } else { "no" }.to_string()),
+    ];
+    let overhead = header.overhead_ms.map(|ms| {
+        let label = if header.overhead_subtracted { "Overhead subtracted (ms)" } else { "Local overhead (ms)" };
+        (label, format!("{:.3}", ms))
+    });
+    let ttl = header.ttl.map(|ttl| ("TTL sent", ttl.to_string()));
+    let interface = header.interface.clone().map(|interface| ("Interface", interface));
+    let source = header.source_address.clone().map(|source| ("Source address", source));
+    let mut row = 2;
+    for (label, value) in info.into_iter().chain(overhead).chain(ttl).chain(interface).chain(source) {
+        sheet.write_string_with_format(row, 0, label, &bold)?;
+        sheet.write_string(row, 1, value)?;
+        row += 1;
+    }
+
+    row += 1;
+    let counters = [
+        ("Packets sent", stats.packets_sent),
+        ("Packets received", stats.packets_received),
+        ("Packets lost", stats.packets_lost),
+        ("Timeouts", stats.packets_timeout),
+        ("Unreachable", stats.packets_unreachable),
+        ("TTL exceeded", stats.packets_ttl_exceeded),
+        ("Parameter problems", stats.packets_parameter_problem),
+        ("Send errors", stats.packets_send_error),
+        ("Corrupted replies", stats.packets_corrupted),
+        ("Corrupted payloads", stats.packets_payload_corrupted),
+        ("Duplicates", stats.packets_duplicate),
+        ("Out of order", stats.packets_reordered),
+        ("Longest loss streak", stats.longest_loss_streak),
+    ];
+    for (label, value) in counters {
+        sheet.write_string_with_format(row, 0, label, &bold)?;
+        sheet.write_number(row, 1, value)?;
+        row += 1;
+    }
+    sheet.write_string_with_format(row, 0, "Packet loss", &bold)?;
+    sheet.write_number_with_format(row, 1, stats.packet_loss_percent, &pct)?;
+    row += 2;
+
+    let rtts = [
+        ("Min RTT", stats.min_ms),
+        ("Avg RTT", stats.avg_ms),
+        ("Max RTT", stats.max_ms),
+        ("StdDev", stats.std_dev_ms),
+        ("p50 RTT", stats.p50_ms),
+        ("p95 RTT", stats.p95_ms),
+        ("p99 RTT", stats.p99_ms),
+        ("Jitter", stats.jitter_ms),
+        ("RTT delta avg", stats.delta_avg_ms),
+        ("RTT delta max", stats.delta_max_ms),
+    ];
+    for (label, value) in rtts {
+        sheet.write_string_with_format(row, 0, label, &bold)?;
+        write_opt(sheet, row, 1, value, &ms)?;
+        row += 1;
+    }
+
+    sheet.set_column_width(0, 18)?;
+    sheet.set_column_width(1, 22)?;
+    Ok(())
+}
+
+fn results_sheet(sheet: &mut Worksheet, results: &[PingResult], raw_rtt: bool) -> Result<(), XlsxError> {
+    let bold = Format::new().set_bold();
+    let ms = Format::new().set_num_format("0.00");
+
+    sheet.set_name("Results")?;
+    let mut columns = vec!["seq", "rtt_ms", "success", "timestamp", "status", "icmp_code", "ttl"];
+    if raw_rtt {
+        columns.push("raw_rtt_ms");
+    }
+    columns.extend(["duplicate", "reordered", "corrupted"]);
+    write_header_row(sheet, &columns, &bold)?;
+    for (i, result) in results.iter().enumerate() {
+        let row = i as u32 + 1;
+        sheet.write_number(row, 0, result.seq)?;
+        write_opt(sheet, row, 1, result.rtt_ms, &ms)?;
+        sheet.write_boolean(row, 2, result.success())?;
+        if let Some(ts) = &result.timestamp {
+            sheet.write_string(row, 3, ts.format(TIMESTAMP_FORMAT).to_string())?;
+        }
+        sheet.write_string(row, 4, result.status.name())?;
+        if let Some(code) = result.status.code() {
+            sheet.write_number(row, 5, code)?;
+        }
+        if let Some(ttl) = result.ttl {
+            sheet.write_number(row, 6, ttl)?;
+        }
+        let mut col = 7;
+        if raw_rtt {
+            write_opt(sheet, row, col, result.raw_rtt_ms, &ms)?;
+            col += 1;
+        }
+        sheet.write_boolean(row, col, result.duplicate)?;
+        sheet.write_boolean(row, col + 1, result.reordered)?;
+        sheet.write_boolean(row, col + 2, result.corrupted)?;
+    }
+    sheet.set_column_width(3, 24)?;
+    Ok(())
+}
+
+fn aggregates_sheet(sheet: &mut Worksheet, aggregates: &[SecondAggregate]) -> Result<(), XlsxError> {
+    let bold = Format::new().set_bold();
+    let ms = Format::new().set_num_format("0.00");
+
+    sheet.set_name("Per-second")?;
+    write_header_row(
+        sheet,
+        &["second", "timestamp", "sent", "received", "lost", "loss_percent", "min_ms", "avg_ms", "max_ms"],
+        &bold,
+    )?;
+    for (i, agg) in aggregates.iter().enumerate() {
+        let row = i as u32 + 1;
+        sheet.write_number(row, 0, agg.second as f64)?;
+        sheet.write_string(row, 1, &agg.timestamp)?;
+        sheet.write_number(row, 2, agg.sent)?;
+        sheet.write_number(row, 3, agg.received)?;
+        sheet.write_number(row, 4, agg.lost)?;
+        sheet.write_number_with_format(row, 5, agg.loss_percent, &ms)?;
+        write_opt(sheet, row, 6, agg.min_ms, &ms)?;
+        write_opt(sheet, row, 7, agg.avg_ms, &ms)?;
+        write_opt(sheet, row, 8, agg.max_ms, &ms)?;
+    }
+    sheet.set_column_width(1, 20)?;
+    Ok(())
+}
+
+/// Build the workbook and return the encoded file contents
+pub fn build_workbook(
+    header: &ReportHeader,
+    timestamp_end: &str,
+    complete: bool,
+    results: &[PingResult],
+    aggregates: Option<&[SecondAggregate]>,
+    stats: &PingStatistics,
+) -> Result<Vec<u8>, String> {
+    let mut workbook = Workbook::new();
+
+    statistics_sheet(workbook.add_worksheet(), header, timestamp_end, complete, stats)
+        .map_err(xlsx_err)?;
+    if !results.is_empty() {
+        results_sheet(workbook.add_worksheet(), results, header.overhead_subtracted).map_err(xlsx_err)?;
+    }
+    if let Some(aggregates) = aggregates {
+        aggregates_sheet(workbook.add_worksheet(), aggregates).map_err(xlsx_err)?;
+    }
+
+    workbook.save_to_buffer().map_err(xlsx_err)
+}